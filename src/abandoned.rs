@@ -0,0 +1,144 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Duration, Local};
+
+use directories::UserDirs;
+
+use ini::Ini;
+
+use log::info;
+
+use crate::archive::Archive;
+use crate::clock;
+use crate::error::{self, Context};
+use crate::manifest::Manifest;
+use crate::profiles::Profile;
+use crate::settings::current_save;
+use crate::store;
+
+/// Weeks without the live save changing after which a campaign counts as abandoned when
+/// `abandoned_weeks` is not set.
+const DEFAULT_ABANDONED_WEEKS: i64 = 8;
+
+/// A save with backups that has not been played for a while.
+pub struct Campaign {
+	pub save: String,
+	pub backup_dir: PathBuf,
+	/// When the live save last changed, or when it was last backed up once it is gone.
+	pub last_played: DateTime<Local>,
+	pub backups: usize,
+	pub bytes: u64,
+}
+
+/// Reads how many weeks a live save must go unchanged for its campaign to count as abandoned from
+/// `abandoned_weeks`.
+pub fn abandoned_weeks(config: &Ini) -> i64 {
+	config
+		.general_section()
+		.get("abandoned_weeks")
+		.and_then(|weeks| weeks.trim().parse::<i64>().ok())
+		.filter(|weeks| *weeks > 0)
+		.unwrap_or(DEFAULT_ABANDONED_WEEKS)
+}
+
+/// Reads where abandoned campaigns are archived to from `cold_storage_folder`, such as a folder on
+/// an external drive, falling back to the home folder.
+pub fn cold_storage(config: &Ini) -> PathBuf {
+	config
+		.general_section()
+		.get("cold_storage_folder")
+		.map(str::trim)
+		.filter(|folder| !folder.is_empty())
+		.map(PathBuf::from)
+		.or_else(|| UserDirs::new().map(|dirs| dirs.home_dir().to_path_buf()))
+		.unwrap_or_default()
+}
+
+/// The saves of a game whose live save has not changed for `abandoned_weeks`, longest unplayed
+/// first. The working save is never counted, however long it was left alone.
+pub fn find(config: &Ini, profile: &Profile) -> io::Result<Vec<Campaign>> {
+	let cutoff = clock::now() - Duration::weeks(abandoned_weeks(config));
+	let working = current_save(config);
+
+	let mut campaigns = Vec::new();
+	for backup_dir in store::save_dirs(&profile.backup_path)? {
+		let save = match profile.save_of(&backup_dir) {
+			Some(save) if Some(save.as_str()) != working => save,
+			_ => continue,
+		};
+		let backups = store::list_backups(&backup_dir)?;
+		if backups.is_empty() {
+			continue;
+		}
+
+		let manifest = Manifest::load(&backup_dir);
+		let last_played = match fs::metadata(profile.save_file(&save)).and_then(|m| m.modified()) {
+			Ok(modified) => DateTime::<Local>::from(modified),
+			Err(_) => match backups.iter().filter_map(|b| b.created(&manifest)).max() {
+				Some(created) => created,
+				None => continue,
+			},
+		};
+		if last_played > cutoff {
+			continue;
+		}
+
+		let mut bytes = 0;
+		for backup in &backups {
+			bytes += fs::metadata(&backup.path)?.len();
+		}
+		campaigns.push(Campaign {
+			save,
+			backup_dir,
+			last_played,
+			backups: backups.len(),
+			bytes,
+		});
+	}
+	campaigns.sort_by_key(|campaign| campaign.last_played);
+
+	Ok(campaigns)
+}
+
+/// Moves every backup of a save into an archive in `folder`, named after the save and the day,
+/// and then into the trash once the archive is read back. Returns how many backups were
+/// archived and where.
+pub fn archive(backup_dir: &Path, save: &str, folder: &Path) -> error::Result<(usize, PathBuf)> {
+	let backups = store::list_backups(backup_dir)?;
+	let (first, last) = match (backups.first(), backups.last()) {
+		(Some(first), Some(last)) => (first.number, last.number),
+		_ => return Err("There are no backups to archive.".into()),
+	};
+
+	fs::create_dir_all(folder).context("create", folder)?;
+	let path = folder.join(format!(
+		"{} backups {}.tar.zst",
+		save,
+		clock::now().format("%Y-%m-%d")
+	));
+	if path.exists() {
+		return Err(format!("{} already exists.", path.display()).into());
+	}
+
+	let archive = Archive::open(&path)?;
+	let exported = archive.export(backup_dir, first, last)?;
+	// the backups only leave the backup folder once the archive is known to hold all of them
+	if archive.backups()?.len() != exported || exported != backups.len() {
+		return Err(format!(
+			"{} does not hold every backup, so they were left in place.",
+			path.display()
+		)
+		.into());
+	}
+	store::trash_backups(backup_dir, &backups)?;
+	info!(
+		"Archived {} backups of {} to {}",
+		exported,
+		save,
+		path.display()
+	);
+
+	Ok((exported, path))
+}
@@ -0,0 +1,171 @@
+use std::path::PathBuf;
+
+use chrono::{DateTime, Local};
+
+use cursive::traits::*;
+use cursive::views::{Dialog, LinearLayout, SelectView, TextView};
+use cursive::Cursive;
+
+use ini::Ini;
+
+use log::info;
+
+use crate::abandoned::{self, abandoned_weeks, cold_storage, Campaign};
+use crate::clock;
+use crate::confirm::confirm;
+use crate::error;
+use crate::format_size;
+use crate::i18n;
+use crate::profiles::Profile;
+use crate::progress_dialog;
+use crate::settings::display_name;
+
+/// Name of the list of abandoned campaigns.
+const CAMPAIGN_LIST_NAME: &str = "abandoned_campaigns";
+
+/// A campaign as a row of the list, in the columns of its header.
+fn label(config: &Ini, campaign: &Campaign, now: DateTime<Local>) -> String {
+	i18n::fill(
+		"abandoned.row",
+		&[
+			&format!("{:<24}", display_name(config, &campaign.save)),
+			&format!("{:>4}", (now - campaign.last_played).num_weeks()),
+			&format!("{:>5}", campaign.backups),
+			&format!("{:>9}", format_size(campaign.bytes)),
+		],
+	)
+}
+
+/// Points out the campaigns of a game that look abandoned, so that they can be archived right
+/// away or later from the menu. Read-only backup folders are left alone, as nothing can be
+/// archived out of them.
+pub fn flag(s: &mut Cursive, profile: &Profile) {
+	if profile.read_only {
+		return;
+	}
+	let config: &mut Ini = s
+		.user_data()
+		.expect("User data not set up correctly on program start");
+	let campaigns = match abandoned::find(config, profile) {
+		Ok(campaigns) if !campaigns.is_empty() => campaigns,
+		_ => return,
+	};
+
+	let weeks = abandoned_weeks(config);
+	let saves = campaigns
+		.iter()
+		.map(|campaign| display_name(config, &campaign.save))
+		.collect::<Vec<_>>()
+		.join(", ");
+	info!(
+		"Not played for {} weeks or more: {}. They can be archived to cold storage from {}",
+		weeks,
+		saves,
+		i18n::text("menu.abandoned")
+	);
+
+	let profile = profile.clone();
+	s.add_layer(
+		Dialog::around(TextView::new(i18n::fill(
+			"abandoned.notice",
+			&[&weeks, &saves],
+		)))
+		.title(i18n::text("menu.abandoned"))
+		.button(i18n::text("abandoned.review"), move |s| {
+			s.pop_layer();
+			if let Err(e) = review(s, &profile) {
+				s.add_layer(Dialog::info(i18n::error(&e)));
+			}
+		})
+		.button(i18n::text("close"), |s| {
+			s.pop_layer();
+		}),
+	);
+}
+
+/// Lists the campaigns not played for `abandoned_weeks`, offering to move the backups of each
+/// into an archive in the `cold_storage_folder`, out of the backup folder and its overviews. The
+/// archive can be restored from or imported again later.
+pub fn review(s: &mut Cursive, profile: &Profile) -> error::Result<()> {
+	let config: &mut Ini = s
+		.user_data()
+		.expect("User data not set up correctly on program start");
+	let weeks = abandoned_weeks(config);
+	let campaigns = abandoned::find(config, profile)?;
+	if campaigns.is_empty() {
+		return Err(i18n::fill("abandoned.all_played", &[&weeks]).into());
+	}
+
+	let folder = cold_storage(config);
+	let now = clock::now();
+	let mut list = SelectView::<(String, PathBuf)>::new();
+	for campaign in &campaigns {
+		list.add_item(
+			label(config, campaign, now),
+			(campaign.save.clone(), campaign.backup_dir.clone()),
+		);
+	}
+
+	let header = format!(
+		"{:<24} {:>10}  {:>13}  {:>9}",
+		i18n::text("column.save"),
+		i18n::text("abandoned.unplayed"),
+		i18n::text("column.backups"),
+		i18n::text("column.size")
+	);
+	s.add_layer(
+		Dialog::around(
+			LinearLayout::vertical()
+				.child(TextView::new(format!(
+					"{}\n\n{}",
+					i18n::fill("abandoned.intro", &[&weeks, &folder.display()]),
+					header
+				)))
+				.child(
+					list.on_submit(move |s, (save, backup_dir): &(String, PathBuf)| {
+						offer_archive(s, save.clone(), backup_dir.clone(), folder.clone())
+					})
+					.with_name(CAMPAIGN_LIST_NAME)
+					.scrollable(),
+				),
+		)
+		.title(i18n::text("menu.abandoned"))
+		.button(i18n::text("ok"), |s| {
+			s.pop_layer();
+		}),
+	);
+
+	Ok(())
+}
+
+/// Confirms archiving the backups of a save, then archives them in the background.
+fn offer_archive(s: &mut Cursive, save: String, backup_dir: PathBuf, folder: PathBuf) {
+	let message = i18n::fill("abandoned.confirm_archive", &[&save, &folder.display()]);
+	confirm(s, &message, move |s| {
+		let save = save.clone();
+		let backup_dir = backup_dir.clone();
+		let folder = folder.clone();
+		progress_dialog::run_in_background(
+			s,
+			i18n::text("abandoned.archiving"),
+			move || abandoned::archive(&backup_dir, &save, &folder).map_err(|e| e.to_string()),
+			|s, archived| match archived {
+				Ok((count, path)) => {
+					s.call_on_name(
+						CAMPAIGN_LIST_NAME,
+						|list: &mut SelectView<(String, PathBuf)>| {
+							if let Some(selected) = list.selected_id() {
+								list.remove_item(selected);
+							}
+						},
+					);
+					s.add_layer(Dialog::info(i18n::fill(
+						"abandoned.archived",
+						&[&count, &path.display()],
+					)));
+				}
+				Err(e) => s.add_layer(Dialog::info(i18n::error(&e))),
+			},
+		);
+	});
+}
@@ -0,0 +1,87 @@
+use std::io::{self, Write};
+
+use ini::Ini;
+
+use log::warn;
+
+use notify_rust::Notification;
+
+use crate::settings::save_setting;
+
+/// How the user wants to be told about backup events for one save, configured with the
+/// `notify_sound`, `notify_desktop` and `notify_webhook` keys.
+///
+/// A `[save.<name>]` section in conf.ini overrides the defaults from the general section for that
+/// save.
+#[derive(Clone, Default)]
+pub struct Preferences {
+	pub sound: bool,
+	pub desktop: bool,
+	pub webhook: Option<String>,
+}
+
+impl Preferences {
+	pub fn load(config: &Ini, save: &str) -> Self {
+		let get = |key: &str| save_setting(config, save, key);
+
+		Self {
+			sound: get("notify_sound") == Some("true"),
+			desktop: get("notify_desktop") == Some("true"),
+			webhook: get("notify_webhook").map(ToString::to_string),
+		}
+	}
+
+	/// Delivers a notification through every enabled channel. Failures are only logged, since a
+	/// missing notification daemon or unreachable webhook must never interrupt backups.
+	pub fn notify(&self, title: &str, message: &str) {
+		if self.sound {
+			// the terminal bell is the one sound available without extra audio dependencies
+			let mut stdout = io::stdout();
+			if let Err(e) = stdout.write_all(b"\x07").and_then(|()| stdout.flush()) {
+				warn!("Could not play notification sound: {}", e);
+			}
+		}
+
+		if self.desktop {
+			if let Err(e) = Notification::new()
+				.appname("Save Manager")
+				.summary(title)
+				.body(message)
+				.show()
+			{
+				warn!("Could not show desktop notification: {}", e);
+			}
+		}
+
+		if let Some(url) = &self.webhook {
+			let body = format!(
+				"{{\"title\":{},\"message\":{}}}",
+				json_string(title),
+				json_string(message)
+			);
+			if let Err(e) = ureq::post(url)
+				.set("Content-Type", "application/json")
+				.send_string(&body)
+			{
+				warn!("Could not call notification webhook: {}", e);
+			}
+		}
+	}
+}
+
+fn json_string(value: &str) -> String {
+	let mut escaped = String::with_capacity(value.len() + 2);
+	escaped.push('"');
+	for c in value.chars() {
+		match c {
+			'"' => escaped.push_str("\\\""),
+			'\\' => escaped.push_str("\\\\"),
+			'\n' => escaped.push_str("\\n"),
+			c if c.is_control() => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+			c => escaped.push(c),
+		}
+	}
+	escaped.push('"');
+
+	escaped
+}
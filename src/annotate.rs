@@ -0,0 +1,284 @@
+use std::io;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+use cursive::traits::*;
+use cursive::views::{Dialog, EditView, LinearLayout, SelectView, TextView};
+use cursive::Cursive;
+
+use crate::history;
+use crate::i18n;
+use crate::manifest::Manifest;
+use crate::store::{self, Backup};
+
+const SCOPE_NAME: &str = "annotate_scope";
+
+const OPERATION_NAME: &str = "annotate_operation";
+
+const TEXT_NAME: &str = "annotate_text";
+
+const REPLACEMENT_NAME: &str = "annotate_replacement";
+
+/// A change made to the notes or tags of every backup picked.
+#[derive(Clone, Copy)]
+enum Operation {
+	Prefix,
+	Append,
+	Replace,
+	AddTag,
+	RemoveTag,
+}
+
+impl Operation {
+	const ALL: [Self; 5] = [
+		Self::Prefix,
+		Self::Append,
+		Self::Replace,
+		Self::AddTag,
+		Self::RemoveTag,
+	];
+
+	const fn describe(self) -> &'static str {
+		match self {
+			Self::Prefix => "Put the text before the note",
+			Self::Append => "Put the text after the note",
+			Self::Replace => "Replace the text in the note",
+			Self::AddTag => "Add the text as a tag",
+			Self::RemoveTag => "Remove the text as a tag",
+		}
+	}
+
+	/// The note and tags of a backup after the operation, which are unchanged when it does not
+	/// apply to them.
+	fn run(
+		self,
+		text: &str,
+		replacement: &str,
+		note: &str,
+		tags: &[String],
+	) -> (String, Vec<String>) {
+		let mut tags = tags.to_vec();
+		let note = match self {
+			Self::Prefix => format!("{} {}", text, note),
+			Self::Append => format!("{} {}", note, text),
+			Self::Replace => note.replace(text, replacement),
+			Self::AddTag => {
+				if !tags.iter().any(|tag| tag == text) {
+					tags.push(text.to_string());
+				}
+				note.to_string()
+			}
+			Self::RemoveTag => {
+				tags.retain(|tag| tag != text);
+				note.to_string()
+			}
+		};
+
+		(note.trim().to_string(), tags)
+	}
+}
+
+/// The note and tags of one backup before and after a bulk edit.
+#[derive(Clone)]
+pub struct Change {
+	pub number: usize,
+	file_name: String,
+	note: (String, String),
+	tags: (Vec<String>, Vec<String>),
+}
+
+impl Change {
+	fn describe(&self) -> String {
+		let mut description = format!("{}:", self.number);
+		if self.note.0 != self.note.1 {
+			description.push_str(&format!(" note \"{}\" -> \"{}\"", self.note.0, self.note.1));
+		}
+		if self.tags.0 != self.tags.1 {
+			description.push_str(&format!(
+				" tags ({}) -> ({})",
+				self.tags.0.join(", "),
+				self.tags.1.join(", ")
+			));
+		}
+		description
+	}
+}
+
+/// The backups of the play session the given backup was taken in, going by the time between
+/// backups like the campaign progress chart does.
+pub fn session_of(backups: Vec<Backup>, manifest: &Manifest, number: usize) -> Vec<Backup> {
+	history::sessions(backups, |backup| backup.created(manifest))
+		.into_iter()
+		.find(|session| session.iter().any(|backup| backup.number == number))
+		.unwrap_or_default()
+}
+
+/// Changes the notes and tags of backups one after the other, recording how to put each back in
+/// `undo` as it goes, so that what was applied before a failure can still be undone.
+pub fn apply(backup_dir: &Path, changes: &[Change], undo: &mut Vec<Change>) -> io::Result<()> {
+	for change in changes {
+		if change.tags.0 != change.tags.1 {
+			Manifest::update(backup_dir, |manifest| {
+				manifest.set_tags(&change.file_name, &change.tags.1);
+				Ok(())
+			})?;
+		}
+		let manifest = Manifest::load(backup_dir);
+
+		let backup = Backup::from_path(backup_dir.join(&change.file_name))
+			.filter(|backup| backup.path.exists())
+			.ok_or_else(|| {
+				io::Error::new(
+					io::ErrorKind::NotFound,
+					format!("Backup {} no longer exists", change.number),
+				)
+			})?
+			.with_recorded_note(&manifest);
+		let backup = if change.note.0 == change.note.1 {
+			backup
+		} else {
+			store::set_note(backup_dir, &backup, &change.note.1)?
+		};
+
+		undo.push(Change {
+			number: change.number,
+			file_name: backup.file_name,
+			note: (change.note.1.clone(), change.note.0.clone()),
+			tags: (change.tags.1.clone(), change.tags.0.clone()),
+		});
+	}
+
+	Ok(())
+}
+
+/// Opens a dialog for changing the notes or tags of many backups at once: those listed in the
+/// browser, or those of the session the selected backup belongs to. The changes are previewed
+/// before being made, and `on_applied` is given what puts them back.
+pub fn open<F>(
+	s: &mut Cursive,
+	backup_dir: PathBuf,
+	listed: Vec<Backup>,
+	session: Vec<Backup>,
+	on_applied: F,
+) where
+	F: Fn(&mut Cursive, Vec<Change>) + 'static,
+{
+	let mut scope = SelectView::new().popup();
+	scope.add_item(format!("The {} listed backups", listed.len()), listed);
+	scope.add_item(
+		format!(
+			"The {} backups of the selected backup's session",
+			session.len()
+		),
+		session,
+	);
+
+	let mut operation = SelectView::new().popup();
+	for item in &Operation::ALL {
+		operation.add_item(item.describe(), *item);
+	}
+
+	let on_applied = Rc::new(on_applied);
+	s.add_layer(
+		Dialog::around(
+			LinearLayout::vertical()
+				.child(scope.with_name(SCOPE_NAME))
+				.child(operation.with_name(OPERATION_NAME))
+				.child(TextView::new("Text:"))
+				.child(EditView::new().with_name(TEXT_NAME).min_width(40))
+				.child(TextView::new("Replace it with, when replacing:"))
+				.child(EditView::new().with_name(REPLACEMENT_NAME).min_width(40)),
+		)
+		.title("Edit backups in bulk")
+		.button("Cancel", |s| {
+			s.pop_layer();
+		})
+		.button("Preview", move |s| {
+			preview(s, &backup_dir, &on_applied);
+		}),
+	);
+}
+
+fn preview<F>(s: &mut Cursive, backup_dir: &Path, on_applied: &Rc<F>)
+where
+	F: Fn(&mut Cursive, Vec<Change>) + 'static,
+{
+	let backups = s
+		.call_on_name(SCOPE_NAME, |view: &mut SelectView<Vec<Backup>>| {
+			view.selection()
+		})
+		.flatten()
+		.expect("SelectView not created for bulk edit scope");
+	let operation = s
+		.call_on_name(OPERATION_NAME, |view: &mut SelectView<Operation>| {
+			view.selection()
+		})
+		.flatten()
+		.expect("SelectView not created for bulk edit operation");
+	let text = s
+		.call_on_name(TEXT_NAME, |view: &mut EditView| view.get_content())
+		.expect("EditView not created for bulk edit text");
+	let replacement = s
+		.call_on_name(REPLACEMENT_NAME, |view: &mut EditView| view.get_content())
+		.expect("EditView not created for bulk edit replacement");
+
+	let text = text.trim();
+	if text.is_empty() {
+		s.add_layer(Dialog::info("Enter the text to change the backups with."));
+		return;
+	}
+
+	let manifest = Manifest::load(backup_dir);
+	let mut changes = Vec::new();
+	for backup in backups.iter() {
+		let tags = manifest.tags(&backup.file_name);
+		let (note, new_tags) = (*operation).run(text, replacement.trim(), &backup.note, &tags);
+		if let Err(e) = store::check_note(&note) {
+			s.add_layer(Dialog::info(i18n::error(&e)));
+			return;
+		}
+		if note != backup.note || new_tags != tags {
+			changes.push(Change {
+				number: backup.number,
+				file_name: backup.file_name.clone(),
+				note: (backup.note.clone(), note),
+				tags: (tags, new_tags),
+			});
+		}
+	}
+
+	if changes.is_empty() {
+		s.add_layer(Dialog::info("None of the backups would change."));
+		return;
+	}
+
+	let summary = changes
+		.iter()
+		.map(Change::describe)
+		.collect::<Vec<_>>()
+		.join("\n");
+	let backup_dir = backup_dir.to_path_buf();
+	let on_applied = Rc::clone(on_applied);
+	s.add_layer(
+		Dialog::around(TextView::new(summary).scrollable())
+			.title(format!("{} backups will change", changes.len()))
+			.button("Back", |s| {
+				s.pop_layer();
+			})
+			.button("Apply", move |s| {
+				let mut undo = Vec::new();
+				let applied = apply(&backup_dir, &changes, &mut undo);
+				// the preview and the bulk edit dialog
+				s.pop_layer();
+				s.pop_layer();
+				let undone = undo.len();
+				on_applied(s, undo);
+				if let Err(e) = applied {
+					s.add_layer(Dialog::info(format!(
+						"Error occurred after changing {} backups: {}",
+						undone, e
+					)));
+				}
+			}),
+	);
+}
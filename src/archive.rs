@@ -0,0 +1,353 @@
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{self, Read, Write};
+use std::path::{Component, Path, PathBuf};
+
+use chrono::Local;
+
+use log::info;
+
+use zip::write::SimpleFileOptions;
+use zip::{CompressionMethod, ZipWriter};
+
+use crate::error;
+use crate::jobs;
+use crate::manifest::{Manifest, Origin, MANIFEST_FILE};
+use crate::store::{self, Backup, IdScheme, SaveLock};
+
+/// An exported archive of backups, laid out like the backup folder with one folder per save.
+pub enum Archive {
+	Zip(PathBuf),
+	TarZstd(PathBuf),
+}
+
+/// A backup stored inside an archive.
+pub struct ArchivedBackup {
+	/// Save the backup belongs to, taken from the folder it is in. Empty for backups at the root
+	/// of the archive.
+	pub save: String,
+	/// Path of the backup inside the archive.
+	pub entry: String,
+	pub backup: Backup,
+}
+
+impl Archive {
+	pub fn open(path: &Path) -> Result<Self, String> {
+		let name = path.to_string_lossy().to_lowercase();
+		if name.ends_with(".zip") {
+			Ok(Self::Zip(path.to_path_buf()))
+		} else if name.ends_with(".tar.zst") {
+			Ok(Self::TarZstd(path.to_path_buf()))
+		} else {
+			Err(format!(
+				"{} is not a .zip or .tar.zst archive.",
+				path.display()
+			))
+		}
+	}
+
+	/// Lists the backups in the archive, ordered by save and backup number. Other files, such as
+	/// manifests, are left out.
+	pub fn backups(&self) -> io::Result<Vec<ArchivedBackup>> {
+		let mut backups = Vec::new();
+
+		match self {
+			Self::Zip(path) => {
+				let zip = zip::ZipArchive::new(File::open(path)?)?;
+				for entry in zip.file_names() {
+					backups.extend(archived_backup(entry));
+				}
+			}
+			Self::TarZstd(path) => {
+				let mut tar = tar::Archive::new(zstd::Decoder::new(File::open(path)?)?);
+				for entry in tar.entries()? {
+					let entry = entry?;
+					if entry.header().entry_type().is_file() {
+						backups.extend(archived_backup(&entry.path()?.to_string_lossy()));
+					}
+				}
+			}
+		}
+
+		backups.sort_by(|a, b| (&a.save, a.backup.number).cmp(&(&b.save, b.backup.number)));
+		Ok(backups)
+	}
+
+	/// Writes the save held by one backup in the archive to `destination`, without unpacking
+	/// anything else. The live save is kept next to the backups in `backup_dir` first, and where it
+	/// was kept is returned so the restore can be undone.
+	pub fn restore(
+		&self,
+		archived: &ArchivedBackup,
+		destination: &Path,
+		backup_dir: &Path,
+	) -> io::Result<Option<PathBuf>> {
+		let _slot = jobs::acquire("Restore");
+		let mut save = SaveLock::acquire(destination)?;
+		let kept = store::keep_live_save(&mut save, backup_dir)?;
+		// written beside the save and moved over it once complete
+		let mut staged = save.stage()?;
+		match self {
+			Self::Zip(path) => {
+				let mut zip = zip::ZipArchive::new(File::open(path)?)?;
+				let entry = zip.by_name(&archived.entry)?;
+				write_save(entry, archived.backup.compressed, staged.file())?;
+			}
+			Self::TarZstd(path) => {
+				let mut tar = tar::Archive::new(zstd::Decoder::new(File::open(path)?)?);
+				let mut entries = tar.entries()?;
+				let entry = loop {
+					match entries.next() {
+						Some(entry) => {
+							let entry = entry?;
+							if entry.path()?.to_string_lossy() == archived.entry {
+								break entry;
+							}
+						}
+						None => {
+							return Err(io::Error::new(
+								io::ErrorKind::NotFound,
+								format!("{} is no longer in the archive", archived.entry),
+							))
+						}
+					}
+				};
+				write_save(entry, archived.backup.compressed, staged.file())?;
+			}
+		}
+		save.replace_with(staged)?;
+
+		info!(
+			"Backup number {} restored from the archive",
+			archived.backup.number
+		);
+
+		Ok(kept)
+	}
+
+	/// Bundles the backups of a save numbered `from` to `to`, with what its manifest records about
+	/// them, into a new archive at the archive's path. They are laid out as `<save>/<backup>`, so
+	/// the archive can be restored from or imported on another machine. Returns how many backups
+	/// were exported.
+	pub fn export(&self, backup_dir: &Path, from: usize, to: usize) -> error::Result<usize> {
+		let save = backup_dir
+			.file_name()
+			.ok_or("Backup directory has no name.")?
+			.to_string_lossy()
+			.into_owned();
+		let backups = store::list_backups(backup_dir)?
+			.into_iter()
+			.filter(|backup| (from..=to).contains(&backup.number))
+			.collect::<Vec<_>>();
+		if backups.is_empty() {
+			return Err(format!("There are no backups numbered {} to {}.", from, to).into());
+		}
+		let names = backups
+			.iter()
+			.map(|backup| backup.file_name.as_str())
+			.collect::<Vec<_>>();
+		let manifest = Manifest::load(backup_dir).subset(&names);
+		let manifest_entry = format!("{}/{}", save, MANIFEST_FILE);
+
+		let _slot = jobs::acquire("Export");
+		match self {
+			Self::Zip(path) => {
+				let mut zip = ZipWriter::new(File::create(path)?);
+				for backup in &backups {
+					// compressed backups would not get any smaller
+					let method = if backup.compressed {
+						CompressionMethod::Stored
+					} else {
+						CompressionMethod::Deflated
+					};
+					zip.start_file(
+						format!("{}/{}", save, backup.file_name),
+						SimpleFileOptions::default().compression_method(method),
+					)?;
+					io::copy(&mut File::open(&backup.path)?, &mut zip)?;
+				}
+				zip.start_file(manifest_entry, SimpleFileOptions::default())?;
+				zip.write_all(manifest.as_bytes())?;
+				zip.finish()?;
+			}
+			Self::TarZstd(path) => {
+				let mut tar = tar::Builder::new(zstd::Encoder::new(File::create(path)?, 0)?);
+				for backup in &backups {
+					tar.append_path_with_name(
+						&backup.path,
+						format!("{}/{}", save, backup.file_name),
+					)?;
+				}
+				let mut header = tar::Header::new_gnu();
+				header.set_size(manifest.len() as u64);
+				header.set_mode(0o644);
+				header.set_mtime(Local::now().timestamp().max(0) as u64);
+				header.set_cksum();
+				tar.append_data(&mut header, manifest_entry, manifest.as_bytes())?;
+				tar.into_inner()?.finish()?;
+			}
+		}
+
+		info!(
+			"Exported backups {} to {} of {} to the archive",
+			from, to, save
+		);
+
+		Ok(backups.len())
+	}
+
+	/// Copies every backup in the archive into the backup folder, along with what the archive's
+	/// manifests record about them. They are numbered after the backups already there, in their
+	/// order in the archive, so nothing is overwritten. Backups at the root of the archive go to
+	/// the working save. Backups whose ID is already in the backup folder are left out, and those
+	/// without one are given one by `ids`. Returns how many backups were imported.
+	pub fn import(
+		&self,
+		backup_path: &Path,
+		working_save: &str,
+		ids: IdScheme,
+	) -> error::Result<usize> {
+		let _slot = jobs::acquire("Import");
+		// the new name of each imported backup by save and archived name, and each save's manifest
+		let mut imported = Vec::new();
+		let mut manifests = HashMap::new();
+
+		let mut import = |name: &str, entry: &mut dyn Read| -> error::Result<()> {
+			let file_name = name.rsplit('/').next().unwrap_or(name);
+			// checked before anything is written for the entry
+			let save = match save_folder(name)? {
+				Some(save) => save,
+				None if !working_save.is_empty() => working_save.to_string(),
+				None => return Err("No save file has been set.".into()),
+			};
+
+			if file_name == MANIFEST_FILE {
+				let mut text = String::new();
+				entry.read_to_string(&mut text)?;
+				manifests.insert(save, Manifest::parse(&text));
+			} else if let Some(archived) = Backup::from_path(PathBuf::from(file_name)) {
+				let backup = store::import_backup(
+					entry,
+					&backup_path.join(&save),
+					&archived.note,
+					archived.compressed,
+				)?;
+				imported.push((save, archived.file_name, backup));
+			}
+
+			Ok(())
+		};
+
+		match self {
+			Self::Zip(path) => {
+				let mut zip = zip::ZipArchive::new(File::open(path)?)?;
+				for i in 0..zip.len() {
+					let mut entry = zip.by_index(i)?;
+					if entry.is_file() {
+						let name = entry.name().to_string();
+						import(&name, &mut entry)?;
+					}
+				}
+			}
+			Self::TarZstd(path) => {
+				let mut tar = tar::Archive::new(zstd::Decoder::new(File::open(path)?)?);
+				for entry in tar.entries()? {
+					let mut entry = entry?;
+					if entry.header().entry_type().is_file() {
+						let name = entry.path()?.to_string_lossy().into_owned();
+						import(&name, &mut entry)?;
+					}
+				}
+			}
+		}
+
+		let mut count = 0;
+		for (save, archived_name, backup) in &imported {
+			let archived = manifests.get(save);
+			let archived_id = archived.and_then(|archived| archived.id(archived_name));
+
+			let new = Manifest::update(&backup_path.join(save), |manifest| {
+				// a backup with an ID that is already here was imported before
+				if let Some(existing) = archived_id.as_ref().and_then(|id| manifest.find_id(id)) {
+					info!(
+						"{} #{} is already here as {}, skipped",
+						save, backup.number, existing
+					);
+					fs::remove_file(&backup.path)?;
+					return Ok(false);
+				}
+
+				if let Some(archived) = archived {
+					manifest.copy_from(&backup.file_name, archived, archived_name);
+				}
+				if archived_id.is_none() {
+					if let Some(id) = ids.generate() {
+						manifest.set_id(&backup.file_name, &id);
+					}
+				}
+				manifest.set_origin(&backup.file_name, Origin::Imported);
+				Ok(true)
+			})?;
+			if new {
+				count += 1;
+			}
+		}
+
+		info!("Imported {} backups from the archive", count);
+
+		Ok(count)
+	}
+}
+
+/// Takes the save a backup in an archive belongs to from the folder it is in, or `None` for
+/// backups at the root of the archive. Entries that would end up outside the backup folder once
+/// joined onto it, through `..`, an absolute path or a Windows separator, are refused, as the
+/// archive may come from anyone.
+fn save_folder(entry: &str) -> error::Result<Option<String>> {
+	let outside = || {
+		format!(
+			"The archive holds {}, which would be written outside the backup folder.",
+			entry
+		)
+	};
+	if entry.contains('\\') || entry.starts_with('/') || Path::new(entry).has_root() {
+		return Err(outside().into());
+	}
+
+	let folder = match entry.rsplit_once('/') {
+		Some((folder, _)) => folder,
+		None => return Ok(None),
+	};
+	if folder.split('/').any(|component| component == "..") {
+		return Err(outside().into());
+	}
+	// one plain name, which stays inside the backup folder
+	let save = folder.rsplit('/').next().unwrap_or_default();
+	let mut components = Path::new(save).components();
+	match (components.next(), components.next()) {
+		(Some(Component::Normal(_)), None) => Ok(Some(save.to_string())),
+		_ => Err(outside().into()),
+	}
+}
+
+/// A backup in an archive, unless the entry is something else or could not be restored safely.
+fn archived_backup(entry: &str) -> Option<ArchivedBackup> {
+	let name = entry.rsplit('/').next().unwrap_or(entry);
+	let backup = Backup::from_path(PathBuf::from(name))?;
+
+	Some(ArchivedBackup {
+		save: save_folder(entry).ok()?.unwrap_or_default(),
+		entry: entry.to_string(),
+		backup,
+	})
+}
+
+fn write_save(entry: impl Read, compressed: bool, file: &mut File) -> io::Result<()> {
+	if compressed {
+		io::copy(&mut zstd::Decoder::new(entry)?, file)?;
+	} else {
+		io::copy(&mut { entry }, file)?;
+	}
+
+	Ok(())
+}
@@ -0,0 +1,122 @@
+use std::rc::Rc;
+
+use cursive::traits::*;
+use cursive::views::{Dialog, LinearLayout, SelectView, TextView};
+use cursive::Cursive;
+
+use ini::Ini;
+
+use crate::archive::Archive;
+use crate::confirm::confirm;
+use crate::error;
+use crate::i18n;
+use crate::profiles::{Profile, IRONMAN_WARNING};
+use crate::rescue;
+use crate::settings::backup_options;
+
+/// Lists the backups in an archive and restores the chosen one over its save, leaving the archive
+/// untouched, or imports all of them. Backups at the root of the archive restore over the working
+/// save.
+pub fn browse(
+	s: &mut Cursive,
+	archive: Archive,
+	profile: &Profile,
+	working_save: &str,
+) -> error::Result<()> {
+	let backups = archive.backups()?;
+	if backups.is_empty() {
+		return Err("The archive holds no backups.".into());
+	}
+
+	let mut list = SelectView::<usize>::new();
+	for (i, archived) in backups.iter().enumerate() {
+		let mut label = format!("{} #{}", archived.save, archived.backup.number);
+		if !archived.backup.note.is_empty() {
+			label.push(' ');
+			label.push_str(&archived.backup.note);
+		}
+		list.add_item(label.trim().to_string(), i);
+	}
+
+	let archive = Rc::new(archive);
+	let backups = Rc::new(backups);
+	let profile = profile.clone();
+	let working_save = working_save.to_string();
+	let import = {
+		let archive = Rc::clone(&archive);
+		let profile = profile.clone();
+		let working_save = working_save.clone();
+		move |s: &mut Cursive| {
+			if profile.read_only {
+				s.add_layer(Dialog::info(crate::i18n::text("read_only")));
+				return;
+			}
+			let config: &mut Ini = s
+				.user_data()
+				.expect("User data not set up correctly on program start");
+			let ids = backup_options(config, &working_save).ids;
+			match archive.import(&profile.backup_path, &working_save, ids) {
+				Ok(count) => {
+					s.pop_layer();
+					s.add_layer(Dialog::info(format!(
+						"Imported {} backups. They were numbered after the backups already there, leaving out any imported before.",
+						count
+					)));
+				}
+				Err(e) => s.add_layer(Dialog::info(i18n::error(&e))),
+			}
+		}
+	};
+
+	s.add_layer(
+		Dialog::around(
+			LinearLayout::vertical()
+				.child(
+					list.on_submit(move |s, &i: &usize| {
+						let archived = &backups[i];
+						let save = if archived.save.is_empty() {
+							&working_save
+						} else {
+							&archived.save
+						};
+						if save.is_empty() {
+							s.add_layer(Dialog::info("No save file has been set."));
+							return;
+						}
+						let destination = profile.save_file(save);
+						let backup_dir = profile.backup_path.join(save);
+
+						let mut message = format!(
+							"Restore backup number {} over {}?",
+							archived.backup.number,
+							destination.display()
+						);
+						if profile.ironman {
+							message.push_str(&format!("\n\n{}", IRONMAN_WARNING));
+						}
+
+						let archive = Rc::clone(&archive);
+						let backups = Rc::clone(&backups);
+						confirm(s, &message, move |s| {
+							match archive.restore(&backups[i], &destination, &backup_dir) {
+								Ok(kept) => {
+									let number = backups[i].backup.number;
+									rescue::show_restored(s, number, kept, destination.clone());
+								}
+								Err(e) => s.add_layer(Dialog::info(i18n::error(&e))),
+							}
+						});
+					})
+					.scrollable(),
+				)
+				.child(TextView::new("[Enter] restore  (read-only archive)")),
+		)
+		.title("Archived backups")
+		.button("Import all", import)
+		.button("Close", |s| {
+			s.pop_layer();
+		}),
+	);
+
+	Ok(())
+}
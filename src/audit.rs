@@ -0,0 +1,276 @@
+//! Watching how the game writes a save without backing it up: when each write happens, how long it
+//! takes, how big the save is and whether it changed.
+//!
+//! Every write is recorded in the journal, and the report at the end suggests how long automatic
+//! backups should wait for a write to finish and how much space backing up every write would take.
+
+use std::collections::HashSet;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, Local};
+
+use log::{info, warn};
+
+use notify::{RawEvent, RecommendedWatcher, RecursiveMode, Watcher as _};
+
+use crate::clock;
+use crate::profiles::Profile;
+use crate::store;
+use crate::{format_duration, format_size, write_journal};
+
+/// First word of the journal entries recording a write of the save.
+const ENTRY_PREFIX: &str = "audit";
+
+/// Time without file system events on the save after which the game is taken to have finished
+/// writing it.
+const SETTLE: Duration = Duration::from_millis(500);
+
+/// How many of the latest writes the report lists.
+const LISTED_WRITES: usize = 20;
+
+/// One write of the save by the game, made of the file system events on it close together.
+#[derive(Clone)]
+pub struct Write {
+	/// When the first event of the write came in.
+	pub at: DateTime<Local>,
+	/// Time from the first event of the write to the last.
+	pub took: Duration,
+	pub events: usize,
+	/// Size of the save once written.
+	pub size: u64,
+	pub hash: String,
+}
+
+/// Watches a save, recording its writes, for as long as it is kept.
+pub struct Audit {
+	save: String,
+	writes: Arc<Mutex<Vec<Write>>>,
+	/// Dropping it ends the thread recording writes.
+	_watcher: RecommendedWatcher,
+}
+
+impl Audit {
+	/// Starts recording the writes of `save`. The save's directory is watched rather than the
+	/// save, as games may replace the file rather than write into it.
+	pub fn start(profile: &Profile, save: &str) -> crate::error::Result<Self> {
+		let file = profile.save_file(save);
+		let directory = file
+			.parent()
+			.map(PathBuf::from)
+			.ok_or("The save is not in a directory.")?;
+
+		let (tx, rx) = mpsc::channel();
+		let mut watcher = notify::raw_watcher(tx)?;
+		watcher.watch(&directory, RecursiveMode::NonRecursive)?;
+
+		let writes = Arc::new(Mutex::new(Vec::new()));
+		let recorded = Arc::clone(&writes);
+		let backup_path = profile.backup_path.clone();
+		let name = save.to_string();
+		thread::spawn(move || {
+			// when the write started, on both clocks, when its last event came in and how many
+			let mut burst: Option<(Instant, DateTime<Local>, Instant, usize)> = None;
+			loop {
+				match rx.recv_timeout(SETTLE) {
+					Ok(RawEvent {
+						path: Some(path), ..
+					}) if path.file_name() == file.file_name() => {
+						let now = Instant::now();
+						burst = Some(burst.map_or_else(
+							|| (now, clock::now(), now, 1),
+							|burst| (burst.0, burst.1, now, burst.3 + 1),
+						));
+					}
+					Ok(_) | Err(RecvTimeoutError::Timeout) => {}
+					// the audit was stopped
+					Err(RecvTimeoutError::Disconnected) => break,
+				}
+
+				if let Some((started, at, last, events)) =
+					burst.filter(|burst| burst.2.elapsed() >= SETTLE)
+				{
+					burst = None;
+					// a save deleted or moved away is no write to record
+					let (size, hash) = match fs::metadata(&file)
+						.and_then(|metadata| Ok((metadata.len(), store::hash_file(&file)?)))
+					{
+						Ok(written) => written,
+						Err(_) => continue,
+					};
+					let write = Write {
+						at,
+						took: last - started,
+						events,
+						size,
+						hash,
+					};
+					record(&backup_path, &name, &write);
+					recorded.lock().unwrap().push(write);
+				}
+			}
+		});
+
+		info!("{}: auditing writes without backing them up", save);
+		Ok(Self {
+			save: save.to_string(),
+			writes,
+			_watcher: watcher,
+		})
+	}
+
+	/// Stops recording writes, returning a report of those recorded.
+	pub fn stop(self) -> Report {
+		let writes = self.writes.lock().unwrap().clone();
+		info!("{}: audit stopped after {} writes", self.save, writes.len());
+
+		Report {
+			save: self.save,
+			writes,
+		}
+	}
+}
+
+/// Adds a write to the journal in the backup folder and the log, with the time since the write
+/// before. Failing to journal it is only worth a warning, as the audit goes on.
+fn record(backup_path: &std::path::Path, save: &str, write: &Write) {
+	let entry = format!(
+		"{} {} events {} ms {} bytes {} {}",
+		ENTRY_PREFIX,
+		write.events,
+		write.took.as_millis(),
+		write.size,
+		write.hash,
+		save
+	);
+	if let Err(e) = write_journal(backup_path, &entry) {
+		warn!("Could not write to journal: {}", e);
+	}
+	info!(
+		"{}: written, {} in {} events over {} ms",
+		save,
+		format_size(write.size),
+		write.events,
+		write.took.as_millis()
+	);
+}
+
+/// What an audit found out about how the game writes a save.
+pub struct Report {
+	pub save: String,
+	pub writes: Vec<Write>,
+}
+
+impl Report {
+	/// Time between each write and the one before.
+	fn intervals(&self) -> Vec<Duration> {
+		self.writes
+			.windows(2)
+			.map(|pair| (pair[1].at - pair[0].at).to_std().unwrap_or_default())
+			.collect()
+	}
+
+	/// How long automatic backups should wait after the save is written before copying it, so
+	/// that the longest write seen had finished: the longest write with time to settle, in whole
+	/// seconds.
+	pub fn suggested_debounce(&self) -> Option<Duration> {
+		let longest = self.writes.iter().map(|write| write.took).max()?;
+		Some(Duration::from_secs(
+			(longest + SETTLE).as_secs_f64().ceil() as u64
+		))
+	}
+
+	/// A summary of the writes followed by a timeline of the latest ones.
+	pub fn describe(&self) -> String {
+		let (first, last) = match (self.writes.first(), self.writes.last()) {
+			(Some(first), Some(last)) => (first, last),
+			_ => return format!("{} was not written during the audit.", self.save),
+		};
+
+		let mut report = format!(
+			"{} writes of {} from {} to {}\n",
+			self.writes.len(),
+			self.save,
+			first.at.format("%H:%M:%S"),
+			last.at.format("%H:%M:%S")
+		);
+
+		let mut intervals = self.intervals();
+		intervals.sort();
+		if let (Some(shortest), Some(longest)) = (intervals.first(), intervals.last()) {
+			report.push_str(&format!(
+				"Between writes: shortest {}, typical {}, longest {}\n",
+				format_duration(*shortest),
+				format_duration(intervals[intervals.len() / 2]),
+				format_duration(*longest)
+			));
+		}
+
+		if let Some(debounce) = self.suggested_debounce() {
+			let events = self
+				.writes
+				.iter()
+				.map(|write| write.events)
+				.max()
+				.unwrap_or_default();
+			report.push_str(&format!(
+				"Writes took up to {} ms and {} file events, so backups should wait at least {} s after a write\n",
+				self.writes.iter().map(|write| write.took).max().unwrap_or_default().as_millis(),
+				events,
+				debounce.as_secs()
+			));
+		}
+
+		let unchanged = self
+			.writes
+			.windows(2)
+			.filter(|pair| pair[0].hash == pair[1].hash)
+			.count();
+		if unchanged > 0 {
+			report.push_str(&format!("{} writes left the save as it was\n", unchanged));
+		}
+
+		let total = self.writes.iter().map(|write| write.size).sum::<u64>();
+		let mut seen = HashSet::new();
+		let distinct = self
+			.writes
+			.iter()
+			.filter(|write| seen.insert(&write.hash))
+			.map(|write| write.size)
+			.sum::<u64>();
+		report.push_str(&format!(
+			"The save went from {} to {}. Backing up every write would take {}, {} with identical saves stored once\n",
+			format_size(first.size),
+			format_size(last.size),
+			format_size(total),
+			format_size(distinct)
+		));
+
+		report.push_str("\nLatest writes\n");
+		let skipped = self.writes.len().saturating_sub(LISTED_WRITES);
+		for (index, write) in self.writes.iter().enumerate().skip(skipped) {
+			let since = index
+				.checked_sub(1)
+				.map(|previous| {
+					(write.at - self.writes[previous].at)
+						.to_std()
+						.unwrap_or_default()
+				})
+				.map_or_else(|| "-".to_string(), format_duration);
+			report.push_str(&format!(
+				"{}  {:>11} after  {:>9}  {:>5} ms  {:>3} events\n",
+				write.at.format("%H:%M:%S"),
+				since,
+				format_size(write.size),
+				write.took.as_millis(),
+				write.events
+			));
+		}
+
+		report
+	}
+}
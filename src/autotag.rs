@@ -0,0 +1,140 @@
+use std::path::Path;
+
+use ini::Ini;
+
+use log::warn;
+
+use crate::manifest::Manifest;
+use crate::parser::{self, Header};
+use crate::store::Backup;
+
+/// Prefix of the conf.ini sections describing a tagging rule, such as `[auto_tag.war]`, named
+/// after the tag they give.
+pub const SECTION_PREFIX: &str = "auto_tag.";
+
+/// What a new backup is checked for, set with the `when` key of a rule.
+#[derive(Clone)]
+pub enum Condition {
+	/// The save shows at least one war going on: `war`.
+	War,
+	/// The save grew by more than this many percent since the previous backup: `growth 10`.
+	Growth(f64),
+	/// The save shrank by more than this many percent since the previous backup: `shrink 10`.
+	Shrink(f64),
+	/// Another character is played than in the previous backup, such as after a succession:
+	/// `new_character`.
+	NewCharacter,
+	/// The played character's primary title changed since the previous backup: `new_realm`.
+	NewRealm,
+	/// Another version of the game wrote the save than the previous backup: `new_version`.
+	NewVersion,
+}
+
+impl Condition {
+	fn from_setting(setting: &str) -> Result<Self, String> {
+		let mut words = setting.split_whitespace();
+		let percent = |words: &mut dyn Iterator<Item = &str>| {
+			words
+				.next()
+				.and_then(|percent| percent.trim_end_matches('%').parse::<f64>().ok())
+				.filter(|percent| *percent >= 0.0)
+				.ok_or_else(|| format!("\"{}\" needs a percentage, such as 10", setting))
+		};
+
+		match words.next() {
+			Some("war") => Ok(Self::War),
+			Some("growth") => percent(&mut words).map(Self::Growth),
+			Some("shrink") => percent(&mut words).map(Self::Shrink),
+			Some("new_character") => Ok(Self::NewCharacter),
+			Some("new_realm") => Ok(Self::NewRealm),
+			Some("new_version") => Ok(Self::NewVersion),
+			_ => Err(format!(
+				"\"{}\" is not war, growth, shrink, new_character, new_realm or new_version",
+				setting
+			)),
+		}
+	}
+}
+
+/// Gives a backup a tag when its condition holds, so that backups are tagged without effort and
+/// tags can be filtered on or kept by retention.
+#[derive(Clone)]
+pub struct Rule {
+	pub tag: String,
+	pub condition: Condition,
+}
+
+/// Loads the tagging rules. Broken rules are logged and left out.
+pub fn rules(config: &Ini) -> Vec<Rule> {
+	config
+		.sections()
+		.flatten()
+		.filter_map(|section| {
+			let tag = section.strip_prefix(SECTION_PREFIX)?.trim();
+			let when = config.get_from(Some(section), "when").unwrap_or_default();
+			if tag.is_empty() || tag.contains(',') {
+				warn!(
+					"Tagging rule \"{}\" is ignored: tags cannot be empty or hold commas",
+					tag
+				);
+				return None;
+			}
+
+			match Condition::from_setting(when) {
+				Ok(condition) => Some(Rule {
+					tag: tag.to_string(),
+					condition,
+				}),
+				Err(e) => {
+					warn!("Tagging rule \"{}\" is ignored: {}", tag, e);
+					None
+				}
+			}
+		})
+		.collect()
+}
+
+/// Tags a save about to be backed up earns under the rules, compared with the previous backup of
+/// it where a rule needs one.
+pub fn apply(
+	rules: &[Rule],
+	save: &Path,
+	header: &Header,
+	size: u64,
+	previous: Option<(&Backup, &Manifest)>,
+) -> Vec<String> {
+	let previous_header = previous.map(|(backup, _)| backup.header());
+	let previous_size = previous.and_then(|(backup, manifest)| backup.size(manifest));
+	// the whole save is read for wars, so only when a rule asks for it
+	let at_war = rules
+		.iter()
+		.any(|rule| matches!(rule.condition, Condition::War))
+		.then(|| parser::count_wars(save).unwrap_or(0) > 0);
+	let changed = |field: fn(&Header) -> Option<String>| {
+		previous_header
+			.as_ref()
+			.and_then(field)
+			.zip(field(header))
+			.is_some_and(|(before, now)| before != now)
+	};
+	let change = previous_size
+		.filter(|before| *before > 0)
+		.map(|before| (size as f64 - before as f64) / before as f64 * 100.0);
+
+	let mut tags = Vec::new();
+	for rule in rules {
+		let holds = match rule.condition {
+			Condition::War => at_war == Some(true),
+			Condition::Growth(percent) => change.is_some_and(|change| change > percent),
+			Condition::Shrink(percent) => change.is_some_and(|change| -change > percent),
+			Condition::NewCharacter => changed(Header::player),
+			Condition::NewRealm => changed(|header| header.player_realm.clone()),
+			Condition::NewVersion => changed(|header| header.version.clone()),
+		};
+		if holds && !tags.contains(&rule.tag) {
+			tags.push(rule.tag.clone());
+		}
+	}
+
+	tags
+}
@@ -0,0 +1,92 @@
+use std::fs;
+
+use chrono::{DateTime, Local};
+
+use cursive::views::{Dialog, TextView};
+use cursive::Cursive;
+
+use ini::Ini;
+
+use crate::error;
+use crate::manifest::Manifest;
+use crate::profiles::Profile;
+use crate::settings::current_save;
+use crate::store;
+use crate::{format_duration, format_size, parser};
+
+/// Tells how far the live save has moved on since its newest backup, so that it is clear whether
+/// the save is protected before trying something risky.
+pub fn check(s: &mut Cursive, profile: &Profile) -> error::Result<()> {
+	let config: &mut Ini = s
+		.user_data()
+		.expect("User data not set up correctly on program start");
+	let save = current_save(config)
+		.ok_or("No save file has been set.")?
+		.to_string();
+
+	let message = report(profile, &save)?;
+	s.add_layer(
+		Dialog::around(TextView::new(message))
+			.title("How far behind is my last backup?")
+			.button("Ok", |s| {
+				s.pop_layer();
+			}),
+	);
+
+	Ok(())
+}
+
+fn report(profile: &Profile, save: &str) -> error::Result<String> {
+	let save_file = profile.save_file(save);
+	let metadata = fs::metadata(&save_file).map_err(|_| "Save file not found.")?;
+	let modified = DateTime::<Local>::from(metadata.modified()?);
+	let date = parser::read_header(&save_file).date;
+
+	let backup_dir = profile.backup_path.join(save);
+	let latest = match store::list_backups(&backup_dir)?.pop() {
+		Some(latest) => latest,
+		None => return Ok("There is no backup of this save yet, so it is not protected.".into()),
+	};
+	let manifest = Manifest::load(&backup_dir);
+	let created = latest.created(&manifest);
+	let size = latest.size(&manifest);
+	let backup_date = latest.game_date(&manifest);
+
+	let mut message = format!(
+		"Live save: written {}, {}, in-game {}\nBackup number {}: taken {}, {}, in-game {}\n\n",
+		modified.format("%Y-%m-%d %H:%M:%S"),
+		format_size(metadata.len()),
+		date.map_or_else(|| "unknown".to_string(), |date| date.to_string()),
+		latest.number,
+		created.map_or_else(
+			|| "unknown".to_string(),
+			|created| created.format("%Y-%m-%d %H:%M:%S").to_string()
+		),
+		size.map_or_else(|| "unknown size".to_string(), format_size),
+		backup_date.map_or_else(|| "unknown".to_string(), |date| date.to_string()),
+	);
+
+	match created {
+		Some(created) if modified <= created => {
+			message.push_str("The save has not been written since, so it is protected.");
+		}
+		Some(created) => {
+			message.push_str(&format!(
+				"The save was written {} after the backup was taken",
+				format_duration((modified - created).to_std().unwrap_or_default())
+			));
+			if let (Some(date), Some(backup_date)) = (date, backup_date) {
+				if date > backup_date {
+					message.push_str(&format!(
+						", and has moved on from {} to {} in-game",
+						backup_date, date
+					));
+				}
+			}
+			message.push_str(". Back up before trying anything risky.");
+		}
+		None => message.push_str("When the backup was taken is unknown."),
+	}
+
+	Ok(message)
+}
@@ -0,0 +1,1187 @@
+use std::cell::{Cell, RefCell};
+use std::error::Error;
+use std::fs;
+use std::io::{self, Read};
+use std::path::PathBuf;
+use std::rc::Rc;
+use std::slice;
+
+use cursive::traits::*;
+use cursive::views::{Dialog, EditView, LinearLayout, OnEventView, SelectView, TextView};
+use cursive::Cursive;
+
+use chrono::{DateTime, Local};
+
+use arboard::Clipboard;
+
+use log::info;
+
+use crate::annotate::{self, Change};
+use crate::confirm::confirm;
+use crate::error;
+use crate::format_size;
+use crate::i18n;
+use crate::manifest::{Manifest, Origin};
+use crate::parser::{self, GameDate, Summary};
+use crate::profiles::IRONMAN_WARNING;
+use crate::progress_dialog;
+use crate::rescue;
+use crate::store::{self, Backup};
+
+const LIST_NAME: &str = "backup_list";
+
+const TAG_EDIT_NAME: &str = "backup_tags";
+
+const NOTE_EDIT_NAME: &str = "backup_note";
+
+const FILTER_FROM_NAME: &str = "filter_from";
+
+const FILTER_TO_NAME: &str = "filter_to";
+
+const SEARCH_NAME: &str = "backup_search";
+
+/// Labels of the character attributes, in the order saves list them.
+pub const ATTRIBUTES: [&str; 5] = [
+	"Diplomacy",
+	"Martial",
+	"Stewardship",
+	"Intrigue",
+	"Learning",
+];
+
+const COPY_NAME_EDIT_NAME: &str = "copy_name";
+
+const HELP: &str =
+	"[Enter/r] restore  [a] restore as copy  [d] delete  [n] note  [t] tag  [p] pin  \
+	[i] inspect  [c] compare  [f] filter by date  [o] filter by origin  [y] copy path  \
+	[e] open folder  [/] search  [b] bulk edit  [u] undo bulk edit  [m] mark to stage  \
+	[s] stage in save folder  [x] remove staged";
+
+const READ_ONLY_HELP: &str = "[Enter/r] restore  [a] restore as copy  [i] inspect  [c] compare  \
+	[f] filter by date  [o] filter by origin  [y] copy path  [e] open folder  [/] search  \
+	[m] mark to stage  [s] stage in save folder  [x] remove staged  (read-only store)";
+
+thread_local! {
+	/// Kept for the life of the program, as on X11 copied text is only available while the
+	/// clipboard that set it still exists.
+	static CLIPBOARD: RefCell<Option<Clipboard>> = const { RefCell::new(None) };
+}
+
+/// Which backups are listed based on how they were created, cycled with a single key.
+#[derive(Clone, Copy)]
+enum OriginFilter {
+	All,
+	/// Only backups taken deliberately from the menu or command line.
+	ManualOnly,
+	/// Everything except backups from automatic sessions.
+	HideAuto,
+}
+
+impl OriginFilter {
+	const fn next(self) -> Self {
+		match self {
+			Self::All => Self::ManualOnly,
+			Self::ManualOnly => Self::HideAuto,
+			Self::HideAuto => Self::All,
+		}
+	}
+
+	fn allows(self, origin: Option<Origin>) -> bool {
+		match self {
+			Self::All => true,
+			Self::ManualOnly => matches!(
+				origin,
+				Some(Origin::Manual) | Some(Origin::Note) | Some(Origin::Cli)
+			),
+			Self::HideAuto => origin != Some(Origin::Auto),
+		}
+	}
+
+	const fn describe(self) -> &'static str {
+		match self {
+			Self::All => "Showing backups of every origin",
+			Self::ManualOnly => "Showing manual backups only",
+			Self::HideAuto => "Hiding automatic backups",
+		}
+	}
+}
+
+/// State shared between the key bindings of one open backup browser.
+struct Browser {
+	save_destination: PathBuf,
+	backup_dir: PathBuf,
+	/// Whether the backup folder is read-only, disabling actions that modify it.
+	read_only: bool,
+	/// Whether the save is an ironman save, for which restores are warned about.
+	ironman: bool,
+	/// Who backups taken here are recorded as taken by. Backups taken by anyone else sharing the
+	/// backup folder are listed with their author.
+	author: String,
+	/// Backup picked as the first half of a comparison, waiting for the second.
+	compare_mark: RefCell<Option<String>>,
+	/// Backups marked to be staged in the save folder together, by file name.
+	stage_marks: RefCell<Vec<String>>,
+	/// Only backups with an in-game date inside this range are listed, when set.
+	date_range: RefCell<Option<(GameDate, GameDate)>>,
+	origin_filter: Cell<OriginFilter>,
+	/// Words typed into the search field, which listed backups must all match.
+	search: RefCell<String>,
+	/// What puts back the last bulk edit of notes and tags, until it is undone.
+	bulk_undo: RefCell<Option<Vec<Change>>>,
+}
+
+/// Opens a single screen listing every backup of the working save, with single-key actions for
+/// managing them.
+pub fn browse(
+	s: &mut Cursive,
+	save_destination: PathBuf,
+	backup_dir: PathBuf,
+	read_only: bool,
+	ironman: bool,
+	author: String,
+) -> error::Result<()> {
+	let browser = Rc::new(Browser {
+		save_destination,
+		backup_dir,
+		read_only,
+		ironman,
+		author,
+		compare_mark: RefCell::new(None),
+		stage_marks: RefCell::new(Vec::new()),
+		date_range: RefCell::new(None),
+		origin_filter: Cell::new(OriginFilter::All),
+		search: RefCell::new(String::new()),
+		bulk_undo: RefCell::new(None),
+	});
+
+	let mut list = SelectView::<String>::new();
+	browser.populate(&mut list)?;
+
+	let list = list
+		.on_submit({
+			let browser = Rc::clone(&browser);
+			move |s, _: &String| browser.restore(s)
+		})
+		.with_name(LIST_NAME)
+		.scrollable();
+
+	let list = OnEventView::new(list)
+		.on_event('r', {
+			let browser = Rc::clone(&browser);
+			move |s| browser.restore(s)
+		})
+		.on_event('a', {
+			let browser = Rc::clone(&browser);
+			move |s| browser.restore_as_copy(s)
+		})
+		.on_event('d', {
+			let browser = Rc::clone(&browser);
+			move |s| browser.delete(s)
+		})
+		.on_event('n', {
+			let browser = Rc::clone(&browser);
+			move |s| browser.edit_note(s)
+		})
+		.on_event('t', {
+			let browser = Rc::clone(&browser);
+			move |s| browser.tag(s)
+		})
+		.on_event('p', {
+			let browser = Rc::clone(&browser);
+			move |s| browser.toggle_pin(s)
+		})
+		.on_event('i', {
+			let browser = Rc::clone(&browser);
+			move |s| browser.inspect(s)
+		})
+		.on_event('c', {
+			let browser = Rc::clone(&browser);
+			move |s| browser.compare(s)
+		})
+		.on_event('f', {
+			let browser = Rc::clone(&browser);
+			move |s| browser.filter_dates(s)
+		})
+		.on_event('o', {
+			let browser = Rc::clone(&browser);
+			move |s| browser.cycle_origin_filter(s)
+		})
+		.on_event('y', {
+			let browser = Rc::clone(&browser);
+			move |s| browser.copy_path(s)
+		})
+		.on_event('e', {
+			let browser = Rc::clone(&browser);
+			move |s| browser.open_folder(s)
+		})
+		.on_event('b', {
+			let browser = Rc::clone(&browser);
+			move |s| browser.bulk_edit(s)
+		})
+		.on_event('u', {
+			let browser = Rc::clone(&browser);
+			move |s| browser.undo_bulk_edit(s)
+		})
+		.on_event('m', {
+			let browser = Rc::clone(&browser);
+			move |s| browser.toggle_stage_mark(s)
+		})
+		.on_event('s', {
+			let browser = Rc::clone(&browser);
+			move |s| browser.stage(s)
+		})
+		.on_event('x', {
+			let browser = Rc::clone(&browser);
+			move |s| browser.remove_staged(s)
+		})
+		.on_event('/', |s| {
+			let _ = s.focus_name(SEARCH_NAME);
+		});
+
+	let search = EditView::new()
+		.on_edit(move |s, search, _| {
+			browser.search.replace(search.to_string());
+			browser.refresh(s);
+		})
+		.on_submit(|s, _| {
+			let _ = s.focus_name(LIST_NAME);
+		})
+		.with_name(SEARCH_NAME)
+		.full_width();
+
+	s.add_layer(
+		Dialog::around(
+			LinearLayout::vertical()
+				.child(
+					LinearLayout::horizontal()
+						.child(TextView::new("Search: "))
+						.child(search),
+				)
+				.child(TextView::new(columns(
+					"#",
+					"Created",
+					"Size",
+					"Date",
+					"Character",
+				)))
+				.child(list)
+				.child(TextView::new(if read_only { READ_ONLY_HELP } else { HELP })),
+		)
+		.title("Backups")
+		.button("Close", |s| {
+			s.pop_layer();
+		}),
+	);
+	// single-key actions stay at hand, the search is a key away
+	let _ = s.focus_name(LIST_NAME);
+
+	Ok(())
+}
+
+impl Browser {
+	fn populate(&self, list: &mut SelectView<String>) -> error::Result<()> {
+		let manifest = Manifest::load(&self.backup_dir);
+		let date_range = *self.date_range.borrow();
+		let origin_filter = self.origin_filter.get();
+		let search = self.search.borrow();
+		let selected = list.selected_id();
+
+		list.clear();
+		for backup in store::list_backups(&self.backup_dir)? {
+			let origin = manifest.origin(&backup.file_name);
+			if !origin_filter.allows(origin) {
+				continue;
+			}
+
+			let game_date = backup.game_date(&manifest);
+			if let Some((from, to)) = date_range {
+				if !game_date.is_some_and(|date| from <= date && date <= to) {
+					continue;
+				}
+			}
+			if !backup.matches(&manifest, &search) {
+				continue;
+			}
+
+			let mut label = columns(
+				&backup.number.to_string(),
+				&backup.created(&manifest).map_or_else(
+					|| "-".to_string(),
+					|created| created.format("%Y-%m-%d %H:%M").to_string(),
+				),
+				&backup
+					.size(&manifest)
+					.map_or_else(|| "-".to_string(), format_size),
+				&game_date.map_or_else(|| "-".to_string(), |date| date.to_string()),
+				&backup.player(&manifest).unwrap_or_else(|| "-".to_string()),
+			);
+			if let Some(origin) = origin {
+				label.push_str(&format!(" [{}]", origin));
+			}
+			if let Some(author) = backup
+				.author(&manifest)
+				.filter(|author| *author != self.author)
+			{
+				label.push_str(&format!(" by {}", author));
+			}
+			if manifest.is_pinned(&backup.file_name) {
+				label.push_str(" [pinned]");
+			}
+			if self.stage_marks.borrow().contains(&backup.file_name) {
+				label.push_str(" [to stage]");
+			}
+			if !backup.note.is_empty() {
+				label.push(' ');
+				label.push_str(&backup.note);
+			}
+			let tags = manifest.tags(&backup.file_name);
+			if !tags.is_empty() {
+				label.push_str(&format!(" ({})", tags.join(", ")));
+			}
+
+			list.add_item(label, backup.file_name);
+		}
+
+		if let Some(selected) = selected {
+			if !list.is_empty() {
+				list.set_selection(selected.min(list.len() - 1));
+			}
+		}
+
+		Ok(())
+	}
+
+	fn refresh(&self, s: &mut Cursive) {
+		if let Some(Err(e)) = s.call_on_name(LIST_NAME, |list: &mut SelectView<String>| {
+			self.populate(list)
+		}) {
+			show_error(s, &e);
+		}
+	}
+
+	fn selected(&self, s: &mut Cursive) -> Option<Backup> {
+		let file_name = s
+			.call_on_name(LIST_NAME, |list: &mut SelectView<String>| list.selection())
+			.flatten()?;
+
+		Backup::from_path(self.backup_dir.join(file_name.as_str()))
+			.map(|backup| backup.with_recorded_note(&Manifest::load(&self.backup_dir)))
+	}
+
+	/// Explains why an action is unavailable when the backup folder cannot be written to.
+	fn refuse_if_read_only(&self, s: &mut Cursive) -> bool {
+		if self.read_only {
+			show_message(s, i18n::text("read_only"));
+		}
+
+		self.read_only
+	}
+
+	fn restore(self: &Rc<Self>, s: &mut Cursive) {
+		if let Some(backup) = self.selected(s) {
+			let browser = Rc::clone(self);
+			let mut message = format!("Restore backup {}", backup.number);
+			if let Some(description) = backup.header().describe() {
+				message.push_str(&format!(" ({})", description));
+			}
+			message.push_str(" over the current save?");
+			if self.ironman {
+				message.push_str(&format!("\n\n{}", IRONMAN_WARNING));
+			}
+			let restore = {
+				let backup = backup.clone();
+				move |s: &mut Cursive| {
+					let number = backup.number;
+					let backup = backup.clone();
+					let destination = browser.save_destination.clone();
+					progress_dialog::run_in_background(
+						s,
+						"Restoring",
+						{
+							let destination = destination.clone();
+							move || {
+								store::restore_core(&backup, &destination)
+									.map_err(|e| e.to_string())
+							}
+						},
+						move |s, restored| match restored {
+							Ok(kept) => {
+								s.pop_layer();
+								rescue::show_restored(s, number, kept, destination);
+							}
+							Err(e) => show_message(s, &i18n::error(&e)),
+						},
+					);
+				}
+			};
+			rescue::confirm_rewind(s, &backup, &self.save_destination, move |s| {
+				confirm(s, &message, restore.clone())
+			});
+		}
+	}
+
+	/// Restores a backup under a new save name next to the live save, so that the game lists it
+	/// as a campaign of its own and the live save is left alone.
+	fn restore_as_copy(self: &Rc<Self>, s: &mut Cursive) {
+		let backup = match self.selected(s) {
+			Some(backup) => backup,
+			None => return,
+		};
+		let number = backup.number;
+		let stem = self
+			.save_destination
+			.file_stem()
+			.unwrap_or_default()
+			.to_string_lossy()
+			.into_owned();
+
+		let submit = {
+			let destination = self.save_destination.clone();
+			move |s: &mut Cursive, name: &str| {
+				let name = name.trim();
+				if name.is_empty() {
+					show_message(s, "Enter a name for the copy.");
+					return;
+				}
+				if let Err(e) = store::check_note(name) {
+					show_error(s, &e);
+					return;
+				}
+
+				let mut file_name = name.to_string();
+				if let Some(extension) = destination.extension() {
+					file_name.push('.');
+					file_name.push_str(&extension.to_string_lossy());
+				}
+				let copy = destination.with_file_name(file_name);
+				let backup = backup.clone();
+				s.pop_layer();
+				progress_dialog::run_in_background(
+					s,
+					"Restoring",
+					{
+						let copy = copy.clone();
+						move || store::restore_copy(&backup, &copy).map_err(|e| e.to_string())
+					},
+					move |s, restored| match restored {
+						Ok(()) => show_message(
+							s,
+							&format!(
+								"Backup {} restored as {}, load it in the game as a separate campaign.",
+								number,
+								copy.display()
+							),
+						),
+						Err(e) => show_message(s, &i18n::error(&e)),
+					},
+				);
+			}
+		};
+		let submit = Rc::new(submit);
+
+		s.add_layer(
+			Dialog::around(
+				LinearLayout::vertical()
+					.child(TextView::new(
+						"Name of the new save, which leaves the current save untouched:",
+					))
+					.child(
+						EditView::new()
+							.content(format!("{} backup {}", stem, number))
+							.on_submit({
+								let submit = Rc::clone(&submit);
+								move |s, name| submit(s, name)
+							})
+							.with_name(COPY_NAME_EDIT_NAME)
+							.min_width(40),
+					),
+			)
+			.title(format!("Restore backup {} as a copy", number))
+			.button("Cancel", |s| {
+				s.pop_layer();
+			})
+			.button("Restore", move |s| {
+				let name = s
+					.call_on_name(COPY_NAME_EDIT_NAME, |view: &mut EditView| {
+						view.get_content()
+					})
+					.expect("EditView not created for copy name entry");
+				submit(s, &name);
+			}),
+		);
+	}
+
+	fn toggle_stage_mark(&self, s: &mut Cursive) {
+		let backup = match self.selected(s) {
+			Some(backup) => backup,
+			None => return,
+		};
+
+		{
+			let mut marks = self.stage_marks.borrow_mut();
+			match marks.iter().position(|marked| *marked == backup.file_name) {
+				Some(index) => {
+					marks.remove(index);
+				}
+				None => marks.push(backup.file_name),
+			}
+		}
+		self.refresh(s);
+	}
+
+	/// Copies the backups marked to stage, or the selected one when none are, next to the live
+	/// save as `<save>_bk<number>`, so that the game's load menu lists them side by side.
+	fn stage(&self, s: &mut Cursive) {
+		let marked = self.stage_marks.borrow().clone();
+		let backups = if marked.is_empty() {
+			self.selected(s).into_iter().collect::<Vec<_>>()
+		} else {
+			marked
+				.iter()
+				.filter_map(|file_name| Backup::from_path(self.backup_dir.join(file_name)))
+				.collect()
+		};
+		if backups.is_empty() {
+			return;
+		}
+
+		// the marks are used up, whether staging succeeds or not
+		self.stage_marks.borrow_mut().clear();
+		self.refresh(s);
+
+		let save_file = self.save_destination.clone();
+		let stem = save_file
+			.file_stem()
+			.unwrap_or_default()
+			.to_string_lossy()
+			.into_owned();
+		let count = backups.len();
+		progress_dialog::run_in_background(
+			s,
+			"Staging backups",
+			move || store::stage_backups(&save_file, &backups).map_err(|e| e.to_string()),
+			move |s, staged| match staged {
+				Ok(staged) => {
+					let mut message = format!(
+						"Staged {} backups in the save folder, load them in the game as {}_bk<number>. \
+						 Press x here to remove them once compared.",
+						staged, stem
+					);
+					if staged < count {
+						message.push_str(&format!(" {} were already staged.", count - staged));
+					}
+					show_message(s, &message);
+				}
+				Err(e) => show_message(s, &i18n::error(&e)),
+			},
+		);
+	}
+
+	/// Removes the copies of this save's backups staged in the save folder, keeping any the game
+	/// has written since.
+	fn remove_staged(&self, s: &mut Cursive) {
+		let staged = match store::staged_copies(&self.save_destination, &self.backup_dir) {
+			Ok(staged) => staged,
+			Err(e) => return show_error(s, &e),
+		};
+		if staged.is_empty() {
+			show_message(s, "No backups of this save are staged in the save folder.");
+			return;
+		}
+
+		let save_file = self.save_destination.clone();
+		let backup_dir = self.backup_dir.clone();
+		confirm(
+			s,
+			&format!(
+				"Remove the {} staged copies of backups from the save folder? Copies the game has \
+				 saved over since are kept.",
+				staged.len()
+			),
+			move |s| match store::remove_staged(&save_file, &backup_dir) {
+				Ok((removed, kept)) if kept.is_empty() => {
+					show_message(s, &format!("Removed {} staged copies.", removed))
+				}
+				Ok((removed, kept)) => show_message(
+					s,
+					&format!(
+						"Removed {} staged copies. Kept {}, as the game saved over them.",
+						removed,
+						kept.join(", ")
+					),
+				),
+				Err(e) => show_error(s, &e),
+			},
+		);
+	}
+
+	fn delete(self: &Rc<Self>, s: &mut Cursive) {
+		if self.refuse_if_read_only(s) {
+			return;
+		}
+
+		let backup = match self.selected(s) {
+			Some(backup) => backup,
+			None => return,
+		};
+
+		if Manifest::load(&self.backup_dir).is_pinned(&backup.file_name) {
+			show_message(s, "This backup is pinned. Unpin it before deleting.");
+			return;
+		}
+
+		let browser = Rc::clone(self);
+		confirm(
+			s,
+			&format!(
+				"Delete backup {}? \"Undo last operation\" in the main menu brings it back until \
+				 you quit.",
+				backup.number
+			),
+			move |s| match store::trash_backups(&browser.backup_dir, slice::from_ref(&backup)) {
+				Ok(()) => browser.refresh(s),
+				Err(e) => show_error(s, &e),
+			},
+		);
+	}
+
+	/// Changes the notes or tags of the listed backups, or of the selected backup's session, at
+	/// once.
+	fn bulk_edit(self: &Rc<Self>, s: &mut Cursive) {
+		if self.refuse_if_read_only(s) {
+			return;
+		}
+
+		let selected = match self.selected(s) {
+			Some(backup) => backup,
+			None => return,
+		};
+		let listed = s
+			.call_on_name(LIST_NAME, |list: &mut SelectView<String>| {
+				list.iter()
+					.map(|(_, file_name)| file_name.clone())
+					.collect::<Vec<_>>()
+			})
+			.unwrap_or_default();
+		let backups = match store::list_backups(&self.backup_dir) {
+			Ok(backups) => backups,
+			Err(e) => return show_error(s, &e),
+		};
+		let session = annotate::session_of(
+			backups.clone(),
+			&Manifest::load(&self.backup_dir),
+			selected.number,
+		);
+		let listed = backups
+			.into_iter()
+			.filter(|backup| listed.contains(&backup.file_name))
+			.collect();
+
+		let browser = Rc::clone(self);
+		annotate::open(
+			s,
+			self.backup_dir.clone(),
+			listed,
+			session,
+			move |s, undo| {
+				browser.bulk_undo.replace(Some(undo));
+				browser.refresh(s);
+			},
+		);
+	}
+
+	fn undo_bulk_edit(self: &Rc<Self>, s: &mut Cursive) {
+		if self.refuse_if_read_only(s) {
+			return;
+		}
+
+		let changes = match self.bulk_undo.borrow().clone() {
+			Some(changes) => changes,
+			None => return show_message(s, "There is no bulk edit to undo."),
+		};
+
+		let browser = Rc::clone(self);
+		confirm(
+			s,
+			&format!(
+				"Put back the notes and tags of the {} backups changed by the last bulk edit?",
+				changes.len()
+			),
+			move |s| {
+				// changes undone so far are put back even when a later one fails
+				let mut redo = Vec::new();
+				let undone = annotate::apply(&browser.backup_dir, &changes, &mut redo);
+				browser
+					.bulk_undo
+					.replace(Some(changes[redo.len()..].to_vec()).filter(|left| !left.is_empty()));
+				browser.refresh(s);
+				if let Err(e) = undone {
+					show_error(s, &e);
+				}
+			},
+		);
+	}
+
+	fn edit_note(self: &Rc<Self>, s: &mut Cursive) {
+		if self.refuse_if_read_only(s) {
+			return;
+		}
+
+		let backup = match self.selected(s) {
+			Some(backup) => backup,
+			None => return,
+		};
+		let number = backup.number;
+		let current = backup.note.clone();
+
+		let submit = {
+			let browser = Rc::clone(self);
+			move |s: &mut Cursive, note: &str| match store::set_note(
+				&browser.backup_dir,
+				&backup,
+				note,
+			) {
+				Ok(_) => {
+					s.pop_layer();
+					browser.refresh(s);
+				}
+				Err(e) => show_error(s, &e),
+			}
+		};
+		let submit = Rc::new(submit);
+
+		s.add_layer(
+			Dialog::around(
+				EditView::new()
+					.content(current)
+					.on_submit({
+						let submit = Rc::clone(&submit);
+						move |s, note| submit(s, note)
+					})
+					.with_name(NOTE_EDIT_NAME)
+					.min_width(30),
+			)
+			.title(format!("Note for backup {}", number))
+			.button("Cancel", |s| {
+				s.pop_layer();
+			})
+			.button("Save", move |s| {
+				let note = s
+					.call_on_name(NOTE_EDIT_NAME, |view: &mut EditView| view.get_content())
+					.expect("EditView not created for note entry");
+				submit(s, &note);
+			}),
+		);
+	}
+
+	fn tag(self: &Rc<Self>, s: &mut Cursive) {
+		if self.refuse_if_read_only(s) {
+			return;
+		}
+
+		let backup = match self.selected(s) {
+			Some(backup) => backup,
+			None => return,
+		};
+
+		let current = Manifest::load(&self.backup_dir)
+			.tags(&backup.file_name)
+			.join(", ");
+
+		let submit = {
+			let browser = Rc::clone(self);
+			let file_name = backup.file_name.clone();
+			move |s: &mut Cursive, tags: &str| {
+				let tags = tags
+					.split(',')
+					.map(str::trim)
+					.filter(|tag| !tag.is_empty())
+					.map(ToString::to_string)
+					.collect::<Vec<_>>();
+
+				if let Err(e) = Manifest::update(&browser.backup_dir, |manifest| {
+					manifest.set_tags(&file_name, &tags);
+					Ok(())
+				}) {
+					show_error(s, &e);
+					return;
+				}
+
+				s.pop_layer();
+				browser.refresh(s);
+			}
+		};
+		let submit = Rc::new(submit);
+
+		s.add_layer(
+			Dialog::around(
+				EditView::new()
+					.content(current)
+					.on_submit({
+						let submit = Rc::clone(&submit);
+						move |s, tags| submit(s, tags)
+					})
+					.with_name(TAG_EDIT_NAME)
+					.min_width(30),
+			)
+			.title(format!(
+				"Tags for backup {} (comma separated)",
+				backup.number
+			))
+			.button("Cancel", |s| {
+				s.pop_layer();
+			})
+			.button("Save", move |s| {
+				let tags = s
+					.call_on_name(TAG_EDIT_NAME, |view: &mut EditView| view.get_content())
+					.expect("EditView not created for tag entry");
+				submit(s, &tags);
+			}),
+		);
+	}
+
+	fn filter_dates(self: &Rc<Self>, s: &mut Cursive) {
+		let (from, to) = self
+			.date_range
+			.borrow()
+			.map(|(from, to)| (from.to_string(), to.to_string()))
+			.unwrap_or_default();
+
+		let browser = Rc::clone(self);
+		let clear_browser = Rc::clone(self);
+		s.add_layer(
+			Dialog::around(
+				LinearLayout::vertical()
+					.child(TextView::new("From (e.g. 1066.1.1, blank for the start):"))
+					.child(EditView::new().content(from).with_name(FILTER_FROM_NAME))
+					.child(TextView::new("To (blank for the end):"))
+					.child(EditView::new().content(to).with_name(FILTER_TO_NAME))
+					.min_width(40),
+			)
+			.title("Filter by in-game date")
+			.button("Cancel", |s| {
+				s.pop_layer();
+			})
+			.button("Clear", move |s| {
+				s.pop_layer();
+				clear_browser.date_range.replace(None);
+				clear_browser.refresh(s);
+			})
+			.button("Apply", move |s| {
+				let parse = |s: &mut Cursive, name: &str, default: GameDate| {
+					let entry = s
+						.call_on_name(name, |view: &mut EditView| view.get_content())
+						.expect("EditView not created for date filter entry");
+					if entry.trim().is_empty() {
+						Ok(default)
+					} else {
+						entry.parse::<GameDate>()
+					}
+				};
+
+				let from = parse(s, FILTER_FROM_NAME, GameDate::MIN);
+				let to = parse(s, FILTER_TO_NAME, GameDate::MAX);
+				match (from, to) {
+					(Ok(from), Ok(to)) => {
+						s.pop_layer();
+						info!("Showing backups dated between {} and {}", from, to);
+						browser.date_range.replace(Some((from, to)));
+						browser.refresh(s);
+					}
+					(Err(e), _) | (_, Err(e)) => show_message(s, &e),
+				}
+			}),
+		);
+	}
+
+	fn cycle_origin_filter(&self, s: &mut Cursive) {
+		let filter = self.origin_filter.get().next();
+		self.origin_filter.set(filter);
+		info!("{}", filter.describe());
+		self.refresh(s);
+	}
+
+	fn copy_path(&self, s: &mut Cursive) {
+		if let Some(backup) = self.selected(s) {
+			let path = backup.path.display().to_string();
+			let copied = CLIPBOARD.with_borrow_mut(|clipboard| {
+				if clipboard.is_none() {
+					*clipboard = Some(Clipboard::new()?);
+				}
+				clipboard
+					.as_mut()
+					.expect("clipboard created above")
+					.set_text(path.as_str())
+			});
+
+			match copied {
+				Ok(()) => info!("Copied {} to the clipboard", path),
+				Err(e) => show_error(s, &e),
+			}
+		}
+	}
+
+	fn open_folder(&self, s: &mut Cursive) {
+		if let Some(backup) = self.selected(s) {
+			let folder = backup.path.parent().unwrap_or(&self.backup_dir);
+			if let Err(e) = open::that_detached(folder) {
+				show_error(s, &e);
+			}
+		}
+	}
+
+	fn toggle_pin(&self, s: &mut Cursive) {
+		if self.refuse_if_read_only(s) {
+			return;
+		}
+
+		if let Some(backup) = self.selected(s) {
+			let pinned = Manifest::update(&self.backup_dir, |manifest| {
+				let pinned = !manifest.is_pinned(&backup.file_name);
+				manifest.set_pinned(&backup.file_name, pinned);
+				Ok(pinned)
+			});
+
+			match pinned {
+				Ok(pinned) => {
+					info!(
+						"Backup number {} {}",
+						backup.number,
+						if pinned { "pinned" } else { "unpinned" }
+					);
+					self.refresh(s);
+				}
+				Err(e) => show_error(s, &e),
+			}
+		}
+	}
+
+	fn inspect(&self, s: &mut Cursive) {
+		if let Some(backup) = self.selected(s) {
+			match describe(&backup, &Manifest::load(&self.backup_dir)) {
+				Ok(details) => s.add_layer(
+					Dialog::around(TextView::new(details))
+						.title(format!("Backup {}", backup.number))
+						.button("Ok", |s| {
+							s.pop_layer();
+						}),
+				),
+				Err(e) => show_error(s, &e),
+			}
+		}
+	}
+
+	fn compare(&self, s: &mut Cursive) {
+		let backup = match self.selected(s) {
+			Some(backup) => backup,
+			None => return,
+		};
+
+		let marked = self.compare_mark.borrow_mut().take();
+		match marked.and_then(|file_name| Backup::from_path(self.backup_dir.join(file_name))) {
+			Some(first) if first.file_name != backup.file_name => match compare(&first, &backup) {
+				Ok(comparison) => s.add_layer(
+					Dialog::around(TextView::new(comparison))
+						.title(format!("Backup {} vs {}", first.number, backup.number))
+						.button("Ok", |s| {
+							s.pop_layer();
+						}),
+				),
+				Err(e) => show_error(s, &e),
+			},
+			_ => {
+				info!(
+					"Backup number {} marked, select another backup and press c to compare",
+					backup.number
+				);
+				*self.compare_mark.borrow_mut() = Some(backup.file_name);
+			}
+		}
+	}
+}
+
+/// Lays out the fixed-width columns at the start of each line of the list, followed by origin,
+/// author, note and tags.
+fn columns(number: &str, created: &str, size: &str, date: &str, player: &str) -> String {
+	format!(
+		"{:>5}  {:<16}  {:>9}  {:<10}  {:<20}",
+		number, created, size, date, player
+	)
+}
+
+fn describe(backup: &Backup, manifest: &Manifest) -> error::Result<String> {
+	let metadata = fs::metadata(&backup.path)?;
+	let tags = manifest.tags(&backup.file_name);
+	let header = backup.header();
+
+	Ok(format!(
+		"File: {}\nID: {}\nNote: {}\nOrigin: {}\nTaken by: {}\nBranch: {}\nSize: {}\nCreated: {}\nModified: {}\nIn-game date: {}\nCharacter: {}\nGame version: {}\nTags: {}\nPinned: {}",
+		backup.path.display(),
+		backup.id(manifest).unwrap_or_else(|| "-".to_string()),
+		if backup.note.is_empty() {
+			"-"
+		} else {
+			&backup.note
+		},
+		manifest
+			.origin(&backup.file_name)
+			.map_or_else(|| "unknown".to_string(), |origin| origin.to_string()),
+		backup
+			.author(manifest)
+			.unwrap_or_else(|| "unknown".to_string()),
+		manifest.branch(&backup.file_name).map_or_else(
+			|| "-".to_string(),
+			|(name, parent)| format!("{} (from backup {})", name, parent)
+		),
+		if backup.compressed {
+			format!("{} (compressed)", format_size(metadata.len()))
+		} else {
+			format_size(metadata.len())
+		},
+		backup.created(manifest).map_or_else(
+			|| "unknown".to_string(),
+			|created| created.format("%Y-%m-%d %H:%M:%S").to_string()
+		),
+		DateTime::<Local>::from(metadata.modified()?).format("%Y-%m-%d %H:%M:%S"),
+		backup
+			.game_date(manifest)
+			.map_or_else(|| "unknown".to_string(), |date| date.to_string()),
+		header.player().unwrap_or_else(|| "unknown".to_string()),
+		header.version.as_deref().unwrap_or("unknown"),
+		if tags.is_empty() {
+			"-".to_string()
+		} else {
+			tags.join(", ")
+		},
+		if manifest.is_pinned(&backup.file_name) {
+			"yes"
+		} else {
+			"no"
+		}
+	))
+}
+
+/// Lays out what two backups show side by side, so that the one from before a disaster can be
+/// told apart without loading the game.
+fn compare(first: &Backup, second: &Backup) -> error::Result<String> {
+	let first_metadata = fs::metadata(&first.path)?;
+	let second_metadata = fs::metadata(&second.path)?;
+
+	// compare the saves themselves, as compressed backups of the same save differ on disk
+	let first_contents = read_contents(first)?;
+	let second_contents = read_contents(second)?;
+	let identical = first_contents == second_contents;
+
+	let first_modified = DateTime::<Local>::from(first_metadata.modified()?);
+	let second_modified = DateTime::<Local>::from(second_metadata.modified()?);
+
+	let mut rows = vec![
+		(
+			"Size",
+			format_size(first_contents.len() as u64),
+			format_size(second_contents.len() as u64),
+		),
+		(
+			"Modified",
+			first_modified.format("%Y-%m-%d %H:%M").to_string(),
+			second_modified.format("%Y-%m-%d %H:%M").to_string(),
+		),
+	];
+
+	let first_header = parser::read_header_from(&first_contents[..]);
+	let second_header = parser::read_header_from(&second_contents[..]);
+	let text = |value: Option<String>| value.unwrap_or_else(|| "-".to_string());
+	rows.push((
+		"In-game date",
+		text(first_header.date.map(|date| date.to_string())),
+		text(second_header.date.map(|date| date.to_string())),
+	));
+	rows.push((
+		"Character",
+		text(first_header.player()),
+		text(second_header.player()),
+	));
+	rows.push((
+		"Version",
+		text(first_header.version),
+		text(second_header.version),
+	));
+
+	let first_summary = parser::summarize_from(&first_contents[..])?;
+	let second_summary = parser::summarize_from(&second_contents[..])?;
+	let amount = |value: Option<f64>| text(value.map(|value| format!("{:.0}", value)));
+	rows.push((
+		"Wealth",
+		amount(first_summary.wealth),
+		amount(second_summary.wealth),
+	));
+	rows.push((
+		"Prestige",
+		amount(first_summary.prestige),
+		amount(second_summary.prestige),
+	));
+	rows.push((
+		"Piety",
+		amount(first_summary.piety),
+		amount(second_summary.piety),
+	));
+	for (index, attribute) in ATTRIBUTES.iter().enumerate() {
+		let value = |summary: &Summary| {
+			text(
+				summary
+					.attributes
+					.map(|attributes| attributes[index].to_string()),
+			)
+		};
+		rows.push((attribute, value(&first_summary), value(&second_summary)));
+	}
+	rows.push((
+		"Titles held",
+		first_summary.titles.to_string(),
+		second_summary.titles.to_string(),
+	));
+	rows.push((
+		"Wars",
+		first_summary.wars.to_string(),
+		second_summary.wars.to_string(),
+	));
+
+	let mut comparison = format!(
+		"{:<14}{:>22}{:>22}\n",
+		"",
+		format!("#{}", first.number),
+		format!("#{}", second.number)
+	);
+	for (label, first, second) in rows {
+		let marker = if first == second { "" } else { "  *" };
+		comparison.push_str(&format!(
+			"{:<14}{:>22}{:>22}{}\n",
+			label, first, second, marker
+		));
+	}
+	comparison.push_str(&format!(
+		"\nSize difference: {:+} bytes\nTime apart: {} minutes\nContents: {}\n* differs",
+		second_contents.len() as i64 - first_contents.len() as i64,
+		(second_modified - first_modified).num_minutes().abs(),
+		if identical { "identical" } else { "different" }
+	));
+
+	Ok(comparison)
+}
+
+fn read_contents(backup: &Backup) -> io::Result<Vec<u8>> {
+	let mut contents = Vec::new();
+	backup.open()?.read_to_end(&mut contents)?;
+
+	Ok(contents)
+}
+
+fn show_message(s: &mut Cursive, message: &str) {
+	s.add_layer(Dialog::around(TextView::new(message)).button("Ok", |s| {
+		s.pop_layer();
+	}));
+}
+
+fn show_error(s: &mut Cursive, e: &dyn Error) {
+	show_message(s, &i18n::error(&e));
+}
@@ -0,0 +1,232 @@
+use std::cell::RefCell;
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+use cursive::traits::*;
+use cursive::views::{Dialog, EditView, LinearLayout, SelectView, TextView};
+use cursive::Cursive;
+
+use log::{error, info};
+
+use crate::confirm::confirm;
+use crate::i18n;
+use crate::manifest::Manifest;
+use crate::store::{self, Backup};
+
+const LIST_NAME: &str = "cleanup_list";
+
+const OLDER_THAN_NAME: &str = "cleanup_older_than";
+
+const SEARCH_NAME: &str = "cleanup_search";
+
+/// Backups marked for deletion, by file name.
+type Marked = Rc<RefCell<BTreeSet<String>>>;
+
+/// Opens a list of every backup of the working save, where backups are marked with Enter and
+/// deleted together. Pinned backups are listed but never deleted.
+pub fn delete_old(s: &mut Cursive, backup_dir: PathBuf) -> crate::error::Result<()> {
+	let backup_dir = Rc::new(backup_dir);
+	let marked: Marked = Rc::new(RefCell::new(BTreeSet::new()));
+
+	let mut list = SelectView::<String>::new();
+	populate(&mut list, &backup_dir, &marked.borrow(), "")?;
+
+	let list = list
+		.on_submit({
+			let backup_dir = Rc::clone(&backup_dir);
+			let marked = Rc::clone(&marked);
+			move |s, file_name: &String| {
+				{
+					let mut marked = marked.borrow_mut();
+					if !marked.remove(file_name) {
+						marked.insert(file_name.clone());
+					}
+				}
+				refresh(s, &backup_dir, &marked);
+			}
+		})
+		.with_name(LIST_NAME)
+		.scrollable();
+
+	let search = EditView::new()
+		.on_edit({
+			let backup_dir = Rc::clone(&backup_dir);
+			let marked = Rc::clone(&marked);
+			move |s, _, _| refresh(s, &backup_dir, &marked)
+		})
+		.on_submit(|s, _| {
+			let _ = s.focus_name(LIST_NAME);
+		})
+		.with_name(SEARCH_NAME)
+		.full_width();
+
+	s.add_layer(
+		Dialog::around(
+			LinearLayout::vertical()
+				.child(
+					LinearLayout::horizontal()
+						.child(TextView::new("Search: "))
+						.child(search),
+				)
+				.child(list)
+				.child(TextView::new("[Enter] mark or unmark a backup")),
+		)
+		.title("Delete old backups")
+		.button("Close", |s| {
+			s.pop_layer();
+		})
+		.button("Delete older than...", {
+			let backup_dir = Rc::clone(&backup_dir);
+			let marked = Rc::clone(&marked);
+			move |s| older_than(s, &backup_dir, &marked)
+		})
+		.button("Delete marked", move |s| {
+			let file_names = marked.borrow().iter().cloned().collect::<Vec<_>>();
+			delete_marked(s, &backup_dir, &marked, file_names);
+		}),
+	);
+
+	Ok(())
+}
+
+fn populate(
+	list: &mut SelectView<String>,
+	backup_dir: &Path,
+	marked: &BTreeSet<String>,
+	search: &str,
+) -> crate::error::Result<()> {
+	let manifest = Manifest::load(backup_dir);
+	let selected = list.selected_id();
+
+	list.clear();
+	for backup in store::list_backups(backup_dir)? {
+		if !backup.matches(&manifest, search) {
+			continue;
+		}
+
+		let mut label = if marked.contains(&backup.file_name) {
+			"[x] ".to_string()
+		} else {
+			"[ ] ".to_string()
+		};
+		label.push_str(&backup.number.to_string());
+		if manifest.is_pinned(&backup.file_name) {
+			label.push_str(" [pinned]");
+		}
+		if !backup.note.is_empty() {
+			label.push(' ');
+			label.push_str(&backup.note);
+		}
+
+		list.add_item(label, backup.file_name);
+	}
+
+	if let Some(selected) = selected {
+		if !list.is_empty() {
+			list.set_selection(selected.min(list.len() - 1));
+		}
+	}
+
+	Ok(())
+}
+
+fn refresh(s: &mut Cursive, backup_dir: &Path, marked: &Marked) {
+	let search = s
+		.call_on_name(SEARCH_NAME, |view: &mut EditView| view.get_content())
+		.unwrap_or_default();
+	if let Some(Err(e)) = s.call_on_name(LIST_NAME, |list: &mut SelectView<String>| {
+		populate(list, backup_dir, &marked.borrow(), &search)
+	}) {
+		s.add_layer(Dialog::info(i18n::error(&e)));
+	}
+}
+
+/// Asks for a backup number and offers to delete every backup numbered below it.
+fn older_than(s: &mut Cursive, backup_dir: &Rc<PathBuf>, marked: &Marked) {
+	let backup_dir = Rc::clone(backup_dir);
+	let marked = Rc::clone(marked);
+
+	s.add_layer(
+		Dialog::around(
+			LinearLayout::vertical()
+				.child(TextView::new("Delete every backup numbered below:"))
+				.child(EditView::new().with_name(OLDER_THAN_NAME))
+				.min_width(40),
+		)
+		.button("Cancel", |s| {
+			s.pop_layer();
+		})
+		.button("Delete", move |s| {
+			let entry = s
+				.call_on_name(OLDER_THAN_NAME, |view: &mut EditView| view.get_content())
+				.expect("EditView not created for backup number entry");
+			let number = match entry.trim().parse::<usize>() {
+				Ok(number) => number,
+				Err(_) => {
+					s.add_layer(Dialog::info("Enter a backup number."));
+					return;
+				}
+			};
+			s.pop_layer();
+
+			let file_names = match store::list_backups(&backup_dir) {
+				Ok(backups) => backups
+					.into_iter()
+					.filter(|backup| backup.number < number)
+					.map(|backup| backup.file_name)
+					.collect(),
+				Err(e) => {
+					s.add_layer(Dialog::info(i18n::error(&e)));
+					return;
+				}
+			};
+			delete_marked(s, &backup_dir, &marked, file_names);
+		}),
+	);
+}
+
+/// Confirms and deletes the given backups, skipping pinned ones.
+fn delete_marked(
+	s: &mut Cursive,
+	backup_dir: &Rc<PathBuf>,
+	marked: &Marked,
+	file_names: Vec<String>,
+) {
+	let manifest = Manifest::load(backup_dir);
+	let (pinned, to_delete): (Vec<_>, Vec<_>) = file_names
+		.into_iter()
+		.partition(|file_name| manifest.is_pinned(file_name));
+
+	if to_delete.is_empty() {
+		s.add_layer(Dialog::info(if pinned.is_empty() {
+			"No backups to delete."
+		} else {
+			"Only pinned backups were chosen, unpin them in the backup browser first."
+		}));
+		return;
+	}
+
+	let mut message = format!("Delete {} backups?", to_delete.len());
+	if !pinned.is_empty() {
+		message.push_str(&format!(" {} pinned backups will be kept.", pinned.len()));
+	}
+
+	let backup_dir = Rc::clone(backup_dir);
+	let marked = Rc::clone(marked);
+	confirm(s, &message, move |s| {
+		let backups = to_delete
+			.iter()
+			.filter_map(|file_name| Backup::from_path(backup_dir.join(file_name)))
+			.collect::<Vec<_>>();
+		match store::trash_backups(&backup_dir, &backups) {
+			Ok(()) => info!("Deleted {} old backups", backups.len()),
+			Err(e) => error!("Could not delete every old backup: {}", e),
+		}
+		for file_name in &to_delete {
+			marked.borrow_mut().remove(file_name);
+		}
+
+		refresh(s, &backup_dir, &marked);
+	});
+}
@@ -0,0 +1,387 @@
+use std::ffi::OsString;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::process;
+use std::thread;
+
+use clap::{Parser, Subcommand};
+
+use ini::Ini;
+
+use log::{error, info};
+
+use crate::audit::Audit;
+use crate::discord::Bot;
+use crate::error::Context;
+use crate::index;
+use crate::jobs::{self, Moment};
+use crate::manifest::{Manifest, Origin};
+use crate::profiles::{Profile, IRONMAN_WARNING};
+use crate::retention;
+use crate::settings::{backup_options, current_save, retention_policy, retention_trigger};
+use crate::store::{self, backup_core};
+use crate::watch::Session;
+use crate::{CANCEL_GRACE, SHUTDOWN_TIMEOUT};
+
+/// Backs up and restores game saves. Opens the interactive menu when no command is given.
+#[derive(Parser)]
+#[command(version)]
+pub struct Args {
+	/// Directory holding the game's saves, if the executable is not installed in the game's mod
+	/// folder and the directory is not in one of the usual places.
+	pub save_dir: Option<PathBuf>,
+
+	/// Config file to use instead of conf.ini in the platform's config folder.
+	#[arg(long, global = true)]
+	pub config: Option<PathBuf>,
+
+	/// Open the interactive menu even when conf.ini sets `launch=background`.
+	#[arg(long)]
+	pub menu: bool,
+
+	#[command(subcommand)]
+	pub command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+	/// Back up the working save.
+	Backup {
+		/// Note to store with the backup.
+		#[arg(short, long, default_value = "")]
+		note: String,
+	},
+	/// Restore a backup of the working save over the live save.
+	Restore {
+		/// Number or ID of the backup to restore. The start of an ID is enough when no other
+		/// backup's ID starts the same way.
+		backup: String,
+	},
+	/// Put back the save that the last restore replaced.
+	UndoRestore,
+	/// Restore a backup, run the game until it exits and back up the save it left behind on a
+	/// branch started from that backup, such as for testing a mod update against old saves from a
+	/// script. Prints the number of the new backup.
+	TestRun {
+		/// Number or ID of the backup to start from.
+		backup: String,
+		/// Name of the branch, which also becomes the note of the new backup. Defaults to
+		/// `test-N` for backup number N.
+		#[arg(short, long)]
+		branch: Option<String>,
+		/// Game executable to run followed by its arguments, after `--`.
+		#[arg(last = true, required = true)]
+		game: Vec<OsString>,
+	},
+	/// List the backups of the working save.
+	List,
+	/// Delete the automatic backups of the working save that its retention policy no longer
+	/// keeps, whenever the policy is otherwise applied. Prints how many were deleted.
+	Prune,
+	/// Back up the working save every time the game writes it, until Enter is pressed.
+	Auto {
+		/// Back up the save once before waiting for the game to write it.
+		#[arg(long)]
+		baseline: bool,
+	},
+	/// Record when and how the game writes the working save, without backing it up, until Enter
+	/// is pressed, then print what was seen and a suggested wait before automatic backups.
+	Audit,
+	/// Play saves taken every few minutes for some days through automatic backups on a simulated
+	/// clock, in a separate folder, and print the backups the retention policy keeps.
+	#[cfg(feature = "simulation")]
+	Simulate {
+		/// Minutes between two saves.
+		#[arg(long, default_value_t = 30)]
+		every: u64,
+		/// Days the game is played for.
+		#[arg(long, default_value_t = 7)]
+		days: u64,
+		/// Local time the simulated clock starts at.
+		#[arg(long, default_value = crate::simulate::DEFAULT_START)]
+		start: String,
+		/// Folder to keep the saves and backups in, instead of a temporary folder removed
+		/// afterwards.
+		#[arg(long)]
+		root: Option<PathBuf>,
+	},
+	/// Print every text of the interface in English as a translation file, to start a
+	/// translation from.
+	Translation,
+	/// Back up the working save every time the game writes it until the process is stopped,
+	/// without a console window on Windows, where messages only go to the log file. When a
+	/// `[discord]` section is set up, a bot also shares the save with a multiplayer group.
+	Background,
+}
+
+/// Runs a command without the interface, returning the exit code.
+pub fn run(command: Command, config: &Ini, profile: Profile) -> i32 {
+	let result = execute(command, config, profile);
+	// pushes to the remote started by a backup finish before exiting
+	if !jobs::wait_idle(SHUTDOWN_TIMEOUT) {
+		jobs::cancel();
+		jobs::wait_idle(CANCEL_GRACE);
+	}
+	if let Err(e) = index::flush() {
+		eprintln!("Could not save the backup index: {}", e);
+	}
+
+	match result {
+		Ok(()) => 0,
+		Err(e) => {
+			error!(event = "command_failed", error_kind = e.kind().as_str(); "Error: {}", e);
+			1
+		}
+	}
+}
+
+fn execute(command: Command, config: &Ini, mut profile: Profile) -> crate::error::Result<()> {
+	// simulations never touch the saves of the game
+	#[cfg(feature = "simulation")]
+	if let Command::Simulate {
+		every,
+		days,
+		start,
+		root,
+	} = command
+	{
+		return crate::simulate::run(config, every, days, &start, root);
+	}
+
+	// the texts are the same whatever the save
+	if matches!(command, Command::Translation) {
+		print!("{}", crate::i18n::template());
+		return Ok(());
+	}
+
+	profile.prepare()?;
+	let save =
+		current_save(config).ok_or("No save file has been set, choose one from the menu first.")?;
+	let backup_dir = profile.backup_path.join(save);
+
+	if profile.read_only
+		&& !matches!(
+			command,
+			Command::List | Command::Restore { .. } | Command::UndoRestore
+		) {
+		return Err(crate::i18n::text("read_only").into());
+	}
+
+	match command {
+		Command::Background => {
+			let session = Session::start_working(config, &profile, false)?;
+			info!(
+				event = "session_started", save = session.saves().join(", ").as_str();
+				"Backing up {} in the background",
+				session.saves().join(", ")
+			);
+			match Bot::load(config) {
+				Ok(Some(bot)) => {
+					bot.spawn(
+						profile.clone(),
+						save.to_string(),
+						backup_options(config, save),
+					);
+				}
+				Ok(None) => {}
+				Err(e) => error!("Discord bot not started: {}", e),
+			}
+			loop {
+				thread::park();
+			}
+		}
+		Command::Backup { note } => {
+			let file_path = profile.save_file(save);
+			if !file_path.is_file() {
+				return Err("Save file not found.".into());
+			}
+			fs::create_dir_all(&backup_dir).context("create", &backup_dir)?;
+
+			let options = backup_options(config, save);
+			let created = backup_core(&file_path, &backup_dir, &note, Origin::Cli, &options)?;
+			println!("{}", created.number);
+		}
+		Command::Restore { backup } => {
+			let backup = store::find_backup(&backup_dir, &backup)?;
+			if profile.ironman {
+				eprintln!("{}", IRONMAN_WARNING);
+			}
+			let save_file = profile.save_file(save);
+			let rewind = store::rewind(&backup, &save_file);
+			let kept = store::restore_core(&backup, &save_file)?;
+			if let Some(rewind) = rewind {
+				eprintln!(
+					"The save was further along, the restore went back\n{}",
+					rewind.describe()
+				);
+			}
+			if let Some(kept) = kept {
+				eprintln!(
+					"The save it replaced was kept as {}, run undo-restore to put it back.",
+					kept.display()
+				);
+			}
+		}
+		Command::TestRun {
+			backup,
+			branch,
+			game,
+		} => {
+			let start = store::find_backup(&backup_dir, &backup)?;
+			let branch = branch.unwrap_or_else(|| format!("test-{}", start.number));
+			store::check_note(&branch)?;
+			if profile.ironman {
+				eprintln!("{}", IRONMAN_WARNING);
+			}
+
+			let save_file = profile.save_file(save);
+			if let Some(kept) = store::restore_core(&start, &save_file)? {
+				eprintln!(
+					"The save it replaced was kept as {}, run undo-restore to put it back.",
+					kept.display()
+				);
+			}
+
+			let (program, arguments) = game.split_first().ok_or("No game to run.")?;
+			info!(
+				event = "test_run_started", save = save, backup = start.number;
+				"Running {} from backup number {}",
+				program.to_string_lossy(),
+				start.number
+			);
+			let status = process::Command::new(program).args(arguments).status()?;
+			if !save_file.is_file() {
+				return Err("The game left no save behind.".into());
+			}
+
+			let options = backup_options(config, save);
+			let created = backup_core(&save_file, &backup_dir, &branch, Origin::TestRun, &options)?;
+			if let Some(result) = store::list_backups(&backup_dir)
+				.context("read", &backup_dir)?
+				.into_iter()
+				.find(|backup| backup.number == created.number)
+			{
+				Manifest::update(&backup_dir, |manifest| {
+					manifest.set_branch(&result.file_name, &branch, start.number);
+					Ok(())
+				})?;
+			}
+			println!("{}", created.number);
+
+			// the save is recorded either way, as a crash is worth keeping when testing
+			if !status.success() {
+				return Err(format!("The game exited with {}", status).into());
+			}
+		}
+		Command::UndoRestore => {
+			let save_file = profile.save_file(save);
+			let kept = store::latest_kept_save(&save_file, &backup_dir)
+				.ok_or("No save has been replaced by a restore yet.")?;
+			store::undo_restore(&kept, &save_file)?;
+		}
+		Command::List => {
+			let manifest = Manifest::load(&backup_dir);
+			for backup in store::list_backups(&backup_dir).context("read", &backup_dir)? {
+				let mut line = backup.number.to_string();
+				if let Some(id) = backup.id(&manifest) {
+					line.push('\t');
+					line.push_str(&id);
+				}
+				if let Some(origin) = manifest.origin(&backup.file_name) {
+					line.push_str(&format!("\t[{}]", origin));
+				}
+				if let Some(author) = backup.author(&manifest) {
+					line.push_str(&format!("\t[by {}]", author));
+				}
+				if manifest.is_pinned(&backup.file_name) {
+					line.push_str("\t[pinned]");
+				}
+				if let Some((branch, parent)) = manifest.branch(&backup.file_name) {
+					line.push_str(&format!("\t[{} from {}]", branch, parent));
+				}
+				if !backup.note.is_empty() {
+					line.push('\t');
+					line.push_str(&backup.note);
+				}
+				println!("{}", line);
+			}
+		}
+		Command::Prune => {
+			let options = backup_options(config, save);
+			if let Some(deleted) = retention::run(
+				&backup_dir,
+				retention_policy(config, save),
+				retention_trigger(config, save),
+				&options.keep_tags,
+				Moment::Manual,
+			) {
+				println!("{}", deleted.context("delete old backups in", &backup_dir)?);
+			}
+		}
+		#[cfg(feature = "simulation")]
+		Command::Simulate { .. } => unreachable!("simulations are run before the save is looked up"),
+		Command::Translation => unreachable!("the texts are printed before the save is looked up"),
+		Command::Auto { baseline } => {
+			let session = Session::start_working(config, &profile, baseline)?;
+			eprintln!(
+				"Automatically backing up {}, press Enter to stop.",
+				session.saves().join(", ")
+			);
+
+			// when started without a terminal to read from, run until the process is killed
+			if io::stdin().read_line(&mut String::new())? == 0 {
+				loop {
+					thread::park();
+				}
+			}
+
+			println!("{}", session.stop());
+			if !jobs::wait_idle(SHUTDOWN_TIMEOUT) {
+				jobs::cancel();
+				if !jobs::wait_idle(CANCEL_GRACE) {
+					eprintln!("Quit with backups still running.");
+				}
+			}
+		}
+		Command::Audit => {
+			let audit = Audit::start(&profile, save)?;
+			eprintln!("Auditing writes of {}, press Enter to stop.", save);
+
+			// when started without a terminal to read from, run until the process is killed
+			if io::stdin().read_line(&mut String::new())? == 0 {
+				loop {
+					thread::park();
+				}
+			}
+
+			println!("{}", audit.stop().describe());
+		}
+	}
+
+	Ok(())
+}
+
+/// Gives the program a console unless it backs up in the background. It is built without one on
+/// Windows, so that starting background backups with a double-click does not flash a console
+/// window. Commands and the menu use the console of the terminal they were started from, or a new
+/// one when started with a double-click.
+#[cfg(windows)]
+pub fn attach_console(command: Option<&Command>) {
+	use winapi::um::consoleapi::AllocConsole;
+	use winapi::um::wincon::{AttachConsole, ATTACH_PARENT_PROCESS};
+
+	if matches!(command, Some(Command::Background)) {
+		return;
+	}
+	// SAFETY: both only connect this process to a console
+	unsafe {
+		if AttachConsole(ATTACH_PARENT_PROCESS) == 0 {
+			AllocConsole();
+		}
+	}
+}
+
+/// Other platforms start programs from a desktop entry or login item without a terminal already.
+#[cfg(not(windows))]
+pub const fn attach_console(_command: Option<&Command>) {}
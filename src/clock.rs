@@ -0,0 +1,35 @@
+#[cfg(not(feature = "simulation"))]
+use chrono::{DateTime, Local};
+
+/// The time backups are recorded and kept by.
+#[cfg(not(feature = "simulation"))]
+pub fn now() -> DateTime<Local> {
+	Local::now()
+}
+
+#[cfg(feature = "simulation")]
+pub use self::simulated::{advance, now, set};
+
+/// A clock that can be set, so that a history of backups spanning days plays out in moments. It
+/// runs with the system clock until set.
+#[cfg(feature = "simulation")]
+mod simulated {
+	use std::sync::Mutex;
+
+	use chrono::{DateTime, Duration, Local};
+
+	static NOW: Mutex<Option<DateTime<Local>>> = Mutex::new(None);
+
+	pub fn now() -> DateTime<Local> {
+		NOW.lock().unwrap().unwrap_or_else(Local::now)
+	}
+
+	pub fn set(time: DateTime<Local>) {
+		*NOW.lock().unwrap() = Some(time);
+	}
+
+	pub fn advance(by: Duration) {
+		let mut now = NOW.lock().unwrap();
+		*now = Some(now.unwrap_or_else(Local::now) + by);
+	}
+}
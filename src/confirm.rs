@@ -0,0 +1,103 @@
+use std::rc::Rc;
+
+use cursive::traits::*;
+use cursive::views::{Dialog, EditView, LinearLayout, TextView};
+use cursive::Cursive;
+
+use ini::Ini;
+
+use crate::i18n;
+use crate::settings::{current_save, save_setting};
+
+const CONFIRM_EDIT_NAME: &str = "confirm_entry";
+
+/// How much friction destructive operations get, set with the `confirmations` key in conf.ini or
+/// the protection level of the working save.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Level {
+	/// Run immediately.
+	None,
+	/// Ask with a yes/no dialog.
+	Normal,
+	/// Require typing "yes".
+	Paranoid,
+}
+
+impl Level {
+	pub fn from_config(config: &Ini) -> Self {
+		let save = current_save(config).unwrap_or_default();
+		match save_setting(config, save, "confirmations") {
+			Some("none") => Self::None,
+			Some("paranoid") => Self::Paranoid,
+			_ => Self::Normal,
+		}
+	}
+}
+
+/// Runs a destructive action after asking for as much confirmation as the configured level
+/// requires.
+pub fn confirm<F>(s: &mut Cursive, message: &str, action: F)
+where
+	F: Fn(&mut Cursive) + 'static,
+{
+	let level = s
+		.with_user_data(|config: &mut Ini| Level::from_config(config))
+		.expect("User data not set up correctly on program start");
+
+	match level {
+		Level::None => action(s),
+		Level::Normal => s.add_layer(
+			Dialog::around(TextView::new(message))
+				.button(i18n::text("no"), |s| {
+					s.pop_layer();
+				})
+				.button(i18n::text("yes"), move |s| {
+					s.pop_layer();
+					action(s);
+				}),
+		),
+		Level::Paranoid => {
+			let action = Rc::new(action);
+			// the English word is always understood, whatever the language
+			let word = i18n::text("yes").to_lowercase();
+			let submit = move |s: &mut Cursive, entry: &str| {
+				let entry = entry.trim();
+				if entry.eq_ignore_ascii_case("yes") || entry.to_lowercase() == word {
+					s.pop_layer();
+					action(s);
+				} else {
+					s.add_layer(Dialog::info(i18n::fill("type_yes", &[&word])));
+				}
+			};
+			let submit = Rc::new(submit);
+
+			s.add_layer(
+				Dialog::around(
+					LinearLayout::vertical()
+						.child(TextView::new(message))
+						.child(TextView::new(i18n::fill(
+							"type_yes_prompt",
+							&[&i18n::text("yes").to_lowercase()],
+						)))
+						.child(
+							EditView::new()
+								.on_submit({
+									let submit = Rc::clone(&submit);
+									move |s, entry| submit(s, entry)
+								})
+								.with_name(CONFIRM_EDIT_NAME),
+						),
+				)
+				.button(i18n::text("cancel"), |s| {
+					s.pop_layer();
+				})
+				.button(i18n::text("continue"), move |s| {
+					let entry = s
+						.call_on_name(CONFIRM_EDIT_NAME, |view: &mut EditView| view.get_content())
+						.expect("EditView not created for confirmation entry");
+					submit(s, &entry);
+				}),
+			);
+		}
+	}
+}
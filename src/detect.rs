@@ -0,0 +1,223 @@
+use std::cell::RefCell;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+use cursive::traits::*;
+use cursive::views::{Dialog, LinearLayout, SelectView, TextView};
+use cursive::Cursive;
+
+use directories::{BaseDirs, UserDirs};
+
+use crate::titles::{Title, SAVE_GAMES};
+
+/// Folder in the documents folder holding the folders of every Paradox game.
+const PARADOX_FOLDER: &str = "Paradox Interactive";
+
+/// Save directories of a game found in the usual places: the documents folder, the folder older
+/// Linux versions used, Steam Cloud folders and Proton prefixes in every Steam library. Only
+/// directories that exist are returned, each once.
+pub fn candidates(title: &Title) -> Vec<PathBuf> {
+	let mut found = Vec::new();
+	if let Some(dirs) = UserDirs::new() {
+		found.extend(
+			dirs.document_dir()
+				.map(|documents| save_games(title, documents)),
+		);
+		found.push(save_games(title, &dirs.home_dir().join("Documents")));
+		// Linux versions used to keep their saves without the "Paradox Interactive" folder
+		found.push(
+			dirs.home_dir()
+				.join(".paradoxinteractive")
+				.join(title.folder)
+				.join(SAVE_GAMES),
+		);
+	}
+
+	for steam in steam_roots() {
+		found.extend(cloud_folders(title, &steam));
+
+		for library in libraries(&steam) {
+			let users = library
+				.join("steamapps")
+				.join("compatdata")
+				.join(title.steam_app_id)
+				.join("pfx")
+				.join("drive_c")
+				.join("users")
+				.join("steamuser");
+			found.push(save_games(title, &users.join("Documents")));
+			found.push(save_games(title, &users.join("My Documents")));
+		}
+	}
+
+	let mut candidates: Vec<PathBuf> = Vec::new();
+	for path in found.into_iter().filter(|path| path.is_dir()) {
+		let path = fs::canonicalize(&path).unwrap_or(path);
+		if !candidates.contains(&path) {
+			candidates.push(path);
+		}
+	}
+
+	candidates
+}
+
+fn save_games(title: &Title, documents: &Path) -> PathBuf {
+	documents
+		.join(PARADOX_FOLDER)
+		.join(title.folder)
+		.join(SAVE_GAMES)
+}
+
+/// Folders in which Steam Cloud keeps a copy of the saves, one for every account that played.
+fn cloud_folders(title: &Title, steam: &Path) -> Vec<PathBuf> {
+	fs::read_dir(steam.join("userdata")).map_or_else(
+		|_| Vec::new(),
+		|entries| {
+			entries
+				.filter_map(Result::ok)
+				.map(|entry| {
+					entry
+						.path()
+						.join(title.steam_app_id)
+						.join("remote")
+						.join(SAVE_GAMES)
+				})
+				.collect()
+		},
+	)
+}
+
+/// The Steam Cloud copy of a game's saves, if Steam keeps one for any account.
+pub fn cloud_folder(title: &Title) -> Option<PathBuf> {
+	steam_roots()
+		.iter()
+		.flat_map(|steam| cloud_folders(title, steam))
+		.find(|folder| folder.is_dir())
+}
+
+/// Where a game keeps its saves in the documents folder, whether or not it has been played.
+pub fn default_save_dir(title: &Title) -> Option<PathBuf> {
+	let dirs = UserDirs::new()?;
+	let documents = dirs
+		.document_dir()
+		.map_or_else(|| dirs.home_dir().join("Documents"), Path::to_path_buf);
+	Some(save_games(title, &documents))
+}
+
+/// Folders Steam is commonly installed to.
+fn steam_roots() -> Vec<PathBuf> {
+	let mut roots = Vec::new();
+	for variable in &["ProgramFiles(x86)", "ProgramFiles"] {
+		if let Some(programs) = env::var_os(variable) {
+			roots.push(PathBuf::from(programs).join("Steam"));
+		}
+	}
+	if let Some(dirs) = BaseDirs::new() {
+		let home = dirs.home_dir();
+		roots.push(home.join(".steam").join("steam"));
+		roots.push(home.join(".local").join("share").join("Steam"));
+		roots.push(
+			home.join(".var")
+				.join("app")
+				.join("com.valvesoftware.Steam")
+				.join(".local")
+				.join("share")
+				.join("Steam"),
+		);
+		roots.push(dirs.data_dir().join("Steam"));
+	}
+
+	roots.retain(|root| root.is_dir());
+	roots
+}
+
+/// Steam libraries known to an install of Steam, starting with the install itself. Further
+/// libraries are listed in `steamapps/libraryfolders.vdf`, either as `"path"` entries or, in older
+/// versions, as numbered entries.
+fn libraries(steam: &Path) -> Vec<PathBuf> {
+	let mut libraries = vec![steam.to_path_buf()];
+	let vdf = match fs::read_to_string(steam.join("steamapps").join("libraryfolders.vdf")) {
+		Ok(vdf) => vdf,
+		Err(_) => return libraries,
+	};
+
+	for line in vdf.lines() {
+		let quoted = line.split('"').skip(1).step_by(2).collect::<Vec<_>>();
+		if let [key, value] = quoted[..] {
+			if key == "path" || key.parse::<u32>().is_ok() {
+				let library = PathBuf::from(value.replace("\\\\", "\\"));
+				if !libraries.contains(&library) {
+					libraries.push(library);
+				}
+			}
+		}
+	}
+
+	libraries
+}
+
+/// Number of saves of a game in a directory, to tell the found directories apart.
+fn save_count(title: &Title, directory: &Path) -> usize {
+	let is_save = |path: &Path| {
+		path.extension()
+			.is_some_and(|extension| title.extension[1..] == *extension)
+	};
+	fs::read_dir(directory).map_or(0, |entries| {
+		entries
+			.filter_map(Result::ok)
+			.map(|entry| entry.path())
+			.map(|path| match fs::read_dir(&path) {
+				// saves kept in folders of their own, such as one per empire
+				Ok(inner) if title.nested => inner
+					.filter_map(Result::ok)
+					.filter(|entry| is_save(&entry.path()))
+					.count(),
+				_ => usize::from(is_save(&path)),
+			})
+			.sum()
+	})
+}
+
+/// Lets the save directory be picked from the ones found, before the rest of the interface is set
+/// up. Returns `None` when the picker is closed without picking one.
+pub fn choose(title: &Title, candidates: Vec<PathBuf>) -> Option<PathBuf> {
+	let picked = Rc::new(RefCell::new(None));
+
+	let mut list = SelectView::<PathBuf>::new();
+	for candidate in candidates {
+		let label = format!(
+			"{} ({} saves)",
+			candidate.display(),
+			save_count(title, &candidate)
+		);
+		list.add_item(label, candidate);
+	}
+
+	let on_pick = Rc::clone(&picked);
+	let mut root = cursive::default();
+	root.add_layer(
+		Dialog::around(
+			LinearLayout::vertical()
+				.child(TextView::new(format!(
+					"Pick the save directory of {} to manage. It is remembered in conf.ini, and another one can be passed as an argument.\n",
+					title.name
+				)))
+				.child(
+					list.on_submit(move |s, directory: &PathBuf| {
+						on_pick.replace(Some(directory.clone()));
+						s.quit();
+					})
+					.scrollable(),
+				)
+				.max_width(100),
+		)
+		.title("Save directories found")
+		.button("Quit", Cursive::quit),
+	);
+	root.run();
+	drop(root);
+
+	picked.take()
+}
@@ -0,0 +1,382 @@
+use std::fs;
+use std::io::{self, Cursor, Write};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use chrono::{Datelike, Local, NaiveDate, NaiveTime, Weekday};
+
+use ini::Ini;
+
+use log::{error, info, warn};
+
+use serde_json::{json, Value as Json};
+
+use zip::write::SimpleFileOptions;
+use zip::{CompressionMethod, ZipWriter};
+
+use crate::i18n;
+use crate::manifest::Origin;
+use crate::profiles::Profile;
+use crate::store::{self, backup_core, Backup, BackupOptions};
+use crate::timing::{self, Operation};
+
+/// Section of conf.ini configuring the bot.
+const SECTION: &str = "discord";
+
+const API: &str = "https://discord.com/api/v10";
+
+/// How often the channel is checked for new commands.
+const POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Largest attachment a bot may post on a server without boosts.
+const UPLOAD_LIMIT: usize = 10 * 1024 * 1024;
+
+/// Times a request is retried when Discord asks to slow down.
+const RATE_LIMIT_RETRIES: usize = 3;
+
+const HELP: &str = "Commands:\n`!latest` posts the newest backup of the session save\n`!publish [note]` backs up the save and posts it, for the host only\n`!help` shows this message";
+
+/// When the group meets, for posting a reminder ahead of each session.
+struct Reminder {
+	at: NaiveTime,
+	/// Weekdays the group meets on, every day when empty.
+	days: Vec<Weekday>,
+	before: Duration,
+}
+
+/// A bot posting the session save of a multiplayer group to one channel, configured in the
+/// `[discord]` section of conf.ini:
+///
+/// * `token`: token of the bot, which needs to read and send messages and attach files
+/// * `channel`: ID of the channel the bot answers in
+/// * `host`: user ID of the host, who alone may publish backups
+/// * `session_at`: time of day sessions start like `20:00`, for reminders
+/// * `session_days`: weekdays sessions are held like `sat,sun`, every day when left out
+/// * `remind_before`: minutes before a session to post the reminder, 30 when left out
+pub struct Bot {
+	token: String,
+	channel: String,
+	host: Option<String>,
+	reminder: Option<Reminder>,
+}
+
+impl Bot {
+	/// Reads the bot settings, returning `None` when no bot is configured.
+	pub fn load(config: &Ini) -> Result<Option<Self>, String> {
+		let get = |key: &str| {
+			config
+				.get_from(Some(SECTION), key)
+				.map(str::trim)
+				.filter(|value| !value.is_empty())
+		};
+
+		let token = match get("token") {
+			Some(token) => token,
+			None => return Ok(None),
+		};
+		let channel = get("channel").ok_or("channel must be set to the ID of a channel")?;
+
+		let reminder = get("session_at")
+			.map(|at| -> Result<_, String> {
+				let at = NaiveTime::parse_from_str(at, "%H:%M")
+					.map_err(|_| "session_at must be a time like 20:00")?;
+				let days = get("session_days")
+					.unwrap_or_default()
+					.split(',')
+					.map(str::trim)
+					.filter(|day| !day.is_empty())
+					.map(|day| {
+						day.parse()
+							.map_err(|_| format!("\"{}\" is not a weekday", day))
+					})
+					.collect::<Result<_, _>>()?;
+				let before = get("remind_before")
+					.map_or(Ok(30), str::parse::<u64>)
+					.map_err(|_| "remind_before must be a number of minutes")?;
+
+				Ok(Reminder {
+					at,
+					days,
+					before: Duration::from_secs(before * 60),
+				})
+			})
+			.transpose()?;
+
+		Ok(Some(Self {
+			token: token.to_string(),
+			channel: channel.to_string(),
+			host: get("host").map(ToString::to_string),
+			reminder,
+		}))
+	}
+
+	/// Answers commands in the channel and posts reminders until the process exits. Only messages
+	/// sent after it starts are answered.
+	pub fn spawn(self, profile: Profile, save: String, options: BackupOptions) {
+		thread::spawn(move || {
+			let mut last = match self.messages(None) {
+				Ok(messages) => messages.first().and_then(message_id),
+				Err(e) => {
+					error!("Discord bot could not read its channel: {}", e);
+					return;
+				}
+			};
+			info!("Discord bot answering in channel {}", self.channel);
+
+			let mut reminded = None;
+			loop {
+				thread::sleep(POLL_INTERVAL);
+
+				if let Some(session) = self.reminder_due(&mut reminded) {
+					let message = reminder_message(&profile, &save, session);
+					if let Err(e) = self.post(&message) {
+						warn!("Discord bot could not post the session reminder: {}", e);
+					}
+				}
+
+				let messages = match self.messages(last.as_deref()) {
+					Ok(messages) => messages,
+					Err(e) => {
+						warn!("Discord bot could not read its channel: {}", e);
+						continue;
+					}
+				};
+				// newest first, so answer from the end
+				for message in messages.iter().rev() {
+					last = message_id(message).or(last);
+					if message["author"]["bot"].as_bool() == Some(true) {
+						continue;
+					}
+
+					let content = message["content"].as_str().unwrap_or_default().trim();
+					let author = message["author"]["id"].as_str().unwrap_or_default();
+					if let Err(e) = self.answer(content, author, &profile, &save, &options) {
+						warn!("Discord bot could not answer \"{}\": {}", content, e);
+						if let Err(e) = self.post(&i18n::error(&e)) {
+							warn!("Discord bot could not post to its channel: {}", e);
+						}
+					}
+				}
+			}
+		});
+	}
+
+	fn answer(
+		&self,
+		content: &str,
+		author: &str,
+		profile: &Profile,
+		save: &str,
+		options: &BackupOptions,
+	) -> crate::error::Result<()> {
+		let (command, argument) = content
+			.split_once(char::is_whitespace)
+			.map_or((content, ""), |(command, argument)| {
+				(command, argument.trim())
+			});
+		let backup_dir = profile.backup_path.join(save);
+
+		match command {
+			"!help" => self.post(HELP),
+			"!latest" => {
+				let latest = store::list_backups(&backup_dir)?
+					.pop()
+					.ok_or("The save has no backups yet.")?;
+				self.upload(&latest, profile, save, "The latest backup")
+			}
+			"!publish" => {
+				if self.host.as_deref() != Some(author) {
+					return self.post("Only the host can publish a backup.");
+				}
+				store::check_note(argument)?;
+				let file_path = profile.save_file(save);
+				if !file_path.is_file() {
+					return Err("Save file not found.".into());
+				}
+				fs::create_dir_all(&backup_dir)?;
+
+				let created = backup_core(
+					&file_path,
+					&backup_dir,
+					argument,
+					Origin::Published,
+					options,
+				)?;
+				let published = store::list_backups(&backup_dir)?
+					.into_iter()
+					.find(|backup| backup.number == created.number)
+					.ok_or("The published backup could not be found.")?;
+				info!(
+					event = "backup_published", save = save, backup = created.number;
+					"Backup number {} published to Discord",
+					created.number
+				);
+				self.upload(&published, profile, save, "The host published")
+			}
+			_ => Ok(()),
+		}
+	}
+
+	/// Start of the session to remind the group of now, if one is coming up today and has not been
+	/// reminded of yet.
+	fn reminder_due(&self, reminded: &mut Option<NaiveDate>) -> Option<NaiveTime> {
+		let reminder = self.reminder.as_ref()?;
+		let now = Local::now().naive_local();
+		let start = now.date().and_time(reminder.at);
+		let before = chrono::Duration::from_std(reminder.before).ok()?;
+
+		let due = (reminder.days.is_empty() || reminder.days.contains(&now.weekday()))
+			&& now >= start - before
+			&& now < start
+			&& *reminded != Some(now.date());
+		if due {
+			*reminded = Some(now.date());
+		}
+		due.then_some(reminder.at)
+	}
+
+	fn request(&self, method: &str, path: &str) -> ureq::Request {
+		ureq::request(method, &format!("{}{}", API, path))
+			.set("Authorization", &format!("Bot {}", self.token))
+	}
+
+	/// Sends a request, waiting as long as Discord asks when it is rate limited.
+	fn send(request: &ureq::Request, body: &[u8]) -> crate::error::Result<ureq::Response> {
+		for _ in 0..RATE_LIMIT_RETRIES {
+			match request.clone().send_bytes(body) {
+				Err(ureq::Error::Status(429, response)) => {
+					let wait = response
+						.into_string()
+						.ok()
+						.and_then(|body| serde_json::from_str::<Json>(&body).ok())
+						.and_then(|body| body["retry_after"].as_f64())
+						.unwrap_or(1.0);
+					thread::sleep(Duration::from_secs_f64(wait.max(0.0)));
+				}
+				result => {
+					return result
+						.map_err(|e| format!("Discord could not be reached: {}", e).into())
+				}
+			}
+		}
+
+		Err("Discord kept refusing requests as too frequent.".into())
+	}
+
+	/// Messages in the channel after the one with the ID given, newest first, or only the newest
+	/// message when none is given.
+	fn messages(&self, after: Option<&str>) -> crate::error::Result<Vec<Json>> {
+		let path = format!("/channels/{}/messages", self.channel);
+		let request = self.request("GET", &path);
+		let request = match after {
+			Some(after) => request.query("after", after).query("limit", "50"),
+			None => request.query("limit", "1"),
+		};
+		let response = Self::send(&request, &[])?;
+
+		let body = response.into_string()?;
+		match serde_json::from_str(&body) {
+			Ok(Json::Array(messages)) => Ok(messages),
+			_ => Err("Discord sent an unexpected reply.".into()),
+		}
+	}
+
+	fn post(&self, content: &str) -> crate::error::Result<()> {
+		let path = format!("/channels/{}/messages", self.channel);
+		let body = json!({ "content": content }).to_string();
+		let request = self
+			.request("POST", &path)
+			.set("Content-Type", "application/json");
+		Self::send(&request, body.as_bytes())?;
+
+		Ok(())
+	}
+
+	/// Posts a backup as a zip holding the save under its usual name, ready to be put in the save
+	/// directory.
+	fn upload(
+		&self,
+		backup: &Backup,
+		profile: &Profile,
+		save: &str,
+		intro: &str,
+	) -> crate::error::Result<()> {
+		let started = Instant::now();
+		let archive = zipped(backup, &format!("{}{}", save, profile.extension))?;
+		if archive.len() > UPLOAD_LIMIT {
+			return self.post(&format!(
+				"Backup number {} is too large to post on Discord.",
+				backup.number
+			));
+		}
+
+		let file_name = format!("{} backup {}.zip", save, backup.number);
+		let payload = json!({
+			"content": format!("{}: backup number {} of {}", intro, backup.number, save),
+			"attachments": [{ "id": 0, "filename": file_name }],
+		});
+
+		// a multipart body by hand, as it only ever holds the message and one file
+		let boundary = format!("save-manager-{}", ulid::Ulid::new());
+		let mut body = Vec::new();
+		write!(
+			body,
+			"--{}\r\nContent-Disposition: form-data; name=\"payload_json\"\r\nContent-Type: application/json\r\n\r\n{}\r\n",
+			boundary, payload
+		)?;
+		write!(
+			body,
+			"--{}\r\nContent-Disposition: form-data; name=\"files[0]\"; filename=\"{}\"\r\nContent-Type: application/zip\r\n\r\n",
+			boundary,
+			file_name.replace('"', "'")
+		)?;
+		body.extend_from_slice(&archive);
+		write!(body, "\r\n--{}--\r\n", boundary)?;
+
+		let path = format!("/channels/{}/messages", self.channel);
+		let request = self.request("POST", &path).set(
+			"Content-Type",
+			&format!("multipart/form-data; boundary={}", boundary),
+		);
+		Self::send(&request, &body)?;
+		timing::record(
+			&profile.backup_path,
+			Operation::Upload,
+			archive.len() as u64,
+			started.elapsed(),
+		);
+
+		Ok(())
+	}
+}
+
+fn message_id(message: &Json) -> Option<String> {
+	message["id"].as_str().map(ToString::to_string)
+}
+
+fn reminder_message(profile: &Profile, save: &str, session: NaiveTime) -> String {
+	let mut message = format!("The session starts at {}.", session.format("%H:%M"));
+	if let Some(latest) = store::list_backups(&profile.backup_path.join(save))
+		.ok()
+		.and_then(|mut backups| backups.pop())
+	{
+		message.push_str(&format!(
+			" The latest save is backup number {}, send `!latest` to get it.",
+			latest.number
+		));
+	}
+
+	message
+}
+
+/// Packs the save a backup holds into a zip in memory.
+fn zipped(backup: &Backup, save_name: &str) -> io::Result<Vec<u8>> {
+	let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+	zip.start_file(
+		save_name,
+		SimpleFileOptions::default().compression_method(CompressionMethod::Deflated),
+	)?;
+	io::copy(&mut backup.open()?, &mut zip)?;
+
+	Ok(zip.finish()?.into_inner())
+}
@@ -0,0 +1,88 @@
+use std::io;
+use std::path::{Path, PathBuf};
+
+use thiserror::Error;
+
+/// Everything that can go wrong in the save manager, shown to the user in a dialog or on the
+/// terminal. Failures involving a file name the file, so that it is clear what to look at.
+#[derive(Debug, Error)]
+pub enum Error {
+	/// Reading or writing a particular file or folder failed.
+	#[error("Could not {action} {}: {source}", path.display())]
+	File {
+		/// What was being done, such as "read" or "write to".
+		action: &'static str,
+		path: PathBuf,
+		source: io::Error,
+	},
+	/// The config file could not be saved, so a changed setting only lasts until the program
+	/// quits.
+	#[error("Could not save the settings to {}: {source}", path.display())]
+	Config { path: PathBuf, source: io::Error },
+	/// The config file could not be understood.
+	#[error("Settings could not be read: {0}")]
+	Settings(#[from] ini::ini::ParseError),
+	/// The folder the executable is in could not be found, which the default save directory is
+	/// found from.
+	#[error("The location of the executable could not be found: {0}")]
+	Executable(io::Error),
+	#[error("Watching for saves failed: {0}")]
+	Watch(#[from] notify::Error),
+	#[error("Archive could not be read: {0}")]
+	Archive(#[from] zip::result::ZipError),
+	#[error(transparent)]
+	Io(#[from] io::Error),
+	/// A problem explained in words, such as a setting that is missing.
+	#[error("{0}")]
+	Message(String),
+}
+
+impl Error {
+	/// Names the kind of the error for structured logs, such as `NotFound` for a missing file.
+	pub fn kind(&self) -> String {
+		match self {
+			Self::File { source, .. } | Self::Config { source, .. } | Self::Io(source) => {
+				format!("{:?}", source.kind())
+			}
+			_ => "Other".to_string(),
+		}
+	}
+
+	/// Whether the failure came from another program, such as the game writing the save, having a
+	/// file open, so that trying again shortly may succeed.
+	pub fn in_use(&self) -> bool {
+		match self {
+			Self::File { source, .. } | Self::Io(source) => crate::store::opened_elsewhere(source),
+			_ => false,
+		}
+	}
+}
+
+impl From<&str> for Error {
+	fn from(message: &str) -> Self {
+		Self::Message(message.to_string())
+	}
+}
+
+impl From<String> for Error {
+	fn from(message: String) -> Self {
+		Self::Message(message)
+	}
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// Names the file an I/O error happened on.
+pub trait Context<T> {
+	fn context(self, action: &'static str, path: &Path) -> Result<T>;
+}
+
+impl<T> Context<T> for io::Result<T> {
+	fn context(self, action: &'static str, path: &Path) -> Result<T> {
+		self.map_err(|source| Error::File {
+			action,
+			path: path.to_path_buf(),
+			source,
+		})
+	}
+}
@@ -0,0 +1,49 @@
+#[cfg(not(target_os = "linux"))]
+use std::process::{Command, Stdio};
+
+/// Whether a process with the given executable name is running, such as `CK2game.exe`. Names are
+/// compared without regard to case on Windows, where file names are not case sensitive.
+#[cfg(target_os = "linux")]
+pub fn is_running(process: &str) -> bool {
+	use std::fs;
+
+	/// Length the kernel cuts process names down to.
+	const COMM_LENGTH: usize = 15;
+
+	let wanted = process.get(..COMM_LENGTH).unwrap_or(process);
+	fs::read_dir("/proc").is_ok_and(|entries| {
+		entries.filter_map(Result::ok).any(|entry| {
+			fs::read_to_string(entry.path().join("comm"))
+				.is_ok_and(|comm| comm.trim_end_matches('\n') == wanted)
+		})
+	})
+}
+
+#[cfg(windows)]
+pub fn is_running(process: &str) -> bool {
+	Command::new("tasklist")
+		.args([
+			"/FI",
+			&format!("IMAGENAME eq {}", process),
+			"/NH",
+			"/FO",
+			"CSV",
+		])
+		.stderr(Stdio::null())
+		.output()
+		.is_ok_and(|output| {
+			String::from_utf8_lossy(&output.stdout)
+				.to_lowercase()
+				.contains(&format!("\"{}\"", process.to_lowercase()))
+		})
+}
+
+#[cfg(not(any(target_os = "linux", windows)))]
+pub fn is_running(process: &str) -> bool {
+	Command::new("pgrep")
+		.args(["-x", process])
+		.stdout(Stdio::null())
+		.stderr(Stdio::null())
+		.status()
+		.is_ok_and(|status| status.success())
+}
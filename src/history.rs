@@ -0,0 +1,219 @@
+use std::io;
+use std::path::Path;
+use std::time::Duration;
+
+use chrono::{DateTime, Local};
+
+use cursive::views::{Dialog, TextView};
+use cursive::Cursive;
+
+use ini::Ini;
+
+use crate::error;
+use crate::format_duration;
+use crate::manifest::Manifest;
+use crate::parser::GameDate;
+use crate::profiles::Profile;
+use crate::settings::{current_save, display_name};
+use crate::store;
+
+/// Time between two backups beyond which the game is taken to have been closed in between, so that
+/// breaks do not count as time played.
+pub const SESSION_GAP: Duration = Duration::from_secs(60 * 60);
+
+/// Groups items taken in order, such as backups, into sessions of play, starting a new session
+/// wherever more than `SESSION_GAP` passed since the item before. Items whose time is not known
+/// join the session they come in, and the gap after them is counted from the last known time.
+pub fn sessions<T>(
+	items: impl IntoIterator<Item = T>,
+	created: impl Fn(&T) -> Option<DateTime<Local>>,
+) -> Vec<Vec<T>> {
+	let mut sessions: Vec<Vec<T>> = Vec::new();
+	let mut previous: Option<DateTime<Local>> = None;
+	for item in items {
+		let created = created(&item);
+		let gap = previous
+			.zip(created)
+			.map(|(previous, created)| (created - previous).to_std());
+		match sessions.last_mut() {
+			Some(session) if gap.is_none_or(|gap| gap.is_ok_and(|gap| gap <= SESSION_GAP)) => {
+				session.push(item);
+			}
+			_ => sessions.push(vec![item]),
+		}
+		previous = created.or(previous);
+	}
+
+	sessions
+}
+
+/// Size of the chart in characters, leaving out its axes.
+const CHART_WIDTH: usize = 60;
+const CHART_HEIGHT: usize = 15;
+
+/// A backup placed on the chart.
+struct Point {
+	/// Hours played up to the backup.
+	played: f64,
+	/// In-game date as a fractional year.
+	year: f64,
+	date: GameDate,
+	/// Whether the backup starts a new session of play.
+	new_session: bool,
+}
+
+fn fractional_year(date: GameDate) -> f64 {
+	f64::from(date.year)
+		+ f64::from(date.month.saturating_sub(1)) / 12.0
+		+ f64::from(date.day.saturating_sub(1)) / 365.0
+}
+
+/// Backups of a save with a known in-game date, placed by how long the save had been played when
+/// each was taken.
+fn points(backup_dir: &Path) -> io::Result<Vec<Point>> {
+	let manifest = Manifest::load(backup_dir);
+	let mut backups = store::list_backups(backup_dir)?
+		.into_iter()
+		.filter_map(|backup| Some((backup.created(&manifest)?, backup.game_date(&manifest)?)))
+		.collect::<Vec<_>>();
+	backups.sort_by_key(|(created, _)| *created);
+
+	let mut points = Vec::new();
+	let mut played = 0.0;
+	for session in sessions(backups, |(created, _)| Some(*created)) {
+		let mut previous: Option<DateTime<Local>> = None;
+		for (created, date) in session {
+			if let Some(previous) = previous {
+				played += (created - previous)
+					.to_std()
+					.unwrap_or_default()
+					.as_secs_f64() / 3600.0;
+			}
+
+			points.push(Point {
+				played,
+				year: fractional_year(date),
+				date,
+				new_session: previous.is_none(),
+			});
+			previous = Some(created);
+		}
+	}
+
+	Ok(points)
+}
+
+/// In-game years passed per hour played, leaving out jumps back in time from restores.
+fn rate(points: &[Point]) -> Option<f64> {
+	let (years, hours) = points
+		.windows(2)
+		.filter(|pair| !pair[1].new_session && pair[1].year >= pair[0].year)
+		.fold((0.0, 0.0), |(years, hours), pair| {
+			(
+				years + pair[1].year - pair[0].year,
+				hours + pair[1].played - pair[0].played,
+			)
+		});
+
+	(hours > 0.0).then(|| years / hours)
+}
+
+/// Draws the in-game date of the backups over the hours played.
+fn render(points: &[Point]) -> String {
+	let first_year = points
+		.iter()
+		.map(|point| point.year)
+		.fold(f64::MAX, f64::min);
+	let last_year = points
+		.iter()
+		.map(|point| point.year)
+		.fold(f64::MIN, f64::max);
+	let played = points.last().map_or(0.0, |point| point.played);
+
+	let mut grid = vec![vec![' '; CHART_WIDTH]; CHART_HEIGHT];
+	for point in points {
+		let column = if played > 0.0 {
+			(point.played / played * (CHART_WIDTH - 1) as f64).round() as usize
+		} else {
+			0
+		};
+		let row = if last_year > first_year {
+			((point.year - first_year) / (last_year - first_year) * (CHART_HEIGHT - 1) as f64)
+				.round() as usize
+		} else {
+			0
+		};
+		grid[CHART_HEIGHT - 1 - row][column] = '*';
+	}
+
+	let mut chart = String::new();
+	for (index, row) in grid.iter().enumerate() {
+		let label = match index {
+			0 => format!("{:>6.0}", last_year.floor()),
+			_ if index == CHART_HEIGHT - 1 => format!("{:>6.0}", first_year.floor()),
+			_ => " ".repeat(6),
+		};
+		chart.push_str(&format!("{} |{}\n", label, row.iter().collect::<String>()));
+	}
+	chart.push_str(&format!("{} +{}\n", " ".repeat(6), "-".repeat(CHART_WIDTH)));
+	chart.push_str(&format!(
+		"{} 0h{:>width$}\n",
+		" ".repeat(7),
+		format!("{:.1}h played", played),
+		width = CHART_WIDTH - 2
+	));
+
+	chart
+}
+
+/// Shows how the campaign of the working save has progressed in-game against the time played,
+/// from the in-game dates of its backups.
+pub fn chart(s: &mut Cursive, profile: &Profile) -> error::Result<()> {
+	let config: &mut Ini = s
+		.user_data()
+		.expect("User data not set up correctly on program start");
+	let save = current_save(config)
+		.ok_or("No save file has been set.")?
+		.to_string();
+	let label = display_name(config, &save);
+
+	let points = points(&profile.backup_path.join(&save))?;
+	if points.len() < 2 {
+		return Err("The save needs at least two backups with an in-game date to chart.".into());
+	}
+
+	let first = &points[0];
+	let last = &points[points.len() - 1];
+	let sessions = points.iter().filter(|point| point.new_session).count();
+	let mut summary = format!(
+		"{} backups over {} sessions, {} played, from {} to {}\n",
+		points.len(),
+		sessions,
+		format_duration(Duration::from_secs_f64(last.played * 3600.0)),
+		first.date,
+		last.date
+	);
+	match rate(&points) {
+		Some(overall) => {
+			summary.push_str(&format!("{:.1} in-game years per hour played", overall));
+			let latest = points
+				.iter()
+				.rposition(|point| point.new_session)
+				.unwrap_or(0);
+			if let Some(latest) = rate(&points[latest..]).filter(|_| sessions > 1) {
+				summary.push_str(&format!(", {:.1} in the latest session", latest));
+			}
+		}
+		None => summary.push_str("Not enough backups within a session to tell the pace"),
+	}
+
+	s.add_layer(
+		Dialog::around(TextView::new(format!("{}\n{}", render(&points), summary)))
+			.title(format!("Campaign progress of {}", label))
+			.button("Ok", |s| {
+				s.pop_layer();
+			}),
+	);
+
+	Ok(())
+}
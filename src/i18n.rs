@@ -0,0 +1,232 @@
+//! Text of the menu and dialogs, looked up by key so that it can be translated. A translation is a
+//! file `translations/<language>.ini` next to the config file, picked with the `language` setting
+//! and holding `key = text` lines for the keys of `ENGLISH`. Keys it leaves out stay in English.
+//! In a text, `{}` stands for what is filled in, such as an error, in the order of the English.
+
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::sync::OnceLock;
+
+use ini::Ini;
+
+use log::{info, warn};
+
+use crate::settings;
+
+/// Folder next to the config file holding the translations.
+const TRANSLATIONS_FOLDER: &str = "translations";
+
+/// Every text that can be translated, by key.
+pub const ENGLISH: &[(&str, &str)] = &[
+	("menu.select_profile", "Select game profile"),
+	("menu.set_game", "Set a new working game"),
+	("menu.backup", "Make a new backup"),
+	("menu.backup_with_note", "Make a new backup (with note)"),
+	("menu.import", "Import a save file"),
+	("menu.find_lost_saves", "Find lost saves"),
+	("menu.browse", "Browse backups"),
+	("menu.panic_restore", "Panic restore (Ctrl+P)"),
+	("menu.undo", "Undo last operation"),
+	("menu.behind", "How far behind is my last backup? (Ctrl+B)"),
+	("menu.inspect", "Inspect current save"),
+	("menu.restore_archive", "Restore from an archive"),
+	("menu.export", "Export backups"),
+	("menu.auto", "Automatically take backups"),
+	(
+		"menu.backup_then_auto",
+		"Back up now, then take backups automatically",
+	),
+	("menu.pause_auto", "Pause automatic backups"),
+	("menu.resume_auto", "Resume automatic backups"),
+	("menu.stop_auto", "Stop automatic backups"),
+	("menu.delete_old", "Delete old backups"),
+	("menu.storage", "Storage"),
+	("menu.progress", "Campaign progress"),
+	("menu.timeline", "Timeline"),
+	("menu.audit", "Audit save writes"),
+	("menu.abandoned", "Abandoned campaigns"),
+	("menu.rebuild_index", "Rebuild backup index"),
+	("menu.check_consistency", "Check backup consistency"),
+	("menu.verify", "Verify backups"),
+	("menu.sync_now", "Sync backups now"),
+	("menu.scheduled_tasks", "Scheduled tasks"),
+	("menu.toggle_compression", "Toggle backup compression"),
+	("menu.toggle_notifications", "Toggle desktop notifications"),
+	("menu.toggle_autosaves", "Toggle autosave generations"),
+	("menu.retention", "Retention of automatic backups"),
+	("menu.share", "Share settings"),
+	("menu.quit", "Quit"),
+	("menu.read_only", "{} (read-only)"),
+	("error", "Error occurred: {}"),
+	(
+		"read_only",
+		"The backup folder is on read-only storage, so backups can only be browsed and restored.",
+	),
+	("auto_not_running", "Automatic backups are not running."),
+	("session_summary", "Session summary"),
+	("ok", "Ok"),
+	("yes", "Yes"),
+	("no", "No"),
+	("cancel", "Cancel"),
+	("continue", "Continue"),
+	("type_yes", "Type \"{}\" to continue."),
+	("type_yes_prompt", "Type \"{}\" to continue:"),
+	("close", "Close"),
+	("no_save", "No save file has been set."),
+	("save_not_found", "Save file not found."),
+	("auto_off", "Auto-backup: OFF"),
+	(
+		"quit.confirm",
+		"Automatic backups are still running. Quit anyway?",
+	),
+	("quit.title", "Quitting"),
+	("quit.now", "Quit now"),
+	("quit.cancelling", "Cancelling {} running jobs..."),
+	(
+		"quit.waiting",
+		"Waiting for {} running jobs to finish, cancelling them in {} seconds...",
+	),
+	("column.save", "Save"),
+	("column.backups", "Backups"),
+	("column.size", "Size"),
+	("audit.title", "Audit of {}"),
+	(
+		"audit.started",
+		"Recording every write of {} without backing it up. Play as usual, then choose this option again to stop and see what was recorded.",
+	),
+	("lost.title", "Lost saves found"),
+	(
+		"lost.no_locations",
+		"No locations to search, set them with search_locations in the config.",
+	),
+	("lost.none_found", "No lost saves were found."),
+	("lost.unknown_date", "unknown date"),
+	("lost.confirm_import", "Import {} as a backup of {}?"),
+	(
+		"lost.imported",
+		"Imported as backup number {} of {}. Make it the working save to restore it.",
+	),
+	("abandoned.unplayed", "Unplayed"),
+	("abandoned.row", "{} {} weeks  {} backups  {}"),
+	(
+		"abandoned.all_played",
+		"Every campaign was played in the last {} weeks.",
+	),
+	(
+		"abandoned.intro",
+		"Not played for {} weeks or more. Choose one to archive its backups to {}.",
+	),
+	(
+		"abandoned.confirm_archive",
+		"Move every backup of {} into an archive in {}? Undo brings them back until the program quits, and after that they can be restored or imported from the archive.",
+	),
+	("abandoned.archiving", "Archiving backups"),
+	("abandoned.archived", "Archived {} backups to {}."),
+	(
+		"abandoned.notice",
+		"Not played for {} weeks or more: {}. Their backups can be archived to cold storage to keep the backup folder to the campaigns still played.",
+	),
+	("abandoned.review", "Review"),
+];
+
+/// The translation picked at startup, empty for English.
+static TRANSLATION: OnceLock<HashMap<String, String>> = OnceLock::new();
+
+/// How many `{}` a text has to fill in.
+fn placeholders(text: &str) -> usize {
+	text.matches("{}").count()
+}
+
+/// Loads the translation named by the `language` setting, if any. Texts for unknown keys, or
+/// filling in a different number of values than the English, are left out with a warning.
+pub fn load(config: &Ini) {
+	let language = match config.general_section().get("language").map(str::trim) {
+		Some(language) if !language.is_empty() && !language.eq_ignore_ascii_case("en") => language,
+		_ => {
+			let _ = TRANSLATION.set(HashMap::new());
+			return;
+		}
+	};
+
+	let path = settings::config_path()
+		.parent()
+		.unwrap_or_else(|| settings::config_path())
+		.join(TRANSLATIONS_FOLDER)
+		.join(format!("{}.ini", language));
+	let file = match Ini::load_from_file(&path) {
+		Ok(file) => file,
+		Err(e) => {
+			warn!("Could not load the translation {}: {}", path.display(), e);
+			let _ = TRANSLATION.set(HashMap::new());
+			return;
+		}
+	};
+
+	let mut translation = HashMap::new();
+	for (key, text) in file.general_section().iter() {
+		match ENGLISH.iter().find(|(english_key, _)| *english_key == key) {
+			Some((_, english)) if placeholders(english) == placeholders(text) => {
+				translation.insert(key.to_string(), text.to_string());
+			}
+			Some(_) => warn!(
+				"{}: the text for {} does not fill in as many values as the English, left out",
+				path.display(),
+				key
+			),
+			None => warn!("{}: {} is not a text of the program", path.display(), key),
+		}
+	}
+	info!(
+		"Loaded the {} translation, {} of {} texts translated",
+		language,
+		translation.len(),
+		ENGLISH.len()
+	);
+	let _ = TRANSLATION.set(translation);
+}
+
+/// The text for a key in the language picked, falling back to English.
+pub fn text(key: &'static str) -> &'static str {
+	TRANSLATION
+		.get()
+		.and_then(|translation| translation.get(key))
+		.map(String::as_str)
+		.or_else(|| {
+			ENGLISH
+				.iter()
+				.find(|(english_key, _)| *english_key == key)
+				.map(|(_, english)| *english)
+		})
+		.unwrap_or(key)
+}
+
+/// The text for a key with each `{}` filled in with the next of `values`.
+pub fn fill(key: &'static str, values: &[&dyn Display]) -> String {
+	let mut parts = text(key).split("{}");
+	let mut filled = parts.next().unwrap_or_default().to_string();
+	for (part, value) in parts.zip(values) {
+		filled.push_str(&value.to_string());
+		filled.push_str(part);
+	}
+
+	filled
+}
+
+/// The message shown for an error in a dialog.
+pub fn error(e: &dyn Display) -> String {
+	fill("error", &[e])
+}
+
+/// Every text in English as a translation file, to start a translation from.
+pub fn template() -> String {
+	let mut template = Ini::new();
+	for (key, english) in ENGLISH {
+		template.with_general_section().set(*key, *english);
+	}
+
+	let mut written = Vec::new();
+	template
+		.write_to(&mut written)
+		.expect("writing to memory cannot fail");
+	String::from_utf8_lossy(&written).into_owned()
+}
@@ -0,0 +1,288 @@
+use std::collections::{HashMap, HashSet};
+use std::fs::{self, File};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+use fs2::FileExt;
+
+use ini::Ini;
+
+/// File at the root of the backup folder holding the index.
+pub const INDEX_FILE: &str = "objects.ini";
+
+/// File next to the index that is locked while it is written, as other machines sharing the backup
+/// folder write it too.
+const LOCK_FILE: &str = ".objects.lock";
+
+/// Number of new entries after which the index is written back.
+const FLUSH_AFTER_CHANGES: usize = 20;
+
+/// Longest time new entries stay unwritten.
+const FLUSH_INTERVAL: Duration = Duration::from_secs(60);
+
+/// The content index, mapping the hash of every distinct backup to one file holding it. It is kept
+/// in memory and written back in batches, so that a backup every minute during an automatic session
+/// does not also mean rewriting the whole index every minute.
+static INDEX: Mutex<Option<Index>> = Mutex::new(None);
+
+struct Index {
+	store_root: PathBuf,
+	ini: Ini,
+	/// Entries recorded and removed since the index was last written, which are merged into what
+	/// others wrote in the meantime.
+	added: HashMap<String, String>,
+	removed: HashSet<String>,
+	/// Set when the whole index was replaced, so that it is written as it is.
+	replaced: bool,
+	pending: usize,
+	last_flush: Instant,
+}
+
+impl Index {
+	fn load(store_root: &Path) -> Self {
+		Self {
+			store_root: store_root.to_path_buf(),
+			ini: Ini::load_from_file(store_root.join(INDEX_FILE)).unwrap_or_else(|_| Ini::new()),
+			added: HashMap::new(),
+			removed: HashSet::new(),
+			replaced: false,
+			pending: 0,
+			last_flush: Instant::now(),
+		}
+	}
+
+	/// Writes the index to a temporary file, syncs it and renames it into place, so a crash never
+	/// leaves a half-written index behind. The changes made here are applied to the index as it is
+	/// on disk, keeping the entries other machines sharing the backup folder recorded meanwhile.
+	fn flush(&mut self) -> io::Result<()> {
+		if self.pending == 0 {
+			return Ok(());
+		}
+
+		// released when dropped
+		let lock = File::create(self.store_root.join(LOCK_FILE))?;
+		lock.lock_exclusive()?;
+
+		let path = self.store_root.join(INDEX_FILE);
+		if !self.replaced {
+			match Ini::load_from_file(&path) {
+				Ok(ini) => self.ini = ini,
+				Err(ini::ini::Error::Io(e)) if e.kind() == io::ErrorKind::NotFound => {
+					self.ini = Ini::new();
+				}
+				// a damaged index is replaced by what is known here
+				Err(_) => {}
+			}
+			for (hash, relative) in &self.added {
+				self.ini.with_general_section().set(hash, relative);
+			}
+			if let Some(section) = self.ini.section_mut(None::<String>) {
+				for hash in &self.removed {
+					section.remove(hash);
+				}
+			}
+		}
+
+		let temporary = path.with_extension("ini.tmp");
+		let mut file = File::create(&temporary)?;
+		self.ini.write_to(&mut file)?;
+		file.sync_all()?;
+		fs::rename(&temporary, &path)?;
+
+		self.added.clear();
+		self.removed.clear();
+		self.replaced = false;
+		self.pending = 0;
+		self.last_flush = Instant::now();
+
+		Ok(())
+	}
+}
+
+/// The index of a backup folder, locked for as long as this is held, so that another backup of the
+/// same content cannot miss it between looking its hash up and recording it.
+pub struct Locked {
+	guard: MutexGuard<'static, Option<Index>>,
+	store_root: PathBuf,
+}
+
+impl Locked {
+	fn index(&mut self) -> &mut Index {
+		self.guard.as_mut().expect("index loaded when locked")
+	}
+
+	/// Finds a stored file with the given content hash.
+	pub fn lookup(&mut self, hash: &str) -> Option<PathBuf> {
+		let store_root = self.store_root.clone();
+		self.index()
+			.ini
+			.section(None::<String>)
+			.and_then(|section| section.get(hash))
+			.map(|relative| store_root.join(relative))
+	}
+
+	/// Records a stored file under its content hash. The index is written back once enough
+	/// changes have built up or enough time has passed.
+	pub fn record(&mut self, hash: &str, file: &Path) -> io::Result<()> {
+		let relative = match relative_path(file, &self.store_root) {
+			Some(relative) => relative,
+			None => return Ok(()),
+		};
+		let index = self.index();
+		index.ini.with_general_section().set(hash, &relative);
+		index.removed.remove(hash);
+		index.added.insert(hash.to_string(), relative);
+		index.pending += 1;
+
+		if index.pending >= FLUSH_AFTER_CHANGES || index.last_flush.elapsed() >= FLUSH_INTERVAL {
+			index.flush()?;
+		}
+
+		Ok(())
+	}
+}
+
+/// Locks the index of the given backup folder, loading it first if needed.
+pub fn lock(store_root: &Path) -> io::Result<Locked> {
+	let mut guard = INDEX.lock().unwrap();
+
+	if guard
+		.as_ref()
+		.is_none_or(|index| index.store_root != store_root)
+	{
+		// a backup folder deleted since has nothing left to write the index to
+		if let Some(index) = guard.as_mut().filter(|index| index.store_root.is_dir()) {
+			index.flush()?;
+		}
+		*guard = Some(Index::load(store_root));
+	}
+
+	Ok(Locked {
+		guard,
+		store_root: store_root.to_path_buf(),
+	})
+}
+
+/// Runs `f` on the index of the given backup folder, loading it first if needed.
+fn with_index<T>(store_root: &Path, f: impl FnOnce(&mut Index) -> io::Result<T>) -> io::Result<T> {
+	f(lock(store_root)?.index())
+}
+
+/// Loads the index of a backup folder ahead of its first use, so that `store_root` finds it.
+pub fn open(store_root: &Path) -> io::Result<()> {
+	with_index(store_root, |_| Ok(()))
+}
+
+/// Backup folder whose index covers the backups in `backup_dir`.
+///
+/// That is usually its parent, but saves in folders of the save directory keep their backups
+/// further down, so the backup folder opened with `open`, or else the topmost folder above with an
+/// index, is preferred.
+pub fn store_root(backup_dir: &Path) -> Option<PathBuf> {
+	let parent = backup_dir.parent()?;
+	let opened = INDEX
+		.lock()
+		.unwrap()
+		.as_ref()
+		.map(|index| index.store_root.clone())
+		.filter(|store_root| parent.starts_with(store_root));
+
+	Some(opened.unwrap_or_else(|| {
+		parent
+			.ancestors()
+			.filter(|folder| folder.join(INDEX_FILE).is_file())
+			.last()
+			.unwrap_or(parent)
+			.to_path_buf()
+	}))
+}
+
+/// Finds a stored file with the given content hash.
+pub fn lookup(store_root: &Path, hash: &str) -> io::Result<Option<PathBuf>> {
+	Ok(lock(store_root)?.lookup(hash))
+}
+
+/// Records a stored file under its content hash. The index is written back once enough changes
+/// have built up or enough time has passed.
+pub fn record(store_root: &Path, hash: &str, file: &Path) -> io::Result<()> {
+	lock(store_root)?.record(hash, file)
+}
+
+/// Lists every entry as a content hash and the file holding it.
+pub fn entries(store_root: &Path) -> io::Result<Vec<(String, PathBuf)>> {
+	with_index(store_root, |index| {
+		Ok(index
+			.ini
+			.section(None::<String>)
+			.map(|section| {
+				section
+					.iter()
+					.map(|(hash, relative)| (hash.to_string(), store_root.join(relative)))
+					.collect()
+			})
+			.unwrap_or_default())
+	})
+}
+
+/// Replaces the whole index, writing it out immediately.
+pub fn replace(
+	store_root: &Path,
+	entries: impl IntoIterator<Item = (String, PathBuf)>,
+) -> io::Result<()> {
+	with_index(store_root, |index| {
+		index.ini = Ini::new();
+		for (hash, file) in entries {
+			if let Some(relative) = relative_path(&file, store_root) {
+				index.ini.with_general_section().set(hash, relative);
+			}
+		}
+		index.replaced = true;
+		index.pending += 1;
+
+		index.flush()
+	})
+}
+
+/// Removes entries by hash, writing the index out immediately.
+pub fn remove(store_root: &Path, hashes: &[String]) -> io::Result<()> {
+	with_index(store_root, |index| {
+		if let Some(section) = index.ini.section_mut(None::<String>) {
+			for hash in hashes {
+				section.remove(hash);
+			}
+		}
+		for hash in hashes {
+			index.added.remove(hash);
+			index.removed.insert(hash.clone());
+		}
+		index.pending += 1;
+
+		index.flush()
+	})
+}
+
+/// Drops the index of a backup folder held in memory, along with entries not yet written, so that
+/// it is read again from disk, such as after it was put back from a snapshot.
+pub fn reload(store_root: &Path) {
+	let mut guard = INDEX.lock().unwrap();
+	if guard
+		.as_ref()
+		.is_some_and(|index| index.store_root == store_root)
+	{
+		*guard = Some(Index::load(store_root));
+	}
+}
+
+/// Writes out any entries still waiting for the next batch.
+pub fn flush() -> io::Result<()> {
+	INDEX.lock().unwrap().as_mut().map_or(Ok(()), Index::flush)
+}
+
+/// Path of a file as recorded in the index, relative to the backup folder.
+fn relative_path(file: &Path, store_root: &Path) -> Option<String> {
+	let relative = file.strip_prefix(store_root).ok()?;
+
+	Some(relative.to_string_lossy().replace('\\', "/"))
+}
@@ -0,0 +1,152 @@
+use std::env;
+use std::fs::{self, File};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use chrono::{DateTime, Local};
+
+use cursive::traits::*;
+use cursive::views::{Dialog, TextView};
+use cursive::Cursive;
+
+use ini::Ini;
+
+use crate::browser::ATTRIBUTES;
+use crate::error;
+use crate::format_size;
+use crate::i18n;
+use crate::parser;
+use crate::profiles::Profile;
+use crate::progress_dialog;
+use crate::settings::{current_save, display_name};
+
+/// Times the live save is copied again when the game writes it while it is being copied.
+const SNAPSHOT_ATTEMPTS: usize = 3;
+
+/// A private copy of the live save in the temporary folder, removed once dropped. It is never added
+/// to the backups.
+struct Snapshot {
+	path: PathBuf,
+	modified: SystemTime,
+	size: u64,
+}
+
+impl Drop for Snapshot {
+	fn drop(&mut self) {
+		let _ = fs::remove_file(&self.path);
+	}
+}
+
+/// When the save was written and how large it is, which change whenever the game writes it.
+fn stamp(save_file: &Path) -> io::Result<(SystemTime, u64)> {
+	let metadata = fs::metadata(save_file)?;
+	Ok((metadata.modified()?, metadata.len()))
+}
+
+/// Copies the live save to the temporary folder. The save is only read, never locked, so that the
+/// game can always write it, and it is copied again when the game wrote it in the meantime.
+fn snapshot(save_file: &Path) -> io::Result<Snapshot> {
+	let extension = save_file
+		.extension()
+		.map(|extension| format!(".{}", extension.to_string_lossy()))
+		.unwrap_or_default();
+	let path = env::temp_dir().join(format!(
+		"save-manager-inspect-{}{}",
+		ulid::Ulid::new(),
+		extension
+	));
+
+	for _ in 0..SNAPSHOT_ATTEMPTS {
+		let before = stamp(save_file)?;
+		io::copy(&mut File::open(save_file)?, &mut File::create(&path)?)?;
+		let snapshot = Snapshot {
+			path: path.clone(),
+			modified: before.0,
+			size: before.1,
+		};
+		if stamp(save_file)? == before {
+			return Ok(snapshot);
+		}
+	}
+
+	Err(io::Error::new(
+		io::ErrorKind::WouldBlock,
+		"The game kept writing the save, try again in a moment.",
+	))
+}
+
+/// Describes the state of the game in a copy of the live save.
+fn report(snapshot: &Snapshot) -> io::Result<String> {
+	let header = parser::read_header(&snapshot.path);
+	let summary = parser::summarize_from(File::open(&snapshot.path)?)?;
+	let text = |value: Option<String>| value.unwrap_or_else(|| "unknown".to_string());
+	let amount = |value: Option<f64>| text(value.map(|value| format!("{:.0}", value)));
+
+	let mut report = format!(
+		"Written: {}\nSize: {}\nIn-game date: {}\nCharacter: {}\nGame version: {}\n\nWealth: {}\nPrestige: {}\nPiety: {}\n",
+		DateTime::<Local>::from(snapshot.modified).format("%Y-%m-%d %H:%M:%S"),
+		format_size(snapshot.size),
+		text(header.date.map(|date| date.to_string())),
+		text(header.player()),
+		text(header.version),
+		amount(summary.wealth),
+		amount(summary.prestige),
+		amount(summary.piety),
+	);
+	for (index, attribute) in ATTRIBUTES.iter().enumerate() {
+		report.push_str(&format!(
+			"{}: {}\n",
+			attribute,
+			text(
+				summary
+					.attributes
+					.map(|attributes| attributes[index].to_string())
+			)
+		));
+	}
+	report.push_str(&format!(
+		"Titles held: {}\nWars: {}",
+		summary.titles, summary.wars
+	));
+
+	Ok(report)
+}
+
+/// Shows the state of the game in the live save, read from a private copy so that the game's own
+/// handle on the save is never in the way. The copy is removed afterwards and no backup is taken.
+pub fn live_save(s: &mut Cursive, profile: &Profile) -> error::Result<()> {
+	let config: &mut Ini = s
+		.user_data()
+		.expect("User data not set up correctly on program start");
+	let save = current_save(config)
+		.ok_or("No save file has been set.")?
+		.to_string();
+	let label = display_name(config, &save);
+	let save_file = profile.save_file(&save);
+	if !save_file.is_file() {
+		return Err("Save file not found.".into());
+	}
+
+	progress_dialog::run_in_background(
+		s,
+		"Reading the save",
+		move || {
+			snapshot(&save_file)
+				.and_then(|snapshot| report(&snapshot))
+				.map_err(|e| e.to_string())
+		},
+		move |s, report| match report {
+			Ok(report) => s.add_layer(
+				Dialog::around(TextView::new(report).scrollable())
+					.title(format!("Current save: {}", label))
+					.button("Ok", |s| {
+						s.pop_layer();
+					}),
+			),
+			Err(e) => s.add_layer(Dialog::info(i18n::error(&e))),
+		},
+	);
+
+	Ok(())
+}
@@ -0,0 +1,133 @@
+use std::io;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Condvar, Mutex};
+use std::time::{Duration, Instant};
+
+use log::info;
+
+/// Most jobs allowed to run at once, or zero for no limit.
+static LIMIT: AtomicUsize = AtomicUsize::new(0);
+
+/// Number of jobs running right now.
+static RUNNING: Mutex<usize> = Mutex::new(0);
+
+/// Signalled whenever a job finishes.
+static FINISHED: Condvar = Condvar::new();
+
+/// Set when quitting cannot wait any longer, telling jobs that can stop safely to do so.
+static CANCELLED: AtomicBool = AtomicBool::new(false);
+
+/// Sets how many jobs that copy, compress or hash saves may run at once, set with the `max_jobs`
+/// key.
+///
+/// One forces everything through the disk in turn, which suits spinning disks, while zero lets
+/// every save be handled in parallel.
+pub fn set_limit(limit: usize) {
+	LIMIT.store(limit, Ordering::Relaxed);
+}
+
+/// A place in the job queue, given back when dropped.
+pub struct Slot(());
+
+impl Drop for Slot {
+	fn drop(&mut self) {
+		*RUNNING.lock().unwrap() -= 1;
+		FINISHED.notify_all();
+	}
+}
+
+/// Waits until fewer jobs than the limit are running, then holds a place until the returned slot
+/// is dropped. Jobs must not wait for a slot while holding one, or they could wait forever.
+pub fn acquire(job: &str) -> Slot {
+	let mut running = RUNNING.lock().unwrap();
+	let limit = LIMIT.load(Ordering::Relaxed);
+
+	if limit > 0 && *running >= limit {
+		info!("{} waits for {} running jobs to finish", job, *running);
+		while *running >= limit {
+			running = FINISHED.wait(running).unwrap();
+		}
+	}
+	*running += 1;
+	drop(running);
+
+	Slot(())
+}
+
+/// Points in the job queue at which follow-up work, such as deleting the backups the retention
+/// policy no longer keeps, may run.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Moment {
+	/// Right after a backup, where the work holds up the next backup of the save.
+	AfterBackup,
+	/// During a scheduled maintenance task.
+	Maintenance,
+	/// When asked for from the menu or the command line.
+	Manual,
+}
+
+/// Longest follow-up work may run for right after a backup, so that a save written again soon
+/// after is not kept waiting. What is left over is done after the next backup.
+pub const AFTER_BACKUP_BUDGET: Duration = Duration::from_secs(2);
+
+/// Decides at which moments a kind of follow-up work runs.
+pub trait Hook {
+	fn runs_at(&self, moment: Moment) -> bool;
+}
+
+/// Runs follow-up work with a place in the queue if its hook lets it run at `moment`, returning
+/// what it returned, or `None` when it does not run then.
+///
+/// Work run right after a backup is given the time to stop by.
+pub fn follow_up<H, T, F>(job: &str, hook: &H, moment: Moment, work: F) -> Option<io::Result<T>>
+where
+	H: Hook,
+	F: FnOnce(Option<Instant>) -> io::Result<T>,
+{
+	if !hook.runs_at(moment) {
+		return None;
+	}
+
+	let _slot = acquire(job);
+	let deadline = (moment == Moment::AfterBackup).then(|| Instant::now() + AFTER_BACKUP_BUDGET);
+	Some(work(deadline))
+}
+
+/// Number of jobs running right now.
+pub fn running() -> usize {
+	*RUNNING.lock().unwrap()
+}
+
+/// Waits until no jobs are running, for at most `timeout`. Returns whether they all finished.
+pub fn wait_idle(timeout: Duration) -> bool {
+	let deadline = Instant::now() + timeout;
+	let mut running = RUNNING.lock().unwrap();
+
+	while *running > 0 {
+		let left = deadline.saturating_duration_since(Instant::now());
+		if left.is_zero() {
+			return false;
+		}
+		running = FINISHED.wait_timeout(running, left).unwrap().0;
+	}
+	drop(running);
+
+	true
+}
+
+/// Asks running jobs to stop at the next point where stopping leaves nothing half done, such as a
+/// backup that can be thrown away.
+///
+/// Restores are always finished, so the live save is never left half written.
+pub fn cancel() {
+	CANCELLED.store(true, Ordering::Relaxed);
+}
+
+/// Fails once jobs were asked to stop, for checking between chunks of work.
+pub fn check_cancelled() -> io::Result<()> {
+	if CANCELLED.load(Ordering::Relaxed) {
+		Err(io::Error::other("Cancelled while quitting"))
+	} else {
+		Ok(())
+	}
+}
@@ -0,0 +1,114 @@
+//! Backs up and restores game saves, keeping every backup of a save in a folder of its own inside
+//! the backup folder, next to a content index that stores identical saves once.
+//!
+//! The `save-manager` program is a menu and command line around this library. Other programs, such
+//! as game launchers, can take and restore backups through `BackupStore` and back up saves as the
+//! game writes them with `Watcher`, without the menu:
+//!
+//! ```no_run
+//! use std::path::Path;
+//!
+//! use save_manager::manifest::Origin;
+//! use save_manager::BackupStore;
+//!
+//! # fn main() -> save_manager::error::Result<()> {
+//! let save = Path::new("saves/Ironman.ck2");
+//! let store = BackupStore::open("saves/save-manager/Ironman")?;
+//! let created = store.back_up(save, "before the crusade", Origin::Cli)?;
+//! let backup = store.find(&created.number.to_string())?;
+//! store.restore(&backup, save)?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Leave out the default `tui` feature to depend on the library without the menu.
+#![warn(
+	clippy::all,
+	clippy::nursery,
+	clippy::cargo,
+	clippy::redundant_closure_for_method_calls
+)]
+#![allow(clippy::multiple_crate_versions)]
+
+pub mod abandoned;
+pub mod alerts;
+pub mod archive;
+pub mod audit;
+pub mod autotag;
+pub mod clock;
+pub mod error;
+pub mod game;
+pub mod index;
+pub mod jobs;
+pub mod lost;
+pub mod manifest;
+pub mod parser;
+pub mod profiles;
+pub mod progress;
+pub mod protection;
+pub mod retention;
+pub mod schedule;
+pub mod settings;
+pub mod share;
+pub mod snapshot;
+pub mod store;
+pub mod sync;
+pub mod timing;
+pub mod titles;
+pub mod trend;
+pub mod undo;
+pub mod watch;
+
+use std::fs::OpenOptions;
+use std::io::{self, Write};
+use std::path::Path;
+use std::time::Duration;
+
+use chrono::Local;
+
+pub use store::{Backup, BackupStore};
+pub use watch::Watcher;
+
+/// Journal kept in the backup folder, recording sessions and how long operations took.
+pub const JOURNAL_FILE: &str = "journal.txt";
+
+/// Appends a timestamped line to the journal kept in the backup folder.
+pub fn write_journal(backup_path: &Path, entry: &str) -> io::Result<()> {
+	let mut journal = OpenOptions::new()
+		.create(true)
+		.append(true)
+		.open(backup_path.join(JOURNAL_FILE))?;
+
+	writeln!(
+		journal,
+		"{}\t{}",
+		Local::now().format("%Y-%m-%d %H:%M:%S"),
+		entry
+	)
+}
+
+/// Writes a duration in hours, minutes and seconds, such as `1h 02m 03s`, leaving out anything
+/// shorter than a second.
+pub fn format_duration(duration: Duration) -> String {
+	let secs = duration.as_secs();
+	format!("{}h {:02}m {:02}s", secs / 3600, secs / 60 % 60, secs % 60)
+}
+
+/// Writes a number of bytes in the largest unit up to gigabytes that keeps it at one or more, such
+/// as `1.5 MB`, with one decimal for anything larger than bytes.
+pub fn format_size(bytes: u64) -> String {
+	const UNITS: [&str; 4] = ["B", "KB", "MB", "GB"];
+
+	let mut size = bytes as f64;
+	let mut unit = 0;
+	while size >= 1024.0 && unit < UNITS.len() - 1 {
+		size /= 1024.0;
+		unit += 1;
+	}
+
+	if unit == 0 {
+		format!("{} {}", bytes, UNITS[0])
+	} else {
+		format!("{:.1} {}", size, UNITS[unit])
+	}
+}
@@ -0,0 +1,145 @@
+use std::fmt::Write as _;
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::sync::{Mutex, OnceLock};
+
+use chrono::Local;
+
+use ini::Ini;
+
+use log::kv::{self, Key, Value, VisitSource};
+use log::{warn, LevelFilter, Log, Metadata, Record};
+
+use serde_json::{Map, Value as Json};
+
+/// Where log messages are shown while the program runs.
+#[derive(Clone, Copy)]
+pub enum Output {
+	/// The log panel of the interface.
+	Interface,
+	/// The terminal, when running a command without the interface.
+	Console,
+}
+
+/// How lines are written to the log file, set with the `log_format` key.
+#[derive(Clone, Copy)]
+enum Format {
+	/// A timestamp and level followed by the message and its fields.
+	Text,
+	/// One JSON object per line, for shipping to journald, ELK and the like.
+	Json,
+}
+
+/// Shows log messages and, when `log_file` is set, also appends them to that file.
+struct Logger {
+	output: Output,
+	file: Option<(Mutex<File>, Format)>,
+}
+
+static LOGGER: OnceLock<Logger> = OnceLock::new();
+
+impl Log for Logger {
+	fn enabled(&self, _: &Metadata) -> bool {
+		true
+	}
+
+	fn log(&self, record: &Record) {
+		match self.output {
+			Output::Interface => cursive::logger::log(record),
+			Output::Console => eprintln!("{}", record.args()),
+		}
+
+		if let Some((file, format)) = &self.file {
+			let line = match format {
+				Format::Text => text_line(record),
+				Format::Json => json_line(record),
+			};
+			// there is nowhere left to report a failure to write the log
+			let _ = writeln!(file.lock().unwrap(), "{}", line);
+		}
+	}
+
+	fn flush(&self) {
+		if let Some((file, _)) = &self.file {
+			let _ = file.lock().unwrap().flush();
+		}
+	}
+}
+
+/// Sets up logging for the rest of the program. Messages go to `output`, and to the file named by
+/// the `log_file` key in the format named by `log_format` (`text` or `json`).
+pub fn init(config: &Ini, output: Output) {
+	let general = config.general_section();
+	let format = match general.get("log_format") {
+		Some("json") => Format::Json,
+		_ => Format::Text,
+	};
+	let path = general
+		.get("log_file")
+		.map(str::trim)
+		.filter(|path| !path.is_empty());
+	let (file, open_error) =
+		match path.map(|path| OpenOptions::new().create(true).append(true).open(path)) {
+			Some(Ok(file)) => (Some((Mutex::new(file), format)), None),
+			Some(Err(e)) => (None, Some(e)),
+			None => (None, None),
+		};
+
+	let logger = LOGGER.get_or_init(|| Logger { output, file });
+	if log::set_logger(logger).is_ok() {
+		log::set_max_level(LevelFilter::Info);
+	}
+
+	if let (Some(path), Some(e)) = (path, open_error) {
+		warn!("Could not open the log file {}: {}", path, e);
+	}
+}
+
+fn text_line(record: &Record) -> String {
+	let mut line = format!(
+		"{} {:<5} {}",
+		Local::now().format("%Y-%m-%d %H:%M:%S"),
+		record.level(),
+		record.args()
+	);
+	for (key, value) in fields(record) {
+		let _ = write!(line, " {}={}", key, value);
+	}
+
+	line
+}
+
+fn json_line(record: &Record) -> String {
+	let mut object = Map::new();
+	object.insert("time".to_string(), Local::now().to_rfc3339().into());
+	object.insert("level".to_string(), record.level().as_str().into());
+	object.insert("event".to_string(), "message".into());
+	object.insert("message".to_string(), record.args().to_string().into());
+	object.extend(fields(record));
+
+	Json::Object(object).to_string()
+}
+
+/// The structured fields attached to a log message, such as `event`, `save` and `backup`.
+fn fields(record: &Record) -> Map<String, Json> {
+	struct Fields(Map<String, Json>);
+
+	impl<'kvs> VisitSource<'kvs> for Fields {
+		fn visit_pair(&mut self, key: Key<'kvs>, value: Value<'kvs>) -> Result<(), kv::Error> {
+			let value = value
+				.to_bool()
+				.map(Json::from)
+				.or_else(|| value.to_u64().map(Json::from))
+				.or_else(|| value.to_i64().map(Json::from))
+				.or_else(|| value.to_f64().map(Json::from))
+				.unwrap_or_else(|| value.to_string().into());
+			self.0.insert(key.to_string(), value);
+			Ok(())
+		}
+	}
+
+	let mut fields = Fields(Map::new());
+	let _ = record.key_values().visit(&mut fields);
+
+	fields.0
+}
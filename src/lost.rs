@@ -0,0 +1,154 @@
+use std::cmp::Reverse;
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Local};
+
+use ini::Ini;
+
+use log::info;
+
+use crate::error::{self, Context};
+use crate::manifest::Origin;
+use crate::parser;
+use crate::profiles::Profile;
+use crate::progress::Progress;
+use crate::settings::backup_options;
+use crate::store::{backup_core, Created};
+use crate::titles;
+
+/// How many bytes of a file are read to tell whether it is a save.
+const SIGNATURE_LENGTH: usize = 16;
+
+/// A file found outside the save directory that looks like a save of the game.
+pub struct Found {
+	pub path: PathBuf,
+	pub size: u64,
+	pub modified: Option<DateTime<Local>>,
+	/// The in-game date and character from its header, when it can be read.
+	pub description: Option<String>,
+}
+
+/// Whether a folder is where the system keeps deleted files, which are not searched: saves in it
+/// can already be put back from there.
+fn is_recycle_bin(name: &str) -> bool {
+	let name = name.to_ascii_lowercase();
+	name.starts_with("$recycle")
+		|| name == "recycler"
+		|| name.trim_start_matches('.') == "trash"
+		|| name.starts_with(".trash-")
+}
+
+/// Whether a file looks like a save of the game, by its extension and the way it starts. Saves of
+/// games that are not known are checked for a date in their header instead.
+fn is_save(path: &Path, profile: &Profile) -> bool {
+	let extension = match path.extension() {
+		Some(extension) => format!(".{}", extension.to_string_lossy()),
+		None => return false,
+	};
+	if !extension.eq_ignore_ascii_case(&profile.extension) {
+		return false;
+	}
+
+	titles::by_extension(&profile.extension).map_or_else(
+		|| parser::read_header(path).date.is_some(),
+		|title| {
+			let mut start = [0; SIGNATURE_LENGTH];
+			let read = File::open(path).and_then(|mut file| file.read(&mut start));
+			read.is_ok_and(|read| title.has_signature(&start[..read]))
+		},
+	)
+}
+
+/// Looks through `locations` and the folders inside them for saves of the game, leaving out the
+/// save directory, the backup folder and recycle bins. Folders that cannot be read are skipped.
+pub fn scan(locations: &[PathBuf], profile: &Profile) -> io::Result<Vec<Found>> {
+	let mut skipped = vec![profile.save_path.clone(), profile.backup_path.clone()];
+	skipped.extend(profile.cloud_path.clone());
+	let skipped = skipped
+		.iter()
+		.map(|path| fs::canonicalize(path).unwrap_or_else(|_| path.clone()))
+		.collect::<Vec<_>>();
+
+	let mut progress = Progress::new("Looking for lost saves", 0);
+	let mut found = Vec::new();
+	let mut folders = locations.to_vec();
+	while let Some(folder) = folders.pop() {
+		progress.check_cancelled()?;
+		if skipped.iter().any(|skipped| folder.starts_with(skipped)) {
+			continue;
+		}
+		let entries = match fs::read_dir(&folder) {
+			Ok(entries) => entries,
+			Err(_) => continue,
+		};
+
+		for entry in entries.filter_map(Result::ok) {
+			// links are not followed, so that a link to a parent folder is not searched forever
+			let file_type = match entry.file_type() {
+				Ok(file_type) => file_type,
+				Err(_) => continue,
+			};
+			let path = entry.path();
+			if file_type.is_dir() {
+				if !is_recycle_bin(&entry.file_name().to_string_lossy()) {
+					folders.push(path);
+				}
+			} else if file_type.is_file() && is_save(&path, profile) {
+				let metadata = match entry.metadata() {
+					Ok(metadata) => metadata,
+					Err(_) => continue,
+				};
+				progress.advance(metadata.len());
+				found.push(Found {
+					size: metadata.len(),
+					modified: metadata.modified().ok().map(DateTime::from),
+					description: parser::read_header(&path).describe(),
+					path,
+				});
+			}
+		}
+	}
+
+	found.sort_by_key(|found| Reverse(found.modified));
+	Ok(found)
+}
+
+/// Name of the save a file found outside the save directory is taken for: its file name without
+/// the game's extension, so that its backups join those of a save of the same name.
+pub fn save_name(profile: &Profile, path: &Path) -> Option<String> {
+	let name = path.file_name()?.to_str()?;
+	let stem = name.len().checked_sub(profile.extension.len())?;
+	(name.is_char_boundary(stem) && name[stem..].eq_ignore_ascii_case(&profile.extension))
+		.then(|| name[..stem].to_string())
+		.filter(|save| !save.is_empty())
+}
+
+/// Stores a save found outside the save directory as a backup of the save it is named after,
+/// noted with its file name. Returns the save and the backup.
+pub fn import(config: &Ini, profile: &Profile, path: &Path) -> error::Result<(String, Created)> {
+	let save = save_name(profile, path).ok_or("The file is not named like a save of the game.")?;
+	let backup_dir = profile.backup_path.join(&save);
+	let note = path
+		.file_stem()
+		.map(|stem| stem.to_string_lossy().into_owned())
+		.unwrap_or_default();
+
+	fs::create_dir_all(&backup_dir).context("create", &backup_dir)?;
+	let created = backup_core(
+		path,
+		&backup_dir,
+		&note,
+		Origin::Imported,
+		&backup_options(config, &save),
+	)?;
+	info!(
+		"Imported {} as backup number {} of {}",
+		path.display(),
+		created.number,
+		save
+	);
+
+	Ok((save, created))
+}
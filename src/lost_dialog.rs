@@ -0,0 +1,104 @@
+use std::path::PathBuf;
+
+use cursive::traits::*;
+use cursive::views::{Dialog, SelectView};
+use cursive::Cursive;
+
+use ini::Ini;
+
+use crate::confirm::confirm;
+use crate::error;
+use crate::i18n;
+use crate::lost::{self, Found};
+use crate::profiles::Profile;
+use crate::progress_dialog;
+use crate::settings::search_locations;
+
+/// Searches the configured locations for copies of saves outside the save directory, such as
+/// ones moved away or left in a download folder, and lists them for importing as backups. Meant
+/// for when a campaign was deleted and no backup of it was taken.
+pub fn find(s: &mut Cursive, profile: &Profile) -> error::Result<()> {
+	let config: &mut Ini = s
+		.user_data()
+		.expect("User data not set up correctly on program start");
+	let locations = search_locations(config);
+	if locations.is_empty() {
+		return Err(i18n::text("lost.no_locations").into());
+	}
+
+	let profile = profile.clone();
+	progress_dialog::run_in_background(
+		s,
+		i18n::text("menu.find_lost_saves"),
+		{
+			let profile = profile.clone();
+			move || lost::scan(&locations, &profile).map_err(|e| e.to_string())
+		},
+		move |s, found| match found {
+			Ok(found) if found.is_empty() => {
+				s.add_layer(Dialog::info(i18n::text("lost.none_found")));
+			}
+			Ok(found) => list(s, found, profile),
+			Err(e) => s.add_layer(Dialog::info(i18n::error(&e))),
+		},
+	);
+
+	Ok(())
+}
+
+/// Lists the saves found, newest first, importing the one picked as a backup of the save with its
+/// name.
+fn list(s: &mut Cursive, found: Vec<Found>, profile: Profile) {
+	let mut select = SelectView::<PathBuf>::new();
+	for candidate in found {
+		let modified = candidate.modified.map_or_else(
+			|| i18n::text("lost.unknown_date").to_string(),
+			|modified| modified.format("%Y-%m-%d %H:%M").to_string(),
+		);
+		let mut label = format!(
+			"{}  {:>9}  {}",
+			modified,
+			crate::format_size(candidate.size),
+			candidate.path.display()
+		);
+		if let Some(description) = candidate.description {
+			label.push_str(&format!(" ({})", description));
+		}
+		select.add_item(label, candidate.path);
+	}
+
+	select.set_on_submit(move |s, path: &PathBuf| {
+		let save = match lost::save_name(&profile, path) {
+			Some(save) => save,
+			None => return,
+		};
+		let path = path.clone();
+		let profile = profile.clone();
+		confirm(
+			s,
+			&i18n::fill("lost.confirm_import", &[&path.display(), &save]),
+			move |s| {
+				let config: &mut Ini = s
+					.user_data()
+					.expect("User data not set up correctly on program start");
+				match lost::import(config, &profile, &path) {
+					Ok((save, created)) => {
+						s.add_layer(Dialog::info(i18n::fill(
+							"lost.imported",
+							&[&created.number, &save],
+						)));
+					}
+					Err(e) => s.add_layer(Dialog::info(i18n::error(&e))),
+				}
+			},
+		);
+	});
+
+	s.add_layer(
+		Dialog::around(select.scrollable().max_height(20))
+			.title(i18n::text("lost.title"))
+			.button(i18n::text("close"), |s| {
+				s.pop_layer();
+			}),
+	);
+}
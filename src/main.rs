@@ -4,100 +4,280 @@
 	clippy::cargo,
 	clippy::redundant_closure_for_method_calls
 )]
+#![allow(clippy::multiple_crate_versions)]
+// a console is attached at startup unless backing up in the background
+#![cfg_attr(windows, windows_subsystem = "windows")]
 
-use std::env;
-use std::error::Error;
-use std::ffi::OsStr;
+mod abandoned_dialog;
+mod annotate;
+mod archive_dialog;
+mod behind;
+mod browser;
+mod cleanup;
+mod cli;
+mod confirm;
+mod detect;
+mod discord;
+mod history;
+mod i18n;
+mod inspect;
+mod logging;
+mod lost_dialog;
+mod maintenance;
+mod menu;
+mod patch;
+mod progress_dialog;
+mod protection_dialog;
+mod rescue;
+mod retention_dialog;
+mod share_dialog;
+#[cfg(feature = "simulation")]
+mod simulate;
+mod storage;
+mod timeline;
+
+use std::cell::{Cell, RefCell};
 use std::fs;
-use std::path::Path;
-use std::sync::mpsc;
-use std::thread;
-use std::time::Duration;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
 
+use cursive::event::{Event, Key};
 use cursive::traits::*;
 use cursive::view::ScrollStrategy;
-use cursive::views::{DebugView, Dialog, EditView, LinearLayout, Panel, SelectView, TextView};
+use cursive::views::{
+	Button, DebugView, Dialog, EditView, LinearLayout, Panel, SelectView, TextView,
+};
 use cursive::Cursive;
 
+use chrono::Local;
+
+use clap::Parser;
+
+use directories::UserDirs;
+
 use ini::Ini;
 
 use log::{error, info, warn};
 
-use notify::{DebouncedEvent, RecursiveMode, Watcher};
+use save_manager::{
+	abandoned, archive, audit, clock, error, format_duration, format_size, index, jobs, lost,
+	manifest, parser, profiles, progress, protection, retention, schedule, settings, share,
+	snapshot, store, sync, timing, titles, undo, watch,
+};
+
+use audit::Audit;
+use confirm::confirm;
+use error::Context;
+use manifest::Origin;
+use menu::MenuOption;
+use profiles::{Games, Profile};
+use settings::{backup_options, save_setting};
+use snapshot::Snapshot;
+use store::{backup_core, BackupOptions, BakFiles, BAK_EXTENSION};
+use watch::{NewSaves, Session};
+
+/// Keys bound everywhere whatever the game profile, to panic restore and check the last backup.
+const RESERVED_KEYS: [Event; 2] = [Event::CtrlChar('p'), Event::CtrlChar('b')];
+
+/// Name of the main menu.
+const MENU_NAME: &str = "main_menu";
+
+/// Name of the save directory entry when adding a game.
+const SAVE_DIR_NAME: &str = "save_dir";
+
+/// Name of the path entry in the import dialog.
+const IMPORT_EDIT_NAME: &str = "import_path";
+
+/// Name of the path entry in the archive dialog.
+const ARCHIVE_EDIT_NAME: &str = "archive_path";
+
+/// Names of the entries in the export dialog.
+const EXPORT_FROM_NAME: &str = "export_from";
+const EXPORT_TO_NAME: &str = "export_to";
+const EXPORT_PATH_NAME: &str = "export_path";
+
+/// Names of the list of saves and the label entry when choosing the working save.
+const SAVE_LIST_NAME: &str = "save_list";
+const SAVE_LABEL_NAME: &str = "save_label";
+
+/// Name of the status line of automatic backups on the main screen.
+const AUTO_STATUS_NAME: &str = "auto_status";
+
+/// Name of the text in the dialog shown while quitting waits for running jobs.
+const SHUTDOWN_TEXT_NAME: &str = "shutdown_text";
 
-const BACKUP_FOLDER: &str = "save-manager";
+/// How long quitting waits for running backups before cancelling them.
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(30);
 
-const EXTENSION: &str = ".ck2";
+/// How long cancelled backups get to clean up after themselves before quitting regardless.
+const CANCEL_GRACE: Duration = Duration::from_secs(5);
 
-const OPTIONS: [&str; 7] = [
-	"Set a new working game",
-	"Make a new backup",
-	"Make a new backup (with note)",
-	"Restore a backup",
-	"Automatically take backups",
-	"Delete old backups",
-	"Quit",
-];
+thread_local! {
+	/// The automatic backup session running in the interface, if any.
+	static AUTO_SESSION: RefCell<Option<Session>> = const { RefCell::new(None) };
+
+	/// When quitting stops waiting for running jobs, once quitting has started.
+	static SHUTDOWN_DEADLINE: Cell<Option<Instant>> = const { Cell::new(None) };
+
+	/// Keys bound to the quick actions of the game profile in use.
+	static HOTKEYS: RefCell<Vec<Event>> = const { RefCell::new(Vec::new()) };
+
+	/// Watch on the save directory of the game profile in use for new saves, if any, with the game
+	/// profiles to look the one in use up in when a new save is adopted.
+	static NEW_SAVES: RefCell<Option<(NewSaves, Rc<Games>)>> = const { RefCell::new(None) };
+
+	/// The audit of the writes of a save running in the interface, if any.
+	static AUDIT: RefCell<Option<Audit>> = const { RefCell::new(None) };
+}
+
+/// Writes a config moved over to a section per save, snapshotting the old one first when there is
+/// a backup folder to keep the snapshot in.
+fn migrate_config(config: &Ini, backup_path: &Path) {
+	let operation = "moving the settings of the working save into a section of its own";
+	let write = || settings::write_config(config).map_err(|e| io::Error::other(e.to_string()));
+	let written = if backup_path.is_dir() {
+		match snapshot::maintain(backup_path, operation, write) {
+			Ok(snapshot::Maintained::Done(())) => Ok(()),
+			// the snapshot is offered to roll back to once the interface starts
+			Ok(snapshot::Maintained::Failed(_, e)) => Err(e),
+			Err(e) => {
+				warn!("Could not snapshot the config before migrating it: {}", e);
+				return;
+			}
+		}
+	} else {
+		write()
+	};
+	match written {
+		Ok(()) => info!("Moved the settings of the working save into a section of its own"),
+		Err(e) => warn!("Could not write the migrated config: {}", e),
+	}
+}
 
 fn main() {
-	let mut root = cursive::default();
-	cursive::logger::init();
+	let args = cli::Args::try_parse().unwrap_or_else(|e| {
+		// help, the version and mistakes in the arguments are printed to a console
+		cli::attach_console(None);
+		e.exit()
+	});
 
-	// get the location of the config file
-	let config_path = env::current_exe()
-		.unwrap()
-		.parent()
-		.unwrap()
-		.join("conf.ini");
+	// get the location of the config file, moving one left next to the executable to the
+	// platform's config folder
+	let migrated = settings::locate_config(args.config.clone());
+	let config_path = settings::config_path();
 
 	// get config file, and create one if it does not exist
-	root.set_user_data(match Ini::load_from_file(&config_path) {
-		Ok(config) => config,
-		Err(_) => Ini::new(),
+	let mut config = Ini::load_from_file(config_path).unwrap_or_else(|_| Ini::new());
+	// settings are read from the general section everywhere, so make sure there is one
+	config.entry(None).or_insert_with(Default::default);
+	jobs::set_limit(settings::max_jobs(&config));
+
+	// started by a double-click when set up to back up in the background
+	let menu = args.menu;
+	let command = args.command.or_else(|| {
+		(!menu && config.general_section().get("launch") == Some("background"))
+			.then_some(cli::Command::Background)
 	});
+	cli::attach_console(command.as_ref());
+
+	// scripts and launchers get plain messages on the terminal instead of the log panel
+	logging::init(
+		&config,
+		if command.is_some() {
+			logging::Output::Console
+		} else {
+			logging::Output::Interface
+		},
+	);
+	match migrated {
+		Ok(Some(old)) => info!(
+			"Moved the config file from {} to {}",
+			old.display(),
+			config_path.display()
+		),
+		Ok(None) => {}
+		Err(e) => warn!(
+			"Could not move the config file to {}: {}",
+			config_path.display(),
+			e
+		),
+	}
+	i18n::load(&config);
 
 	//
 	// set up paths
 	//
 
-	// the user may optionally specify a path directly
-	let args: Vec<String> = env::args().collect();
+	// get the location of the "save games" directory, unless the user specified it directly. A
+	// relative path is made absolute, as file watcher events always report absolute paths
+	let save_path = args.save_dir.map_or_else(
+		|| {
+			let installed = profiles::installed_save_dir();
+			match installed {
+				Ok(installed) if installed.is_dir() || !profiles::needs_builtin(&config) => {
+					installed
+				}
+				installed => find_save_dir(&mut config, command.is_none())
+					.or_else(|| installed.ok())
+					.unwrap_or_default(),
+			}
+		},
+		|save_dir| fs::canonicalize(&save_dir).unwrap_or(save_dir),
+	);
 
-	// get the location of the "save games" directory
-	let save_path = if args.len() > 1 {
-		Path::new(args[1].as_str()).to_path_buf()
-	} else {
-		env::current_exe()
-			.unwrap()
-			.parent()
-			.unwrap()
-			.parent()
-			.unwrap()
-			.parent()
-			.unwrap()
-			.join("save games")
-	};
+	// use the game profile picked last time, if any
+	let builtin = Profile::builtin(save_path);
+	let mut active = config
+		.general_section()
+		.get("game_profile")
+		.and_then(|name| Profile::load(&config, name, &builtin))
+		.unwrap_or_else(|| builtin.clone());
+
+	// each save has had a section of its own since the working save stopped being a single key
+	if settings::migrate_flat_layout(&mut config) {
+		migrate_config(&config, &active.backup_path);
+	}
+
+	// scripts and launchers get the same operations without the interface
+	if let Some(command) = command {
+		process::exit(cli::run(command, &config, active));
+	}
+
+	let mut root = cursive::default();
+	root.set_user_data(config);
 
-	if !save_path.is_dir() {
+	if !active.save_path.is_dir() {
 		root.add_layer(
 			Dialog::around(
-				TextView::new("Executable must either be located in the ../Crusader Kings II/mod/save-manager/ directory, or have a directory path as an argument.")
+				TextView::new(if active.name == builtin.name {
+					"No save directory of Crusader Kings II was found. Pass its path as an argument, or place the executable in the ../Crusader Kings II/mod/save-manager/ directory.".to_string()
+				} else {
+					format!("The save directory of {} does not exist.", active.name)
+				})
 			)
-			.button("Ok", Cursive::quit)
+			.button(i18n::text("ok"), Cursive::quit)
 		);
 	} else {
 		// create the backup directory if it does not exist
-		let backup_path = save_path.join(BACKUP_FOLDER);
-		if !backup_path.is_dir() {
-			if let Err(e) = fs::create_dir(&backup_path) {
-				root.add_layer(
-					Dialog::around(TextView::new(format!("Error occurred: {}", e.to_string())))
-						.button("Ok", Cursive::quit),
+		if let Err(e) = active.prepare() {
+			root.add_layer(
+				Dialog::around(TextView::new(i18n::error(&e)))
+					.button(i18n::text("ok"), Cursive::quit),
+			);
+		}
+
+		if active.backup_path.is_dir() {
+			let read_only = active.read_only;
+			if read_only {
+				warn!(
+					"{} is read-only, only browsing and restoring backups is available",
+					active.backup_path.display()
 				);
 			}
-		}
 
-		if backup_path.is_dir() {
 			//
 			// set up UI
 			//
@@ -108,81 +288,654 @@ fn main() {
 				.scroll_strategy(ScrollStrategy::StickToBottom);
 
 			// set up the main screen for user interaction
-			let mut main_view = SelectView::<String>::new()
-				.on_submit(move |s, option| select_option(s, option, &save_path, &backup_path))
+			let profile = active.clone();
+			let games = Rc::new(Games {
+				builtin,
+				active: RefCell::new(active),
+			});
+
+			// reachable from anywhere, as a disaster does not wait for the main menu
+			let panic_games = Rc::clone(&games);
+			root.add_global_callback(Event::CtrlChar('p'), move |s| {
+				let profile = panic_games.active.borrow().clone();
+				if let Err(e) = rescue::panic_restore(s, &profile) {
+					s.add_layer(Dialog::info(i18n::error(&e)));
+				}
+			});
+
+			// a quick look before trying something risky, wherever the interface is
+			let behind_games = Rc::clone(&games);
+			root.add_global_callback(Event::CtrlChar('b'), move |s| {
+				let profile = behind_games.active.borrow().clone();
+				if let Err(e) = behind::check(s, &profile) {
+					s.add_layer(Dialog::info(i18n::error(&e)));
+				}
+			});
+
+			bind_hotkeys(&mut root, &games);
+			let new_save_games = Rc::clone(&games);
+
+			let layout = menu::layout(
+				root.user_data::<Ini>()
+					.expect("User data not set up correctly on program start"),
+				&profile.name,
+			);
+			let mut main_view = SelectView::<MenuOption>::new()
+				.on_submit(move |s, option| select_option(s, *option, &games))
 				.autojump();
-			main_view.add_all_str(OPTIONS.to_vec());
+			fill_menu(&mut main_view, layout, read_only);
 
+			let protection_label = protection_dialog::button_label(
+				root.user_data::<Ini>()
+					.expect("User data not set up correctly on program start"),
+			);
 			root.add_fullscreen_layer(
-				LinearLayout::horizontal()
-					.child(Panel::new(main_view).full_screen())
-					.child(Panel::new(log_view).full_screen())
-					.full_screen(),
+				LinearLayout::vertical()
+					.child(
+						LinearLayout::horizontal()
+							.child(Panel::new(main_view.with_name(MENU_NAME)).full_screen())
+							.child(Panel::new(log_view).full_screen())
+							.full_screen(),
+					)
+					.child(
+						LinearLayout::horizontal()
+							.child(
+								TextView::new(i18n::text("auto_off"))
+									.with_name(AUTO_STATUS_NAME)
+									.full_width(),
+							)
+							.child(
+								Button::new_raw(protection_label, protection_dialog::pick)
+									.with_name(protection_dialog::BUTTON_NAME),
+							),
+					),
 			);
+
+			if let Err(e) = patch::check(&mut root, &profile) {
+				warn!("Could not check for a game update: {}", e);
+			}
+			watch_new_saves(&mut root, &new_save_games);
+			update_auto_status(&mut root);
+			abandoned_dialog::flag(&mut root, &profile);
+			if let Some(snapshot) = Snapshot::leftover(&profile.backup_path) {
+				maintenance::offer_rollback(
+					&mut root,
+					snapshot,
+					"The program was closed before it finished.",
+				);
+			}
 		}
 	}
 
+	// quitting while automatic backups run should be confirmed like the menu entry
+	root.clear_global_callbacks(Event::CtrlChar('c'));
+	root.add_global_callback(Event::CtrlChar('c'), quit);
+	root.add_global_callback(Event::Refresh, |s| {
+		update_auto_status(s);
+		protection_dialog::update_button(s);
+		wait_for_jobs(s);
+	});
+
 	info!("Started CK2 Save Manager");
 
 	root.run();
+
+	if let Err(e) = undo::empty_trash() {
+		eprintln!("Could not empty the trash: {}", e);
+	}
+	if let Err(e) = index::flush() {
+		eprintln!("Could not save the backup index: {}", e);
+	}
+}
+
+/// Finds the save directory of the built-in game when the executable is not installed in its mod
+/// folder: the directory picked on an earlier run, or one found in the usual places. The interface
+/// lets the user pick from the ones found and remembers the pick in conf.ini, while commands use
+/// the first one found.
+fn find_save_dir(config: &mut Ini, interactive: bool) -> Option<PathBuf> {
+	let section = profiles::section(profiles::BUILTIN_NAME);
+	if let Some(remembered) = config
+		.get_from(Some(section.as_str()), "save_dir")
+		.map(PathBuf::from)
+		.filter(|save_dir| save_dir.is_dir())
+	{
+		return Some(remembered);
+	}
+
+	let builtin = titles::find(profiles::BUILTIN_NAME).expect("the built-in game is a known title");
+	let candidates = detect::candidates(builtin);
+	if !interactive {
+		let found = candidates.into_iter().next()?;
+		info!("Using the save directory found at {}", found.display());
+		return Some(found);
+	}
+	if candidates.is_empty() {
+		return None;
+	}
+
+	// closing the picker without a pick quits, rather than complaining that nothing was found
+	let picked = detect::choose(builtin, candidates).unwrap_or_else(|| process::exit(0));
+	config
+		.with_section(Some(section))
+		.set("save_dir", picked.to_string_lossy());
+	match settings::write_config(config) {
+		Ok(()) => info!("Remembered {} as the save directory", picked.display()),
+		Err(e) => warn!("Could not remember the save directory: {}", e),
+	}
+
+	Some(picked)
+}
+
+/// Reads the key bound to a quick action on the main screen from `key` of the game profile in use,
+/// then of the general settings, falling back to `default`. Keys are written like `F5`, `Ctrl+B`,
+/// `Alt+X` or a single character, and `none` leaves the action unbound.
+fn hotkey(config: &Ini, profile: &str, key: &str, default: &str) -> Option<Event> {
+	let setting = config
+		.get_from(Some(profiles::section(profile)), key)
+		.or_else(|| config.general_section().get(key))
+		.map_or(default, str::trim);
+	if setting.eq_ignore_ascii_case("none") {
+		return None;
+	}
+
+	let single = |text: &str| {
+		let mut chars = text.chars();
+		chars.next().filter(|_| chars.next().is_none())
+	};
+	let lower = setting.to_lowercase();
+	let event = match lower.split_once('+') {
+		Some(("ctrl", rest)) => single(rest).map(Event::CtrlChar),
+		Some(("alt", rest)) => single(rest).map(Event::AltChar),
+		Some(_) => None,
+		None => match lower.strip_prefix('f').map(str::parse::<u8>) {
+			Some(Ok(number @ 1..=12)) => Some(Event::Key(Key::from_f(number))),
+			_ => single(setting).map(Event::Char),
+		},
+	};
+
+	if event.is_none() {
+		warn!("\"{}\" is not a key that {} can be set to", setting, key);
+	}
+	// panic restore and the backup check are reachable everywhere, whatever the profile
+	if event
+		.as_ref()
+		.is_some_and(|event| RESERVED_KEYS.contains(event))
+	{
+		warn!(
+			"{} cannot be set to {}, which is always bound",
+			key, setting
+		);
+		return None;
+	}
+	event
 }
 
-fn select_option(s: &mut Cursive, option: &str, save_path: &Path, backup_path: &Path) {
+/// Binds the quick actions of the game profile in use, unbinding those of the previous one. The
+/// `key_quick_backup`, `key_backup_note` and `key_restore_latest` keys bind backing up and
+/// restoring mid-game without going through the menu, and `key_<option>` binds any menu option
+/// shown, such as `key_browse = F7`.
+fn bind_hotkeys(root: &mut Cursive, games: &Rc<Games>) {
+	for event in HOTKEYS.take() {
+		root.clear_global_callbacks(event);
+	}
+
+	type Action = fn(&mut Cursive, &Profile) -> error::Result<()>;
+	let actions: [(&str, &str, bool, Action); 3] = [
+		("key_quick_backup", "F5", true, |s, profile| {
+			backup(s, profile, false)
+		}),
+		("key_backup_note", "F6", true, |s, profile| {
+			backup(s, profile, true)
+		}),
+		("key_restore_latest", "F9", false, rescue::restore_latest),
+	];
+
+	let profile = games.active.borrow().name.clone();
+	let config = root
+		.user_data::<Ini>()
+		.expect("User data not set up correctly on program start");
+	let bound_actions = actions
+		.iter()
+		.copied()
+		.filter_map(|(key, default, writes, action)| {
+			hotkey(config, &profile, key, default).map(|event| (event, writes, action))
+		})
+		.collect::<Vec<_>>();
+	// options hidden from the menu with `menu_hidden` cannot be reached through a key either
+	let bound_options = menu::layout(config, &profile)
+		.into_iter()
+		.filter_map(|option| {
+			let key = format!("key_{}", option.id());
+			hotkey(config, &profile, &key, "none").map(|event| (event, option))
+		})
+		.collect::<Vec<_>>();
+
+	let mut bound = Vec::new();
+	for (event, writes, action) in bound_actions {
+		let games = Rc::clone(games);
+		bound.push(event.clone());
+		root.add_global_callback(event, move |s| {
+			let profile = games.active.borrow().clone();
+			let result = if writes && profile.read_only {
+				Err(i18n::text("read_only").into())
+			} else {
+				action(s, &profile)
+			};
+			if let Err(e) = result {
+				s.add_layer(Dialog::info(i18n::error(&e)));
+			}
+		});
+	}
+	for (event, option) in bound_options {
+		let games = Rc::clone(games);
+		bound.push(event.clone());
+		root.add_global_callback(event, move |s| select_option(s, option, &games));
+	}
+	HOTKEYS.set(bound);
+}
+
+/// Lists the menu options in the order of the game profile, marking those that need a writable
+/// backup folder when it is read-only.
+fn fill_menu(menu: &mut SelectView<MenuOption>, layout: Vec<MenuOption>, read_only: bool) {
+	menu.clear();
+	for option in layout {
+		if read_only && option.writes() {
+			menu.add_item(i18n::fill("menu.read_only", &[&option.label()]), option);
+		} else {
+			menu.add_item(option.label(), option);
+		}
+	}
+}
+
+fn select_option(s: &mut Cursive, option: MenuOption, games: &Rc<Games>) {
+	let profile = games.active.borrow().clone();
+	let backup_path = profile.backup_path.as_path();
+
+	if profile.read_only && option.writes() {
+		s.add_layer(
+			Dialog::around(TextView::new(i18n::text("read_only"))).button(i18n::text("ok"), |s| {
+				s.pop_layer();
+			}),
+		);
+		return;
+	}
+
 	if let Err(e) = match option {
-		"Set a new working game" => set_game(s, save_path),
-		"Make a new backup" => backup(s, save_path, backup_path, false),
-		"Make a new backup (with note)" => backup(s, save_path, backup_path, true),
-		"Restore a backup" => restore(s, save_path, backup_path),
-		"Automatically take backups" => auto(s, save_path, backup_path),
-		// "Delete old backups" => delete(s, backup_path),
-		"Quit" => {
-			s.quit();
+		MenuOption::SelectProfile => select_profile(s, games),
+		MenuOption::SetGame => set_game(s, &profile),
+		MenuOption::Backup => backup(s, &profile, false),
+		MenuOption::BackupWithNote => backup(s, &profile, true),
+		MenuOption::Import => import(s, backup_path),
+		MenuOption::FindLostSaves => lost_dialog::find(s, &profile),
+		MenuOption::Browse => browse(s, &profile),
+		MenuOption::PanicRestore => rescue::panic_restore(s, &profile),
+		MenuOption::Undo => undo_last(s),
+		MenuOption::Behind => behind::check(s, &profile),
+		MenuOption::Inspect => inspect::live_save(s, &profile),
+		MenuOption::RestoreArchive => restore_archive(s, &profile),
+		MenuOption::Export => export(s, &profile),
+		MenuOption::Auto => auto(s, &profile, false),
+		MenuOption::BackupThenAuto => auto(s, &profile, true),
+		MenuOption::PauseAuto => pause_auto(s, true),
+		MenuOption::ResumeAuto => pause_auto(s, false),
+		MenuOption::StopAuto => {
+			if auto_running() {
+				stop_auto(s, |_| {});
+				Ok(())
+			} else {
+				Err(i18n::text("auto_not_running").into())
+			}
+		}
+		MenuOption::DeleteOld => delete(s, backup_path),
+		MenuOption::Storage => storage::overview(s, &profile),
+		MenuOption::Progress => history::chart(s, &profile),
+		MenuOption::Timeline => timeline::show(s, &profile),
+		MenuOption::Audit => audit(s, &profile),
+		MenuOption::Abandoned => abandoned_dialog::review(s, &profile),
+		MenuOption::RebuildIndex => maintenance::rebuild(s, backup_path),
+		MenuOption::CheckConsistency => maintenance::check_consistency(s, backup_path),
+		MenuOption::Verify => maintenance::verify_backups(s, backup_path),
+		MenuOption::SyncNow => maintenance::sync_now(s, backup_path),
+		MenuOption::ScheduledTasks => scheduled_tasks(s, &profile),
+		MenuOption::ToggleCompression => toggle_compression(s),
+		MenuOption::ToggleNotifications => toggle_notifications(s),
+		MenuOption::ToggleAutosaves => toggle_autosaves(s),
+		MenuOption::Retention => {
+			retention_dialog::settings(s, &profile);
+			Ok(())
+		}
+		MenuOption::ShareSettings => {
+			share_dialog::dialog(s, &profile);
+			Ok(())
+		}
+		MenuOption::Quit => {
+			quit(s);
 			Ok(())
 		}
-		_ => unimplemented!(),
 	} {
 		s.add_layer(
-			Dialog::around(TextView::new(format!("Error occurred: {}", e))).button("Ok", |s| {
+			Dialog::around(TextView::new(i18n::error(&e))).button(i18n::text("ok"), |s| {
 				s.pop_layer();
 			}),
 		);
 	}
 }
 
-fn set_game(s: &mut Cursive, save_path: &Path) -> Result<(), Box<dyn Error>> {
-	let save_files = fs::read_dir(save_path)?
-		.filter_map(Result::ok)
-		.filter(|file| file.path().is_file())
-		.filter_map(|file| {
-			file.path()
-				.file_stem()
-				.and_then(OsStr::to_str)
-				.map(|stem| stem.to_string())
-		});
+/// Quits, asking first if automatic backups are still running.
+fn quit(s: &mut Cursive) {
+	if auto_running() {
+		confirm(s, i18n::text("quit.confirm"), shut_down);
+	} else {
+		shut_down(s);
+	}
+}
+
+/// Stops automatic backups and quits once the backups and other jobs still running have
+/// finished, rather than cutting them off mid-copy. Backups still running after a while are
+/// cancelled and thrown away.
+fn shut_down(s: &mut Cursive) {
+	if SHUTDOWN_DEADLINE.get().is_some() {
+		return;
+	}
+	// quits once the summary of the session has been seen
+	if auto_running() {
+		stop_auto(s, shut_down);
+		return;
+	}
+	if jobs::running() == 0 {
+		s.quit();
+		return;
+	}
+
+	SHUTDOWN_DEADLINE.set(Some(Instant::now() + SHUTDOWN_TIMEOUT));
+	// the dialog is updated on every refresh
+	s.set_fps(2);
+	s.add_layer(
+		Dialog::around(TextView::new("").with_name(SHUTDOWN_TEXT_NAME))
+			.title(i18n::text("quit.title"))
+			.button(i18n::text("quit.now"), |s| {
+				SHUTDOWN_DEADLINE.set(Some(Instant::now()));
+				wait_for_jobs(s);
+			}),
+	);
+	wait_for_jobs(s);
+}
+
+/// Quits once shutting down has no more jobs to wait for, cancelling them when out of time.
+fn wait_for_jobs(s: &mut Cursive) {
+	let deadline = match SHUTDOWN_DEADLINE.get() {
+		Some(deadline) => deadline,
+		None => return,
+	};
+
+	let running = jobs::running();
+	let now = Instant::now();
+	if running == 0 || now >= deadline + CANCEL_GRACE {
+		if running > 0 {
+			warn!("Quit with {} jobs still running", running);
+		}
+		s.quit();
+		return;
+	}
+
+	let message = if now >= deadline {
+		jobs::cancel();
+		i18n::fill("quit.cancelling", &[&running])
+	} else {
+		i18n::fill(
+			"quit.waiting",
+			&[&running, &((deadline - now).as_secs() + 1)],
+		)
+	};
+	s.call_on_name(SHUTDOWN_TEXT_NAME, |view: &mut TextView| {
+		view.set_content(message)
+	});
+}
+
+/// Offers every configured game profile to switch to.
+fn select_profile(s: &mut Cursive, games: &Rc<Games>) -> error::Result<()> {
+	let names = s
+		.with_user_data(|config: &mut Ini| profiles::names(config))
+		.expect("User data not set up correctly on program start");
+	let on_pick = Rc::clone(games);
+	let on_add = Rc::clone(games);
+
+	s.add_layer(
+		Dialog::around(SelectView::<String>::new().with_all_str(names).on_submit(
+			move |s, name: &String| {
+				s.pop_layer();
+				if let Err(e) = switch_profile(s, &on_pick, name) {
+					s.add_layer(Dialog::around(TextView::new(i18n::error(&e))).button(
+						i18n::text("ok"),
+						|s| {
+							s.pop_layer();
+						},
+					));
+				}
+			},
+		))
+		.title(i18n::text("menu.select_profile"))
+		.button("Add a Paradox game", move |s| {
+			s.pop_layer();
+			add_game(s, &on_add);
+		})
+		.button(i18n::text("cancel"), |s| {
+			s.pop_layer();
+		}),
+	);
+
+	Ok(())
+}
+
+/// Offers the Paradox games the save manager knows, setting up a profile for the one picked from
+/// where its saves were found, so that nothing has to be written into conf.ini by hand.
+fn add_game(s: &mut Cursive, games: &Rc<Games>) {
+	let configured = s
+		.with_user_data(|config: &mut Ini| profiles::names(config))
+		.expect("User data not set up correctly on program start");
+
+	let mut list = SelectView::<&'static titles::Title>::new();
+	for title in &titles::TITLES {
+		let status = if configured.iter().any(|name| name == title.name) {
+			"set up".to_string()
+		} else {
+			match detect::candidates(title).len() {
+				0 => "no saves found".to_string(),
+				1 => "saves found".to_string(),
+				found => format!("saves found in {} places", found),
+			}
+		};
+		let mut format = title.format.describe().to_string();
+		if title.ironman_copies {
+			format.push_str(", _Backup copies");
+		}
+		list.add_item(
+			format!(
+				"{:<22} {:<6} {:<40} {}",
+				title.name, title.extension, format, status
+			),
+			title,
+		);
+	}
+
+	let games = Rc::clone(games);
+	s.add_layer(
+		Dialog::around(
+			list.on_submit(move |s, title: &&'static titles::Title| {
+				s.pop_layer();
+				confirm_save_dir(s, &games, title);
+			})
+			.scrollable(),
+		)
+		.title("Add a Paradox game")
+		.button(i18n::text("cancel"), |s| {
+			s.pop_layer();
+		}),
+	);
+}
+
+/// Asks where the saves of a newly picked game are, suggesting where they were found or where the
+/// game keeps them, then writes its profile and switches to it.
+fn confirm_save_dir(s: &mut Cursive, games: &Rc<Games>, title: &'static titles::Title) {
+	let suggested = detect::candidates(title)
+		.into_iter()
+		.next()
+		.or_else(|| detect::default_save_dir(title))
+		.map(|save_dir| save_dir.to_string_lossy().into_owned())
+		.unwrap_or_default();
+
+	let games = Rc::clone(games);
+	s.add_layer(
+		Dialog::around(
+			LinearLayout::vertical()
+				.child(TextView::new(format!("Save directory of {}:", title.name)))
+				.child(
+					EditView::new()
+						.content(suggested)
+						.with_name(SAVE_DIR_NAME)
+						.min_width(60),
+				),
+		)
+		.title(title.name)
+		.button(i18n::text("cancel"), |s| {
+			s.pop_layer();
+		})
+		.button("Add", move |s| {
+			let save_dir = s
+				.call_on_name(SAVE_DIR_NAME, |view: &mut EditView| view.get_content())
+				.expect("EditView not created for the save directory");
+			if !Path::new(save_dir.trim()).is_dir() {
+				s.add_layer(Dialog::info(format!(
+					"{} is not a directory, start the game once or pick another one.",
+					save_dir.trim()
+				)));
+				return;
+			}
+			let written = s
+				.with_user_data(|config: &mut Ini| {
+					let section = profiles::section(title.name);
+					config
+						.with_section(Some(section.as_str()))
+						.set("save_dir", save_dir.trim())
+						.set("extension", title.extension)
+						.set("process", title.process());
+					if title.nested {
+						config
+							.with_section(Some(section.as_str()))
+							.set("recursive", "true");
+					}
+					if let Some(cloud_dir) = detect::cloud_folder(title) {
+						config
+							.with_section(Some(section.as_str()))
+							.set("cloud_dir", cloud_dir.to_string_lossy());
+					}
+					settings::write_config(config)
+				})
+				.expect("User data not set up correctly on program start");
+
+			let result = written.and_then(|()| switch_profile(s, &games, title.name));
+			match result {
+				Ok(()) => {
+					s.pop_layer();
+				}
+				Err(e) => s.add_layer(Dialog::info(i18n::error(&e))),
+			}
+		}),
+	);
+}
+
+/// Makes another game profile the active one. Each game remembers its own working save, kept in its
+/// profile section while another game is active.
+fn switch_profile(s: &mut Cursive, games: &Rc<Games>, name: &str) -> error::Result<()> {
+	let config: &mut Ini = s
+		.user_data()
+		.expect("User data not set up correctly on program start");
+	let mut profile = Profile::load(config, name, &games.builtin)
+		.ok_or("This game profile does not set a save_dir.")?;
+	profile.prepare()?;
+
+	settings::switch_current_save(config, &games.active.borrow().name, name);
+	config.with_general_section().set("game_profile", name);
+	settings::write_config(config)?;
+
+	if profile.read_only {
+		warn!(
+			"{} is read-only, only browsing and restoring backups is available",
+			profile.backup_path.display()
+		);
+	}
+	info!("Game profile set to: {}", name);
+
+	let read_only = profile.read_only;
+	let layout = menu::layout(config, name);
+	games.active.replace(profile);
+	s.call_on_name(MENU_NAME, |menu: &mut SelectView<MenuOption>| {
+		fill_menu(menu, layout, read_only)
+	});
+	bind_hotkeys(s, games);
+	watch_new_saves(s, games);
+	update_auto_status(s);
+	abandoned_dialog::flag(s, &games.active.borrow());
+
+	Ok(())
+}
+
+fn set_game(s: &mut Cursive, profile: &Profile) -> error::Result<()> {
+	let config: &mut Ini = s
+		.user_data()
+		.expect("User data not set up correctly on program start");
+	let bak_files = BakFiles::from_setting(config.general_section().get("bak_files"));
+
+	let mut save_files = SelectView::<String>::new();
+	for path in profile.files()? {
+		// saves in folders of the save directory are shown with their path inside it
+		let file_name = match profile.relative(&path) {
+			Some(file_name) => file_name,
+			None => continue,
+		};
+
+		// the game's own backups share the save's name, so offer the save they belong to
+		if let Some(save) = file_name.strip_suffix(BAK_EXTENSION) {
+			if bak_files == BakFiles::Include {
+				if let Some(save) = save.strip_suffix(profile.extension.as_str()) {
+					save_files.add_item(format!("{} (game backup)", file_name), save.to_string());
+				}
+			}
+		} else if let Some(save) = Path::new(&file_name).with_extension("").to_str() {
+			if !profile.is_ironman_copy(save) {
+				save_files.add_item(save_item(config, save), save.to_string());
+			}
+		}
+	}
 
 	let file_selection_dialog = Dialog::around(
-		SelectView::<String>::new()
-			.with_all_str(save_files)
+		save_files
 			.on_submit(|s: &mut Cursive, save_file: &String| {
-				s.with_user_data(|config: &mut Ini| {
-					config.with_general_section().set("save_file", save_file);
-					config
-						.write_to_file(
-							env::current_exe()
-								.unwrap()
-								.parent()
-								.unwrap()
-								.join("conf.ini"),
-						)
-						.unwrap();
-				});
-
-				info!("Save file set to: {}", save_file);
+				let written = s
+					.with_user_data(|config: &mut Ini| settings::pick_save(config, save_file))
+					.expect("User data not set up correctly on program start");
+
+				protection_dialog::update_button(s);
+				update_auto_status(s);
 
 				s.pop_layer();
-			}),
+				if let Err(e) = written {
+					s.add_layer(Dialog::info(i18n::error(&e)));
+				}
+			})
+			.with_name(SAVE_LIST_NAME),
 	)
 	.title("Select game save")
+	.button("Label", |s| {
+		let save = s
+			.call_on_name(SAVE_LIST_NAME, |list: &mut SelectView<String>| {
+				list.selection()
+			})
+			.flatten();
+		if let Some(save) = save {
+			label_save(s, save.to_string());
+		}
+	})
 	.button("Manually enter save name", |s| {
 		s.pop_layer();
 
@@ -190,32 +943,31 @@ fn set_game(s: &mut Cursive, save_path: &Path) -> Result<(), Box<dyn Error>> {
 			if save_file.is_empty() {
 				s.add_layer(
 					Dialog::around(TextView::new("Enter a name of a save game.")).button(
-						"Ok",
+						i18n::text("ok"),
 						|s| {
 							s.pop_layer();
 						},
 					),
 				)
 			} else {
-				s.with_user_data(|config: &mut Ini| {
-					config.with_general_section().set("save_file", save_file);
-					config
-						.write_to_file(
-							env::current_exe()
-								.unwrap()
-								.parent()
-								.unwrap()
-								.join("conf.ini"),
-						)
-						.unwrap();
-				});
+				let written = s
+					.with_user_data(|config: &mut Ini| {
+						settings::set_current_save(config, save_file);
+						settings::write_config(config)
+					})
+					.expect("User data not set up correctly on program start");
 
 				warn!("Save file manually set to: {}", save_file);
+				protection_dialog::update_button(s);
+				update_auto_status(s);
 
 				s.pop_layer();
+				if let Err(e) = written {
+					s.add_layer(Dialog::info(i18n::error(&e)));
+				}
 			}
 		}))
-		.button("Cancel", |s| {
+		.button(i18n::text("cancel"), |s| {
 			s.pop_layer();
 		});
 
@@ -226,203 +978,778 @@ fn set_game(s: &mut Cursive, save_path: &Path) -> Result<(), Box<dyn Error>> {
 	Ok(())
 }
 
-fn backup(
-	s: &mut Cursive,
-	save_path: &Path,
-	backup_path: &Path,
-	has_note: bool,
-) -> Result<(), Box<dyn Error>> {
+/// How a save is listed when choosing the working save: by its label followed by its name, so
+/// that the file it stands for stays clear.
+fn save_item(config: &Ini, save: &str) -> String {
+	settings::save_label(config, save)
+		.map_or_else(|| save.to_string(), |label| format!("{} ({})", label, save))
+}
+
+/// Asks for a label to show a save by in place of its name, such as `Ireland 867 - Attempt 2`
+/// for `ironman_autosave_2`. A blank label shows the save by its name again.
+fn label_save(s: &mut Cursive, save: String) {
+	let current = s
+		.with_user_data(|config: &mut Ini| {
+			settings::save_label(config, &save)
+				.unwrap_or_default()
+				.to_string()
+		})
+		.expect("User data not set up correctly on program start");
+
+	let submit = Rc::new(move |s: &mut Cursive, label: &str| {
+		let (written, item) = s
+			.with_user_data(|config: &mut Ini| {
+				settings::set_save_label(config, &save, label);
+				(settings::write_config(config), save_item(config, &save))
+			})
+			.expect("User data not set up correctly on program start");
+		info!("Label of {} set to \"{}\"", save, label.trim());
+
+		s.pop_layer();
+		s.call_on_name(SAVE_LIST_NAME, |list: &mut SelectView<String>| {
+			if let Some(selected) = list.selected_id() {
+				if let Some((text, _)) = list.get_item_mut(selected) {
+					*text = item.into();
+				}
+			}
+		});
+		update_auto_status(s);
+		if let Err(e) = written {
+			s.add_layer(Dialog::info(i18n::error(&e)));
+		}
+	});
+
+	s.add_layer(
+		Dialog::around(
+			EditView::new()
+				.content(current)
+				.on_submit({
+					let submit = Rc::clone(&submit);
+					move |s, label| submit(s, label)
+				})
+				.with_name(SAVE_LABEL_NAME)
+				.min_width(30),
+		)
+		.title("Label (blank for none)")
+		.button(i18n::text("cancel"), |s| {
+			s.pop_layer();
+		})
+		.button("Save", move |s| {
+			let label = s
+				.call_on_name(SAVE_LABEL_NAME, |view: &mut EditView| view.get_content())
+				.expect("EditView not created for label entry");
+			submit(s, &label);
+		}),
+	);
+}
+
+fn backup(s: &mut Cursive, profile: &Profile, has_note: bool) -> error::Result<()> {
 	let config: &mut Ini = s
 		.user_data()
 		.expect("User data not set up correctly on program start");
-	let mut general = config.with_general_section();
-	let file_to_backup = general
-		.get("save_file")
-		.ok_or("No file has been set to backup.")?;
+	let file_to_backup = settings::current_save(config)
+		.ok_or("No file has been set to backup.")?
+		.to_string();
+	let note_template =
+		save_setting(config, &file_to_backup, "note_template").map(ToString::to_string);
+	let note_prompt = save_setting(config, &file_to_backup, "note_prompt") != Some("false");
+	let session_label = save_setting(config, &file_to_backup, "session_label")
+		.unwrap_or_default()
+		.to_string();
+	let options = backup_options(config, &file_to_backup);
 
-	let file_path = save_path.join(file_to_backup.to_string() + EXTENSION);
+	let file_path = profile.save_file(&file_to_backup);
 
 	if !file_path.is_file() {
 		s.add_layer(
-			Dialog::around(TextView::new("Save file not found.")).button("Ok", |s| {
-				s.pop_layer();
-			}),
+			Dialog::around(TextView::new(i18n::text("save_not_found"))).button(
+				i18n::text("ok"),
+				|s| {
+					s.pop_layer();
+				},
+			),
 		)
 	} else {
-		let backup_dir = backup_path.join(file_to_backup);
+		let backup_dir = profile.backup_path.join(&file_to_backup);
 		if !backup_dir.is_dir() {
-			fs::create_dir(&backup_dir)?;
+			fs::create_dir_all(&backup_dir).context("create", &backup_dir)?;
 		}
 
-		if has_note {
-			let file_path_copy = file_path.clone();
-			let backup_dir_copy = backup_dir.clone();
+		let default_note = note_template
+			.map(|template| {
+				store::expand_note(&template, &file_to_backup, &file_path, &session_label)
+			})
+			.unwrap_or_default();
+
+		if has_note && !note_prompt && !default_note.is_empty() {
+			store::check_note(&default_note)?;
+			backup_in_background(
+				s,
+				file_path,
+				backup_dir,
+				default_note,
+				Origin::Note,
+				options,
+			);
+		} else if has_note {
+			let submit = Rc::new(move |s: &mut Cursive, note: &str| {
+				// the dialog stays open to correct a note that cannot be part of a file name
+				if let Err(e) = store::check_note(note) {
+					s.add_layer(Dialog::info(i18n::error(&e)));
+					return;
+				}
+				s.pop_layer();
+				backup_in_background(
+					s,
+					file_path.clone(),
+					backup_dir.clone(),
+					note.to_string(),
+					Origin::Note,
+					options.clone(),
+				);
+			});
+			let submit_button = Rc::clone(&submit);
 
 			s.add_layer(
 				Dialog::around(
 					EditView::new()
-						.on_submit(move |s, note| {
-							if let Err(e) = backup_core(&file_path, &backup_dir, note) {
-								error!("{}", e);
-							}
-							s.pop_layer();
-						})
+						.content(default_note)
+						.on_submit(move |s, note| submit(s, note))
 						.with_name("note"),
 				)
-				.button("Cancel", |s| {
+				.button(i18n::text("cancel"), |s| {
 					s.pop_layer();
 				})
 				.button("Enter", move |s| {
 					let note = s
 						.call_on_name("note", |view: &mut EditView| view.get_content())
 						.expect("EditView not created for user note entry");
-					if let Err(e) = backup_core(&file_path_copy, &backup_dir_copy, &note) {
-						error!("{}", e);
-					}
-					s.pop_layer();
+					submit_button(s, &note);
 				}),
 			);
 		} else {
-			backup_core(&file_path, &backup_dir, "")?;
+			backup_in_background(
+				s,
+				file_path,
+				backup_dir,
+				String::new(),
+				Origin::Manual,
+				options,
+			);
 		}
 	}
 
 	Ok(())
 }
 
-fn backup_core(file_path: &Path, backup_dir: &Path, note: &str) -> Result<(), Box<dyn Error>> {
-	let save_number = fs::read_dir(&backup_dir)?
-		.filter_map(Result::ok)
-		.filter(|file| file.path().is_file())
-		.filter_map(|file| file.file_name().to_str().map(|file| file.to_string()))
-		.filter_map(|file| file.splitn(2, '_').next().unwrap().parse::<usize>().ok())
-		.max();
+/// Takes a backup on another thread with a progress dialog, so that large saves do not freeze the
+/// interface.
+fn backup_in_background(
+	s: &mut Cursive,
+	file_path: PathBuf,
+	backup_dir: PathBuf,
+	note: String,
+	origin: Origin,
+	options: BackupOptions,
+) {
+	progress_dialog::run_in_background(
+		s,
+		"Backing up",
+		move || {
+			backup_core(&file_path, &backup_dir, &note, origin, &options).map_err(|e| e.to_string())
+		},
+		|s, created| {
+			if let Err(e) = created {
+				error!("{}", e);
+				s.add_layer(Dialog::info(i18n::error(&e)));
+			}
+		},
+	);
+}
+
+/// Asks for the path of any save file, such as one shared by a friend, and stores it as a backup of
+/// the working save. Paths pasted or dropped into the terminal may come quoted, which is ignored.
+fn import(s: &mut Cursive, backup_path: &Path) -> error::Result<()> {
+	let config: &mut Ini = s
+		.user_data()
+		.expect("User data not set up correctly on program start");
+	let file_to_backup = settings::current_save(config)
+		.ok_or("No file has been set to backup.")?
+		.to_string();
+	let options = backup_options(config, &file_to_backup);
+	let backup_dir = backup_path.join(&file_to_backup);
 
-	let save_number = match save_number {
-		Some(x) => x + 1,
-		None => 1,
-	};
+	let submit = Rc::new(move |s: &mut Cursive, entry: &str| {
+		let entry = entry.trim().trim_matches(|c| c == '"' || c == '\'');
+		let file_path = Path::new(entry.strip_prefix("file://").unwrap_or(entry));
 
-	if note.is_empty() {
-		fs::copy(file_path, backup_dir.join(save_number.to_string()))
-	} else {
-		fs::copy(
-			file_path,
-			backup_dir.join(save_number.to_string() + "_" + note.trim()),
+		if !file_path.is_file() {
+			s.add_layer(Dialog::info("No save file found at that path."));
+			return;
+		}
+
+		let note = file_path
+			.file_stem()
+			.map(|stem| stem.to_string_lossy().into_owned())
+			.unwrap_or_default();
+		let imported = fs::create_dir_all(&backup_dir)
+			.context("create", &backup_dir)
+			.and_then(|()| backup_core(file_path, &backup_dir, &note, Origin::Imported, &options));
+
+		s.pop_layer();
+		match imported {
+			Ok(created) => info!(
+				"Imported {} as backup number {}",
+				file_path.display(),
+				created.number
+			),
+			Err(e) => s.add_layer(Dialog::info(i18n::error(&e))),
+		}
+	});
+
+	s.add_layer(
+		Dialog::around(
+			LinearLayout::vertical()
+				.child(TextView::new(format!(
+					"Path of the save file to import as a backup of {}:",
+					file_to_backup
+				)))
+				.child(
+					EditView::new()
+						.on_submit({
+							let submit = Rc::clone(&submit);
+							move |s, entry| submit(s, entry)
+						})
+						.with_name(IMPORT_EDIT_NAME),
+				)
+				.min_width(60),
 		)
-	}?;
+		.title("Import a save file")
+		.button(i18n::text("cancel"), |s| {
+			s.pop_layer();
+		})
+		.button("Import", move |s| {
+			let entry = s
+				.call_on_name(IMPORT_EDIT_NAME, |view: &mut EditView| view.get_content())
+				.expect("EditView not created for import path entry");
+			submit(s, &entry);
+		}),
+	);
 
-	info!("Backup number {} created", save_number);
+	Ok(())
+}
+
+fn browse(s: &mut Cursive, profile: &Profile) -> error::Result<()> {
+	let config: &mut Ini = s
+		.user_data()
+		.expect("User data not set up correctly on program start");
+	let file_to_backup = settings::current_save(config).ok_or("No save file has been set.")?;
+
+	let save_destination = profile.save_file(file_to_backup);
+	let game_backup_folder = profile.backup_path.join(file_to_backup);
+	let author = settings::author(config);
+
+	browser::browse(
+		s,
+		save_destination,
+		game_backup_folder,
+		profile.read_only,
+		profile.ironman,
+		author,
+	)
+}
+
+/// Reverses the latest restore or deletion made since the program started, after asking.
+fn undo_last(s: &mut Cursive) -> error::Result<()> {
+	let last = undo::describe_last().ok_or("Nothing to undo in this session.")?;
+
+	confirm(s, &format!("Undo: {}?", last), |s| {
+		match undo::undo_last() {
+			Ok(undone) => s.add_layer(Dialog::info(format!("Undone: {}.", undone))),
+			Err(e) => s.add_layer(Dialog::info(i18n::error(&e))),
+		}
+	});
 
 	Ok(())
 }
 
-fn restore(s: &mut Cursive, save_path: &Path, backup_path: &Path) -> Result<(), Box<dyn Error>> {
-	let config: &mut Ini = s.user_data().expect("User data not set up correctly on program start");
-	let mut general = config.with_general_section();
-	let file_to_backup = general
-		.get("save_file")
-		.ok_or("No save file has been set.")?;
-
-	let save_destination = save_path.join(file_to_backup.to_string() + EXTENSION);
-	let game_backup_folder = backup_path.join(file_to_backup);
-
-	let backup_selection = SelectView::<String>::new()
-		.with_all_str({
-			let mut items = fs::read_dir(backup_path.join(file_to_backup))?
-				.filter_map(Result::ok)
-				.filter(|file| file.path().is_file())
-				.filter_map(|file| file.file_name().to_str().map(|file| file.to_string()))
-				.filter(|file| file.splitn(2, '_').next().unwrap().parse::<usize>().is_ok())
-				.collect::<Vec<String>>();
-			items.sort_unstable_by_key(|key| {
-				key.splitn(2, '_').next().unwrap().parse::<usize>().unwrap()
-			});
-			items
+/// Asks for the path of an exported archive and lists the backups inside it for restoring.
+fn restore_archive(s: &mut Cursive, profile: &Profile) -> error::Result<()> {
+	let config: &mut Ini = s
+		.user_data()
+		.expect("User data not set up correctly on program start");
+	let working_save = settings::current_save(config)
+		.unwrap_or_default()
+		.to_string();
+	let profile = profile.clone();
+
+	let submit = Rc::new(move |s: &mut Cursive, entry: &str| {
+		let entry = entry.trim().trim_matches(|c| c == '"' || c == '\'');
+		let path = Path::new(entry.strip_prefix("file://").unwrap_or(entry));
+
+		match archive::Archive::open(path)
+			.map_err(error::Error::from)
+			.and_then(|archive| {
+				s.pop_layer();
+				archive_dialog::browse(s, archive, &profile, &working_save)
+			}) {
+			Ok(()) => (),
+			Err(e) => s.add_layer(Dialog::info(i18n::error(&e))),
+		}
+	});
+
+	s.add_layer(
+		Dialog::around(
+			LinearLayout::vertical()
+				.child(TextView::new("Path of the .zip or .tar.zst archive:"))
+				.child(
+					EditView::new()
+						.on_submit({
+							let submit = Rc::clone(&submit);
+							move |s, entry| submit(s, entry)
+						})
+						.with_name(ARCHIVE_EDIT_NAME),
+				)
+				.min_width(60),
+		)
+		.title("Restore from an archive")
+		.button(i18n::text("cancel"), |s| {
+			s.pop_layer();
 		})
-		.on_submit(move |s: &mut Cursive, backup: &String| {
-			match fs::copy(game_backup_folder.join(backup), &save_destination) {
-				Ok(_) => {
+		.button("Open", move |s| {
+			let entry = s
+				.call_on_name(ARCHIVE_EDIT_NAME, |view: &mut EditView| view.get_content())
+				.expect("EditView not created for archive path entry");
+			submit(s, &entry);
+		}),
+	);
+
+	Ok(())
+}
+
+/// Asks for a range of backups of the working save and bundles them into a .zip or .tar.zst
+/// archive, for sharing with co-op partners.
+fn export(s: &mut Cursive, profile: &Profile) -> error::Result<()> {
+	let config: &mut Ini = s
+		.user_data()
+		.expect("User data not set up correctly on program start");
+	let save = settings::current_save(config)
+		.ok_or("No save file has been set.")?
+		.to_string();
+	let label = settings::display_name(config, &save);
+	let backup_dir = profile.backup_path.join(&save);
+	let backups = store::list_backups(&backup_dir).context("read", &backup_dir)?;
+	let (first, last) = match (backups.first(), backups.last()) {
+		(Some(first), Some(last)) => (first.number, last.number),
+		_ => return Err("There are no backups to export.".into()),
+	};
+	let default_path = UserDirs::new()
+		.map_or_else(PathBuf::new, |dirs| dirs.home_dir().to_path_buf())
+		.join(format!("{} backups.zip", save));
+
+	let entry = |label: &str, name: &str, content: String, width: usize| {
+		LinearLayout::horizontal()
+			.child(TextView::new(label).fixed_width(22))
+			.child(
+				EditView::new()
+					.content(content)
+					.with_name(name)
+					.min_width(width),
+			)
+	};
+
+	s.add_layer(
+		Dialog::around(
+			LinearLayout::vertical()
+				.child(entry(
+					"From backup number:",
+					EXPORT_FROM_NAME,
+					first.to_string(),
+					8,
+				))
+				.child(entry(
+					"To backup number:",
+					EXPORT_TO_NAME,
+					last.to_string(),
+					8,
+				))
+				.child(entry(
+					"Archive (.zip, .tar.zst):",
+					EXPORT_PATH_NAME,
+					default_path.display().to_string(),
+					50,
+				)),
+		)
+		.title(format!("Export backups of {}", label))
+		.button(i18n::text("cancel"), |s| {
+			s.pop_layer();
+		})
+		.button("Export", move |s| {
+			let content = |s: &mut Cursive, name: &str| {
+				s.call_on_name(name, |view: &mut EditView| view.get_content())
+					.expect("EditView not created for export entry")
+					.trim()
+					.to_string()
+			};
+			let from = content(s, EXPORT_FROM_NAME);
+			let to = content(s, EXPORT_TO_NAME);
+			let path = PathBuf::from(content(s, EXPORT_PATH_NAME));
+
+			let exported = match (from.parse(), to.parse()) {
+				(Ok(from), Ok(to)) => archive::Archive::open(&path)
+					.map_err(error::Error::from)
+					.and_then(|archive| archive.export(&backup_dir, from, to)),
+				_ => Err("Backup numbers must be whole numbers.".into()),
+			};
+			match exported {
+				Ok(count) => {
 					s.pop_layer();
+					s.add_layer(Dialog::info(format!(
+						"Exported {} backups to {}.",
+						count,
+						path.display()
+					)));
 				}
-				Err(e) => s.add_layer(Dialog::around(TextView::new(format!(
-					"Error occurred: {}",
-					e
-				)))),
+				Err(e) => s.add_layer(Dialog::info(i18n::error(&e))),
 			}
+		}),
+	);
+
+	Ok(())
+}
+
+fn auto(s: &mut Cursive, profile: &Profile, take_baseline: bool) -> error::Result<()> {
+	if auto_running() {
+		return Err("Automatic backups are already running.".into());
+	}
+
+	let config: &mut Ini = s
+		.user_data()
+		.expect("User data not set up correctly on program start");
+	let session = Session::start_working(config, profile, take_baseline)?;
+	AUTO_SESSION.with_borrow_mut(|running| *running = Some(session));
+
+	// this is needed to see new backup log messages and the status line without user input
+	s.set_fps(1);
+	update_auto_status(s);
+
+	Ok(())
+}
+
+/// Starts auditing the writes of the working save, or stops the audit running and shows what it
+/// found, such as to pick how long automatic backups wait before taking up disk space with them.
+fn audit(s: &mut Cursive, profile: &Profile) -> error::Result<()> {
+	if let Some(audit) = AUDIT.with_borrow_mut(Option::take) {
+		if !auto_running() {
+			s.set_fps(0);
+		}
+		let report = audit.stop();
+		s.add_layer(
+			Dialog::around(TextView::new(report.describe()).scrollable())
+				.title(i18n::fill("audit.title", &[&report.save]))
+				.button(i18n::text("ok"), |s| {
+					s.pop_layer();
+				}),
+		);
+		return Ok(());
+	}
+
+	let config: &mut Ini = s
+		.user_data()
+		.expect("User data not set up correctly on program start");
+	let save = settings::current_save(config)
+		.ok_or_else(|| i18n::text("no_save"))?
+		.to_string();
+	let audit = Audit::start(profile, &save)?;
+	AUDIT.set(Some(audit));
+
+	// this is needed to see the writes logged without user input
+	s.set_fps(1);
+	s.add_layer(
+		Dialog::around(TextView::new(i18n::fill("audit.started", &[&save]))).button(
+			i18n::text("ok"),
+			|s| {
+				s.pop_layer();
+			},
+		),
+	);
+
+	Ok(())
+}
+
+/// Watches the save directory of the game profile in use for new saves, offering to adopt each, in
+/// place of the profile watched before. Set `offer_new_saves = false` to leave new saves alone.
+fn watch_new_saves(s: &mut Cursive, games: &Rc<Games>) {
+	NEW_SAVES.set(None);
+	let offer = s
+		.with_user_data(|config: &mut Ini| {
+			config.general_section().get("offer_new_saves") != Some("false")
 		})
-		.autojump()
-		.scrollable();
+		.expect("User data not set up correctly on program start");
+	let profile = games.active.borrow();
+	if !offer || profile.read_only {
+		return;
+	}
 
-	s.add_layer(Dialog::around(backup_selection).button("Cancel", |s| {
-		s.pop_layer();
-	}));
+	let sink = s.cb_sink().clone();
+	let watched = profile.name.clone();
+	let watch = NewSaves::watch(&profile, move |save| {
+		let watched = watched.clone();
+		// the interface may already be closing
+		let _ = sink.send(Box::new(move |s| offer_new_save(s, &watched, save)));
+	});
+	match watch {
+		Ok(watch) => NEW_SAVES.set(Some((watch, Rc::clone(games)))),
+		Err(e) => warn!(
+			"Could not watch {} for new saves: {}",
+			profile.save_path.display(),
+			e
+		),
+	}
+}
+
+/// Asks whether to make a save that just appeared in the save directory of the game profile
+/// `watched` the working save and back it up automatically, such as the first save of a new
+/// campaign. Saves found before another game profile was picked are no longer offered.
+fn offer_new_save(s: &mut Cursive, watched: &str, save: String) {
+	let games =
+		match NEW_SAVES.with_borrow(|watch| watch.as_ref().map(|(_, games)| Rc::clone(games))) {
+			Some(games) if games.active.borrow().name == watched => games,
+			_ => return,
+		};
+	let current = s
+		.with_user_data(|config: &mut Ini| settings::current_save(config).map(str::to_string))
+		.expect("User data not set up correctly on program start");
+	if current.as_deref() == Some(save.as_str()) {
+		return;
+	}
+
+	s.add_layer(
+		Dialog::around(TextView::new(format!(
+			"A new save, {}, appeared. Make it the working save and back it up automatically?",
+			save
+		)))
+		.title("New save")
+		.button("Not now", |s| {
+			s.pop_layer();
+		})
+		.button("Adopt", move |s| {
+			s.pop_layer();
+			// the profile as it is now, edits made while the offer was open included
+			let profile = games.active.borrow().clone();
+			if let Err(e) = adopt_save(s, &profile, &save) {
+				s.add_layer(Dialog::info(i18n::error(&e)));
+			}
+		}),
+	);
+}
+
+/// Makes `save` the working save and starts automatic backups of it, from a backup of its current
+/// state, in place of any session already running.
+fn adopt_save(s: &mut Cursive, profile: &Profile, save: &str) -> error::Result<()> {
+	let config: &mut Ini = s
+		.user_data()
+		.expect("User data not set up correctly on program start");
+	settings::pick_save(config, save)?;
+	protection_dialog::update_button(s);
+
+	stop_auto(s, |_| {});
+	auto(s, profile, true)
+}
+
+/// Whether an automatic backup session is running in the interface, paused or not.
+fn auto_running() -> bool {
+	AUTO_SESSION.with_borrow(Option::is_some)
+}
+
+/// Pauses or resumes the automatic backup session running in the interface.
+fn pause_auto(s: &mut Cursive, pause: bool) -> error::Result<()> {
+	AUTO_SESSION.with_borrow(|session| -> error::Result<()> {
+		let session = session
+			.as_ref()
+			.ok_or_else(|| i18n::text("auto_not_running"))?;
+		match (pause, session.is_paused()) {
+			(true, true) => Err("Automatic backups are already paused.".into()),
+			(false, false) => Err("Automatic backups are not paused.".into()),
+			(true, false) => {
+				session.pause();
+				Ok(())
+			}
+			(false, true) => {
+				session.resume();
+				Ok(())
+			}
+		}
+	})?;
+	update_auto_status(s);
 
 	Ok(())
 }
 
-fn auto(s: &mut Cursive, save_path: &Path, backup_path: &Path) -> Result<(), Box<dyn Error>> {
-	let config: &mut Ini = s.user_data().expect("User data not set up correctly on program start");
-	let mut general = config.with_general_section();
-	let file_to_backup = general
-		.get("save_file")
-		.ok_or("No save file has been set.")?;
+/// Shows the state of automatic backups in the status line of the main screen.
+fn update_auto_status(s: &mut Cursive) {
+	let mut status = AUTO_SESSION.with_borrow(|session| {
+		session
+			.as_ref()
+			.map_or_else(|| i18n::text("auto_off").to_string(), Session::status)
+	});
+	// the working save comes first, by its label when it has one
+	if let Some(save) = s
+		.with_user_data(|config: &mut Ini| {
+			settings::current_save(config).map(|save| settings::display_name(config, save))
+		})
+		.flatten()
+	{
+		status = format!("{} | {}", save, status);
+	}
+	s.call_on_name(AUTO_STATUS_NAME, |view: &mut TextView| {
+		view.set_content(status)
+	});
+}
 
-	let (tx, rx) = mpsc::channel();
-	let mut watcher = notify::watcher(tx, Duration::from_secs(10))?;
-	watcher.watch(
-		save_path.join(file_to_backup.to_string() + EXTENSION),
-		RecursiveMode::NonRecursive,
-	)?;
+/// Stops the automatic backup session running in the interface, if any, and shows its summary,
+/// running `then` once the summary is dismissed. Without a session, `then` runs straight away.
+fn stop_auto(s: &mut Cursive, then: impl Fn(&mut Cursive) + 'static) {
+	let session = match AUTO_SESSION.with_borrow_mut(Option::take) {
+		Some(session) => session,
+		None => return then(s),
+	};
 
-	let file_path = save_path.join(file_to_backup.to_string() + EXTENSION);
+	if AUDIT.with_borrow(Option::is_none) {
+		s.set_fps(0);
+	}
+	update_auto_status(s);
 
-	if !file_path.is_file() {
-		s.add_layer(
-			Dialog::around(TextView::new("Save file not found.")).button("Ok", |s| {
+	s.add_layer(
+		Dialog::around(TextView::new(session.stop()))
+			.title(i18n::text("session_summary"))
+			.button(i18n::text("ok"), move |s| {
 				s.pop_layer();
+				then(s);
 			}),
-		)
-	} else {
-		let backup_dir = backup_path.join(file_to_backup);
-		if !backup_dir.is_dir() {
-			fs::create_dir(&backup_dir)?;
-		}
+	);
+}
 
-		thread::spawn(move || loop {
-			match rx.recv() {
-				Ok(event) => {
-					if let DebouncedEvent::Write(_) = event {
-						if let Err(e) = backup_core(&file_path, &backup_dir, "") {
-							error!("{}", e);
-							break;
-						}
-					}
-				}
-				Err(e) => {
-					warn!("{}", e);
-					break;
-				}
-			}
-		});
+/// Lists the tasks scheduled for a game profile with when each runs next.
+fn scheduled_tasks(s: &mut Cursive, profile: &Profile) -> error::Result<()> {
+	let config: &mut Ini = s
+		.user_data()
+		.expect("User data not set up correctly on program start");
+	let schedules = schedule::load(config, &profile.name);
 
-		// this is needed to see new backup log messages without user input
-		s.set_fps(1);
+	if schedules.is_empty() {
+		s.add_layer(Dialog::info(format!(
+			"No tasks are scheduled for {}.\nAdd [schedule.<name>] sections to conf.ini to schedule some.",
+			profile.name
+		)));
+		return Ok(());
+	}
 
-		let cancel_dialog = Dialog::around(TextView::new("Automatically backing up save files..."))
-			.button("Cancel", move |s| {
-				// prevent the watcher from being dropped until the dialog is dismissed
-				&watcher;
+	let now = Local::now().naive_local();
+	let mut text = String::new();
+	for schedule in &schedules {
+		text.push_str(&format!(
+			"{}: {}\n  next: {}\n",
+			schedule.name,
+			schedule.describe(),
+			schedule.next_run(now).map_or_else(
+				|| "never".to_string(),
+				|next| next.format("%Y-%m-%d %H:%M").to_string()
+			)
+		));
+	}
+	text.push_str("\nScheduled tasks run while automatic backups are running.");
 
-				info!("Stopped automatic backups");
-				s.set_fps(0);
+	s.add_layer(
+		Dialog::around(TextView::new(text).scrollable())
+			.title(format!("Scheduled tasks for {}", profile.name))
+			.button(i18n::text("ok"), |s| {
 				s.pop_layer();
-			});
+			}),
+	);
+
+	Ok(())
+}
+
+/// Switches new backups between compressed and uncompressed. Existing backups stay as they are and
+/// remain restorable either way.
+fn toggle_compression(s: &mut Cursive) -> error::Result<()> {
+	let config: &mut Ini = s
+		.user_data()
+		.expect("User data not set up correctly on program start");
+	let compress = config.general_section().get("compression") != Some("zstd");
+	config
+		.with_general_section()
+		.set("compression", if compress { "zstd" } else { "none" });
+	settings::write_config(config)?;
+
+	if compress {
+		info!("New backups will be compressed");
+	} else {
+		info!("New backups will not be compressed");
+	}
+
+	Ok(())
+}
+
+/// Turns keeping generations of the game's autosave during automatic backups on or off. Sessions
+/// already running keep what they did until restarted.
+fn toggle_autosaves(s: &mut Cursive) -> error::Result<()> {
+	let config: &mut Ini = s
+		.user_data()
+		.expect("User data not set up correctly on program start");
+	let keep = settings::autosave_generations(config).is_none();
+	config.with_general_section().set(
+		"autosave_generations",
+		if keep {
+			settings::DEFAULT_AUTOSAVE_GENERATIONS
+		} else {
+			0
+		}
+		.to_string(),
+	);
+	settings::write_config(config)?;
 
-		s.add_layer(cancel_dialog);
+	if keep {
+		info!(
+			"Automatic backups will keep the last {} autosaves",
+			settings::DEFAULT_AUTOSAVE_GENERATIONS
+		);
+	} else {
+		info!("Automatic backups will not keep autosaves");
 	}
 
 	Ok(())
 }
 
-// fn delete(s: &mut Cursive, backup_path: &Path) -> Result<(), Box<dyn Error>> {}
+/// Turns desktop notifications about automatic backups and their errors on or off in the general
+/// section. Saves with their own `notify_desktop` setting or protection preset keep theirs, and
+/// sessions already running keep theirs until restarted.
+fn toggle_notifications(s: &mut Cursive) -> error::Result<()> {
+	let config: &mut Ini = s
+		.user_data()
+		.expect("User data not set up correctly on program start");
+	let desktop = config.general_section().get("notify_desktop") != Some("true");
+	config
+		.with_general_section()
+		.set("notify_desktop", desktop.to_string());
+	settings::write_config(config)?;
+
+	if desktop {
+		info!("Automatic backups will show desktop notifications");
+	} else {
+		info!("Automatic backups will not show desktop notifications");
+	}
+
+	Ok(())
+}
+
+fn delete(s: &mut Cursive, backup_path: &Path) -> error::Result<()> {
+	let config: &mut Ini = s
+		.user_data()
+		.expect("User data not set up correctly on program start");
+	let file_to_backup = settings::current_save(config).ok_or("No save file has been set.")?;
+	let backup_dir = backup_path.join(file_to_backup);
+
+	cleanup::delete_old(s, backup_dir)
+}
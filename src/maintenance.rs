@@ -0,0 +1,338 @@
+use std::cell::Cell;
+use std::path::Path;
+use std::rc::Rc;
+
+use cursive::traits::*;
+use cursive::views::{Dialog, OnEventView, TextView};
+use cursive::Cursive;
+
+use ini::Ini;
+
+use log::{error, warn};
+
+use crate::confirm::confirm;
+use crate::error::{self, Context};
+use crate::format_size;
+use crate::i18n;
+use crate::jobs;
+use crate::progress_dialog;
+use crate::settings::{self, backup_options};
+use crate::snapshot::{self, Maintained, Snapshot};
+use crate::store;
+use crate::sync;
+
+/// Runs a maintenance operation that rewrites the metadata of the backup folder, snapshotting the
+/// index and manifests first. When the operation fails midway, offers to put them back as they
+/// were and returns `None`.
+fn maintain<T>(
+	s: &mut Cursive,
+	backup_path: &Path,
+	operation: &str,
+	run: impl FnOnce() -> std::io::Result<T>,
+) -> error::Result<Option<T>> {
+	match snapshot::maintain(backup_path, operation, run)
+		.context("snapshot the backup metadata in", backup_path)?
+	{
+		Maintained::Done(done) => Ok(Some(done)),
+		Maintained::Failed(snapshot, e) => {
+			offer_rollback(s, snapshot, &format!("It failed midway: {}", e));
+			Ok(None)
+		}
+	}
+}
+
+/// Asks whether to put the backup index and manifests back as they were before a maintenance
+/// operation that did not finish, or to keep them as the operation left them.
+pub fn offer_rollback(s: &mut Cursive, snapshot: Snapshot, reason: &str) {
+	let operation = snapshot
+		.operation()
+		.unwrap_or_else(|_| "maintenance".to_string());
+	// taken by whichever button is pressed
+	let snapshot = Rc::new(Cell::new(Some(snapshot)));
+	let keep = Rc::clone(&snapshot);
+
+	s.add_layer(
+		Dialog::around(TextView::new(format!(
+			"{} did not finish. {}\n\nThe backup index and manifests were snapshotted before it started. Roll them back to how they were? Backup files it renamed stay as they are.",
+			operation, reason
+		)))
+		.title("Maintenance interrupted")
+		.button("Keep as is", move |s| {
+			s.pop_layer();
+			if let Some(Err(e)) = keep.take().map(Snapshot::discard) {
+				warn!("Could not remove the snapshot of the backup metadata: {}", e);
+			}
+		})
+		.button("Roll back", move |s| {
+			s.pop_layer();
+			match snapshot.take().map(Snapshot::roll_back) {
+				Some(Ok(_)) => s.add_layer(Dialog::info(
+					"Put back the backup index and manifests as they were.",
+				)),
+				Some(Err(e)) => s.add_layer(Dialog::info(i18n::error(&e))),
+				None => {}
+			}
+		}),
+	);
+}
+
+pub fn rebuild(s: &mut Cursive, backup_path: &Path) -> error::Result<()> {
+	let config: &mut Ini = s
+		.user_data()
+		.expect("User data not set up correctly on program start");
+	let ids = backup_options(config, "").ids;
+	let rebuilt = match maintain(s, backup_path, "rebuilding the backup index", || {
+		store::rebuild_index(backup_path, ids)
+	})? {
+		Some(rebuilt) => rebuilt,
+		None => return Ok(()),
+	};
+
+	s.add_layer(
+		Dialog::around(TextView::new(format!(
+			"Scanned {} backups of {} saves.\n{} have distinct contents.\n{} stale manifest entries removed.\n{} backups given an ID.",
+			rebuilt.backups, rebuilt.saves, rebuilt.unique, rebuilt.stale, rebuilt.identified
+		)))
+		.title("Backup index rebuilt")
+		.button("Ok", |s| {
+			s.pop_layer();
+		}),
+	);
+
+	Ok(())
+}
+
+pub fn check_consistency(s: &mut Cursive, backup_path: &Path) -> error::Result<()> {
+	let consistency = store::check_consistency(backup_path)?;
+
+	if consistency.is_empty() {
+		s.add_layer(Dialog::info(
+			"Every backup is known to the index and manifests.",
+		));
+		return Ok(());
+	}
+
+	let mut report = String::new();
+	let mut list = |title: &str, items: Vec<String>| {
+		if !items.is_empty() {
+			report.push_str(&format!("{} ({}):\n", title, items.len()));
+			for item in items {
+				report.push_str(&format!("  {}\n", item));
+			}
+		}
+	};
+	list(
+		"Backups missing from the index",
+		consistency
+			.unindexed
+			.iter()
+			.map(|path| path.display().to_string())
+			.collect(),
+	);
+	list(
+		"Unrecognised files in backup folders",
+		consistency
+			.strays
+			.iter()
+			.map(|path| path.display().to_string())
+			.collect(),
+	);
+	list(
+		"Index entries without a file",
+		consistency.missing_objects.clone(),
+	);
+	list(
+		"Manifest entries without a backup",
+		consistency
+			.missing_backups
+			.iter()
+			.map(|(dir, name)| dir.join(name).display().to_string())
+			.collect(),
+	);
+	report.push_str("\n[a] adopt files on disk  [c] clean up stale entries");
+
+	let consistency = Rc::new(consistency);
+	let adopt = {
+		let consistency = Rc::clone(&consistency);
+		let backup_path = backup_path.to_path_buf();
+		move |s: &mut Cursive| {
+			s.pop_layer();
+			if let Err(e) = maintain(s, &backup_path, "adopting files on disk", || {
+				store::adopt_orphans(&backup_path, &consistency)
+			}) {
+				error!("{}", e);
+			}
+		}
+	};
+	let clean = {
+		let backup_path = backup_path.to_path_buf();
+		move |s: &mut Cursive| {
+			s.pop_layer();
+			if let Err(e) = maintain(s, &backup_path, "cleaning up stale entries", || {
+				store::clean_orphans(&backup_path, &consistency)
+			}) {
+				error!("{}", e);
+			}
+		}
+	};
+	let adopt = Rc::new(adopt);
+	let clean = Rc::new(clean);
+
+	s.add_layer(
+		OnEventView::new(
+			Dialog::around(TextView::new(report).scrollable())
+				.title("Backup consistency")
+				.button("Adopt", {
+					let adopt = Rc::clone(&adopt);
+					move |s| adopt(s)
+				})
+				.button("Clean up", {
+					let clean = Rc::clone(&clean);
+					move |s| clean(s)
+				})
+				.button("Close", |s| {
+					s.pop_layer();
+				}),
+		)
+		.on_event('a', move |s| adopt(s))
+		.on_event('c', move |s| clean(s)),
+	);
+
+	Ok(())
+}
+
+/// Hashes the backups of the working save again to find any damaged on disk, offering to delete
+/// them.
+pub fn verify_backups(s: &mut Cursive, backup_path: &Path) -> error::Result<()> {
+	let config: &mut Ini = s
+		.user_data()
+		.expect("User data not set up correctly on program start");
+	let save = settings::current_save(config).ok_or("No save file has been set.")?;
+	let backup_dir = backup_path.join(save);
+
+	progress_dialog::run_in_background(
+		s,
+		"Verifying backups",
+		{
+			let backup_dir = backup_dir.clone();
+			move || store::verify_backups(&backup_dir).map_err(|e| e.to_string())
+		},
+		move |s, verified| {
+			let verification = match verified {
+				Ok(verification) => verification,
+				Err(e) => {
+					s.add_layer(Dialog::info(i18n::error(&e)));
+					return;
+				}
+			};
+
+			let mut report = format!("{} backups checked.", verification.checked);
+			if verification.recorded > 0 {
+				report.push_str(&format!(
+					"\n{} older backups had no checksum, which is recorded now.",
+					verification.recorded
+				));
+			}
+			if verification.damaged.is_empty() {
+				report.push_str("\nEvery backup matches its checksum.");
+				s.add_layer(Dialog::info(report).title("Verify backups"));
+				return;
+			}
+
+			report.push_str(&format!(
+				"\n\nDamaged backups ({}):\n",
+				verification.damaged.len()
+			));
+			for (backup, problem) in &verification.damaged {
+				report.push_str(&format!("  {}: {}\n", backup.number, problem));
+			}
+
+			let damaged = verification
+				.damaged
+				.into_iter()
+				.map(|(backup, _)| backup)
+				.collect::<Vec<_>>();
+			s.add_layer(
+				Dialog::around(TextView::new(report).scrollable())
+					.title("Verify backups")
+					.button("Close", |s| {
+						s.pop_layer();
+					})
+					.button("Delete damaged", move |s| {
+						let damaged = damaged.clone();
+						let backup_dir = backup_dir.clone();
+						confirm(
+							s,
+							&format!(
+								"Delete the {} damaged backups? This cannot be undone.",
+								damaged.len()
+							),
+							move |s| {
+								s.pop_layer();
+								for backup in &damaged {
+									if let Err(e) = store::delete_backup(&backup_dir, backup) {
+										error!(
+											"Could not delete backup number {}: {}",
+											backup.number, e
+										);
+									}
+								}
+							},
+						);
+					}),
+			);
+		},
+	);
+
+	Ok(())
+}
+
+/// Pulls what other machines pushed to the remote set up in the `[sync]` section of conf.ini, then
+/// pushes the backup folder there, so that campaigns can be carried on from another machine.
+pub fn sync_now(s: &mut Cursive, backup_path: &Path) -> error::Result<()> {
+	let config: &mut Ini = s
+		.user_data()
+		.expect("User data not set up correctly on program start");
+	let remote = sync::Remote::load(config)?
+		.ok_or("No remote has been set up, add a [sync] section to conf.ini first.")?;
+	let backup_path = backup_path.to_path_buf();
+	let destination = remote.describe();
+
+	progress_dialog::run_in_background(
+		s,
+		"Syncing backups",
+		move || {
+			let _slot = jobs::acquire("Sync");
+			let pulled = sync::pull(&remote, &backup_path).map_err(|e| e.to_string())?;
+			let pushed = sync::push(&remote, &backup_path).map_err(|e| e.to_string())?;
+			Ok((pulled, pushed))
+		},
+		move |s, synced| match synced {
+			Ok((pulled, pushed)) if pulled.files == 0 && pulled.kept == 0 && pushed.files == 0 => {
+				s.add_layer(Dialog::info(format!("{} is up to date.", destination)));
+			}
+			Ok((pulled, pushed)) => {
+				let mut text = format!(
+					"Pulled {} files ({}) from {}.\nPushed {} files ({}) to it.",
+					pulled.files,
+					format_size(pulled.bytes),
+					destination,
+					pushed.files,
+					format_size(pushed.bytes)
+				);
+				if pulled.kept > 0 {
+					text.push_str(&format!(
+						"\n{} backups on the remote were not pulled, as backups of the same name were taken here.",
+						pulled.kept
+					));
+				}
+				s.add_layer(Dialog::info(text));
+			}
+			Err(e) => {
+				s.add_layer(Dialog::info(i18n::error(&e)));
+			}
+		},
+	);
+
+	Ok(())
+}
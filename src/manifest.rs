@@ -0,0 +1,374 @@
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use chrono::{DateTime, Local};
+
+use ini::Ini;
+
+use log::warn;
+
+use crate::parser::GameDate;
+use crate::store;
+
+pub const MANIFEST_FILE: &str = "manifest.ini";
+
+/// How a backup came to exist.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Origin {
+	/// Taken from the menu without a note.
+	Manual,
+	/// Taken from the menu with a note.
+	Note,
+	/// Taken by an automatic backup session.
+	Auto,
+	/// Taken from the command line.
+	Cli,
+	/// Taken by a scheduled task.
+	Scheduled,
+	/// Imported from a save file elsewhere.
+	Imported,
+	/// A stray file taken into the store by the consistency check.
+	Adopted,
+	/// Taken before playing on a new version of the game.
+	PrePatch,
+	/// The save a game left behind after a test run started from another backup.
+	TestRun,
+	/// Published to a multiplayer group by its host through the Discord bot.
+	Published,
+	/// Taken by an automatic backup session when the game exited at the end of a play session.
+	SessionEnd,
+	/// The cloud copy of a save, taken when the cloud sync wrote one that differs from the local
+	/// save.
+	Cloud,
+}
+
+impl fmt::Display for Origin {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		f.write_str(match self {
+			Self::Manual => "manual",
+			Self::Note => "note",
+			Self::Auto => "auto",
+			Self::Cli => "cli",
+			Self::Scheduled => "scheduled",
+			Self::Imported => "imported",
+			Self::Adopted => "adopted",
+			Self::PrePatch => "pre-patch",
+			Self::TestRun => "test-run",
+			Self::Published => "published",
+			Self::SessionEnd => "session-end",
+			Self::Cloud => "cloud",
+		})
+	}
+}
+
+impl FromStr for Origin {
+	type Err = String;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		match s {
+			"manual" => Ok(Self::Manual),
+			"note" => Ok(Self::Note),
+			"auto" => Ok(Self::Auto),
+			"cli" => Ok(Self::Cli),
+			"scheduled" => Ok(Self::Scheduled),
+			"imported" => Ok(Self::Imported),
+			"adopted" => Ok(Self::Adopted),
+			"pre-patch" => Ok(Self::PrePatch),
+			"test-run" => Ok(Self::TestRun),
+			"published" => Ok(Self::Published),
+			"session-end" => Ok(Self::SessionEnd),
+			"cloud" => Ok(Self::Cloud),
+			_ => Err(format!("\"{}\" is not a backup origin", s)),
+		}
+	}
+}
+
+/// Per-save metadata about backups that cannot be stored in the file name, kept as a
+/// `manifest.ini` next to the backups with one section per backup file.
+pub struct Manifest {
+	path: PathBuf,
+	ini: Ini,
+}
+
+impl Manifest {
+	/// Loads the manifest for a backup directory, starting empty if none exists yet.
+	pub fn load(backup_dir: &Path) -> Self {
+		let path = backup_dir.join(MANIFEST_FILE);
+		let ini = match Ini::load_from_file(&path) {
+			Ok(ini) => ini,
+			Err(ini::ini::Error::Io(_)) => Ini::new(),
+			Err(e) => {
+				// keep the damaged file around rather than overwriting it on the next save
+				warn!("{} is damaged and was set aside: {}", path.display(), e);
+				let _ = fs::rename(&path, path.with_extension("ini.damaged"));
+				Ini::new()
+			}
+		};
+
+		Self { path, ini }
+	}
+
+	/// Loads the manifest for a backup directory, lets `change` modify it and writes it back,
+	/// keeping other writers of the backup folder out in between so that nothing they record
+	/// meanwhile is lost. Nothing is written when `change` fails.
+	pub fn update<T>(
+		backup_dir: &Path,
+		change: impl FnOnce(&mut Self) -> io::Result<T>,
+	) -> io::Result<T> {
+		let _writers = store::lock_writers(backup_dir)?;
+		let mut manifest = Self::load(backup_dir);
+		let changed = change(&mut manifest)?;
+		manifest.save()?;
+
+		Ok(changed)
+	}
+
+	/// Reads a manifest that is not in a backup folder, such as one inside an archive. It cannot be
+	/// saved.
+	pub fn parse(text: &str) -> Self {
+		Self {
+			path: PathBuf::new(),
+			ini: Ini::load_from_str(text).unwrap_or_default(),
+		}
+	}
+
+	/// Writes the manifest beside itself and moves it into place, so that it is never read half
+	/// written by someone loading it without the writers' lock.
+	pub fn save(&self) -> io::Result<()> {
+		let mut staged = self.path.clone().into_os_string();
+		staged.push(format!(".{}", ulid::Ulid::new()));
+		let staged = PathBuf::from(staged);
+
+		self.ini
+			.write_to_file(&staged)
+			.and_then(|()| fs::rename(&staged, &self.path))
+			.inspect_err(|_| {
+				let _ = fs::remove_file(&staged);
+			})
+	}
+
+	/// The text of a manifest holding only what is recorded about the given backups, for
+	/// bundling with them.
+	pub fn subset(&self, backups: &[&str]) -> String {
+		let mut subset = Ini::new();
+		for backup in backups {
+			if let Some(properties) = self.ini.section(Some(*backup)) {
+				subset
+					.entry(Some(backup.to_string()))
+					.or_insert_with(|| properties.clone());
+			}
+		}
+
+		let mut text = Vec::new();
+		// writing to memory cannot fail
+		let _ = subset.write_to(&mut text);
+		String::from_utf8_lossy(&text).into_owned()
+	}
+
+	/// Records about a backup everything another manifest records about one of its backups.
+	pub fn copy_from(&mut self, backup: &str, other: &Self, other_backup: &str) {
+		if let Some(properties) = other.ini.section(Some(other_backup)) {
+			self.ini.delete(Some(backup));
+			self.ini
+				.entry(Some(backup.to_string()))
+				.or_insert_with(|| properties.clone());
+		}
+	}
+
+	pub fn tags(&self, backup: &str) -> Vec<String> {
+		self.ini
+			.get_from(Some(backup), "tags")
+			.map(|tags| {
+				tags.split(',')
+					.map(str::trim)
+					.filter(|tag| !tag.is_empty())
+					.map(ToString::to_string)
+					.collect()
+			})
+			.unwrap_or_default()
+	}
+
+	pub fn set_tags(&mut self, backup: &str, tags: &[String]) {
+		if tags.is_empty() {
+			self.ini.delete_from(Some(backup), "tags");
+		} else {
+			self.ini
+				.with_section(Some(backup))
+				.set("tags", tags.join(","));
+		}
+	}
+
+	pub fn is_pinned(&self, backup: &str) -> bool {
+		self.ini.get_from(Some(backup), "pinned") == Some("true")
+	}
+
+	/// Whether a backup is spared by automatic deletion, being pinned or carrying one of the tags
+	/// the profile keeps.
+	pub fn is_kept(&self, backup: &str, keep_tags: &[String]) -> bool {
+		self.is_pinned(backup) || self.tags(backup).iter().any(|tag| keep_tags.contains(tag))
+	}
+
+	pub fn set_pinned(&mut self, backup: &str, pinned: bool) {
+		if pinned {
+			self.ini.with_section(Some(backup)).set("pinned", "true");
+		} else {
+			self.ini.delete_from(Some(backup), "pinned");
+		}
+	}
+
+	/// How a backup was created, unknown for backups taken before origins were recorded.
+	pub fn origin(&self, backup: &str) -> Option<Origin> {
+		self.ini.get_from(Some(backup), "origin")?.parse().ok()
+	}
+
+	pub fn set_origin(&mut self, backup: &str, origin: Origin) {
+		self.ini
+			.with_section(Some(backup))
+			.set("origin", origin.to_string());
+	}
+
+	/// When a backup was taken, unknown for backups taken before creation times were recorded.
+	pub fn created(&self, backup: &str) -> Option<DateTime<Local>> {
+		let created = self.ini.get_from(Some(backup), "created")?;
+		DateTime::parse_from_rfc3339(created)
+			.ok()
+			.map(|created| created.with_timezone(&Local))
+	}
+
+	pub fn set_created(&mut self, backup: &str, created: DateTime<Local>) {
+		self.ini
+			.with_section(Some(backup))
+			.set("created", created.to_rfc3339());
+	}
+
+	/// Size of the save a backup holds, before any compression.
+	pub fn size(&self, backup: &str) -> Option<u64> {
+		self.ini.get_from(Some(backup), "size")?.parse().ok()
+	}
+
+	pub fn set_size(&mut self, backup: &str, size: u64) {
+		self.ini
+			.with_section(Some(backup))
+			.set("size", size.to_string());
+	}
+
+	/// Content hash of the save a backup holds, recorded for backups taken since hashes were kept.
+	pub fn hash(&self, backup: &str) -> Option<String> {
+		self.ini
+			.get_from(Some(backup), "hash")
+			.map(ToString::to_string)
+	}
+
+	pub fn set_hash(&mut self, backup: &str, hash: &str) {
+		self.ini.with_section(Some(backup)).set("hash", hash);
+	}
+
+	/// In-game date of the save a backup holds, if it could be read when the backup was taken.
+	pub fn game_date(&self, backup: &str) -> Option<GameDate> {
+		self.ini.get_from(Some(backup), "game_date")?.parse().ok()
+	}
+
+	pub fn set_game_date(&mut self, backup: &str, date: GameDate) {
+		self.ini
+			.with_section(Some(backup))
+			.set("game_date", date.to_string());
+	}
+
+	/// Character played in the save a backup holds, such as "King Murchad", if it could be read
+	/// when the backup was taken.
+	pub fn player(&self, backup: &str) -> Option<String> {
+		self.ini
+			.get_from(Some(backup), "player")
+			.map(ToString::to_string)
+	}
+
+	pub fn set_player(&mut self, backup: &str, player: &str) {
+		self.ini.with_section(Some(backup)).set("player", player);
+	}
+
+	/// Who took a backup, such as `anna@living-room`, recorded for backups taken since authors were
+	/// kept so that backups in a backup folder shared by several people can be told apart.
+	pub fn author(&self, backup: &str) -> Option<String> {
+		self.ini
+			.get_from(Some(backup), "author")
+			.map(ToString::to_string)
+	}
+
+	pub fn set_author(&mut self, backup: &str, author: &str) {
+		self.ini.with_section(Some(backup)).set("author", author);
+	}
+
+	/// Identifier of a backup that stays unique across machines, imports and syncs.
+	pub fn id(&self, backup: &str) -> Option<String> {
+		self.ini
+			.get_from(Some(backup), "id")
+			.map(ToString::to_string)
+	}
+
+	pub fn set_id(&mut self, backup: &str, id: &str) {
+		self.ini.with_section(Some(backup)).set("id", id);
+	}
+
+	/// Note given to a backup, recorded for backups named from a template since their file name
+	/// holds more than the note.
+	pub fn note(&self, backup: &str) -> Option<String> {
+		self.ini
+			.get_from(Some(backup), "note")
+			.map(ToString::to_string)
+	}
+
+	pub fn set_note(&mut self, backup: &str, note: &str) {
+		self.ini.with_section(Some(backup)).set("note", note);
+	}
+
+	/// Branch a backup belongs to and the number of the backup it was started from, recorded for
+	/// backups taken by test runs.
+	pub fn branch(&self, backup: &str) -> Option<(String, usize)> {
+		let name = self.ini.get_from(Some(backup), "branch")?;
+		let parent = self.ini.get_from(Some(backup), "parent")?.parse().ok()?;
+		Some((name.to_string(), parent))
+	}
+
+	pub fn set_branch(&mut self, backup: &str, name: &str, parent: usize) {
+		self.ini
+			.with_section(Some(backup))
+			.set("branch", name)
+			.set("parent", parent.to_string());
+	}
+
+	/// Name of the backup with the given identifier.
+	pub fn find_id(&self, id: &str) -> Option<String> {
+		self.backups()
+			.into_iter()
+			.find(|backup| self.ini.get_from(Some(backup.as_str()), "id") == Some(id))
+	}
+
+	/// Names of all backups with recorded metadata.
+	pub fn backups(&self) -> Vec<String> {
+		self.ini
+			.sections()
+			.flatten()
+			.map(ToString::to_string)
+			.collect()
+	}
+
+	/// Forgets everything recorded about a backup.
+	pub fn remove(&mut self, backup: &str) {
+		self.ini.delete(Some(backup));
+	}
+
+	/// Moves everything recorded about a backup over to its new file name, in place of anything
+	/// recorded under that name before.
+	pub fn rename(&mut self, backup: &str, new_name: &str) {
+		if let Some(properties) = self.ini.delete(Some(backup)) {
+			// anything left under the new name belonged to a file that is no longer there
+			self.ini.delete(Some(new_name));
+			self.ini
+				.entry(Some(new_name.to_string()))
+				.or_insert(properties);
+		}
+	}
+}
@@ -0,0 +1,204 @@
+use ini::Ini;
+
+use log::warn;
+
+use crate::i18n;
+use crate::profiles;
+
+/// An option of the main menu. Game profiles name options by their id, which is the key of their
+/// text without `menu.`, such as `auto` or `browse`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MenuOption {
+	SelectProfile,
+	SetGame,
+	Backup,
+	BackupWithNote,
+	Import,
+	FindLostSaves,
+	Browse,
+	PanicRestore,
+	Undo,
+	Behind,
+	Inspect,
+	RestoreArchive,
+	Export,
+	Auto,
+	BackupThenAuto,
+	PauseAuto,
+	ResumeAuto,
+	StopAuto,
+	DeleteOld,
+	Storage,
+	Progress,
+	Timeline,
+	Audit,
+	Abandoned,
+	RebuildIndex,
+	CheckConsistency,
+	Verify,
+	SyncNow,
+	ScheduledTasks,
+	ToggleCompression,
+	ToggleNotifications,
+	ToggleAutosaves,
+	Retention,
+	ShareSettings,
+	Quit,
+}
+
+impl MenuOption {
+	/// Every option, in the order of the menu.
+	pub const ALL: [Self; 35] = [
+		Self::SelectProfile,
+		Self::SetGame,
+		Self::Backup,
+		Self::BackupWithNote,
+		Self::Import,
+		Self::FindLostSaves,
+		Self::Browse,
+		Self::PanicRestore,
+		Self::Undo,
+		Self::Behind,
+		Self::Inspect,
+		Self::RestoreArchive,
+		Self::Export,
+		Self::Auto,
+		Self::BackupThenAuto,
+		Self::PauseAuto,
+		Self::ResumeAuto,
+		Self::StopAuto,
+		Self::DeleteOld,
+		Self::Storage,
+		Self::Progress,
+		Self::Timeline,
+		Self::Audit,
+		Self::Abandoned,
+		Self::RebuildIndex,
+		Self::CheckConsistency,
+		Self::Verify,
+		Self::SyncNow,
+		Self::ScheduledTasks,
+		Self::ToggleCompression,
+		Self::ToggleNotifications,
+		Self::ToggleAutosaves,
+		Self::Retention,
+		Self::ShareSettings,
+		Self::Quit,
+	];
+
+	/// Key of the option's text in the localization table.
+	pub const fn key(self) -> &'static str {
+		match self {
+			Self::SelectProfile => "menu.select_profile",
+			Self::SetGame => "menu.set_game",
+			Self::Backup => "menu.backup",
+			Self::BackupWithNote => "menu.backup_with_note",
+			Self::Import => "menu.import",
+			Self::FindLostSaves => "menu.find_lost_saves",
+			Self::Browse => "menu.browse",
+			Self::PanicRestore => "menu.panic_restore",
+			Self::Undo => "menu.undo",
+			Self::Behind => "menu.behind",
+			Self::Inspect => "menu.inspect",
+			Self::RestoreArchive => "menu.restore_archive",
+			Self::Export => "menu.export",
+			Self::Auto => "menu.auto",
+			Self::BackupThenAuto => "menu.backup_then_auto",
+			Self::PauseAuto => "menu.pause_auto",
+			Self::ResumeAuto => "menu.resume_auto",
+			Self::StopAuto => "menu.stop_auto",
+			Self::DeleteOld => "menu.delete_old",
+			Self::Storage => "menu.storage",
+			Self::Progress => "menu.progress",
+			Self::Timeline => "menu.timeline",
+			Self::Audit => "menu.audit",
+			Self::Abandoned => "menu.abandoned",
+			Self::RebuildIndex => "menu.rebuild_index",
+			Self::CheckConsistency => "menu.check_consistency",
+			Self::Verify => "menu.verify",
+			Self::SyncNow => "menu.sync_now",
+			Self::ScheduledTasks => "menu.scheduled_tasks",
+			Self::ToggleCompression => "menu.toggle_compression",
+			Self::ToggleNotifications => "menu.toggle_notifications",
+			Self::ToggleAutosaves => "menu.toggle_autosaves",
+			Self::Retention => "menu.retention",
+			Self::ShareSettings => "menu.share",
+			Self::Quit => "menu.quit",
+		}
+	}
+
+	/// Name of the option in conf.ini.
+	pub fn id(self) -> &'static str {
+		self.key().trim_start_matches("menu.")
+	}
+
+	/// The option named `id` in conf.ini.
+	pub fn from_id(id: &str) -> Option<Self> {
+		Self::ALL.iter().copied().find(|option| option.id() == id)
+	}
+
+	/// The option's text in the language picked.
+	pub fn label(self) -> &'static str {
+		i18n::text(self.key())
+	}
+
+	/// Whether the option writes to the backup folder, so is unavailable when it is read-only.
+	pub const fn writes(self) -> bool {
+		matches!(
+			self,
+			Self::Backup
+				| Self::BackupWithNote
+				| Self::Import
+				| Self::FindLostSaves
+				| Self::Auto | Self::BackupThenAuto
+				| Self::Audit
+				| Self::Abandoned
+				| Self::DeleteOld
+				| Self::RebuildIndex
+				| Self::CheckConsistency
+				| Self::Verify
+				| Self::SyncNow
+		)
+	}
+}
+
+/// Reads a comma separated list of options from `key` of a game profile, warning about names that
+/// are not options.
+fn options(config: &Ini, profile: &str, key: &str) -> Vec<MenuOption> {
+	config
+		.get_from(Some(profiles::section(profile)), key)
+		.unwrap_or_default()
+		.split(',')
+		.map(str::trim)
+		.filter(|id| !id.is_empty())
+		.filter_map(|id| {
+			let option = MenuOption::from_id(id);
+			if option.is_none() {
+				warn!("{} in {} is not a menu option", id, key);
+			}
+			option
+		})
+		.collect()
+}
+
+/// The options of the main menu for a game profile. Its `menu_order` key lists the options that
+/// come first, in that order, with the rest following in the usual order, and `menu_hidden` the
+/// options left out, such as `auto, backup_then_auto` for a profile only kept as an archive.
+/// Selecting a profile and quitting are never hidden.
+pub fn layout(config: &Ini, profile: &str) -> Vec<MenuOption> {
+	let mut layout = Vec::new();
+	for option in options(config, profile, "menu_order")
+		.into_iter()
+		.chain(MenuOption::ALL)
+	{
+		if !layout.contains(&option) {
+			layout.push(option);
+		}
+	}
+
+	let hidden = options(config, profile, "menu_hidden");
+	layout.retain(|option| {
+		matches!(option, MenuOption::SelectProfile | MenuOption::Quit) || !hidden.contains(option)
+	});
+	layout
+}
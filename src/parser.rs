@@ -0,0 +1,273 @@
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Cursor, Read};
+use std::path::Path;
+use std::str::FromStr;
+
+use zip::ZipArchive;
+
+/// How much of a save to scan for header fields, which all come before the bulk of the game state.
+const HEADER_LIMIT: u64 = 64 * 1024;
+
+/// First bytes of a zip archive, which is how the game stores saves when compression is on.
+pub const ZIP_MAGIC: &[u8] = b"PK\x03\x04";
+
+/// An in-game date, written as `year.month.day` in Paradox saves.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct GameDate {
+	pub year: u16,
+	pub month: u8,
+	pub day: u8,
+}
+
+impl GameDate {
+	pub const MIN: Self = Self {
+		year: 0,
+		month: 1,
+		day: 1,
+	};
+
+	pub const MAX: Self = Self {
+		year: u16::MAX,
+		month: 12,
+		day: 31,
+	};
+}
+
+impl FromStr for GameDate {
+	type Err = String;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		let invalid = || format!("\"{}\" is not a date like 1066.9.15", s);
+
+		let mut parts = s.trim().trim_matches('"').split('.');
+		let mut next = || parts.next().ok_or_else(invalid);
+		let year = next()?.parse().map_err(|_| invalid())?;
+		let month = next()?.parse().map_err(|_| invalid())?;
+		let day = next()?.parse().map_err(|_| invalid())?;
+
+		Ok(Self { year, month, day })
+	}
+}
+
+impl fmt::Display for GameDate {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "{}.{}.{}", self.year, self.month, self.day)
+	}
+}
+
+/// What the header of a save tells about the game in it. Fields the header lacks are `None`.
+#[derive(Default)]
+pub struct Header {
+	pub date: Option<GameDate>,
+	/// Name of the character being played.
+	pub player_name: Option<String>,
+	/// Primary title of the character being played, such as `k_munster`.
+	pub player_realm: Option<String>,
+	/// Version of the game that wrote the save.
+	pub version: Option<String>,
+}
+
+impl Header {
+	/// The character being played with their rank, such as "King Murchad", taken from the tier of
+	/// their primary title.
+	pub fn player(&self) -> Option<String> {
+		let name = self.player_name.as_ref()?;
+		let rank = match self
+			.player_realm
+			.as_deref()
+			.and_then(|realm| realm.split_once('_'))
+		{
+			Some(("e", _)) => "Emperor ",
+			Some(("k", _)) => "King ",
+			Some(("d", _)) => "Duke ",
+			Some(("c", _)) => "Count ",
+			Some(("b", _)) => "Baron ",
+			_ => "",
+		};
+
+		Some(format!("{}{}", rank, name))
+	}
+
+	/// The in-game date and character, such as "1187.3.12, King Murchad", or `None` when the
+	/// header holds neither.
+	pub fn describe(&self) -> Option<String> {
+		let parts = self
+			.date
+			.map(|date| date.to_string())
+			.into_iter()
+			.chain(self.player())
+			.collect::<Vec<_>>();
+
+		(!parts.is_empty()).then(|| parts.join(", "))
+	}
+}
+
+/// Reads the header of a save file. Anything that cannot be read is left out of the header.
+pub fn read_header(path: &Path) -> Header {
+	File::open(path).map_or_else(|_| Header::default(), read_header_from)
+}
+
+/// Reads the header of a save being read from anywhere, such as a compressed backup. Saves the
+/// game compressed itself are zip archives, whose `meta` entry holds the header.
+pub fn read_header_from(save: impl Read) -> Header {
+	let mut reader = BufReader::new(save);
+	match reader.fill_buf() {
+		Ok(start) if start.starts_with(ZIP_MAGIC) => read_zipped_header(reader).unwrap_or_default(),
+		Ok(_) => parse_header(reader),
+		Err(_) => Header::default(),
+	}
+}
+
+/// Standing of the character being played, taken from the game state rather than the header.
+/// Fields the save does not show are `None`.
+#[derive(Default)]
+pub struct Summary {
+	pub wealth: Option<f64>,
+	pub prestige: Option<f64>,
+	pub piety: Option<f64>,
+	/// Diplomacy, martial, stewardship, intrigue and learning, without bonuses from traits.
+	pub attributes: Option<[i32; 5]>,
+	/// Titles held, from baronies up.
+	pub titles: usize,
+	/// Wars going on anywhere in the world.
+	pub wars: usize,
+}
+
+/// Counts the wars going on in a save. Unlike the header, this reads the whole save.
+pub fn count_wars(path: &Path) -> io::Result<usize> {
+	Ok(summarize_from(File::open(path)?)?.wars)
+}
+
+/// Reads the standing of the character being played from a save being read from anywhere, such as
+/// a compressed backup. This reads the whole save.
+pub fn summarize_from(save: impl Read) -> io::Result<Summary> {
+	let mut reader = BufReader::new(save);
+	if !reader.fill_buf()?.starts_with(ZIP_MAGIC) {
+		return Ok(summarize(reader));
+	}
+
+	// the game state is the entry next to `meta`
+	let mut contents = Vec::new();
+	reader.read_to_end(&mut contents)?;
+	let mut zip = ZipArchive::new(Cursor::new(contents))?;
+	for index in 0..zip.len() {
+		let entry = zip.by_index(index)?;
+		if entry.name() != "meta" {
+			return Ok(summarize(BufReader::new(entry)));
+		}
+	}
+
+	Ok(Summary::default())
+}
+
+/// Walks the blocks of the game state, keeping track of which block each line is in. The player's
+/// ID comes first, followed by their character in the `character` block and the holders of titles
+/// in the `title` block.
+fn summarize(reader: impl BufRead) -> Summary {
+	let mut summary = Summary::default();
+	let mut player = None;
+	let mut blocks: Vec<String> = Vec::new();
+	let mut pending = None;
+
+	for line in reader.split(b'\n').filter_map(Result::ok) {
+		let line = String::from_utf8_lossy(&line);
+		let line = line.trim();
+		if line == "}" {
+			blocks.pop();
+			continue;
+		}
+
+		// blocks open either on the line of their key or on the line after it
+		let opened: String;
+		let (key, value) = match line.split_once('=') {
+			Some((key, value)) => (key.trim(), value.trim()),
+			None if line == "{" => {
+				opened = pending.take().unwrap_or_default();
+				(opened.as_str(), "{")
+			}
+			None => continue,
+		};
+		pending = None;
+		if value.is_empty() {
+			pending = Some(key.to_string());
+			continue;
+		}
+		if value == "{" {
+			if blocks.is_empty() && key == "active_war" {
+				summary.wars += 1;
+			}
+			blocks.push(key.to_string());
+			continue;
+		}
+
+		let number = || value.trim_matches('"').parse::<f64>().ok();
+		let path = blocks.iter().map(String::as_str).collect::<Vec<_>>();
+		match path.as_slice() {
+			["player"] if key == "id" => player = Some(value.to_string()),
+			["character", id] if Some(*id) == player.as_deref() => match key {
+				"wealth" => summary.wealth = number(),
+				"prestige" => summary.prestige = number(),
+				"piety" => summary.piety = number(),
+				"att" => {
+					let values = value
+						.trim_matches(|c| c == '{' || c == '}')
+						.split_whitespace()
+						.filter_map(|value| value.parse().ok())
+						.collect::<Vec<i32>>();
+					if let [diplomacy, martial, stewardship, intrigue, learning] = values[..] {
+						summary.attributes =
+							Some([diplomacy, martial, stewardship, intrigue, learning]);
+					}
+				}
+				_ => {}
+			},
+			["title", _] if key == "holder" && Some(value) == player.as_deref() => {
+				summary.titles += 1;
+			}
+			_ => {}
+		}
+	}
+
+	summary
+}
+
+fn read_zipped_header(mut reader: impl Read) -> Option<Header> {
+	// zip archives are read from the end, so the whole save has to be at hand
+	let mut contents = Vec::new();
+	reader.read_to_end(&mut contents).ok()?;
+	let mut zip = ZipArchive::new(Cursor::new(contents)).ok()?;
+	let index = zip.index_for_name("meta").unwrap_or(0);
+	let entry = zip.by_index(index).ok()?;
+
+	Some(parse_header(BufReader::new(entry)))
+}
+
+fn parse_header(reader: impl BufRead) -> Header {
+	let mut header = Header::default();
+
+	for line in reader
+		.take(HEADER_LIMIT)
+		.split(b'\n')
+		.filter_map(Result::ok)
+	{
+		let line = String::from_utf8_lossy(&line);
+		let (key, value) = match line.trim().split_once('=') {
+			Some((key, value)) => (key, value.trim_matches('"')),
+			None => continue,
+		};
+		match key {
+			"date" if header.date.is_none() => header.date = value.parse().ok(),
+			"player_name" if header.player_name.is_none() => {
+				header.player_name = Some(value.to_string());
+			}
+			"player_realm" if header.player_realm.is_none() => {
+				header.player_realm = Some(value.to_string());
+			}
+			"version" if header.version.is_none() => header.version = Some(value.to_string()),
+			_ => {}
+		}
+	}
+
+	header
+}
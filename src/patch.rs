@@ -0,0 +1,160 @@
+use std::fs;
+use std::time::SystemTime;
+
+use cursive::views::{Dialog, TextView};
+use cursive::Cursive;
+
+use ini::Ini;
+
+use log::{info, warn};
+
+use serde_json::Value as Json;
+
+use crate::error;
+use crate::i18n;
+use crate::manifest::Origin;
+use crate::parser;
+use crate::profiles::{self, Profile};
+use crate::settings::{backup_options, write_config};
+use crate::store::{self, backup_core};
+
+/// Key of a game profile naming a file the game's version can be read from, such as the
+/// launcher's `launcher-settings.json`.
+const VERSION_FILE_KEY: &str = "version_file";
+
+/// Key of a game profile recording the game's version when the save manager last ran.
+const SEEN_VERSION_KEY: &str = "seen_version";
+
+/// The version of the game right now. It is read from the profile's `version_file` when set,
+/// which knows about a patch before it is played, and otherwise from the header of the newest
+/// save, which only does once a save was written on it.
+fn current_version(config: &Ini, profile: &Profile) -> Option<String> {
+	let version_file = config
+		.get_from(Some(profiles::section(&profile.name)), VERSION_FILE_KEY)
+		.map(str::trim)
+		.filter(|path| !path.is_empty());
+
+	let version = match version_file {
+		Some(path) => {
+			let contents = fs::read_to_string(path).ok()?;
+			serde_json::from_str::<Json>(&contents).map_or_else(
+				|_| contents.lines().next().map(ToString::to_string),
+				|json| {
+					["rawVersion", "version"]
+						.iter()
+						.find_map(|key| json.get(key)?.as_str().map(ToString::to_string))
+				},
+			)
+		}
+		None => {
+			let newest = fs::read_dir(&profile.save_path)
+				.ok()?
+				.filter_map(Result::ok)
+				.map(|entry| entry.path())
+				.filter(|path| path.to_string_lossy().ends_with(&profile.extension))
+				.max_by_key(|path| {
+					fs::metadata(path)
+						.and_then(|metadata| metadata.modified())
+						.unwrap_or(SystemTime::UNIX_EPOCH)
+				})?;
+			parser::read_header(&newest).version
+		}
+	};
+
+	version
+		.map(|version| version.trim().to_string())
+		.filter(|version| !version.is_empty())
+}
+
+/// Checks whether the game was updated since the last run, and if so offers to back up every
+/// tracked save before playing on the new version, when a rollback is most likely to be needed.
+pub fn check(s: &mut Cursive, profile: &Profile) -> error::Result<()> {
+	let config: &mut Ini = s
+		.user_data()
+		.expect("User data not set up correctly on program start");
+	let version = match current_version(config, profile) {
+		Some(version) => version,
+		None => return Ok(()),
+	};
+
+	let section = profiles::section(&profile.name);
+	let seen = config
+		.get_from(Some(&section), SEEN_VERSION_KEY)
+		.map(ToString::to_string);
+	if seen.as_ref() == Some(&version) {
+		return Ok(());
+	}
+
+	config
+		.with_section(Some(&section))
+		.set(SEEN_VERSION_KEY, &version);
+	write_config(config)?;
+
+	// the first run has nothing to compare against
+	let previous = match seen {
+		Some(previous) => previous,
+		None => return Ok(()),
+	};
+	info!(
+		"{} was updated from version {} to {}",
+		profile.name, previous, version
+	);
+	if profile.read_only {
+		return Ok(());
+	}
+
+	let profile = profile.clone();
+	s.add_layer(
+		Dialog::around(TextView::new(format!(
+			"{} was updated from version {} to {}.\n\nBack up every tracked save before playing on the new version?",
+			profile.name, previous, version
+		)))
+		.title("Game updated")
+		.button("Skip", |s| {
+			s.pop_layer();
+		})
+		.button("Back up all saves", move |s| {
+			s.pop_layer();
+			match snapshot(s, &profile, &previous) {
+				Ok(count) => s.add_layer(Dialog::info(format!(
+					"Backed up {} saves from version {}.",
+					count, previous
+				))),
+				Err(e) => s.add_layer(Dialog::info(i18n::error(&e))),
+			}
+		}),
+	);
+
+	Ok(())
+}
+
+/// Backs up every save that has backups and still exists, noting the version it was played on.
+fn snapshot(s: &mut Cursive, profile: &Profile, version: &str) -> error::Result<usize> {
+	let config: &mut Ini = s
+		.user_data()
+		.expect("User data not set up correctly on program start");
+	let note = format!("pre-patch {}", version);
+
+	let mut count = 0;
+	for backup_dir in store::save_dirs(&profile.backup_path)? {
+		let save = match profile.save_of(&backup_dir) {
+			Some(save) => save,
+			None => continue,
+		};
+		let file_path = profile.save_file(&save);
+		if !file_path.is_file() {
+			warn!("{} no longer exists, not backed up", file_path.display());
+			continue;
+		}
+
+		let options = backup_options(config, &save);
+		let created = backup_core(&file_path, &backup_dir, &note, Origin::PrePatch, &options)?;
+		info!(
+			"{}: backup number {} taken before the update",
+			save, created.number
+		);
+		count += 1;
+	}
+
+	Ok(count)
+}
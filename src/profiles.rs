@@ -0,0 +1,288 @@
+use std::cell::RefCell;
+use std::env;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use ini::Ini;
+
+use crate::error;
+use crate::index;
+use crate::store;
+use crate::titles;
+
+/// Prefix of the conf.ini sections describing a game, such as `[game.Europa Universalis IV]`.
+pub const SECTION_PREFIX: &str = "game.";
+
+/// Name of the profile for the game the save manager was installed into.
+pub const BUILTIN_NAME: &str = "Crusader Kings II";
+
+/// Folder inside the save directory that backups go to, unless a profile sets `backup_dir`.
+const BACKUP_FOLDER: &str = "save-manager";
+
+/// Suffix of the copy the game keeps next to an ironman save, such as `Ironman_Backup.eu4`.
+const IRONMAN_COPY_SUFFIX: &str = "_Backup";
+
+pub const IRONMAN_WARNING: &str =
+	"This game is played in ironman mode, restoring a save behind its back may cost it achievements.";
+
+/// A game whose saves are managed: where its saves are, what they are called and where their
+/// backups go.
+///
+/// Profiles are configured in conf.ini sections named `game.<name>` with the keys `save_dir`,
+/// `extension`, `backup_dir`, `ironman`, `recursive`, `process`, `cloud_dir` and `keep_tags`.
+#[derive(Clone)]
+pub struct Profile {
+	pub name: String,
+	pub save_path: PathBuf,
+	pub backup_path: PathBuf,
+	/// Extension of save files including the dot, such as `.ck2`.
+	pub extension: String,
+	/// Whether the backup folder is read-only, found out when the profile is activated.
+	pub read_only: bool,
+	/// Whether the game is played in ironman mode, where it keeps overwriting one save and holds
+	/// it locked while writing.
+	pub ironman: bool,
+	/// Whether saves are also looked for in folders inside the save directory, such as cloud or
+	/// per-mod folders. Such saves are named by their path inside the save directory, and their
+	/// backups kept under the same path inside the backup folder.
+	pub recursive: bool,
+	/// Executable name of the game, such as `CK2game.exe`, watched by automatic sessions to back
+	/// up the saves played on when the game exits.
+	pub process: Option<String>,
+	/// Folder the game's cloud sync keeps its copy of the saves in, such as Steam's
+	/// `userdata/<account>/<app>/remote/save games`, watched by automatic sessions so that both
+	/// copies are kept when they disagree.
+	pub cloud_path: Option<PathBuf>,
+}
+
+impl Profile {
+	/// The profile for CK2 saves in the directory found at startup.
+	pub fn builtin(save_path: PathBuf) -> Self {
+		Self {
+			name: BUILTIN_NAME.to_string(),
+			backup_path: save_path.join(BACKUP_FOLDER),
+			save_path,
+			extension: ".ck2".to_string(),
+			read_only: false,
+			ironman: false,
+			recursive: false,
+			process: None,
+			cloud_path: None,
+		}
+	}
+
+	/// Loads a configured profile. The built-in profile may be configured too, with anything left
+	/// out falling back to its defaults. Without an `extension`, the one of the known game named
+	/// like the profile or making up most of the save directory is used.
+	pub fn load(config: &Ini, name: &str, builtin: &Self) -> Option<Self> {
+		let section = config.section(Some(section(name)));
+		let get = |key: &str| {
+			section
+				.and_then(|section| section.get(key))
+				.filter(|value| !value.trim().is_empty())
+		};
+
+		let save_path = match get("save_dir") {
+			Some(save_dir) => PathBuf::from(save_dir),
+			None if name == builtin.name => builtin.save_path.clone(),
+			None => return None,
+		};
+		let backup_path =
+			get("backup_dir").map_or_else(|| save_path.join(BACKUP_FOLDER), PathBuf::from);
+		let extension = get("extension").map_or_else(
+			|| {
+				titles::find(name)
+					.or_else(|| titles::guess(&save_path))
+					.map_or_else(
+						|| builtin.extension.clone(),
+						|title| title.extension.to_string(),
+					)
+			},
+			|extension| {
+				if extension.starts_with('.') {
+					extension.to_string()
+				} else {
+					format!(".{}", extension)
+				}
+			},
+		);
+
+		Some(Self {
+			name: name.to_string(),
+			save_path,
+			backup_path,
+			extension,
+			read_only: false,
+			ironman: get("ironman") == Some("true"),
+			recursive: get("recursive") == Some("true"),
+			process: get("process").map(|process| process.trim().to_string()),
+			cloud_path: get("cloud_dir").map(PathBuf::from),
+		})
+	}
+
+	/// Path of a save of this game, given its name without the extension.
+	pub fn save_file(&self, save: &str) -> PathBuf {
+		self.save_path.join(save.to_string() + &self.extension)
+	}
+
+	/// Path of the cloud copy of a save, when the profile has a cloud folder.
+	pub fn cloud_file(&self, save: &str) -> Option<PathBuf> {
+		self.cloud_path
+			.as_ref()
+			.map(|cloud_path| cloud_path.join(save.to_string() + &self.extension))
+	}
+
+	/// Files in the save directory, and in the folders inside it when the profile is `recursive`,
+	/// leaving out the backup folder.
+	pub fn files(&self) -> io::Result<Vec<PathBuf>> {
+		fn walk(profile: &Profile, directory: &Path, files: &mut Vec<PathBuf>) -> io::Result<()> {
+			for entry in fs::read_dir(directory)?.filter_map(Result::ok) {
+				let path = entry.path();
+				if path.is_file() {
+					files.push(path);
+				} else if profile.recursive && path.is_dir() && path != profile.backup_path {
+					walk(profile, &path, files)?;
+				}
+			}
+
+			Ok(())
+		}
+
+		let mut files = Vec::new();
+		walk(self, &self.save_path, &mut files)?;
+		files.sort();
+
+		Ok(files)
+	}
+
+	/// Path of a file inside the save directory, with `/` between folders.
+	pub fn relative(&self, path: &Path) -> Option<String> {
+		let relative = path.strip_prefix(&self.save_path).ok()?;
+		if !self.recursive && relative.components().count() != 1 {
+			return None;
+		}
+
+		let parts = relative
+			.components()
+			.map(|part| part.as_os_str().to_str())
+			.collect::<Option<Vec<_>>>()?;
+		Some(parts.join("/"))
+	}
+
+	/// Name of the save a file holds, which is its path inside the save directory without the
+	/// extension, if it is a save of this game outside the backup folder.
+	pub fn save_name(&self, path: &Path) -> Option<String> {
+		if path.starts_with(&self.backup_path) {
+			return None;
+		}
+
+		self.relative(path)?
+			.strip_suffix(self.extension.as_str())
+			.filter(|save| !save.is_empty() && !save.ends_with('/'))
+			.map(ToString::to_string)
+	}
+
+	/// Name of the save whose backups are kept in a folder of the backup folder.
+	pub fn save_of(&self, backup_dir: &Path) -> Option<String> {
+		let parts = backup_dir
+			.strip_prefix(&self.backup_path)
+			.ok()?
+			.components()
+			.map(|part| part.as_os_str().to_str())
+			.collect::<Option<Vec<_>>>()?;
+		Some(parts.join("/"))
+	}
+
+	/// Whether a save is the game's own copy of an ironman save rather than a save of its own.
+	pub fn is_ironman_copy(&self, save: &str) -> bool {
+		self.ironman && save.ends_with(IRONMAN_COPY_SUFFIX)
+	}
+
+	/// Creates the backup folder if needed and checks whether it can be written to.
+	pub fn prepare(&mut self) -> io::Result<()> {
+		if !self.save_path.is_dir() {
+			return Err(io::Error::new(
+				io::ErrorKind::NotFound,
+				format!("{} is not a directory", self.save_path.display()),
+			));
+		}
+
+		if !self.backup_path.is_dir() {
+			fs::create_dir_all(&self.backup_path)?;
+		}
+		self.read_only = store::is_read_only(&self.backup_path);
+		// saves in folders of the save directory find the index of the whole backup folder
+		index::open(&self.backup_path)?;
+
+		Ok(())
+	}
+}
+
+/// The built-in profile and the one currently in use.
+pub struct Games {
+	pub builtin: Profile,
+	pub active: RefCell<Profile>,
+}
+
+/// Names of every game profile, starting with the built-in one.
+pub fn names(config: &Ini) -> Vec<String> {
+	let mut names = vec![BUILTIN_NAME.to_string()];
+	names.extend(
+		config
+			.sections()
+			.flatten()
+			.filter_map(|section| section.strip_prefix(SECTION_PREFIX))
+			.filter(|name| *name != BUILTIN_NAME)
+			.map(ToString::to_string),
+	);
+
+	names
+}
+
+/// The "save games" directory of the game the executable is installed into, three folders up in
+/// `mod/save-manager`.
+pub fn installed_save_dir() -> error::Result<PathBuf> {
+	let executable = env::current_exe().map_err(error::Error::Executable)?;
+	let game = executable
+		.ancestors()
+		.nth(3)
+		.ok_or("The executable is not inside the game's mod folder.")?;
+
+	Ok(game.join("save games"))
+}
+
+/// Whether the built-in profile is the one to use, as no other game profile with its own save
+/// directory was picked.
+pub fn needs_builtin(config: &Ini) -> bool {
+	config
+		.general_section()
+		.get("game_profile")
+		.is_none_or(|name| {
+			name == BUILTIN_NAME || config.get_from(Some(section(name)), "save_dir").is_none()
+		})
+}
+
+/// Name of the conf.ini section describing a game.
+pub fn section(name: &str) -> String {
+	format!("{}{}", SECTION_PREFIX, name)
+}
+
+/// Tags whose backups are never deleted by the retention policy or to free space, set with the
+/// comma separated `keep_tags` key of the game profile in use, such as `milestone,pre-patch`.
+pub fn keep_tags(config: &Ini) -> Vec<String> {
+	let name = config
+		.general_section()
+		.get("game_profile")
+		.unwrap_or(BUILTIN_NAME);
+	config
+		.get_from(Some(section(name)), "keep_tags")
+		.map(|tags| {
+			tags.split(',')
+				.map(str::trim)
+				.filter(|tag| !tag.is_empty())
+				.map(ToString::to_string)
+				.collect()
+		})
+		.unwrap_or_default()
+}
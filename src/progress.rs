@@ -0,0 +1,166 @@
+use std::cell::RefCell;
+use std::io;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use log::info;
+
+use crate::{format_duration, format_size};
+
+/// How often a running operation reports how far along it is.
+const REPORT_INTERVAL: Duration = Duration::from_secs(2);
+
+thread_local! {
+	/// Where operations on this thread report their progress, when run in the background.
+	static TRACKER: RefCell<Option<Arc<Tracker>>> = const { RefCell::new(None) };
+}
+
+/// How far the operation of a background job has come, shared between the thread running it and
+/// whatever shows it, such as a progress dialog.
+#[derive(Default)]
+pub struct Tracker {
+	label: Mutex<String>,
+	done: AtomicU64,
+	total: AtomicU64,
+	cancelled: AtomicBool,
+	finished: AtomicBool,
+}
+
+impl Tracker {
+	/// Makes the operations on the current thread report to `tracker` from now on.
+	pub fn track(tracker: Arc<Self>) {
+		TRACKER.with_borrow_mut(|current| *current = Some(tracker));
+	}
+
+	/// Asks the operation to stop at its next check.
+	pub fn cancel(&self) {
+		self.cancelled.store(true, Ordering::Relaxed);
+	}
+
+	pub fn is_cancelled(&self) -> bool {
+		self.cancelled.load(Ordering::Relaxed)
+	}
+
+	/// Marks the job as done, once its operations have returned.
+	pub fn finish(&self) {
+		self.finished.store(true, Ordering::Relaxed);
+	}
+
+	pub fn is_finished(&self) -> bool {
+		self.finished.load(Ordering::Relaxed)
+	}
+
+	pub fn describe(&self) -> String {
+		let done = self.done.load(Ordering::Relaxed);
+		let total = self.total.load(Ordering::Relaxed);
+		let label = self.label.lock().unwrap().clone();
+
+		if label.is_empty() {
+			"Starting...".to_string()
+		} else if total == 0 {
+			format!("{}: {} done", label, format_size(done))
+		} else {
+			format!(
+				"{}: {} of {} ({}%)",
+				label,
+				format_size(done.min(total)),
+				format_size(total),
+				done.min(total) * 100 / total
+			)
+		}
+	}
+}
+
+/// Tracks how far a long operation over a known number of bytes has come, to estimate how long
+/// the rest will take. Progress is logged every few seconds, so quick operations stay quiet.
+pub struct Progress {
+	label: String,
+	total: u64,
+	done: u64,
+	started: Instant,
+	last_report: Instant,
+	/// Set when the operation runs as a background job with a progress dialog.
+	tracker: Option<Arc<Tracker>>,
+}
+
+impl Progress {
+	pub fn new(label: &str, total: u64) -> Self {
+		let now = Instant::now();
+		let tracker = TRACKER.with_borrow(Clone::clone);
+		if let Some(tracker) = &tracker {
+			*tracker.label.lock().unwrap() = label.to_string();
+			tracker.done.store(0, Ordering::Relaxed);
+			tracker.total.store(total, Ordering::Relaxed);
+		}
+
+		Self {
+			label: label.to_string(),
+			total,
+			done: 0,
+			started: now,
+			last_report: now,
+			tracker,
+		}
+	}
+
+	pub fn advance(&mut self, bytes: u64) {
+		self.done += bytes;
+		if let Some(tracker) = &self.tracker {
+			tracker.done.store(self.done, Ordering::Relaxed);
+		}
+
+		if self.last_report.elapsed() >= REPORT_INTERVAL {
+			self.last_report = Instant::now();
+			info!("{}: {}", self.label, self.describe());
+		}
+	}
+
+	/// Fails once the job running the operation was cancelled from its progress dialog, for
+	/// checking between chunks of work.
+	pub fn check_cancelled(&self) -> io::Result<()> {
+		match &self.tracker {
+			Some(tracker) if tracker.cancelled.load(Ordering::Relaxed) => {
+				Err(io::Error::other("Cancelled"))
+			}
+			_ => Ok(()),
+		}
+	}
+
+	/// Bytes processed per second so far.
+	pub fn throughput(&self) -> f64 {
+		let elapsed = self.started.elapsed().as_secs_f64();
+		if elapsed > 0.0 {
+			self.done as f64 / elapsed
+		} else {
+			0.0
+		}
+	}
+
+	/// Time left at the throughput so far, unknown until something has been processed.
+	pub fn eta(&self) -> Option<Duration> {
+		let throughput = self.throughput();
+		if throughput <= 0.0 || self.total == 0 {
+			return None;
+		}
+
+		let remaining = self.total.saturating_sub(self.done);
+		Some(Duration::from_secs_f64(remaining as f64 / throughput))
+	}
+
+	pub fn describe(&self) -> String {
+		let mut description = (self.done.min(self.total) * 100)
+			.checked_div(self.total)
+			.map_or_else(
+				|| format_size(self.done),
+				|percent| format!("{}% of {}", percent, format_size(self.total)),
+			);
+
+		description.push_str(&format!(", {}/s", format_size(self.throughput() as u64)));
+		if let Some(eta) = self.eta() {
+			description.push_str(&format!(", about {} left", format_duration(eta)));
+		}
+
+		description
+	}
+}
@@ -0,0 +1,77 @@
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use cursive::traits::*;
+use cursive::views::{Dialog, TextView};
+use cursive::Cursive;
+
+use crate::progress::Tracker;
+
+/// How often the progress dialog of a background job is updated.
+const DIALOG_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Names of the progress dialog of a background job and of its text.
+const PROGRESS_DIALOG_NAME: &str = "progress_dialog";
+const PROGRESS_TEXT_NAME: &str = "progress_text";
+
+/// Runs a job that copies saves on another thread so the interface keeps responding, showing its
+/// progress in a dialog with a button to cancel it. `done` is called with the job's result once
+/// it finishes, after the dialog is closed.
+pub fn run_in_background<T, J, D>(s: &mut Cursive, title: &str, job: J, done: D)
+where
+	T: Send + 'static,
+	J: FnOnce() -> Result<T, String> + Send + 'static,
+	D: FnOnce(&mut Cursive, Result<T, String>) + Send + 'static,
+{
+	let tracker = Arc::new(Tracker::default());
+	let sink = s.cb_sink().clone();
+
+	s.add_layer(
+		Dialog::around(TextView::new("Starting...").with_name(PROGRESS_TEXT_NAME))
+			.title(title)
+			.button("Cancel", {
+				let tracker = Arc::clone(&tracker);
+				move |_| tracker.cancel()
+			})
+			.with_name(PROGRESS_DIALOG_NAME)
+			.min_width(50),
+	);
+
+	// keeps the dialog up to date until the job finishes
+	{
+		let tracker = Arc::clone(&tracker);
+		let sink = sink.clone();
+		thread::spawn(move || {
+			while !tracker.is_finished() {
+				let text = if tracker.is_cancelled() {
+					"Cancelling...".to_string()
+				} else {
+					tracker.describe()
+				};
+				let update = sink.send(Box::new(move |s| {
+					s.call_on_name(PROGRESS_TEXT_NAME, |view: &mut TextView| {
+						view.set_content(text)
+					});
+				}));
+				if update.is_err() {
+					break;
+				}
+				thread::sleep(DIALOG_INTERVAL);
+			}
+		});
+	}
+
+	thread::spawn(move || {
+		Tracker::track(Arc::clone(&tracker));
+		let result = job();
+		tracker.finish();
+
+		let _ = sink.send(Box::new(move |s| {
+			if let Some(position) = s.screen_mut().find_layer_from_name(PROGRESS_DIALOG_NAME) {
+				s.screen_mut().remove_layer(position);
+			}
+			done(s, result);
+		}));
+	});
+}
@@ -0,0 +1,97 @@
+use ini::Ini;
+
+use crate::settings::save_section;
+
+/// A bundle of settings for a save, picked with its `protection` key instead of tweaking each
+/// setting on its own, or `none` for no preset.
+///
+/// Keys set in the save's own `[save.<name>]` section still win over the preset, and the preset
+/// wins over the general section.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Protection {
+	/// Back up on every write and every few minutes, keep everything, confirm by typing and
+	/// notify about every backup.
+	Paranoid,
+	/// Back up when the game writes, thin out old automatic backups and stay quiet.
+	Casual,
+	/// Back up compressed once an hour and keep everything, for campaigns that are mostly done.
+	ArchiveOnly,
+}
+
+impl Protection {
+	pub const ALL: [Self; 3] = [Self::Paranoid, Self::Casual, Self::ArchiveOnly];
+
+	pub fn from_setting(setting: Option<&str>) -> Option<Self> {
+		match setting? {
+			"paranoid" => Some(Self::Paranoid),
+			"casual" => Some(Self::Casual),
+			"archive" => Some(Self::ArchiveOnly),
+			_ => None,
+		}
+	}
+
+	/// Value of the `protection` key that picks the preset.
+	pub const fn key(self) -> &'static str {
+		match self {
+			Self::Paranoid => "paranoid",
+			Self::Casual => "casual",
+			Self::ArchiveOnly => "archive",
+		}
+	}
+
+	pub const fn label(self) -> &'static str {
+		match self {
+			Self::Paranoid => "Ironman-paranoid",
+			Self::Casual => "Casual",
+			Self::ArchiveOnly => "Archive-only",
+		}
+	}
+
+	/// Value the preset gives a setting, if it covers it.
+	pub fn setting(self, key: &str) -> Option<&'static str> {
+		let settings: &[(&str, &str)] = match self {
+			Self::Paranoid => &[
+				("auto_mode", "both"),
+				("auto_interval", "5"),
+				("retention", "all"),
+				("size_alert_ratio", "1.5"),
+				("confirmations", "paranoid"),
+				("notify_desktop", "true"),
+				("notify_sound", "true"),
+			],
+			Self::Casual => &[
+				("auto_mode", "watch"),
+				("retention", "tiered"),
+				("retention_hourly", "12"),
+				("retention_daily", "14"),
+				("confirmations", "normal"),
+				("notify_desktop", "false"),
+				("notify_sound", "false"),
+			],
+			Self::ArchiveOnly => &[
+				("auto_mode", "interval"),
+				("auto_interval", "60"),
+				("retention", "all"),
+				("compression", "zstd"),
+				("confirmations", "normal"),
+				("notify_desktop", "false"),
+				("notify_sound", "false"),
+			],
+		};
+
+		settings
+			.iter()
+			.find(|(setting, _)| *setting == key)
+			.map(|(_, value)| *value)
+	}
+}
+
+/// Protection level of a save, from its own section or the general section.
+pub fn protection(config: &Ini, save: &str) -> Option<Protection> {
+	Protection::from_setting(
+		config
+			.get_from(Some(save_section(save)), "protection")
+			.or_else(|| config.general_section().get("protection"))
+			.map(str::trim),
+	)
+}
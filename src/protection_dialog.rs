@@ -0,0 +1,94 @@
+use cursive::views::{Button, Dialog, SelectView};
+use cursive::Cursive;
+
+use ini::Ini;
+
+use log::info;
+
+use crate::i18n;
+use crate::protection::{protection, Protection};
+use crate::settings::{current_save, save_section, write_config};
+
+/// Name of the button in the status line that shows and switches the protection level.
+pub const BUTTON_NAME: &str = "protection_button";
+
+/// Text of the status line button for the working save.
+pub fn button_label(config: &Ini) -> String {
+	let save = current_save(config).unwrap_or_default();
+	format!(
+		"Protection: {}",
+		protection(config, save).map_or("custom", Protection::label)
+	)
+}
+
+/// Shows the protection level of the working save in the status line.
+pub fn update_button(s: &mut Cursive) {
+	let label = s
+		.with_user_data(|config: &mut Ini| button_label(config))
+		.expect("User data not set up correctly on program start");
+	s.call_on_name(BUTTON_NAME, |button: &mut Button| button.set_label(label));
+}
+
+/// Lets the protection level of the working save be switched, writing it to the save's section.
+pub fn pick(s: &mut Cursive) {
+	let config: &mut Ini = s
+		.user_data()
+		.expect("User data not set up correctly on program start");
+	let save = match current_save(config) {
+		Some(save) => save.to_string(),
+		None => {
+			s.add_layer(Dialog::info("No save file has been set."));
+			return;
+		}
+	};
+	let current = protection(config, &save);
+
+	let mut list = SelectView::<Option<Protection>>::new();
+	for preset in &Protection::ALL {
+		list.add_item(preset.label(), Some(*preset));
+	}
+	list.add_item("Custom (the settings in conf.ini)", None);
+	let selected = Protection::ALL
+		.iter()
+		.position(|preset| Some(*preset) == current)
+		.unwrap_or(Protection::ALL.len());
+	list.set_selection(selected);
+
+	s.add_layer(
+		Dialog::around(list.on_submit(move |s, preset: &Option<Protection>| {
+			let preset = *preset;
+			let written = s
+				.with_user_data(|config: &mut Ini| {
+					let section = save_section(&save);
+					match preset {
+						Some(preset) => {
+							config
+								.with_section(Some(section))
+								.set("protection", preset.key());
+						}
+						// set rather than removed, so that a preset in the general section does not apply
+						None => {
+							config.with_section(Some(section)).set("protection", "none");
+						}
+					}
+					write_config(config)
+				})
+				.expect("User data not set up correctly on program start");
+			info!(
+				"Protection of {} set to {}, automatic sessions started from now on use it",
+				save,
+				preset.map_or("custom", Protection::label)
+			);
+
+			s.pop_layer();
+			update_button(s);
+			if let Err(e) = written {
+				s.add_layer(Dialog::info(i18n::error(&e)));
+			}
+		}))
+		.title("Protection level")
+		.button("Cancel", |s| {
+			s.pop_layer();
+		}),
+	);
+}
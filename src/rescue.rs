@@ -0,0 +1,235 @@
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+use cursive::views::{Dialog, TextView};
+use cursive::Cursive;
+
+use ini::Ini;
+
+use crate::confirm::confirm;
+use crate::error;
+use crate::i18n;
+use crate::manifest::{Manifest, Origin};
+use crate::profiles::{Profile, IRONMAN_WARNING};
+use crate::progress_dialog;
+use crate::settings::current_save;
+use crate::store::{self, Backup};
+
+/// Newest backup worth trusting after a disaster: pinned, or taken on purpose rather than by an
+/// automatic session that may have captured the disaster itself.
+fn latest_trusted(backup_dir: &Path) -> io::Result<Option<Backup>> {
+	let manifest = Manifest::load(backup_dir);
+
+	Ok(store::list_backups(backup_dir)?
+		.into_iter()
+		.rev()
+		.find(|backup| {
+			manifest.is_pinned(&backup.file_name)
+				|| matches!(
+					manifest.origin(&backup.file_name),
+					Some(Origin::Manual)
+						| Some(Origin::Note)
+						| Some(Origin::Cli)
+						| Some(Origin::PrePatch)
+				)
+		}))
+}
+
+/// Puts the newest pinned or manual backup back in one confirmed step, for the moment right after
+/// an ironman disaster. Automatic backups are stopped first so the restore is not backed up over
+/// the good state, and the restored save is checked against the backup before it is reported
+/// ready.
+pub fn panic_restore(s: &mut Cursive, profile: &Profile) -> error::Result<()> {
+	let config: &mut Ini = s
+		.user_data()
+		.expect("User data not set up correctly on program start");
+	let save = current_save(config)
+		.ok_or("No save file has been set.")?
+		.to_string();
+
+	let backup = latest_trusted(&profile.backup_path.join(&save))?
+		.ok_or("There is no pinned or manual backup to restore.")?;
+	let destination = profile.save_file(&save);
+
+	let auto_running = crate::auto_running();
+	let mut message = format!("Restore backup number {}", backup.number);
+	if let Some(description) = backup.header().describe() {
+		message.push_str(&format!(" from {}", description));
+	}
+	if !backup.note.is_empty() {
+		message.push_str(&format!(" ({})", backup.note));
+	}
+	message.push_str(&format!(" over {}?", destination.display()));
+	// one step is all there is time for, so how far back it goes is told here
+	if let Some(rewind) = store::rewind(&backup, &destination) {
+		message.push_str(&format!("\nThis goes back\n{}", rewind.describe()));
+	}
+	if auto_running {
+		message.push_str("\nAutomatic backups will be stopped first.");
+	}
+	if profile.ironman {
+		message.push_str(&format!("\n\n{}", IRONMAN_WARNING));
+	}
+
+	confirm(s, &message, move |s| {
+		let started = Instant::now();
+		crate::stop_auto(s, |_| {});
+
+		let number = backup.number;
+		let destination = destination.clone();
+		let restore = {
+			let backup = backup.clone();
+			let destination = destination.clone();
+			move || {
+				let kept = store::restore_core(&backup, &destination)?;
+				let verified = store::hash_file(&destination)? == store::hash_file(&backup.path)?;
+				Ok((kept, verified))
+			}
+		};
+		progress_dialog::run_in_background(
+			s,
+			"Restoring",
+			move || restore().map_err(|e: error::Error| e.to_string()),
+			move |s, restored| {
+				let (report, kept) = match restored {
+					Ok((kept, true)) => (
+						Dialog::around(TextView::new(format!(
+							"Backup number {} restored and verified in {:.1} seconds.\n\nThe save is ready to load.",
+							number,
+							started.elapsed().as_secs_f64()
+						)))
+						.title("Ready"),
+						kept,
+					),
+					Ok((kept, false)) => (
+						Dialog::around(TextView::new(format!(
+							"Backup number {} was restored, but the save does not match it.\n\nTry again, or pick a backup from Browse backups.",
+							number
+						)))
+						.title("Not verified"),
+						kept,
+					),
+					Err(e) => (
+						Dialog::around(TextView::new(i18n::error(&e))),
+						None,
+					),
+				};
+				let report = report.button("Ok", |s| {
+					s.pop_layer();
+				});
+				s.add_layer(match kept {
+					Some(kept) => with_undo(report, kept, destination),
+					None => report,
+				});
+			},
+		);
+	});
+
+	Ok(())
+}
+
+/// Restores the newest backup of the working save, whatever its origin, after a confirmation.
+pub fn restore_latest(s: &mut Cursive, profile: &Profile) -> error::Result<()> {
+	let config: &mut Ini = s
+		.user_data()
+		.expect("User data not set up correctly on program start");
+	let save = current_save(config)
+		.ok_or("No save file has been set.")?
+		.to_string();
+
+	let backup = store::list_backups(&profile.backup_path.join(&save))?
+		.pop()
+		.ok_or("There is no backup of this save yet.")?;
+	let destination = profile.save_file(&save);
+
+	let mut message = format!("Restore the newest backup, number {}", backup.number);
+	if let Some(description) = backup.header().describe() {
+		message.push_str(&format!(" from {}", description));
+	}
+	message.push_str(&format!(" over {}?", destination.display()));
+	if profile.ironman {
+		message.push_str(&format!("\n\n{}", IRONMAN_WARNING));
+	}
+
+	let restore = {
+		let backup = backup.clone();
+		let destination = destination.clone();
+		move |s: &mut Cursive| {
+			let number = backup.number;
+			let backup = backup.clone();
+			let destination = destination.clone();
+			progress_dialog::run_in_background(
+				s,
+				"Restoring",
+				{
+					let destination = destination.clone();
+					move || store::restore_core(&backup, &destination).map_err(|e| e.to_string())
+				},
+				move |s, restored| match restored {
+					Ok(kept) => show_restored(s, number, kept, destination),
+					Err(e) => s.add_layer(Dialog::info(i18n::error(&e))),
+				},
+			);
+		}
+	};
+	confirm_rewind(s, &backup, &destination, move |s| {
+		confirm(s, &message, restore.clone())
+	});
+
+	Ok(())
+}
+
+/// Asks separately before restoring a backup over a save that is further along than it, with how
+/// far back the restore goes, then carries on with `restore`.
+pub fn confirm_rewind<F>(s: &mut Cursive, backup: &Backup, save_file: &Path, restore: F)
+where
+	F: Fn(&mut Cursive) + 'static,
+{
+	let rewind = match store::rewind(backup, save_file) {
+		Some(rewind) => rewind,
+		None => return restore(s),
+	};
+
+	s.add_layer(
+		Dialog::around(TextView::new(format!(
+			"The current save is further along than backup {}. Restoring it goes back\n\n{}",
+			backup.number,
+			rewind.describe()
+		)))
+		.title("Going back")
+		.button("Cancel", |s| {
+			s.pop_layer();
+		})
+		.button("Go back", move |s| {
+			s.pop_layer();
+			restore(s);
+		}),
+	);
+}
+
+/// Reports a finished restore, offering to put back the save it replaced when that was kept.
+pub fn show_restored(s: &mut Cursive, number: usize, kept: Option<PathBuf>, save_file: PathBuf) {
+	let report = Dialog::around(TextView::new(format!("Backup number {} restored.", number)))
+		.button("Ok", |s| {
+			s.pop_layer();
+		});
+	s.add_layer(match kept {
+		Some(kept) => with_undo(report, kept, save_file),
+		None => report,
+	});
+}
+
+/// Adds a button to a restore report that copies the save kept before the restore back over the
+/// live save.
+fn with_undo(report: Dialog, kept: PathBuf, save_file: PathBuf) -> Dialog {
+	report.button("Undo restore", move |s| {
+		s.pop_layer();
+		match store::undo_restore(&kept, &save_file) {
+			Ok(()) => s.add_layer(Dialog::info(
+				"Restore undone. The save is back as it was before the restore.",
+			)),
+			Err(e) => s.add_layer(Dialog::info(i18n::error(&e))),
+		}
+	})
+}
@@ -0,0 +1,168 @@
+use std::collections::HashSet;
+use std::io;
+use std::path::Path;
+use std::time::Instant;
+
+use chrono::Duration;
+
+use log::info;
+
+use crate::clock;
+use crate::jobs::{self, Hook, Moment};
+use crate::manifest::{Manifest, Origin};
+use crate::store::{self, delete_backup};
+
+/// Which automatic backups of a save are kept once a new one has been taken.
+#[derive(Clone, Copy)]
+pub enum Policy {
+	KeepAll,
+	/// Keep this many of the newest.
+	Last(usize),
+	/// Keep those taken within this many days.
+	Days(u32),
+	/// Keep the newest taken in each of the last `hourly` hours and each of the last `daily` days.
+	Tiered {
+		hourly: u32,
+		daily: u32,
+	},
+}
+
+impl Policy {
+	pub fn describe(self) -> String {
+		match self {
+			Self::KeepAll => "keeping every automatic backup".to_string(),
+			Self::Last(count) => format!("keeping the newest {} automatic backups", count),
+			Self::Days(days) => format!("keeping automatic backups from the last {} days", days),
+			Self::Tiered { hourly, daily } => format!(
+				"keeping one automatic backup per hour for {} hours and per day for {} days",
+				hourly, daily
+			),
+		}
+	}
+}
+
+/// When the automatic backups the policy no longer keeps are deleted.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Trigger {
+	/// Right after every automatic backup, for a moment at most, and by scheduled `prune` tasks.
+	AfterBackup,
+	/// Only by scheduled `prune` tasks, so that backups are never held up by deleting.
+	Scheduled,
+	/// Only when asked for.
+	Manual,
+}
+
+impl Trigger {
+	pub const fn name(self) -> &'static str {
+		match self {
+			Self::AfterBackup => "backup",
+			Self::Scheduled => "schedule",
+			Self::Manual => "manual",
+		}
+	}
+}
+
+impl Hook for Trigger {
+	fn runs_at(&self, moment: Moment) -> bool {
+		match moment {
+			Moment::AfterBackup => *self == Self::AfterBackup,
+			Moment::Maintenance => *self != Self::Manual,
+			Moment::Manual => true,
+		}
+	}
+}
+
+/// Applies the policy to the automatic backups of a save if the trigger lets it at `moment`,
+/// returning how many backups were deleted, or `None` when it is not the trigger's moment.
+pub fn run(
+	backup_dir: &Path,
+	policy: Policy,
+	trigger: Trigger,
+	keep_tags: &[String],
+	moment: Moment,
+) -> Option<io::Result<usize>> {
+	jobs::follow_up("Retention", &trigger, moment, |deadline| {
+		enforce(backup_dir, policy, keep_tags, deadline)
+	})
+}
+
+/// Deletes the automatic backups of a save that the policy does not keep, returning how many were
+/// deleted. Pinned backups, backups with one of `keep_tags`, backups taken any other way and the
+/// newest backup are never deleted. Kept backups do not count towards the policy either. Deleting
+/// stops at `deadline` when given, leaving the rest for the next time.
+fn enforce(
+	backup_dir: &Path,
+	policy: Policy,
+	keep_tags: &[String],
+	deadline: Option<Instant>,
+) -> io::Result<usize> {
+	if matches!(policy, Policy::KeepAll) {
+		return Ok(0);
+	}
+
+	let manifest = Manifest::load(backup_dir);
+	let mut backups = store::list_backups(backup_dir)?;
+	let newest = backups.last().map(|backup| backup.number);
+
+	// newest first, so that the tiered policy keeps the latest backup of each hour and day
+	backups.reverse();
+	let automatic = backups
+		.into_iter()
+		.filter(|backup| {
+			manifest.origin(&backup.file_name) == Some(Origin::Auto)
+				&& !manifest.is_kept(&backup.file_name, keep_tags)
+		})
+		.filter_map(|backup| {
+			let created = backup.created(&manifest)?;
+			Some((backup, created))
+		});
+
+	let now = clock::now();
+	let expired = match policy {
+		Policy::KeepAll => Vec::new(),
+		Policy::Last(count) => automatic.skip(count).collect(),
+		Policy::Days(days) => {
+			let cutoff = now - Duration::days(i64::from(days));
+			automatic.filter(|(_, created)| *created < cutoff).collect()
+		}
+		Policy::Tiered { hourly, daily } => {
+			let mut hours = HashSet::new();
+			let mut days = HashSet::new();
+			automatic
+				.filter(|(_, created)| {
+					// counted in hours of the clock like days are in dates, as hours counted back
+					// from now shift with every backup and would delete the one an hour keeps
+					let hour =
+						now.timestamp().div_euclid(3600) - created.timestamp().div_euclid(3600);
+					let day = (now.date() - created.date()).num_days();
+					let kept_for_hour = hour < i64::from(hourly) && hours.insert(hour);
+					let kept_for_day = day < i64::from(daily) && days.insert(day);
+					!kept_for_hour && !kept_for_day
+				})
+				.collect::<Vec<_>>()
+		}
+	};
+
+	let mut deleted = 0;
+	for (backup, _) in expired {
+		if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+			info!("Stopped deleting automatic backups to not hold up the next backup");
+			break;
+		}
+		if Some(backup.number) != newest {
+			delete_backup(backup_dir, &backup)?;
+			deleted += 1;
+		}
+	}
+
+	if deleted > 0 {
+		info!(
+			event = "retention_applied", deleted = deleted;
+			"Deleted {} automatic backups, {}",
+			deleted,
+			policy.describe()
+		);
+	}
+
+	Ok(deleted)
+}
@@ -0,0 +1,242 @@
+use std::path::Path;
+
+use cursive::traits::*;
+use cursive::views::{Dialog, EditView, LinearLayout, RadioGroup, TextView};
+use cursive::Cursive;
+
+use ini::Ini;
+
+use log::info;
+
+use crate::error::{self, Context};
+use crate::i18n;
+use crate::jobs::Moment;
+use crate::profiles::{self, Profile};
+use crate::retention::{run, Policy, Trigger};
+use crate::settings::{
+	current_save, retention_policy, retention_trigger, save_section, save_setting, write_config,
+	DEFAULT_RETENTION_COUNT, DEFAULT_RETENTION_DAILY, DEFAULT_RETENTION_DAYS,
+	DEFAULT_RETENTION_HOURLY,
+};
+
+/// Names of the number entries in the retention settings dialog, with the conf.ini key each sets.
+const COUNT_NAME: &str = "retention_count";
+const DAYS_NAME: &str = "retention_days";
+const HOURLY_NAME: &str = "retention_hourly";
+const DAILY_NAME: &str = "retention_daily";
+
+/// Name of the entry for the tags the game profile never deletes backups with.
+const KEEP_TAGS_NAME: &str = "keep_tags";
+
+/// Lets the retention policy for automatic backups and when it is applied be chosen and stores them
+/// in the working save's section of conf.ini, or the general section when no save has been chosen,
+/// so that each campaign keeps its own. The tags whose backups are kept are stored with the game
+/// profile. The policy can also be applied to the working save right away.
+pub fn settings(s: &mut Cursive, profile: &Profile) {
+	let config: &mut Ini = s
+		.user_data()
+		.expect("User data not set up correctly on program start");
+	let working = current_save(config).unwrap_or_default().to_string();
+	let policy = retention_policy(config, &working);
+	let trigger = retention_trigger(config, &working);
+	let current = |key: &str, default: String| {
+		save_setting(config, &working, key).map_or(default, ToString::to_string)
+	};
+	let count = current(COUNT_NAME, DEFAULT_RETENTION_COUNT.to_string());
+	let days = current(DAYS_NAME, DEFAULT_RETENTION_DAYS.to_string());
+	let hourly = current(HOURLY_NAME, DEFAULT_RETENTION_HOURLY.to_string());
+	let daily = current(DAILY_NAME, DEFAULT_RETENTION_DAILY.to_string());
+	let keep_tags = profiles::keep_tags(config).join(",");
+
+	let mut group = RadioGroup::new();
+	let mut button = |value: &'static str, label: &str, selected: bool| {
+		let button = group.button(value, label);
+		if selected {
+			button.selected()
+		} else {
+			button
+		}
+	};
+	let buttons = LinearLayout::vertical()
+		.child(button(
+			"all",
+			"Keep every automatic backup",
+			matches!(policy, Policy::KeepAll),
+		))
+		.child(button(
+			"last",
+			"Keep the newest automatic backups",
+			matches!(policy, Policy::Last(_)),
+		))
+		.child(button(
+			"days",
+			"Keep automatic backups up to an age",
+			matches!(policy, Policy::Days(_)),
+		))
+		.child(button(
+			"tiered",
+			"Keep one automatic backup per hour, then per day",
+			matches!(policy, Policy::Tiered { .. }),
+		));
+
+	let mut triggers = RadioGroup::new();
+	let mut trigger_button = |value: Trigger, label: &str| {
+		let button = triggers.button(value.name(), label);
+		if value == trigger {
+			button.selected()
+		} else {
+			button
+		}
+	};
+	let trigger_buttons = LinearLayout::vertical()
+		.child(trigger_button(
+			Trigger::AfterBackup,
+			"Delete old backups right after each automatic backup",
+		))
+		.child(trigger_button(
+			Trigger::Scheduled,
+			"Delete old backups only in scheduled prune tasks",
+		))
+		.child(trigger_button(
+			Trigger::Manual,
+			"Delete old backups only when applied by hand",
+		));
+
+	let name = profile.name.clone();
+	let backup_dir = (!working.is_empty()).then(|| profile.backup_path.join(&working));
+	let entry = |label: &str, name: &str, content: String| {
+		LinearLayout::horizontal()
+			.child(TextView::new(label).fixed_width(30))
+			.child(
+				EditView::new()
+					.content(content)
+					.with_name(name)
+					.min_width(8),
+			)
+	};
+
+	s.add_layer(
+		Dialog::around(
+			LinearLayout::vertical()
+				.child(buttons)
+				.child(TextView::new(" "))
+				.child(entry("Newest backups to keep:", COUNT_NAME, count))
+				.child(entry("Days to keep backups for:", DAYS_NAME, days))
+				.child(entry("Hours to keep one per hour:", HOURLY_NAME, hourly))
+				.child(entry("Days to keep one per day:", DAILY_NAME, daily))
+				.child(
+					LinearLayout::horizontal()
+						.child(TextView::new("Never delete backups tagged:").fixed_width(30))
+						.child(
+							EditView::new()
+								.content(keep_tags)
+								.with_name(KEEP_TAGS_NAME)
+								.min_width(20),
+						),
+				)
+				.child(TextView::new(" "))
+				.child(trigger_buttons)
+				.child(TextView::new(
+					"\nOnly automatic backups are deleted, never pinned ones, tagged ones above or the newest.",
+				)),
+		)
+		.title("Retention of automatic backups")
+		.button("Cancel", |s| {
+			s.pop_layer();
+		})
+		.button("Save", {
+			let (group, triggers, name) = (group.clone(), triggers.clone(), name.clone());
+			move |s| {
+				if let Err(e) = save(s, &group.selection(), &triggers.selection(), &name) {
+					s.add_layer(Dialog::info(i18n::error(&e)));
+				}
+			}
+		})
+		.button("Save and apply now", move |s| {
+			if let Err(e) = save(s, &group.selection(), &triggers.selection(), &name)
+				.and_then(|()| apply_now(s, backup_dir.as_deref()))
+			{
+				s.add_layer(Dialog::info(i18n::error(&e)));
+			}
+		}),
+	);
+}
+
+/// Applies the saved retention policy to the backups of the working save, whatever its trigger.
+fn apply_now(s: &mut Cursive, backup_dir: Option<&Path>) -> error::Result<()> {
+	let backup_dir = backup_dir.ok_or("No save file has been set, choose one first.")?;
+	let config: &mut Ini = s
+		.user_data()
+		.expect("User data not set up correctly on program start");
+	let working = current_save(config).unwrap_or_default();
+	let deleted = run(
+		backup_dir,
+		retention_policy(config, working),
+		retention_trigger(config, working),
+		&profiles::keep_tags(config),
+		Moment::Manual,
+	)
+	.unwrap_or(Ok(0))
+	.context("delete old backups in", backup_dir)?;
+
+	s.add_layer(Dialog::info(format!(
+		"Deleted {} automatic backups.",
+		deleted
+	)));
+	Ok(())
+}
+
+fn save(s: &mut Cursive, retention: &str, trigger: &str, profile: &str) -> error::Result<()> {
+	let mut numbers = Vec::new();
+	for name in &[COUNT_NAME, DAYS_NAME, HOURLY_NAME, DAILY_NAME] {
+		let entry = s
+			.call_on_name(name, |view: &mut EditView| view.get_content())
+			.expect("EditView not created for retention setting");
+		if entry.trim().parse::<u32>().is_err() {
+			return Err(
+				format!("{} is not a whole number of backups, hours or days.", entry).into(),
+			);
+		}
+		numbers.push((*name, entry.trim().to_string()));
+	}
+	let keep_tags = s
+		.call_on_name(KEEP_TAGS_NAME, |view: &mut EditView| view.get_content())
+		.expect("EditView not created for kept tags");
+	let keep_tags = keep_tags
+		.split(',')
+		.map(str::trim)
+		.filter(|tag| !tag.is_empty())
+		.collect::<Vec<_>>()
+		.join(",");
+
+	let config: &mut Ini = s
+		.user_data()
+		.expect("User data not set up correctly on program start");
+	let save = current_save(config).map(ToString::to_string);
+	let section = save.as_deref().map(save_section);
+	config
+		.with_section(section.clone())
+		.set("retention", retention);
+	config
+		.with_section(section.clone())
+		.set("retention_trigger", trigger);
+	for (name, number) in numbers {
+		config.with_section(section.clone()).set(name, number);
+	}
+	if keep_tags.is_empty() {
+		config.delete_from(Some(profiles::section(profile)), KEEP_TAGS_NAME);
+	} else {
+		config
+			.with_section(Some(profiles::section(profile)))
+			.set(KEEP_TAGS_NAME, keep_tags);
+	}
+	write_config(config)?;
+
+	info!(
+		"Retention policy saved, {}",
+		retention_policy(config, save.as_deref().unwrap_or_default()).describe()
+	);
+	s.pop_layer();
+
+	Ok(())
+}
@@ -0,0 +1,280 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use chrono::{Datelike, NaiveDateTime, NaiveTime, Weekday};
+
+use ini::Ini;
+
+use log::{error, info, warn};
+
+use crate::clock;
+use crate::jobs::Moment;
+use crate::manifest::Origin;
+use crate::profiles::{self, Profile};
+use crate::retention::{self, Policy, Trigger};
+use crate::snapshot::{self, Maintained};
+use crate::store::{self, backup_retrying, BackupOptions};
+
+/// Prefix of the conf.ini sections describing a scheduled task, such as `[schedule.weekly verify]`.
+pub const SECTION_PREFIX: &str = "schedule.";
+
+/// How often the scheduler checks whether a task is due.
+const CHECK_INTERVAL: Duration = Duration::from_secs(15);
+
+/// Work a schedule can run.
+#[derive(Clone, Copy)]
+pub enum Task {
+	/// Back up the working save.
+	Backup,
+	/// Check the backups against the content index and manifests.
+	Verify,
+	/// Rebuild the content index.
+	Rebuild,
+	/// Delete the automatic backups the retention policy no longer keeps, unless it is only
+	/// applied when asked for.
+	Prune,
+}
+
+impl Task {
+	const fn name(self) -> &'static str {
+		match self {
+			Self::Backup => "backup",
+			Self::Verify => "verify",
+			Self::Rebuild => "rebuild",
+			Self::Prune => "prune",
+		}
+	}
+}
+
+/// A task run at a set time, configured in a `schedule.<name>` section with the keys:
+///
+/// * `task`: `backup`, `verify`, `rebuild` or `prune`
+/// * `at`: time of day like `03:00`
+/// * `days`: weekdays like `sat,sun`, every day when left out
+/// * `day_of_month`: only run on this day of the month, such as `1`
+/// * `profile`: game profile the task belongs to, the built-in one when left out
+pub struct Schedule {
+	pub name: String,
+	pub task: Task,
+	pub at: NaiveTime,
+	pub days: Vec<Weekday>,
+	pub day_of_month: Option<u32>,
+}
+
+impl Schedule {
+	fn load(config: &Ini, name: &str) -> Result<Self, String> {
+		let get = |key: &str| config.get_from(Some(format!("{}{}", SECTION_PREFIX, name)), key);
+
+		let task = match get("task") {
+			Some("backup") => Task::Backup,
+			Some("verify") => Task::Verify,
+			Some("rebuild") => Task::Rebuild,
+			Some("prune") => Task::Prune,
+			_ => return Err("task must be backup, verify, rebuild or prune".to_string()),
+		};
+		let at = get("at")
+			.and_then(|at| NaiveTime::parse_from_str(at.trim(), "%H:%M").ok())
+			.ok_or("at must be a time like 03:00")?;
+		let days = get("days")
+			.unwrap_or_default()
+			.split(',')
+			.map(str::trim)
+			.filter(|day| !day.is_empty())
+			.map(|day| {
+				day.parse()
+					.map_err(|_| format!("\"{}\" is not a weekday", day))
+			})
+			.collect::<Result<_, _>>()?;
+		let day_of_month = get("day_of_month")
+			.map(|day| {
+				day.trim()
+					.parse()
+					.map_err(|_| "day_of_month must be a number")
+			})
+			.transpose()?;
+
+		Ok(Self {
+			name: name.to_string(),
+			task,
+			at,
+			days,
+			day_of_month,
+		})
+	}
+
+	/// Whether the task was due at some point after `since` and up to `now`. Runs missed while the
+	/// computer slept or the clock jumped ahead make it due once, however many were missed.
+	pub fn is_due(&self, since: NaiveDateTime, now: NaiveDateTime) -> bool {
+		self.next_run(since).is_some_and(|next| next <= now)
+	}
+
+	fn runs_on(&self, date: NaiveDateTime) -> bool {
+		(self.days.is_empty() || self.days.contains(&date.weekday()))
+			&& self.day_of_month.is_none_or(|day| date.day() == day)
+	}
+
+	/// When the task next runs after `after`, if within a year.
+	pub fn next_run(&self, after: NaiveDateTime) -> Option<NaiveDateTime> {
+		let mut date = after.date();
+		for _ in 0..=366 {
+			let candidate = date.and_time(self.at);
+			if candidate > after && self.runs_on(candidate) {
+				return Some(candidate);
+			}
+			date = date.succ();
+		}
+
+		None
+	}
+
+	pub fn describe(&self) -> String {
+		let mut description = format!("{} at {}", self.task.name(), self.at.format("%H:%M"));
+		if !self.days.is_empty() {
+			let days = self
+				.days
+				.iter()
+				.map(ToString::to_string)
+				.collect::<Vec<_>>();
+			description.push_str(&format!(" on {}", days.join(", ")));
+		}
+		if let Some(day) = self.day_of_month {
+			description.push_str(&format!(" on day {} of the month", day));
+		}
+
+		description
+	}
+}
+
+/// Names of the conf.ini sections of the schedules belonging to a game profile.
+pub fn sections(config: &Ini, profile: &str) -> Vec<String> {
+	config
+		.sections()
+		.flatten()
+		.filter(|section| section.starts_with(SECTION_PREFIX))
+		.filter(|section| {
+			config
+				.get_from(Some(*section), "profile")
+				.unwrap_or(profiles::BUILTIN_NAME)
+				== profile
+		})
+		.map(ToString::to_string)
+		.collect()
+}
+
+/// Loads the schedules belonging to a game profile. Broken schedules are logged and left out.
+pub fn load(config: &Ini, profile: &str) -> Vec<Schedule> {
+	sections(config, profile)
+		.iter()
+		.filter_map(|section| section.strip_prefix(SECTION_PREFIX))
+		.filter_map(|name| match Schedule::load(config, name) {
+			Ok(schedule) => Some(schedule),
+			Err(e) => {
+				warn!("Schedule \"{}\" is ignored: {}", name, e);
+				None
+			}
+		})
+		.collect()
+}
+
+/// Runs schedules in the background until `stop` is set, on the time of `clock::now`. `retention`
+/// is the retention policy of the save and when it is applied.
+pub fn spawn(
+	schedules: Vec<Schedule>,
+	profile: Profile,
+	save: String,
+	options: BackupOptions,
+	retention: (Policy, Trigger),
+	stop: Arc<AtomicBool>,
+) {
+	if schedules.is_empty() {
+		return;
+	}
+
+	thread::spawn(move || {
+		// never goes back, so that the hour repeated when clocks are turned back runs nothing twice
+		let mut checked = clock::now().naive_local();
+
+		while !stop.load(Ordering::Relaxed) {
+			thread::sleep(CHECK_INTERVAL);
+			let now = clock::now().naive_local();
+			if now <= checked {
+				continue;
+			}
+
+			for schedule in schedules
+				.iter()
+				.filter(|schedule| schedule.is_due(checked, now))
+			{
+				info!(
+					event = "scheduled_task", task = schedule.task.name();
+					"Running scheduled task \"{}\"",
+					schedule.name
+				);
+				run(schedule.task, &profile, &save, &options, retention);
+			}
+			checked = now;
+		}
+	});
+}
+
+fn run(
+	task: Task,
+	profile: &Profile,
+	save: &str,
+	options: &BackupOptions,
+	(policy, trigger): (Policy, Trigger),
+) {
+	let backup_path = &profile.backup_path;
+
+	match task {
+		Task::Backup => {
+			let file_path = profile.save_file(save);
+			if let Err(e) = backup_retrying(
+				&file_path,
+				&backup_path.join(save),
+				"",
+				Origin::Scheduled,
+				options,
+			) {
+				error!(
+					event = "backup_failed", save = save, error_kind = e.kind().as_str();
+					"Scheduled backup failed: {}",
+					e
+				);
+			}
+		}
+		Task::Verify => match store::check_consistency(backup_path) {
+			Ok(consistency) if consistency.is_empty() => info!("Scheduled check found no problems"),
+			Ok(_) => warn!("Scheduled check found problems, run the consistency check for details"),
+			Err(e) => error!("Scheduled check failed: {}", e),
+		},
+		Task::Rebuild => {
+			match snapshot::maintain(backup_path, "rebuilding the backup index", || {
+				store::rebuild_index(backup_path, options.ids)
+			}) {
+				Ok(Maintained::Done(_)) => info!("Scheduled index rebuild finished"),
+				// the snapshot is offered to roll back to the next time the program starts
+				Ok(Maintained::Failed(..)) => {}
+				Err(e) => error!(
+					"Scheduled index rebuild skipped, no snapshot could be taken: {}",
+					e
+				),
+			}
+		}
+		Task::Prune => match retention::run(
+			&backup_path.join(save),
+			policy,
+			trigger,
+			&options.keep_tags,
+			Moment::Maintenance,
+		) {
+			Some(Ok(deleted)) => info!("Scheduled pruning deleted {} backups", deleted),
+			Some(Err(e)) => error!("Scheduled pruning failed: {}", e),
+			None => {
+				info!("Scheduled pruning skipped, the retention policy is only applied by hand")
+			}
+		},
+	}
+}
@@ -0,0 +1,477 @@
+use std::env;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use std::time::Duration;
+
+use directories::{ProjectDirs, UserDirs};
+
+use ini::Ini;
+
+use log::{info, warn};
+
+use crate::autotag;
+use crate::error::{self, Error};
+use crate::profiles;
+use crate::protection::protection;
+use crate::retention::{Policy, Trigger};
+use crate::store::{local_author, BackupOptions, BakFiles, IdScheme, LockRetry, LowSpaceAction};
+use crate::sync::Remote;
+use crate::watch::{Mode, Scope};
+
+/// Free space kept on the backup drive when `min_free_space` is not set, in megabytes.
+const DEFAULT_MIN_FREE_SPACE: u64 = 1024;
+
+/// Minutes between backups in the `interval` and `both` automatic modes when `auto_interval` is
+/// not set.
+const DEFAULT_AUTO_INTERVAL: u64 = 10;
+
+/// How many times larger or smaller than usual a save must be to raise a size alert when
+/// `size_alert_ratio` is not set.
+const DEFAULT_SIZE_ALERT_RATIO: f64 = 2.0;
+
+/// Automatic backups kept by the `last` retention policy when `retention_count` is not set.
+pub const DEFAULT_RETENTION_COUNT: usize = 50;
+
+/// Age in days up to which the `days` retention policy keeps automatic backups by default.
+pub const DEFAULT_RETENTION_DAYS: u32 = 30;
+
+/// Hours and days the `tiered` retention policy keeps one automatic backup for by default.
+pub const DEFAULT_RETENTION_HOURLY: u32 = 24;
+pub const DEFAULT_RETENTION_DAILY: u32 = 30;
+
+/// Generations of the game's autosave kept when they are turned on from the menu.
+pub const DEFAULT_AUTOSAVE_GENERATIONS: usize = 10;
+
+/// Name of the config file.
+const CONFIG_FILE: &str = "conf.ini";
+
+/// The config file used for the whole run, picked once at startup.
+static CONFIG_PATH: OnceLock<PathBuf> = OnceLock::new();
+
+/// Picks the config file for the rest of the program.
+///
+/// That is `explicit` when given with `--config`, otherwise conf.ini in the platform's config
+/// folder, such as `$XDG_CONFIG_HOME/save-manager` or `%APPDATA%\save-manager\config`. A conf.ini
+/// left next to the executable by older versions is moved there the first time, and its old path
+/// returned.
+pub fn locate_config(explicit: Option<PathBuf>) -> io::Result<Option<PathBuf>> {
+	let beside_exe = env::current_exe()?
+		.parent()
+		.map(|directory| directory.join(CONFIG_FILE));
+	let platform =
+		ProjectDirs::from("", "", "save-manager").map(|dirs| dirs.config_dir().join(CONFIG_FILE));
+
+	let path = match explicit
+		.or_else(|| platform.clone())
+		.or_else(|| beside_exe.clone())
+	{
+		Some(path) => path,
+		None => return Ok(None),
+	};
+	let _ = CONFIG_PATH.set(path.clone());
+
+	// only the default location takes over the old config
+	let old = match beside_exe {
+		Some(old) if Some(&path) == platform.as_ref() && !path.exists() && old.is_file() => old,
+		_ => return Ok(None),
+	};
+	if let Some(directory) = path.parent() {
+		fs::create_dir_all(directory)?;
+	}
+	fs::copy(&old, &path)?;
+	// an install that cannot be written to keeps its copy, which is then just ignored
+	let _ = fs::remove_file(&old);
+
+	Ok(Some(old))
+}
+
+/// Path of the config file picked by `locate_config`.
+pub fn config_path() -> &'static Path {
+	CONFIG_PATH
+		.get()
+		.map_or_else(|| Path::new(CONFIG_FILE), PathBuf::as_path)
+}
+
+/// Makes `save` the working save and writes the config, such as when the user picks another save.
+pub fn pick_save(config: &mut Ini, save: &str) -> error::Result<()> {
+	set_current_save(config, save);
+	write_config(config)?;
+	info!("Save file set to: {}", save);
+
+	Ok(())
+}
+
+/// Saves the settings to the config file picked at startup.
+pub fn write_config(config: &Ini) -> error::Result<()> {
+	let path = config_path();
+	config.write_to_file(path).map_err(|source| Error::Config {
+		path: path.to_path_buf(),
+		source,
+	})
+}
+
+/// Name of the conf.ini section holding settings that apply to a single save.
+pub fn save_section(save: &str) -> String {
+	format!("save.{}", save)
+}
+
+/// Key of a save's section giving it a label to show in place of its file name, such as
+/// `Ireland 867 - Attempt 2` for `ironman_autosave_2`.
+const LABEL_KEY: &str = "label";
+
+/// Label given to a save, if any. Only the save's own section is read, as a label for every save
+/// would tell none apart.
+pub fn save_label<'a>(config: &'a Ini, save: &str) -> Option<&'a str> {
+	config
+		.get_from(Some(save_section(save)), LABEL_KEY)
+		.map(str::trim)
+		.filter(|label| !label.is_empty())
+}
+
+/// How a save is shown in lists and the status line: by its label, or its name without one.
+pub fn display_name(config: &Ini, save: &str) -> String {
+	save_label(config, save).unwrap_or(save).to_string()
+}
+
+/// Gives a save a label, or takes it away when `label` is blank.
+pub fn set_save_label(config: &mut Ini, save: &str, label: &str) {
+	let section = save_section(save);
+	if label.trim().is_empty() {
+		config.delete_from(Some(section), LABEL_KEY);
+	} else {
+		config
+			.with_section(Some(section))
+			.set(LABEL_KEY, label.trim());
+	}
+}
+
+/// Key naming the working save, in the general section for the active game and in each game
+/// profile's section for the others.
+const CURRENT_KEY: &str = "current";
+
+/// Key the working save was named by before each save had a section of its own.
+const OLD_CURRENT_KEY: &str = "save_file";
+
+/// Settings that belong to a campaign rather than the whole program, copied into the working
+/// save's section by `migrate_flat_layout`.
+const PER_SAVE_KEYS: [&str; 9] = [
+	"note_template",
+	"note_prompt",
+	"retention",
+	"retention_count",
+	"retention_days",
+	"retention_hourly",
+	"retention_daily",
+	"auto_mode",
+	"auto_interval",
+];
+
+/// Name of the working save of the active game, if one has been chosen.
+pub fn current_save(config: &Ini) -> Option<&str> {
+	config
+		.general_section()
+		.get(CURRENT_KEY)
+		.filter(|save| !save.trim().is_empty())
+}
+
+/// Makes `save` the working save, giving it a `[save.<name>]` section of its own for its settings
+/// if it has none yet.
+pub fn set_current_save(config: &mut Ini, save: &str) {
+	config.with_general_section().set(CURRENT_KEY, save);
+	config
+		.entry(Some(save_section(save)))
+		.or_insert_with(Default::default);
+}
+
+/// Moves the working save of the active game into the section of the game profile `profile`, and
+/// makes the one remembered for `next` the working save instead.
+pub fn switch_current_save(config: &mut Ini, profile: &str, next: &str) {
+	if let Some(save) = current_save(config).map(ToString::to_string) {
+		config
+			.with_section(Some(profiles::section(profile)))
+			.set(CURRENT_KEY, save);
+	}
+	match config
+		.get_from(Some(profiles::section(next)), CURRENT_KEY)
+		.map(ToString::to_string)
+	{
+		Some(save) => set_current_save(config, &save),
+		None => {
+			config.general_section_mut().remove(CURRENT_KEY);
+		}
+	}
+}
+
+/// Moves a config from when a single `save_file` key named the working save and every setting was
+/// in the general section over to a `current` key and a section per save.
+///
+/// The per-campaign settings of the general section are copied into the working save's section,
+/// where neither it nor the preset of its protection level sets them already, and stay in the
+/// general section as the defaults for other saves. Returns whether anything changed.
+pub fn migrate_flat_layout(config: &mut Ini) -> bool {
+	let mut migrated = false;
+	let sections = config
+		.sections()
+		.map(|section| section.map(ToString::to_string))
+		.collect::<Vec<_>>();
+	for section in sections {
+		let old = match config.delete_from(section.as_deref(), OLD_CURRENT_KEY) {
+			Some(old) => old,
+			None => continue,
+		};
+		migrated = true;
+		if config.get_from(section.as_deref(), CURRENT_KEY).is_none() {
+			config.with_section(section).set(CURRENT_KEY, old);
+		}
+	}
+
+	if !migrated {
+		return false;
+	}
+
+	if let Some(save) = current_save(config).map(ToString::to_string) {
+		let section = save_section(&save);
+		let preset = protection(config, &save);
+		for key in &PER_SAVE_KEYS {
+			// a preset of the save's protection level stays in charge of what it sets
+			if config.get_from(Some(section.as_str()), key).is_some()
+				|| preset.is_some_and(|preset| preset.setting(key).is_some())
+			{
+				continue;
+			}
+			if let Some(value) = config.general_section().get(key).map(ToString::to_string) {
+				config.with_section(Some(section.as_str())).set(*key, value);
+			}
+		}
+		set_current_save(config, &save);
+	}
+
+	true
+}
+
+/// Looks up a setting for a save, where the save's own `[save.<name>]` section overrides its
+/// protection level preset, which overrides the general section. Blank values count as unset.
+pub fn save_setting<'a>(config: &'a Ini, save: &str, key: &str) -> Option<&'a str> {
+	let set = |value: &&str| !value.trim().is_empty();
+
+	config
+		.get_from(Some(save_section(save)), key)
+		.filter(set)
+		.or_else(|| protection(config, save).and_then(|preset| preset.setting(key)))
+		.or_else(|| config.general_section().get(key).filter(set))
+		.map(str::trim)
+}
+
+/// Reads the settings that shape how backups of a save are taken:
+///
+/// * `min_free_space`: megabytes to leave free on the backup drive, 0 to never check
+/// * `low_space_action`: `warn`, `block`, `prune` or `secondary` when that space would run out
+/// * `secondary_backup_folder`: where backups go for `secondary`
+/// * `bak_files`: `ignore`, `include` or `archive` the game's own `.bak` copies of the save
+/// * `compression`: `zstd` to compress new backups, `none` to store them as they are
+/// * `size_alert_ratio`: how many times larger or smaller than its recent backups a save must be
+///   to raise an alert, 0 to never check
+/// * `backup_ids`: `ulid`, `uuid` or `none` for the identifiers new backups are given
+/// * `backup_name`: template for the file names of new backups, such as
+///   `{num}_{date:%Y-%m-%d_%H%M}_{note}`, or unset to name them `N_note`
+pub fn backup_options(config: &Ini, save: &str) -> BackupOptions {
+	let min_free_space = save_setting(config, save, "min_free_space")
+		.and_then(|megabytes| megabytes.parse::<u64>().ok())
+		.unwrap_or(DEFAULT_MIN_FREE_SPACE)
+		* 1024 * 1024;
+
+	let low_space_action = match save_setting(config, save, "low_space_action") {
+		Some("block") => LowSpaceAction::Block,
+		Some("prune") => LowSpaceAction::Prune,
+		Some("secondary") => save_setting(config, save, "secondary_backup_folder")
+			.map_or(LowSpaceAction::Block, |folder| {
+				LowSpaceAction::Secondary(PathBuf::from(folder))
+			}),
+		_ => LowSpaceAction::Warn,
+	};
+
+	BackupOptions {
+		min_free_space,
+		low_space_action,
+		bak_files: BakFiles::from_setting(save_setting(config, save, "bak_files")),
+		compress: save_setting(config, save, "compression") == Some("zstd"),
+		size_alert: Some(
+			save_setting(config, save, "size_alert_ratio")
+				.and_then(|ratio| ratio.parse::<f64>().ok())
+				.unwrap_or(DEFAULT_SIZE_ALERT_RATIO),
+		)
+		.filter(|&ratio| ratio > 0.0),
+		ids: IdScheme::from_setting(save_setting(config, save, "backup_ids")),
+		naming: save_setting(config, save, "backup_name").map(ToString::to_string),
+		tag_rules: autotag::rules(config),
+		keep_tags: profiles::keep_tags(config),
+		sync: sync_after_backup(config),
+		author: author(config),
+		lock_retry: lock_retry(config, save),
+	}
+}
+
+/// Reads how automatic backups retry a save the game has open: `lock_attempts` tries in all,
+/// with `lock_retry_delay` milliseconds before the second, doubled before each one after it.
+fn lock_retry(config: &Ini, save: &str) -> LockRetry {
+	let default = LockRetry::default();
+	LockRetry {
+		attempts: save_setting(config, save, "lock_attempts")
+			.and_then(|attempts| attempts.parse::<u32>().ok())
+			.map_or(default.attempts, |attempts| attempts.max(1)),
+		delay: save_setting(config, save, "lock_retry_delay")
+			.and_then(|milliseconds| milliseconds.parse::<u64>().ok())
+			.map_or(default.delay, Duration::from_millis),
+	}
+}
+
+/// Reads who new backups are recorded as taken by from `author`, falling back to the login and
+/// machine name, so that backups in a backup folder shared on a network drive can be told apart.
+pub fn author(config: &Ini) -> String {
+	config
+		.general_section()
+		.get("author")
+		.map(str::trim)
+		.filter(|author| !author.is_empty())
+		.map_or_else(local_author, ToString::to_string)
+}
+
+/// Reads the folders searched for lost saves from `search_locations`, separated like the `PATH`
+/// variable, falling back to the home folder.
+pub fn search_locations(config: &Ini) -> Vec<PathBuf> {
+	config
+		.general_section()
+		.get("search_locations")
+		.map(|locations| {
+			env::split_paths(locations)
+				.filter(|location| !location.as_os_str().is_empty())
+				.collect::<Vec<_>>()
+		})
+		.filter(|locations| !locations.is_empty())
+		.or_else(|| UserDirs::new().map(|dirs| vec![dirs.home_dir().to_path_buf()]))
+		.unwrap_or_default()
+}
+
+/// The remote to push the backup folder to after each backup, when `after_backup` is set for it.
+fn sync_after_backup(config: &Ini) -> Option<Remote> {
+	match Remote::load(config) {
+		Ok(remote) => remote.filter(|remote| remote.after_backup),
+		Err(e) => {
+			warn!("Backups are not pushed to the remote: {}", e);
+			None
+		}
+	}
+}
+
+/// Reads which automatic backups of a save to keep:
+///
+/// * `retention`: `all`, `last`, `days` or `tiered`
+/// * `retention_count`: how many of the newest to keep for `last`
+/// * `retention_days`: how many days back to keep for `days`
+/// * `retention_hourly` and `retention_daily`: how many hours and days back to keep the newest of
+///   each for `tiered`
+pub fn retention_policy(config: &Ini, save: &str) -> Policy {
+	fn number<T: std::str::FromStr>(config: &Ini, save: &str, key: &str, default: T) -> T {
+		save_setting(config, save, key)
+			.and_then(|value| value.parse().ok())
+			.unwrap_or(default)
+	}
+
+	match save_setting(config, save, "retention") {
+		Some("last") => Policy::Last(number(
+			config,
+			save,
+			"retention_count",
+			DEFAULT_RETENTION_COUNT,
+		)),
+		Some("days") => Policy::Days(number(
+			config,
+			save,
+			"retention_days",
+			DEFAULT_RETENTION_DAYS,
+		)),
+		Some("tiered") => Policy::Tiered {
+			hourly: number(config, save, "retention_hourly", DEFAULT_RETENTION_HOURLY),
+			daily: number(config, save, "retention_daily", DEFAULT_RETENTION_DAILY),
+		},
+		_ => Policy::KeepAll,
+	}
+}
+
+/// Reads when the automatic backups of a save that the retention policy no longer keeps are
+/// deleted, set with `retention_trigger`.
+///
+/// That is `backup` right after each automatic backup, `schedule` only by scheduled `prune` tasks,
+/// or `manual` only when asked for.
+pub fn retention_trigger(config: &Ini, save: &str) -> Trigger {
+	match save_setting(config, save, "retention_trigger") {
+		Some("schedule") => Trigger::Scheduled,
+		Some("manual") => Trigger::Manual,
+		_ => Trigger::AfterBackup,
+	}
+}
+
+/// Reads what makes automatic sessions for a save take backups:
+///
+/// * `auto_mode`: `watch` when the game writes the save, `interval` at a fixed interval if the save
+///   changed, or `both`
+/// * `auto_interval`: minutes between backups for `interval` and `both`
+pub fn auto_mode(config: &Ini, save: &str) -> Mode {
+	let interval = Duration::from_secs(
+		save_setting(config, save, "auto_interval")
+			.and_then(|minutes| minutes.parse::<u64>().ok())
+			.filter(|minutes| *minutes > 0)
+			.unwrap_or(DEFAULT_AUTO_INTERVAL)
+			* 60,
+	);
+
+	match save_setting(config, save, "auto_mode") {
+		Some("interval") => Mode::Interval(interval),
+		Some("both") => Mode::Both(interval),
+		_ => Mode::Watch,
+	}
+}
+
+/// Reads which saves automatic sessions back up from `auto_saves`: `all` for every save in the save
+/// directory, or a comma-separated list of save names.
+///
+/// Left out, only the working save `save` is backed up.
+pub fn auto_scope(config: &Ini, save: &str) -> Scope {
+	match config.general_section().get("auto_saves").map(str::trim) {
+		Some("all") => Scope::All,
+		Some(saves) if !saves.is_empty() => Scope::Saves(
+			saves
+				.split(',')
+				.map(str::trim)
+				.filter(|save| !save.is_empty())
+				.map(ToString::to_string)
+				.collect(),
+		),
+		_ => Scope::Saves(vec![save.to_string()]),
+	}
+}
+
+/// Reads how many generations of the game's autosave automatic sessions keep from
+/// `autosave_generations`.
+///
+/// Zero or leaving it out means the autosave is not watched unless it is one of the saves backed
+/// up.
+pub fn autosave_generations(config: &Ini) -> Option<usize> {
+	config
+		.general_section()
+		.get("autosave_generations")
+		.and_then(|generations| generations.trim().parse().ok())
+		.filter(|generations| *generations > 0)
+}
+
+/// Reads how many jobs copying, compressing or hashing saves may run at once from `max_jobs`,
+/// where zero or leaving it out means no limit.
+pub fn max_jobs(config: &Ini) -> usize {
+	config
+		.general_section()
+		.get("max_jobs")
+		.and_then(|jobs| jobs.trim().parse().ok())
+		.unwrap_or(0)
+}
@@ -0,0 +1,91 @@
+use ini::Ini;
+
+use crate::autotag;
+use crate::error;
+use crate::profiles;
+use crate::schedule;
+
+/// Keys left out of shared settings, as they hold paths, save names, secrets or state that only
+/// make sense on one machine.
+const LOCAL_KEYS: [&str; 16] = [
+	"current",
+	"game_profile",
+	"save_dir",
+	"backup_dir",
+	"cloud_dir",
+	"secondary_backup_folder",
+	"cold_storage_folder",
+	"notify_webhook",
+	"version_file",
+	"seen_version",
+	"launch",
+	"auto_saves",
+	"search_locations",
+	"language",
+	"log_file",
+	"author",
+];
+
+/// Writes out the general settings, a game profile, its scheduled tasks and the tagging rules as a
+/// conf.ini snippet that others can import, leaving out anything local to this machine.
+pub fn export(config: &Ini, profile: &str) -> String {
+	let mut snippet = Ini::new();
+	let mut sections = vec![None, Some(profiles::section(profile))];
+	sections.extend(schedule::sections(config, profile).into_iter().map(Some));
+	sections.extend(
+		config
+			.sections()
+			.flatten()
+			.filter(|section| section.starts_with(autotag::SECTION_PREFIX))
+			.map(|section| Some(section.to_string())),
+	);
+
+	for section in sections {
+		if let Some(properties) = config.section(section.as_deref()) {
+			for (key, value) in properties.iter() {
+				if !LOCAL_KEYS.contains(&key) {
+					snippet.with_section(section.as_deref()).set(key, value);
+				}
+			}
+		}
+	}
+
+	let mut text = format!("; Save manager settings for {}\n", profile);
+	let mut written = Vec::new();
+	snippet
+		.write_to(&mut written)
+		.expect("writing to memory cannot fail");
+	text.push_str(&String::from_utf8_lossy(&written));
+	text
+}
+
+/// Merges a snippet written by `export` into the config, returning how many settings it set.
+///
+/// Keys local to a machine are ignored, as are sections other than the general section, game
+/// profiles, scheduled tasks and tagging rules.
+pub fn import(config: &mut Ini, snippet: &str) -> error::Result<usize> {
+	let snippet = Ini::load_from_str(snippet)?;
+
+	let mut count = 0;
+	for (section, properties) in snippet.iter() {
+		if !section.is_none_or(|section| {
+			section.starts_with(profiles::SECTION_PREFIX)
+				|| section.starts_with(schedule::SECTION_PREFIX)
+				|| section.starts_with(autotag::SECTION_PREFIX)
+		}) {
+			continue;
+		}
+
+		for (key, value) in properties.iter() {
+			if !LOCAL_KEYS.contains(&key) {
+				config.with_section(section).set(key, value);
+				count += 1;
+			}
+		}
+	}
+
+	if count == 0 {
+		return Err("The file holds no settings to import.".into());
+	}
+	Ok(count)
+}
@@ -0,0 +1,96 @@
+use std::fs;
+use std::path::PathBuf;
+
+use cursive::traits::*;
+use cursive::views::{Dialog, EditView, LinearLayout, TextView};
+use cursive::Cursive;
+
+use directories::UserDirs;
+
+use ini::Ini;
+
+use log::info;
+
+use crate::error;
+use crate::i18n;
+use crate::profiles::Profile;
+use crate::settings::write_config;
+use crate::share::{export, import};
+
+/// Name of the path entry in the share settings dialog.
+const PATH_EDIT_NAME: &str = "share_path";
+
+/// Asks for a file to export the settings of the active game profile to, or to import settings
+/// from.
+pub fn dialog(s: &mut Cursive, profile: &Profile) {
+	let default_path = UserDirs::new()
+		.map_or_else(PathBuf::new, |dirs| dirs.home_dir().to_path_buf())
+		.join(format!("{} settings.ini", profile.name));
+	let name = profile.name.clone();
+
+	s.add_layer(
+		Dialog::around(
+			LinearLayout::vertical()
+				.child(TextView::new(
+					"Settings are shared without save names, folders or webhooks.\n\nFile:",
+				))
+				.child(
+					EditView::new()
+						.content(default_path.to_string_lossy())
+						.with_name(PATH_EDIT_NAME),
+				)
+				.min_width(60),
+		)
+		.title("Share settings")
+		.button("Cancel", |s| {
+			s.pop_layer();
+		})
+		.button("Import", |s| {
+			let path = entered_path(s);
+			let imported = fs::read_to_string(&path)
+				.map_err(error::Error::from)
+				.and_then(|snippet| {
+					s.with_user_data(|config: &mut Ini| {
+						let count = import(config, &snippet)?;
+						write_config(config)?;
+						Ok(count)
+					})
+					.expect("User data not set up correctly on program start")
+				});
+
+			match imported {
+				Ok(count) => {
+					info!("Imported {} settings from {}", count, path.display());
+					s.pop_layer();
+					s.add_layer(Dialog::info(format!(
+						"Imported {} settings. Automatic sessions started from now on use them.",
+						count
+					)));
+				}
+				Err(e) => s.add_layer(Dialog::info(i18n::error(&e))),
+			}
+		})
+		.button("Export", move |s| {
+			let path = entered_path(s);
+			let snippet = s
+				.with_user_data(|config: &mut Ini| export(config, &name))
+				.expect("User data not set up correctly on program start");
+
+			match fs::write(&path, snippet) {
+				Ok(()) => {
+					info!("Exported the settings of {} to {}", name, path.display());
+					s.pop_layer();
+				}
+				Err(e) => s.add_layer(Dialog::info(i18n::error(&e))),
+			}
+		}),
+	);
+}
+
+fn entered_path(s: &mut Cursive) -> PathBuf {
+	let entry = s
+		.call_on_name(PATH_EDIT_NAME, |view: &mut EditView| view.get_content())
+		.expect("EditView not created for settings path entry");
+	let entry = entry.trim().trim_matches(|c| c == '"' || c == '\'');
+	PathBuf::from(entry.strip_prefix("file://").unwrap_or(entry))
+}
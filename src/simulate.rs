@@ -0,0 +1,120 @@
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::atomic::AtomicBool;
+use std::sync::{Arc, Mutex};
+
+use chrono::{Duration, Local, NaiveDateTime, TimeZone};
+
+use ini::Ini;
+
+use save_manager::alerts::Preferences;
+
+use crate::clock;
+use crate::error::{self, Context};
+use crate::index;
+use crate::manifest::Manifest;
+use crate::profiles::Profile;
+use crate::settings::retention_policy;
+use crate::store;
+use crate::watch::{SessionStats, Target, Worker};
+
+/// Name of the save played through a simulation.
+const SAVE_NAME: &str = "simulation";
+
+/// Local time simulations start at unless told otherwise, so that they come out the same every
+/// time.
+pub const DEFAULT_START: &str = "2024-01-01 00:00";
+
+/// Plays a game that saves every `every` minutes for `days` days through automatic backups, with
+/// the clock starting at `start`, like `2024-01-01 18:30`, and moving on by the same amount
+/// between saves. Then prints the backups the retention
+/// policy kept: their number, when they were taken and how they were taken. The settings come from
+/// the config as for any save without its own section.
+///
+/// The backups are kept in `root` when given, which must not hold backups already, and in a
+/// temporary folder removed afterwards otherwise.
+pub fn run(
+	config: &Ini,
+	every: u64,
+	days: u64,
+	start: &str,
+	root: Option<PathBuf>,
+) -> error::Result<()> {
+	if every == 0 {
+		return Err("Saves must be at least a minute apart.".into());
+	}
+	let start = NaiveDateTime::parse_from_str(start.trim(), "%Y-%m-%d %H:%M")
+		.ok()
+		.and_then(|start| Local.from_local_datetime(&start).earliest())
+		.ok_or_else(|| format!("\"{}\" is not a time like {}", start, DEFAULT_START))?;
+	let keep = root.is_some();
+	let root = root.unwrap_or_else(|| {
+		env::temp_dir().join(format!("save-manager-simulation-{}", ulid::Ulid::new()))
+	});
+	let save_dir = root.join("saves");
+	fs::create_dir_all(&save_dir).context("create", &save_dir)?;
+
+	let profile = Profile::builtin(save_dir);
+	let file_path = profile.save_file(SAVE_NAME);
+	let mut target = Target::new(
+		config,
+		&profile,
+		SAVE_NAME,
+		&Arc::new(AtomicBool::new(false)),
+	)?;
+	if store::list_backups(&target.backup_dir).is_ok_and(|backups| !backups.is_empty()) {
+		return Err(format!("{} already holds backups.", target.backup_dir.display()).into());
+	}
+	// a simulation is not worth telling anyone about, or keeping anywhere else
+	target.alerts = Preferences::default();
+	target.options.sync = None;
+
+	let stats = Arc::new(Mutex::new(SessionStats::default()));
+	let mut worker = Worker::new(file_path.clone(), &target, Arc::clone(&stats));
+	let saves = days * 24 * 60 / every;
+	clock::set(start);
+	for step in 0..saves {
+		clock::advance(Duration::minutes(every as i64));
+		// a year passes in game every hour played
+		let minutes = step * every;
+		let contents = format!(
+			"CK2txt\ndate=\"{}.{}.1\"\nstep={}\n",
+			1066 + minutes / 60,
+			minutes % 60 / 5 + 1,
+			step
+		);
+		fs::write(&file_path, contents).context("write", &file_path)?;
+		if !worker.on_write() {
+			break;
+		}
+	}
+
+	let manifest = Manifest::load(&target.backup_dir);
+	let backups = store::list_backups(&target.backup_dir).context("read", &target.backup_dir)?;
+	for backup in &backups {
+		let created = backup
+			.created(&manifest)
+			.map_or_else(String::new, |created| {
+				created.format("%Y-%m-%d %H:%M").to_string()
+			});
+		let origin = manifest
+			.origin(&backup.file_name)
+			.map_or_else(String::new, |origin| origin.to_string());
+		println!("{}\t{}\t{}", backup.number, created, origin);
+	}
+	eprintln!(
+		"{} saves over {} days, {} backups kept, {}",
+		saves,
+		days,
+		backups.len(),
+		retention_policy(config, SAVE_NAME).describe()
+	);
+
+	index::flush().context("write the backup index in", &root)?;
+	if !keep {
+		fs::remove_dir_all(&root).context("remove", &root)?;
+	}
+
+	Ok(())
+}
@@ -0,0 +1,219 @@
+//! Copies of the metadata of a backup folder taken before maintenance rewrites it, so that an
+//! operation failing midway can be rolled back rather than leaving the index and manifests half
+//! converted.
+//!
+//! Only the content index, the manifests and the config are copied, never the backups themselves.
+
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::path::{Component, Path, PathBuf};
+
+use chrono::Local;
+
+use log::{error, info, warn};
+
+use crate::index::{self, INDEX_FILE};
+use crate::manifest::MANIFEST_FILE;
+use crate::settings;
+use crate::store;
+
+/// File at the root of the backup folder holding the snapshot while an operation runs.
+const SNAPSHOT_FILE: &str = "maintenance-snapshot.tar";
+
+/// Entry of the snapshot naming the operation it was taken before.
+const OPERATION_ENTRY: &str = "operation";
+
+/// Entry of the snapshot holding the config file, which lives outside the backup folder.
+const CONFIG_ENTRY: &str = "config";
+
+/// Writes the operation, the config, the content index and the manifests of every save into a tar
+/// file at `path`, returning how many manifests it holds.
+fn write(path: &Path, store_root: &Path, operation: &str) -> io::Result<usize> {
+	let mut tar = tar::Builder::new(File::create(path)?);
+	let mut header = tar::Header::new_gnu();
+	header.set_size(operation.len() as u64);
+	header.set_mode(0o644);
+	header.set_mtime(Local::now().timestamp().max(0) as u64);
+	header.set_cksum();
+	tar.append_data(&mut header, OPERATION_ENTRY, operation.as_bytes())?;
+
+	let config = settings::config_path();
+	if config.is_file() {
+		tar.append_path_with_name(config, CONFIG_ENTRY)?;
+	}
+	let index_file = store_root.join(INDEX_FILE);
+	if index_file.is_file() {
+		tar.append_path_with_name(&index_file, INDEX_FILE)?;
+	}
+	let mut manifests = 0;
+	for backup_dir in store::save_dirs(store_root)? {
+		let manifest = backup_dir.join(MANIFEST_FILE);
+		if let (true, Ok(relative)) = (manifest.is_file(), manifest.strip_prefix(store_root)) {
+			tar.append_path_with_name(&manifest, relative)?;
+			manifests += 1;
+		}
+	}
+
+	tar.into_inner()?.sync_all()?;
+	Ok(manifests)
+}
+
+/// The index and manifests of a backup folder and the config as they were before a maintenance
+/// operation.
+pub struct Snapshot {
+	store_root: PathBuf,
+}
+
+impl Snapshot {
+	/// Copies the config, the content index and every manifest of the backup folder, after writing
+	/// out the index entries still waiting, before `operation` is run on it.
+	pub fn take(store_root: &Path, operation: &str) -> io::Result<Self> {
+		index::open(store_root)?;
+		index::flush()?;
+
+		let snapshot = Self {
+			store_root: store_root.to_path_buf(),
+		};
+		let staged = store_root.join(format!("{}.{}", SNAPSHOT_FILE, ulid::Ulid::new()));
+		let manifests = write(&staged, store_root, operation)
+			.and_then(|manifests| fs::rename(&staged, snapshot.path()).map(|()| manifests))
+			.inspect_err(|_| {
+				let _ = fs::remove_file(&staged);
+			})?;
+
+		info!(
+			"Snapshotted the backup index and {} manifests before {}",
+			manifests, operation
+		);
+		Ok(snapshot)
+	}
+
+	/// The snapshot left behind by an operation that never finished, such as when the program
+	/// was closed midway.
+	pub fn leftover(store_root: &Path) -> Option<Self> {
+		let snapshot = Self {
+			store_root: store_root.to_path_buf(),
+		};
+
+		snapshot.path().is_file().then_some(snapshot)
+	}
+
+	fn path(&self) -> PathBuf {
+		self.store_root.join(SNAPSHOT_FILE)
+	}
+
+	/// The operation the snapshot was taken before.
+	pub fn operation(&self) -> io::Result<String> {
+		let mut archive = tar::Archive::new(File::open(self.path())?);
+		for entry in archive.entries()? {
+			let mut entry = entry?;
+			if entry.path()?.as_ref() == Path::new(OPERATION_ENTRY) {
+				let mut operation = String::new();
+				entry.read_to_string(&mut operation)?;
+				return Ok(operation);
+			}
+		}
+
+		Ok("maintenance".to_string())
+	}
+
+	/// Puts the config, index and manifests back as they were and removes the snapshot, returning
+	/// how many files were put back. Backups the operation renamed or removed stay as they are.
+	pub fn roll_back(self) -> io::Result<usize> {
+		let mut restored = 0;
+		let mut archive = tar::Archive::new(File::open(self.path())?);
+		for entry in archive.entries()? {
+			let mut entry = entry?;
+			let relative = entry.path()?.into_owned();
+			if relative == Path::new(CONFIG_ENTRY) {
+				put_back(&mut entry, settings::config_path())?;
+				restored += 1;
+				continue;
+			}
+			// nothing else is written outside the backup folder, whatever the snapshot holds
+			if relative == Path::new(OPERATION_ENTRY)
+				|| !relative
+					.components()
+					.all(|component| matches!(component, Component::Normal(_)))
+			{
+				continue;
+			}
+
+			// the folder of a save deleted since is not brought back for its manifest alone
+			let destination = self.store_root.join(&relative);
+			if !destination.parent().is_some_and(Path::is_dir) {
+				continue;
+			}
+			put_back(&mut entry, &destination)?;
+			restored += 1;
+		}
+
+		// entries the operation recorded in memory would otherwise be written over the old index
+		index::reload(&self.store_root);
+		info!(
+			"Rolled back {} files of backup metadata in {} to before {}",
+			restored,
+			self.store_root.display(),
+			self.operation()?
+		);
+		self.discard()?;
+
+		Ok(restored)
+	}
+
+	/// Removes the snapshot, once the operation finished or its result is kept.
+	pub fn discard(self) -> io::Result<()> {
+		fs::remove_file(self.path())
+	}
+}
+
+/// Writes a file of the snapshot back over `destination`, through a staged copy so that a failure
+/// leaves the file as it was.
+fn put_back(entry: &mut impl Read, destination: &Path) -> io::Result<()> {
+	let mut staged = destination.as_os_str().to_os_string();
+	staged.push(format!(".{}", ulid::Ulid::new()));
+	let staged = PathBuf::from(staged);
+	File::create(&staged)
+		.and_then(|mut file| io::copy(entry, &mut file))
+		.and_then(|_| fs::rename(&staged, destination))
+		.inspect_err(|_| {
+			let _ = fs::remove_file(&staged);
+		})
+}
+
+/// How a maintenance operation run by `maintain` went.
+pub enum Maintained<T> {
+	Done(T),
+	/// It failed midway, leaving the snapshot taken before it to roll back to.
+	Failed(Snapshot, io::Error),
+}
+
+/// Runs a maintenance operation that rewrites the metadata of the backup folder `store_root` or the
+/// config, snapshotting them first.
+///
+/// The snapshot is removed once the operation succeeds. When it fails midway, the snapshot is
+/// returned to be rolled back or discarded, and if neither happens it is found as a leftover the
+/// next time the program starts.
+pub fn maintain<T>(
+	store_root: &Path,
+	operation: &str,
+	run: impl FnOnce() -> io::Result<T>,
+) -> io::Result<Maintained<T>> {
+	let snapshot = Snapshot::take(store_root, operation)?;
+
+	match run() {
+		Ok(done) => {
+			if let Err(e) = snapshot.discard() {
+				warn!(
+					"Could not remove the snapshot of the backup metadata: {}",
+					e
+				);
+			}
+			Ok(Maintained::Done(done))
+		}
+		Err(e) => {
+			error!("{} failed: {}", operation, e);
+			Ok(Maintained::Failed(snapshot, e))
+		}
+	}
+}
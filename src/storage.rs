@@ -0,0 +1,341 @@
+use std::cmp::Reverse;
+use std::collections::HashSet;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Duration, Local};
+
+use cursive::traits::*;
+use cursive::views::{Dialog, EditView, LinearLayout, SelectView, TextView};
+use cursive::Cursive;
+
+use ini::Ini;
+
+use log::{error, info};
+
+use crate::clock;
+use crate::confirm::confirm;
+use crate::format_size;
+use crate::i18n;
+use crate::manifest::Manifest;
+use crate::profiles::{self, Profile};
+use crate::settings::display_name;
+use crate::store::{self, Backup};
+use crate::timing;
+
+const OLDEST_COUNT_NAME: &str = "storage_oldest_count";
+
+/// Age after which backups without a note are suggested for deletion.
+const STALE_DAYS: i64 = 30;
+
+/// How much space the backups of one save take up.
+struct Usage {
+	/// The save, by its label when it has one.
+	save: String,
+	backup_dir: PathBuf,
+	backups: usize,
+	/// Size of the backup files, counting backups that share their contents each time.
+	bytes: u64,
+	oldest: Option<DateTime<Local>>,
+	newest: Option<DateTime<Local>>,
+}
+
+impl Usage {
+	fn measure(
+		config: &Ini,
+		profile: &Profile,
+		backup_dir: PathBuf,
+		seen: &mut HashSet<String>,
+		on_disk: &mut u64,
+	) -> io::Result<Self> {
+		let manifest = Manifest::load(&backup_dir);
+		let backups = store::list_backups(&backup_dir)?;
+
+		let mut usage = Self {
+			save: display_name(config, &profile.save_of(&backup_dir).unwrap_or_default()),
+			backups: backups.len(),
+			bytes: 0,
+			oldest: None,
+			newest: None,
+			backup_dir,
+		};
+		for backup in &backups {
+			let len = fs::metadata(&backup.path)?.len();
+			usage.bytes += len;
+			// backups with the same contents are stored once, as long as their hash is known
+			if manifest
+				.hash(&backup.file_name)
+				.is_none_or(|hash| seen.insert(hash))
+			{
+				*on_disk += len;
+			}
+
+			if let Some(created) = backup.created(&manifest) {
+				usage.oldest = Some(usage.oldest.map_or(created, |oldest| oldest.min(created)));
+				usage.newest = Some(usage.newest.map_or(created, |newest| newest.max(created)));
+			}
+		}
+
+		Ok(usage)
+	}
+
+	fn label(&self) -> String {
+		let date = |date: Option<DateTime<Local>>| {
+			date.map_or_else(
+				|| "-".to_string(),
+				|date| date.format("%Y-%m-%d").to_string(),
+			)
+		};
+
+		format!(
+			"{:<24} {:>7} {:>10}  {:<10}  {:<10}",
+			self.save,
+			self.backups,
+			format_size(self.bytes),
+			date(self.oldest),
+			date(self.newest)
+		)
+	}
+}
+
+/// Backups of a save that can be deleted: neither pinned, tagged with one of `keep_tags` nor the
+/// newest, oldest first.
+fn deletable(backup_dir: &Path, keep_tags: &[String]) -> io::Result<Vec<Backup>> {
+	let manifest = Manifest::load(backup_dir);
+	let mut backups = store::list_backups(backup_dir)?;
+	backups.pop();
+
+	Ok(backups
+		.into_iter()
+		.filter(|backup| !manifest.is_kept(&backup.file_name, keep_tags))
+		.collect())
+}
+
+/// Deletable backups of a save without a note, taken more than `STALE_DAYS` days ago.
+fn stale(backup_dir: &Path, keep_tags: &[String]) -> io::Result<Vec<Backup>> {
+	let manifest = Manifest::load(backup_dir);
+	let cutoff = clock::now() - Duration::days(STALE_DAYS);
+
+	Ok(deletable(backup_dir, keep_tags)?
+		.into_iter()
+		.filter(|backup| backup.note.is_empty())
+		.filter(|backup| {
+			backup
+				.created(&manifest)
+				.is_some_and(|created| created < cutoff)
+		})
+		.collect())
+}
+
+fn total_size(backups: &[Backup]) -> u64 {
+	backups
+		.iter()
+		.filter_map(|backup| fs::metadata(&backup.path).ok())
+		.map(|metadata| metadata.len())
+		.sum()
+}
+
+/// Shows how much space the backups of every save take up, and offers ways to free some for the
+/// save picked from the list.
+pub fn overview(s: &mut Cursive, profile: &Profile) -> crate::error::Result<()> {
+	let config: &mut Ini = s
+		.user_data()
+		.expect("User data not set up correctly on program start");
+	let mut seen = HashSet::new();
+	let mut on_disk = 0;
+	let mut usages = Vec::new();
+	for backup_dir in store::save_dirs(&profile.backup_path)? {
+		usages.push(Usage::measure(
+			config,
+			profile,
+			backup_dir,
+			&mut seen,
+			&mut on_disk,
+		)?);
+	}
+	usages.sort_unstable_by_key(|usage| Reverse(usage.bytes));
+
+	let backups = usages.iter().map(|usage| usage.backups).sum::<usize>();
+	let bytes = usages.iter().map(|usage| usage.bytes).sum::<u64>();
+	let mut summary = format!(
+		"{} backups of {} saves, {}",
+		backups,
+		usages.len(),
+		format_size(bytes)
+	);
+	if on_disk < bytes {
+		summary.push_str(&format!(" ({} on disk)", format_size(on_disk)));
+	}
+
+	// how long operations take, to show when they get slower
+	let timings = timing::read(&profile.backup_path)?;
+	let timings = if timings.is_empty() {
+		"No operations timed yet".to_string()
+	} else {
+		timings
+			.iter()
+			.map(timing::Timings::describe)
+			.collect::<Vec<_>>()
+			.join("\n")
+	};
+
+	let mut list = SelectView::<PathBuf>::new();
+	for usage in &usages {
+		list.add_item(usage.label(), usage.backup_dir.clone());
+	}
+
+	let read_only = profile.read_only;
+	s.add_layer(
+		Dialog::around(
+			LinearLayout::vertical()
+				.child(TextView::new(format!(
+					"{:<24} {:>7} {:>10}  {:<10}  {:<10}",
+					"Save", "Backups", "Size", "Oldest", "Newest"
+				)))
+				.child(
+					list.on_submit(move |s, backup_dir: &PathBuf| {
+						if read_only {
+							s.add_layer(Dialog::info(crate::i18n::text("read_only")));
+						} else if let Err(e) = suggest(s, backup_dir) {
+							s.add_layer(Dialog::info(i18n::error(&e)));
+						}
+					})
+					.scrollable(),
+				)
+				.child(TextView::new(format!("\n{}", summary)))
+				.child(TextView::new(format!("\nTimings\n{}", timings)))
+				.child(TextView::new("\n[Enter] clean up a save")),
+		)
+		.title("Storage")
+		.button("Close", |s| {
+			s.pop_layer();
+		}),
+	);
+
+	Ok(())
+}
+
+/// Tags of the game profile in use whose backups are never suggested for deletion.
+fn keep_tags(s: &mut Cursive) -> Vec<String> {
+	s.with_user_data(|config: &mut Ini| profiles::keep_tags(config))
+		.expect("User data not set up correctly on program start")
+}
+
+/// Offers cleanup actions for one save, with how much each would free.
+fn suggest(s: &mut Cursive, backup_dir: &Path) -> crate::error::Result<()> {
+	let keep_tags = keep_tags(s);
+	let old = stale(backup_dir, &keep_tags)?;
+	let deletable = deletable(backup_dir, &keep_tags)?;
+
+	let mut message = format!(
+		"{} of the backups can be deleted, leaving out pinned backups, backups with kept tags and the newest.",
+		deletable.len()
+	);
+	if !old.is_empty() {
+		message.push_str(&format!(
+			"\n\n{} backups without a note are older than {} days, deleting them frees {}.",
+			old.len(),
+			STALE_DAYS,
+			format_size(total_size(&old))
+		));
+	}
+
+	let stale_dir = backup_dir.to_path_buf();
+	let oldest_dir = backup_dir.to_path_buf();
+	let review_dir = backup_dir.to_path_buf();
+	s.add_layer(
+		Dialog::around(TextView::new(message))
+			.title(format!(
+				"Clean up {}",
+				backup_dir.file_name().unwrap_or_default().to_string_lossy()
+			))
+			.button("Cancel", |s| {
+				s.pop_layer();
+			})
+			.button("Delete old without notes", move |s| {
+				match stale(&stale_dir, &keep_tags) {
+					Ok(backups) => delete(s, &stale_dir, backups),
+					Err(e) => s.add_layer(Dialog::info(i18n::error(&e))),
+				}
+			})
+			.button("Delete oldest...", move |s| delete_oldest(s, &oldest_dir))
+			.button("Pick backups", move |s| {
+				s.pop_layer();
+				if let Err(e) = crate::cleanup::delete_old(s, review_dir.clone()) {
+					s.add_layer(Dialog::info(i18n::error(&e)));
+				}
+			}),
+	);
+
+	Ok(())
+}
+
+/// Asks how many of the oldest deletable backups of a save to delete.
+fn delete_oldest(s: &mut Cursive, backup_dir: &Path) {
+	let backup_dir = backup_dir.to_path_buf();
+
+	s.add_layer(
+		Dialog::around(
+			LinearLayout::vertical()
+				.child(TextView::new("Number of the oldest backups to delete:"))
+				.child(EditView::new().with_name(OLDEST_COUNT_NAME))
+				.min_width(40),
+		)
+		.button("Cancel", |s| {
+			s.pop_layer();
+		})
+		.button("Delete", move |s| {
+			let entry = s
+				.call_on_name(OLDEST_COUNT_NAME, |view: &mut EditView| view.get_content())
+				.expect("EditView not created for backup count entry");
+			let count = match entry.trim().parse::<usize>() {
+				Ok(count) => count,
+				Err(_) => {
+					s.add_layer(Dialog::info("Enter a number of backups."));
+					return;
+				}
+			};
+			s.pop_layer();
+
+			match deletable(&backup_dir, &keep_tags(s)) {
+				Ok(mut backups) => {
+					backups.truncate(count);
+					delete(s, &backup_dir, backups);
+				}
+				Err(e) => s.add_layer(Dialog::info(i18n::error(&e))),
+			}
+		}),
+	);
+}
+
+/// Confirms and deletes the given backups of a save, then closes the cleanup dialog.
+fn delete(s: &mut Cursive, backup_dir: &Path, backups: Vec<Backup>) {
+	if backups.is_empty() {
+		s.add_layer(Dialog::info("No backups to delete."));
+		return;
+	}
+
+	let message = format!(
+		"Delete {} backups, freeing up to {}?",
+		backups.len(),
+		format_size(total_size(&backups))
+	);
+	let backup_dir = backup_dir.to_path_buf();
+	confirm(s, &message, move |s| {
+		let mut deleted = 0;
+		for backup in &backups {
+			match store::delete_backup(&backup_dir, backup) {
+				Ok(()) => deleted += 1,
+				Err(e) => error!("Could not delete {}: {}", backup.file_name, e),
+			}
+		}
+		info!("Deleted {} backups to free space", deleted);
+
+		s.pop_layer();
+		s.add_layer(Dialog::info(format!(
+			"Deleted {} backups. Reopen Storage to see the new totals.",
+			deleted
+		)));
+	});
+}
@@ -0,0 +1,2256 @@
+use std::collections::{HashMap, HashSet};
+use std::env;
+use std::ffi::{OsStr, OsString};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, MutexGuard};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use chrono::format::{Item, StrftimeItems};
+use chrono::{DateTime, Local};
+
+use fs2::FileExt;
+
+use log::{info, warn};
+
+use crate::autotag::{self, Rule};
+use crate::clock;
+use crate::error::{self, Context};
+use crate::format_size;
+use crate::index;
+use crate::jobs::{self, Moment};
+use crate::manifest::{Manifest, Origin, MANIFEST_FILE};
+use crate::parser::{self, GameDate, Header};
+use crate::progress::Progress;
+use crate::retention::{self, Policy, Trigger};
+use crate::sync::{self, Remote};
+use crate::timing::{self, Operation};
+use crate::trend::{self, Anomaly};
+use crate::undo::{self, Step, Trashed, TRASH_FOLDER};
+
+/// Suffix the game adds to its own backup copy of a save.
+pub const BAK_EXTENSION: &str = ".bak";
+
+/// Placeholder for the backup's number in naming templates, filled in once the number is known.
+const NUMBER_PLACEHOLDER: &str = "{num}";
+
+/// Suffix of backups stored compressed with zstd.
+pub const COMPRESSED_EXTENSION: &str = ".zst";
+
+/// Folder next to the backups of a save where the game's `.bak` files are archived.
+const BAK_FOLDER: &str = "bak";
+
+/// Folder next to the backups of a save where the live save is copied before a restore overwrites
+/// it.
+const PRE_RESTORE_FOLDER: &str = "pre-restore";
+
+/// How many copies of the live save taken before restores are kept for each save.
+const PRE_RESTORE_KEEP: usize = 10;
+
+/// File next to the backups of a save that is locked while a backup number is handed out or the
+/// manifest is written, keeping apart everyone backing up into a shared backup folder.
+const NUMBER_LOCK_FILE: &str = ".numbers.lock";
+
+/// Held with the file lock, since threads of one process may not be kept apart by it.
+static ALLOCATING: Mutex<()> = Mutex::new(());
+
+/// A newly created backup.
+pub struct Created {
+	pub number: usize,
+	/// Size of the backup file.
+	pub size: u64,
+	/// Bytes written to disk, which is zero when identical content was already stored.
+	pub written: u64,
+	/// Whether the save was unchanged since the newest backup, so no backup was taken and
+	/// `number` is that of the newest backup.
+	pub skipped: bool,
+	/// Set when the save is far off the usual size of its backups.
+	pub anomaly: Option<Anomaly>,
+}
+
+/// What to do when a backup would leave less free space than configured on its drive.
+#[derive(Clone)]
+pub enum LowSpaceAction {
+	/// Log a warning and back up anyway.
+	Warn,
+	/// Refuse to back up.
+	Block,
+	/// Delete the oldest unpinned backups of the save until there is room.
+	Prune,
+	/// Back up into this folder instead.
+	Secondary(PathBuf),
+}
+
+/// What to do with the `.ck2.bak` files the game sometimes leaves next to its saves, set with the
+/// `bak_files` key.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum BakFiles {
+	/// Leave them out of save listings.
+	Ignore,
+	/// List them alongside the save they belong to.
+	Include,
+	/// Leave them out of save listings, but keep a copy in a `bak` folder next to the backups of
+	/// their save whenever it is backed up.
+	Archive,
+}
+
+impl BakFiles {
+	pub fn from_setting(setting: Option<&str>) -> Self {
+		match setting {
+			Some("include") => Self::Include,
+			Some("archive") => Self::Archive,
+			_ => Self::Ignore,
+		}
+	}
+}
+
+/// How backups are given an identifier that stays unique across machines, imports and syncs,
+/// set with the `backup_ids` key. Backups keep their numbers and notes as display names either way.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum IdScheme {
+	/// Sortable by creation time.
+	Ulid,
+	/// Random version 4 UUIDs.
+	Uuid,
+	/// Backups are known by their number only.
+	None,
+}
+
+impl IdScheme {
+	pub fn from_setting(setting: Option<&str>) -> Self {
+		match setting {
+			Some("uuid") => Self::Uuid,
+			Some("none") => Self::None,
+			_ => Self::Ulid,
+		}
+	}
+
+	/// A new identifier, or `None` when backups are not given one.
+	pub fn generate(self) -> Option<String> {
+		match self {
+			Self::Ulid => Some(ulid::Ulid::new().to_string()),
+			Self::Uuid => Some(uuid::Uuid::new_v4().to_string()),
+			Self::None => None,
+		}
+	}
+}
+
+/// Settings that shape how backups are taken.
+#[derive(Clone)]
+pub struct BackupOptions {
+	/// Free space to leave on the backup drive in bytes, or zero to never check.
+	pub min_free_space: u64,
+	pub low_space_action: LowSpaceAction,
+	pub bak_files: BakFiles,
+	/// Whether new backups are compressed.
+	pub compress: bool,
+	/// How many times larger or smaller than usual a save must be to raise a size alert, or
+	/// `None` to never check.
+	pub size_alert: Option<f64>,
+	pub ids: IdScheme,
+	/// Template for the part of a new backup's file name after its number, or `None` to name
+	/// backups after their note alone. See `render_name`.
+	pub naming: Option<String>,
+	/// Rules tagging new backups from what the save shows.
+	pub tag_rules: Vec<Rule>,
+	/// Tags whose backups are not pruned to free space.
+	pub keep_tags: Vec<String>,
+	/// Remote the backup folder is pushed to after each backup.
+	pub sync: Option<Remote>,
+	/// Who new backups are recorded as taken by.
+	pub author: String,
+	pub lock_retry: LockRetry,
+}
+
+impl Default for BackupOptions {
+	fn default() -> Self {
+		Self {
+			min_free_space: 0,
+			low_space_action: LowSpaceAction::Warn,
+			bak_files: BakFiles::Ignore,
+			compress: false,
+			size_alert: None,
+			ids: IdScheme::Ulid,
+			naming: None,
+			tag_rules: Vec::new(),
+			keep_tags: Vec::new(),
+			sync: None,
+			author: local_author(),
+			lock_retry: LockRetry::default(),
+		}
+	}
+}
+
+/// How automatic backups retry a save the game has open, such as while it is still writing it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct LockRetry {
+	/// How many times a backup is tried in all, where one means it is never retried.
+	pub attempts: u32,
+	/// Wait before the second try, doubled before each try after it.
+	pub delay: Duration,
+}
+
+impl Default for LockRetry {
+	fn default() -> Self {
+		Self {
+			attempts: 5,
+			delay: Duration::from_secs(1),
+		}
+	}
+}
+
+impl LockRetry {
+	/// Runs `attempt` until it succeeds, fails other than with `in_use`, or runs out of tries,
+	/// waiting twice as long before each try as before the one before it. The tries used up are
+	/// taken off, so that what is left can be handed on to a later step of the same backup.
+	pub fn run<T, E>(
+		&mut self,
+		name: &str,
+		in_use: impl Fn(&E) -> bool,
+		mut attempt: impl FnMut() -> Result<T, E>,
+	) -> Result<T, E> {
+		loop {
+			match attempt() {
+				Err(e) if in_use(&e) && self.attempts > 1 => {
+					warn!(
+						"{}: in use by the game, trying again in {} ms ({} tries left)",
+						name,
+						self.delay.as_millis(),
+						self.attempts - 1
+					);
+					thread::sleep(self.delay);
+					self.attempts -= 1;
+					self.delay *= 2;
+				}
+				result => return result,
+			}
+		}
+	}
+}
+
+/// Who backups taken on this machine are recorded as taken by when no name is set: the login name
+/// and the machine name, like `anna@living-room`.
+pub fn local_author() -> String {
+	let user = env::var("USER")
+		.or_else(|_| env::var("USERNAME"))
+		.unwrap_or_else(|_| "unknown".to_string());
+	let machine = env::var("COMPUTERNAME")
+		.or_else(|_| env::var("HOSTNAME"))
+		.ok()
+		.or_else(|| fs::read_to_string("/etc/hostname").ok())
+		.map(|machine| machine.trim().to_string())
+		.filter(|machine| !machine.is_empty());
+
+	match machine {
+		Some(machine) => format!("{}@{}", user, machine),
+		None => user,
+	}
+}
+
+/// What rebuilding the backup index found.
+#[derive(Default)]
+pub struct Rebuilt {
+	pub saves: usize,
+	pub backups: usize,
+	/// Backups with distinct contents.
+	pub unique: usize,
+	/// Manifest entries dropped because their backup no longer exists.
+	pub stale: usize,
+	/// Backups given an ID for the first time.
+	pub identified: usize,
+}
+
+/// Differences between the backup files on disk and the metadata describing them.
+#[derive(Default)]
+pub struct Consistency {
+	/// Backups whose contents are missing from the content index.
+	pub unindexed: Vec<PathBuf>,
+	/// Files in a save's backup folder that are not named like backups.
+	pub strays: Vec<PathBuf>,
+	/// Content index entries whose file no longer exists.
+	pub missing_objects: Vec<String>,
+	/// Manifest entries whose backup no longer exists, with the folder of the manifest.
+	pub missing_backups: Vec<(PathBuf, String)>,
+}
+
+impl Consistency {
+	pub const fn is_empty(&self) -> bool {
+		self.unindexed.is_empty()
+			&& self.strays.is_empty()
+			&& self.missing_objects.is_empty()
+			&& self.missing_backups.is_empty()
+	}
+}
+
+/// What hashing the backups of a save again found.
+#[derive(Default)]
+pub struct Verification {
+	pub checked: usize,
+	/// Backups that can no longer be read in full or whose contents changed, with what is wrong.
+	pub damaged: Vec<(Backup, String)>,
+	/// Backups taken before checksums were recorded, which have one now.
+	pub recorded: usize,
+}
+
+/// A single numbered backup of a save file, stored as `N` or `N_note`, with a `.zst` suffix when
+/// compressed.
+///
+/// Backups named from a template are stored as `N_name`, with their note recorded in the manifest.
+#[derive(Clone)]
+pub struct Backup {
+	pub number: usize,
+	pub note: String,
+	pub file_name: String,
+	pub path: PathBuf,
+	pub compressed: bool,
+}
+
+impl Backup {
+	pub fn from_path(path: PathBuf) -> Option<Self> {
+		let file_name = path.file_name()?.to_str()?.to_string();
+		let number = parse_number(&file_name)?;
+		let name = file_name
+			.strip_suffix(COMPRESSED_EXTENSION)
+			.unwrap_or(&file_name);
+		let note = name
+			.split_once('_')
+			.map_or("", |(_, note)| note)
+			.to_string();
+
+		Some(Self {
+			number,
+			note,
+			compressed: name.len() != file_name.len(),
+			file_name,
+			path,
+		})
+	}
+
+	/// Takes the note recorded in the manifest over the one read from the file name, for backups
+	/// named from a template whose name holds more than the note.
+	pub fn with_recorded_note(mut self, manifest: &Manifest) -> Self {
+		if let Some(note) = manifest.note(&self.file_name) {
+			self.note = note;
+		}
+		self
+	}
+
+	/// Part of the file name after the backup number.
+	fn name(&self) -> &str {
+		self.file_name
+			.strip_suffix(COMPRESSED_EXTENSION)
+			.unwrap_or(&self.file_name)
+			.split_once('_')
+			.map_or("", |(_, name)| name)
+	}
+
+	/// Opens the backup for reading the save it holds.
+	pub fn open(&self) -> io::Result<Box<dyn Read>> {
+		open_stored(&self.path)
+	}
+
+	/// When the backup was taken, as recorded in the manifest. Older backups fall back to the time
+	/// their file was last modified, which for deduplicated backups is when the content was first
+	/// stored.
+	pub fn created(&self, manifest: &Manifest) -> Option<DateTime<Local>> {
+		manifest.created(&self.file_name).or_else(|| {
+			fs::metadata(&self.path)
+				.and_then(|metadata| metadata.modified())
+				.ok()
+				.map(DateTime::from)
+		})
+	}
+
+	/// Size of the save the backup holds, as recorded in the manifest. Older backups fall back to
+	/// the size of their file, which is smaller for compressed backups.
+	pub fn size(&self, manifest: &Manifest) -> Option<u64> {
+		manifest
+			.size(&self.file_name)
+			.or_else(|| fs::metadata(&self.path).ok().map(|metadata| metadata.len()))
+	}
+
+	/// In-game date of the save the backup holds, as recorded in the manifest. Older backups have
+	/// their header read instead.
+	pub fn game_date(&self, manifest: &Manifest) -> Option<GameDate> {
+		manifest
+			.game_date(&self.file_name)
+			.or_else(|| self.header().date)
+	}
+
+	/// Whether every word of a search appears in the backup's number, note, creation time, in-game
+	/// date or author, ignoring case. An empty search matches every backup.
+	pub fn matches(&self, manifest: &Manifest, search: &str) -> bool {
+		let search = search.to_lowercase();
+		if search.trim().is_empty() {
+			return true;
+		}
+
+		let mut text = format!("{} {}", self.number, self.note.to_lowercase());
+		if let Some(created) = self.created(manifest) {
+			text.push_str(&created.format(" %Y-%m-%d %H:%M").to_string());
+		}
+		if let Some(date) = self.game_date(manifest) {
+			text.push_str(&format!(" {}", date));
+		}
+		if let Some(author) = manifest.author(&self.file_name) {
+			text.push_str(&format!(" {}", author.to_lowercase()));
+		}
+
+		search.split_whitespace().all(|word| text.contains(word))
+	}
+
+	/// Character being played in the save the backup holds, as recorded in the manifest. Older
+	/// backups have their header read instead.
+	pub fn player(&self, manifest: &Manifest) -> Option<String> {
+		manifest
+			.player(&self.file_name)
+			.or_else(|| self.header().player())
+	}
+
+	/// Who took the backup, recorded in the manifest for backups taken since authors were kept.
+	pub fn author(&self, manifest: &Manifest) -> Option<String> {
+		manifest.author(&self.file_name)
+	}
+
+	/// Identifier of the backup that is unique across machines, recorded in the manifest for
+	/// backups taken or imported since identifiers were given out.
+	pub fn id(&self, manifest: &Manifest) -> Option<String> {
+		manifest.id(&self.file_name)
+	}
+
+	/// Reads the header of the save the backup holds.
+	pub fn header(&self) -> Header {
+		self.open()
+			.map_or_else(|_| Header::default(), parser::read_header_from)
+	}
+}
+
+/// The backups of one save, kept in a folder of their own inside the backup folder. Programs using
+/// the library take, find and restore backups through it rather than the functions below.
+pub struct BackupStore {
+	backup_dir: PathBuf,
+	options: BackupOptions,
+}
+
+impl BackupStore {
+	/// Opens the backup folder of a save, creating it when missing. Backups are taken with the
+	/// default options until others are given with `with_options`.
+	pub fn open(backup_dir: impl AsRef<Path>) -> io::Result<Self> {
+		let backup_dir = backup_dir.as_ref();
+		fs::create_dir_all(backup_dir)?;
+		Ok(Self {
+			backup_dir: backup_dir.to_path_buf(),
+			options: BackupOptions::default(),
+		})
+	}
+
+	pub fn with_options(mut self, options: BackupOptions) -> Self {
+		self.options = options;
+		self
+	}
+
+	pub fn path(&self) -> &Path {
+		&self.backup_dir
+	}
+
+	/// Every backup of the save, oldest first.
+	pub fn backups(&self) -> io::Result<Vec<Backup>> {
+		list_backups(&self.backup_dir)
+	}
+
+	/// What is recorded about the backups, such as when each was taken and its tags.
+	pub fn manifest(&self) -> Manifest {
+		Manifest::load(&self.backup_dir)
+	}
+
+	/// Finds a backup by its number or its identifier, as `find_backup` does.
+	pub fn find(&self, reference: &str) -> error::Result<Backup> {
+		find_backup(&self.backup_dir, reference)
+	}
+
+	/// Backs up the save file under the next free number. An unchanged save without a note is not
+	/// backed up again, and the newest backup is returned as skipped instead.
+	pub fn back_up(&self, save_file: &Path, note: &str, origin: Origin) -> error::Result<Created> {
+		backup_core(save_file, &self.backup_dir, note, origin, &self.options)
+	}
+
+	/// Restores a backup over the save file, returning where the save it replaced was kept.
+	pub fn restore(&self, backup: &Backup, save_file: &Path) -> error::Result<Option<PathBuf>> {
+		restore_core(backup, save_file)
+	}
+
+	/// Puts back the save kept by the latest restore over the save file.
+	pub fn undo_restore(&self, save_file: &Path) -> error::Result<()> {
+		let kept = latest_kept_save(save_file, &self.backup_dir)
+			.ok_or("No save was kept before a restore.")?;
+		undo_restore(&kept, save_file).context("write to", save_file)
+	}
+
+	/// Deletes the automatic backups the policy does not keep, never those tagged with one of
+	/// `keep_tags`, returning how many were deleted.
+	pub fn apply_retention(&self, policy: Policy, keep_tags: &[String]) -> io::Result<usize> {
+		retention::run(
+			&self.backup_dir,
+			policy,
+			Trigger::Manual,
+			keep_tags,
+			Moment::Manual,
+		)
+		.unwrap_or(Ok(0))
+	}
+
+	pub fn delete(&self, backup: &Backup) -> io::Result<()> {
+		delete_backup(&self.backup_dir, backup)
+	}
+}
+
+/// Extracts the backup number from a backup file name.
+pub fn parse_number(file_name: &str) -> Option<usize> {
+	file_name
+		.strip_suffix(COMPRESSED_EXTENSION)
+		.unwrap_or(file_name)
+		.split('_')
+		.next()?
+		.parse::<usize>()
+		.ok()
+}
+
+fn is_compressed(path: &Path) -> bool {
+	path.file_name()
+		.and_then(OsStr::to_str)
+		.is_some_and(|name| name.ends_with(COMPRESSED_EXTENSION))
+}
+
+/// Opens a stored file for reading its contents, decompressing it if needed.
+fn open_stored(path: &Path) -> io::Result<Box<dyn Read>> {
+	let file = File::open(path)?;
+	if is_compressed(path) {
+		Ok(Box::new(zstd::Decoder::new(file)?))
+	} else {
+		Ok(Box::new(file))
+	}
+}
+
+/// Lists every backup in the directory, ordered by backup number.
+pub fn list_backups(backup_dir: &Path) -> io::Result<Vec<Backup>> {
+	let manifest = Manifest::load(backup_dir);
+	let mut backups = fs::read_dir(backup_dir)?
+		.filter_map(Result::ok)
+		.map(|file| file.path())
+		.filter(|path| path.is_file())
+		.filter_map(Backup::from_path)
+		.map(|backup| backup.with_recorded_note(&manifest))
+		.collect::<Vec<_>>();
+	backups.sort_unstable_by_key(|backup| backup.number);
+
+	Ok(backups)
+}
+
+/// Finds a backup of a save by its number, its identifier, or the start of its identifier when no
+/// other backup's identifier starts the same way.
+pub fn find_backup(backup_dir: &Path, reference: &str) -> error::Result<Backup> {
+	let mut backups = list_backups(backup_dir)?;
+	let numbered = reference
+		.parse::<usize>()
+		.ok()
+		.and_then(|number| backups.iter().position(|backup| backup.number == number));
+	if let Some(position) = numbered {
+		return Ok(backups.swap_remove(position));
+	}
+
+	let manifest = Manifest::load(backup_dir);
+	let reference = reference.to_lowercase();
+	let mut matching = backups.into_iter().filter(|backup| {
+		backup
+			.id(&manifest)
+			.is_some_and(|id| id.to_lowercase().starts_with(&reference))
+	});
+	match (matching.next(), matching.next()) {
+		(Some(backup), None) if !reference.is_empty() => Ok(backup),
+		(Some(_), Some(_)) => Err(format!(
+			"More than one backup has an ID starting with {}.",
+			reference
+		)
+		.into()),
+		_ => Err(format!("There is no backup {}.", reference).into()),
+	}
+}
+
+/// Checks whether files can be created in the backup folder, which fails when it lives on
+/// read-only media or a locked network share.
+pub fn is_read_only(backup_path: &Path) -> bool {
+	let probe = backup_path.join(".write-test");
+	match fs::File::create(&probe) {
+		Ok(_) => {
+			let _ = fs::remove_file(&probe);
+			false
+		}
+		Err(_) => true,
+	}
+}
+
+/// Name of the save whose backups are kept in a directory, for log messages.
+fn save_name(backup_dir: &Path) -> String {
+	backup_dir
+		.file_name()
+		.map(|name| name.to_string_lossy().into_owned())
+		.unwrap_or_default()
+}
+
+/// Copies the save file into the backup directory under the next free number. A note that cannot
+/// become part of a file name is refused.
+pub fn backup_core(
+	file_path: &Path,
+	backup_dir: &Path,
+	note: &str,
+	origin: Origin,
+	options: &BackupOptions,
+) -> error::Result<Created> {
+	check_note(note)?;
+	let started = Instant::now();
+	let slot = jobs::acquire("Backup");
+
+	// a backup with a note is deliberate, so only unannotated ones are skipped
+	let hash = hash_file(file_path).context("read", file_path)?;
+	if note.is_empty() {
+		if let Some(latest) = newest_matching(backup_dir, &hash)? {
+			info!(
+				event = "backup_skipped", save = save_name(backup_dir).as_str(), backup = latest.number;
+				"Save unchanged since backup number {}, skipped",
+				latest.number
+			);
+			return Ok(Created {
+				number: latest.number,
+				size: 0,
+				written: 0,
+				skipped: true,
+				anomaly: None,
+			});
+		}
+	}
+
+	let backup_dir = &make_room(file_path, backup_dir, options)?;
+	let store_root = &index::store_root(backup_dir)
+		.ok_or("Backup directory is not inside the backup folder.")?;
+	let note = note.trim();
+	let header = parser::read_header(file_path);
+	let name = options.naming.as_ref().map_or_else(
+		|| note.to_string(),
+		|template| render_name(template, note, &save_name(backup_dir), &header),
+	);
+	let tags = if options.tag_rules.is_empty() {
+		Vec::new()
+	} else {
+		let manifest = Manifest::load(backup_dir);
+		let previous = list_backups(backup_dir)?.pop();
+		autotag::apply(
+			&options.tag_rules,
+			file_path,
+			&header,
+			fs::metadata(file_path)?.len(),
+			previous.as_ref().map(|previous| (previous, &manifest)),
+		)
+	};
+	let (save_number, destination) = reserve(
+		backup_dir,
+		|number| {
+			// a note given without a template is taken as it is
+			if options.naming.is_some() {
+				name.replace(NUMBER_PLACEHOLDER, &number.to_string())
+			} else {
+				name.clone()
+			}
+		},
+		options.compress,
+	)?;
+
+	let (size, written, hash) = match store_deduplicated(file_path, &hash, &destination, store_root)
+	{
+		Ok(stored) => stored,
+		Err(e) => {
+			// give the number back rather than leave an empty backup behind
+			let _ = fs::remove_file(&destination);
+			return Err(e.into());
+		}
+	};
+	let anomaly = match options.size_alert {
+		Some(ratio) => trend::check(backup_dir, save_number, size, ratio)?,
+		None => None,
+	};
+
+	if let Some(file_name) = destination.file_name() {
+		let file_name = file_name.to_string_lossy();
+		// loaded under the lock so that a backup taken elsewhere at the same time is not lost
+		let _writers = lock_writers(backup_dir)?;
+		let mut manifest = Manifest::load(backup_dir);
+		if let Some(anomaly) = &anomaly {
+			manifest.set_pinned(&anomaly.restore_point.file_name, true);
+		}
+		manifest.set_origin(&file_name, origin);
+		manifest.set_author(&file_name, &options.author);
+		manifest.set_created(&file_name, clock::now());
+		manifest.set_size(&file_name, size);
+		manifest.set_hash(&file_name, &hash);
+		if let Some(id) = options.ids.generate() {
+			manifest.set_id(&file_name, &id);
+		}
+		// the name no longer tells the note apart from the rest
+		if options.naming.is_some() {
+			manifest.set_note(&file_name, note);
+		}
+		if let Some(date) = header.date {
+			manifest.set_game_date(&file_name, date);
+		}
+		if let Some(player) = header.player() {
+			manifest.set_player(&file_name, &player);
+		}
+		manifest.set_tags(&file_name, &tags);
+		manifest.save()?;
+	}
+
+	if options.bak_files == BakFiles::Archive {
+		// the backup itself succeeded, so a problem with the game's own copy is only worth a warning
+		if let Err(e) = archive_bak_file(file_path, backup_dir) {
+			warn!("Could not archive the game's .bak file: {}", e);
+		}
+	}
+
+	let save = save_name(backup_dir);
+	let duration_ms = started.elapsed().as_millis() as u64;
+	timing::record(store_root, Operation::Backup, size, started.elapsed());
+	if written == 0 {
+		info!(
+			event = "backup_created", save = save.as_str(), backup = save_number, duration_ms = duration_ms;
+			"Backup number {} created (identical to an existing backup, stored once)",
+			save_number
+		);
+	} else {
+		info!(
+			event = "backup_created", save = save.as_str(), backup = save_number, duration_ms = duration_ms;
+			"Backup number {} created",
+			save_number
+		);
+	}
+
+	if !tags.is_empty() {
+		info!("Backup number {} tagged {}", save_number, tags.join(", "));
+	}
+	if let Some(anomaly) = &anomaly {
+		warn!(
+			event = "size_anomaly", save = save.as_str(), backup = save_number, size = size, usual = anomaly.usual;
+			"Backup number {}: {}",
+			save_number,
+			anomaly.describe()
+		);
+	}
+
+	// the push waits for a place in the job queue of its own
+	drop(slot);
+	if let Some(remote) = &options.sync {
+		sync::push_soon(remote.clone(), store_root.clone());
+	}
+
+	Ok(Created {
+		number: save_number,
+		size,
+		written,
+		skipped: false,
+		anomaly,
+	})
+}
+
+/// Backs up a save like `backup_core`, trying again while the game has the save open.
+///
+/// Windows refuses to share a save while it is being written, so each try waits a little longer
+/// than the last for as long as `options.lock_retry` allows.
+pub fn backup_retrying(
+	file_path: &Path,
+	backup_dir: &Path,
+	note: &str,
+	origin: Origin,
+	options: &BackupOptions,
+) -> error::Result<Created> {
+	let mut retry = options.lock_retry;
+	retry.run(&save_name(backup_dir), error::Error::in_use, || {
+		backup_core(file_path, backup_dir, note, origin, options)
+	})
+}
+
+/// The newest backup in a directory, if it holds the save with the given content hash.
+fn newest_matching(backup_dir: &Path, hash: &str) -> io::Result<Option<Backup>> {
+	if !backup_dir.is_dir() {
+		return Ok(None);
+	}
+	let latest = match list_backups(backup_dir)?.pop() {
+		Some(latest) => latest,
+		None => return Ok(None),
+	};
+
+	// backups from before hashes were recorded are hashed on the spot
+	let latest_hash = match Manifest::load(backup_dir).hash(&latest.file_name) {
+		Some(latest_hash) => latest_hash,
+		None => hash_file(&latest.path)?,
+	};
+
+	Ok((latest_hash == hash).then_some(latest))
+}
+
+/// Applies the low space policy before a backup, returning the directory the backup should go to.
+fn make_room(
+	file_path: &Path,
+	backup_dir: &Path,
+	options: &BackupOptions,
+) -> error::Result<PathBuf> {
+	if options.min_free_space == 0 {
+		return Ok(backup_dir.to_path_buf());
+	}
+
+	let needed = fs::metadata(file_path)?.len() + options.min_free_space;
+	let low_on_space = |dir: &Path| -> io::Result<bool> { Ok(fs2::available_space(dir)? < needed) };
+
+	if !low_on_space(backup_dir)? {
+		return Ok(backup_dir.to_path_buf());
+	}
+
+	let threshold = format_size(options.min_free_space);
+	match &options.low_space_action {
+		LowSpaceAction::Warn => {
+			warn!(
+				"Less than {} will be left free on the backup drive",
+				threshold
+			);
+			Ok(backup_dir.to_path_buf())
+		}
+		LowSpaceAction::Block => Err(format!(
+			"Backup skipped, it would leave less than {} free on the backup drive.",
+			threshold
+		)
+		.into()),
+		LowSpaceAction::Prune => {
+			let manifest = Manifest::load(backup_dir);
+			let backups = list_backups(backup_dir)?;
+
+			// the newest backup is never pruned, in case the new one fails
+			for backup in backups.iter().rev().skip(1).rev() {
+				if !low_on_space(backup_dir)? {
+					break;
+				}
+				if !manifest.is_kept(&backup.file_name, &options.keep_tags) {
+					warn!("Pruning backup number {} to free space", backup.number);
+					delete_backup(backup_dir, backup)?;
+				}
+			}
+
+			if low_on_space(backup_dir)? {
+				Err(format!(
+					"Backup skipped, pruning could not free enough space to keep {} free.",
+					threshold
+				)
+				.into())
+			} else {
+				Ok(backup_dir.to_path_buf())
+			}
+		}
+		LowSpaceAction::Secondary(folder) => {
+			let secondary = folder.join(
+				backup_dir
+					.file_name()
+					.ok_or("Backup directory has no name.")?,
+			);
+			fs::create_dir_all(&secondary)?;
+
+			if low_on_space(&secondary)? {
+				return Err(format!(
+					"Backup skipped, neither backup drive has {} to spare.",
+					threshold
+				)
+				.into());
+			}
+
+			warn!(
+				"Backup drive is low on space, backing up to {} instead",
+				secondary.display()
+			);
+			Ok(secondary)
+		}
+	}
+}
+
+/// Copies the game's `.bak` file for a save into the `bak` folder of its backups, named after when
+/// the game wrote it so that each version is kept once.
+fn archive_bak_file(file_path: &Path, backup_dir: &Path) -> io::Result<()> {
+	let mut bak_name = file_path.as_os_str().to_os_string();
+	bak_name.push(BAK_EXTENSION);
+	let bak_path = PathBuf::from(bak_name);
+	if !bak_path.is_file() {
+		return Ok(());
+	}
+
+	let modified = DateTime::<Local>::from(fs::metadata(&bak_path)?.modified()?);
+	let archive_dir = backup_dir.join(BAK_FOLDER);
+	let destination =
+		archive_dir.join(modified.format("%Y-%m-%d_%H-%M-%S").to_string() + BAK_EXTENSION);
+	if destination.exists() {
+		return Ok(());
+	}
+
+	fs::create_dir_all(&archive_dir)?;
+	fs::copy(&bak_path, &destination)?;
+	info!("Archived the game's .bak file as {}", destination.display());
+
+	Ok(())
+}
+
+/// Stores a backup read from elsewhere, such as an archive, as the next backup of a save. The
+/// backup is stored as it is, so `compressed` tells whether it is compressed. Returns the new
+/// backup.
+pub fn import_backup(
+	reader: &mut dyn Read,
+	backup_dir: &Path,
+	note: &str,
+	compressed: bool,
+) -> io::Result<Backup> {
+	fs::create_dir_all(backup_dir)?;
+
+	let (_, destination) = reserve(backup_dir, |_| note.to_string(), compressed)?;
+	if let Err(e) = io::copy(reader, &mut File::create(&destination)?) {
+		let _ = fs::remove_file(&destination);
+		return Err(e);
+	}
+
+	// later backups of the same save can be linked to this one
+	if let Some(store_root) = index::store_root(backup_dir) {
+		let hash = hash_file(&destination)?;
+		let mut index = index::lock(&store_root)?;
+		if index.lookup(&hash).is_none() {
+			index.record(&hash, &destination)?;
+		}
+	}
+
+	Ok(Backup::from_path(destination).expect("file name starts with the number"))
+}
+
+/// Finds the number the next backup in the directory should get.
+fn next_number(backup_dir: &Path) -> io::Result<usize> {
+	Ok(list_backups(backup_dir)?
+		.last()
+		.map_or(1, |backup| backup.number + 1))
+}
+
+/// Fills in a note template. `{save}`, `{date}`, `{time}`, `{game_date}` and `{label}` (the
+/// `session_label` setting) are replaced.
+#[allow(clippy::literal_string_with_formatting_args)]
+pub fn expand_note(template: &str, save: &str, file_path: &Path, session_label: &str) -> String {
+	let now = clock::now();
+	let mut note = template
+		.replace("{save}", save)
+		.replace("{date}", &now.format("%Y-%m-%d").to_string())
+		.replace("{time}", &now.format("%H.%M").to_string())
+		.replace("{label}", session_label);
+
+	if note.contains("{game_date}") {
+		let game_date = parser::read_header(file_path)
+			.date
+			.map(|date| date.to_string())
+			.unwrap_or_default();
+		note = note.replace("{game_date}", &game_date);
+	}
+
+	note.trim().to_string()
+}
+
+/// Fills in a naming template for the part of a backup's file name after its number. The template
+/// may hold:
+///
+/// * `{note}`: the note given to the backup
+/// * `{date}`: when the backup is taken, like `2024-05-01_1830`, or `{date:<format>}` for a
+///   strftime format such as `{date:%Y%m%d}`
+/// * `{game_date}`: the in-game date of the save
+/// * `{save}`: the name of the save
+/// * `{num}`: the backup's number, which is only known once its file is reserved and so is left
+///   in place to be filled in then
+///
+/// The number always comes first, so a leading `{num}` is left out. Placeholders that are empty
+/// leave no stray underscores behind, and characters that cannot appear in a file name become
+/// dashes.
+fn render_name(template: &str, note: &str, save: &str, header: &Header) -> String {
+	let now = clock::now();
+	let template = template.trim();
+	let template = template
+		.strip_prefix(NUMBER_PLACEHOLDER)
+		.map_or(template, |rest| {
+			rest.trim_start_matches(&['_', '-', ' '][..])
+		});
+
+	let mut name = String::new();
+	let mut rest = template;
+	while let Some(start) = rest.find('{') {
+		name.push_str(&rest[..start]);
+		let end = match rest[start..].find('}') {
+			Some(end) => start + end,
+			None => break,
+		};
+		let placeholder = &rest[start + 1..end];
+		match placeholder.split_once(':') {
+			None if placeholder == "note" => name.push_str(note),
+			None if placeholder == "num" => name.push_str(NUMBER_PLACEHOLDER),
+			None if placeholder == "save" => name.push_str(save),
+			None if placeholder == "game_date" => {
+				if let Some(date) = header.date {
+					name.push_str(&date.to_string());
+				}
+			}
+			None if placeholder == "date" => {
+				name.push_str(&now.format("%Y-%m-%d_%H%M").to_string());
+			}
+			// an invalid format would panic when written out
+			Some(("date", format))
+				if !StrftimeItems::new(format).any(|item| matches!(item, Item::Error)) =>
+			{
+				name.push_str(&now.format(format).to_string());
+			}
+			_ => name.push_str(&rest[start..=end]),
+		}
+		rest = &rest[end + 1..];
+	}
+	name.push_str(rest);
+
+	let name = name.replace(NOTE_FORBIDDEN, "-");
+	let mut tidy = String::with_capacity(name.len());
+	for c in name.chars() {
+		if !(c == '_' && tidy.ends_with('_')) {
+			tidy.push(c);
+		}
+	}
+
+	tidy.trim_matches(&['_', '-', ' '][..]).to_string()
+}
+
+/// Hands out the next number of a save's backups and reserves the backup's file by creating it
+/// empty, so that a backup taken at the same moment by another thread or process, such as an
+/// automatic session and a manual backup, gets a different number. The part of the file name after
+/// the number is `name` of that number. Returns the number and the reserved file, which the caller
+/// fills in or removes.
+fn reserve(
+	backup_dir: &Path,
+	name: impl Fn(usize) -> String,
+	compressed: bool,
+) -> io::Result<(usize, PathBuf)> {
+	let _writers = lock_writers(backup_dir)?;
+
+	let mut number = next_number(backup_dir)?;
+	loop {
+		let mut file_name = number.to_string();
+		let name = name(number);
+		if !name.is_empty() {
+			file_name.push('_');
+			file_name.push_str(&name);
+		}
+		if compressed {
+			file_name.push_str(COMPRESSED_EXTENSION);
+		}
+		let destination = backup_dir.join(file_name);
+
+		match OpenOptions::new()
+			.write(true)
+			.create_new(true)
+			.open(&destination)
+		{
+			Ok(_) => return Ok((number, destination)),
+			// written by something that does not take the lock
+			Err(e) if e.kind() == io::ErrorKind::AlreadyExists => number += 1,
+			Err(e) => return Err(e),
+		}
+	}
+}
+
+/// Keeps other threads, processes and machines from handing out backup numbers or writing the
+/// manifest of a save until dropped.
+pub(crate) struct WritersLock {
+	// the file lock goes first, while the mutex still keeps this process's threads out
+	_file: File,
+	_allocating: MutexGuard<'static, ()>,
+}
+
+pub(crate) fn lock_writers(backup_dir: &Path) -> io::Result<WritersLock> {
+	let allocating = ALLOCATING.lock().unwrap();
+	let file = File::create(backup_dir.join(NUMBER_LOCK_FILE))?;
+	file.lock_exclusive()?;
+
+	Ok(WritersLock {
+		_file: file,
+		_allocating: allocating,
+	})
+}
+
+/// Whether another program, such as the game in the middle of writing an ironman save, holds a
+/// file so that it cannot be read.
+pub fn is_locked(path: &Path) -> bool {
+	match File::open(path) {
+		Ok(file) => FileExt::try_lock_shared(&file).is_err(),
+		Err(e) => e.kind() != io::ErrorKind::NotFound,
+	}
+}
+
+/// Why a restore was refused when the game has the save open.
+pub const SAVE_IN_USE: &str =
+	"The game is using this save. Close the game or return to the main menu first, then restore again.";
+
+/// How many times a restored save is moved over the live one while something else has it open, and
+/// how long to wait in between.
+const REPLACE_ATTEMPTS: u32 = 20;
+const REPLACE_RETRY_DELAY: Duration = Duration::from_millis(250);
+
+/// The live save held locked while a restore prepares to replace it, so that the restore and the
+/// game's own writes cannot interleave.
+///
+/// Everything the restore reads from the save goes through the lock, as some systems shut out even
+/// the process holding it from other handles.
+pub struct SaveLock {
+	path: PathBuf,
+	file: File,
+	/// Whether the save existed before, rather than being created for the restore.
+	existed: bool,
+}
+
+impl SaveLock {
+	/// Locks a save, creating it if there is none yet. Fails with `SAVE_IN_USE` when the game has
+	/// the save open or locked.
+	pub fn acquire(path: &Path) -> io::Result<Self> {
+		let in_use = || io::Error::new(io::ErrorKind::WouldBlock, SAVE_IN_USE);
+		let existed = path.is_file();
+		let file = OpenOptions::new()
+			.read(true)
+			.write(true)
+			.create(true)
+			.truncate(false)
+			.open(path)
+			.map_err(|e| if opened_elsewhere(&e) { in_use() } else { e })?;
+		FileExt::try_lock_exclusive(&file).map_err(|_| in_use())?;
+
+		Ok(Self {
+			path: path.to_path_buf(),
+			file,
+			existed,
+		})
+	}
+
+	/// Copies the save as it is to `destination`.
+	fn copy_to(&mut self, destination: &Path) -> io::Result<u64> {
+		self.file.seek(SeekFrom::Start(0))?;
+		io::copy(&mut self.file, &mut File::create(destination)?)
+	}
+
+	/// Copies the save to a file that does not exist yet, failing rather than overwriting one.
+	fn copy_to_new(&mut self, destination: &Path) -> io::Result<u64> {
+		let mut copy = OpenOptions::new()
+			.write(true)
+			.create_new(true)
+			.open(destination)?;
+		self.file.seek(SeekFrom::Start(0))?;
+		io::copy(&mut self.file, &mut copy)
+	}
+
+	/// Creates the file a restore writes the save to. It is hidden next to the save, so that it can
+	/// be moved over the save in one step.
+	pub fn stage(&self) -> io::Result<Staged> {
+		let mut name = OsString::from(".");
+		name.push(self.path.file_name().unwrap_or_default());
+		name.push(format!(".{}.restoring", ulid::Ulid::new()));
+		let path = self.path.with_file_name(name);
+		let file = File::create(&path)?;
+
+		Ok(Staged {
+			path,
+			file: Some(file),
+		})
+	}
+
+	/// Moves a completely written save over the live one in one step, so that the game never reads
+	/// a partly written save. The lock is let go first, as Windows does not replace a file that is
+	/// open, and the move is retried for a while in case the game or a virus scanner opens the
+	/// save in the meantime.
+	pub fn replace_with(self, mut staged: Staged) -> io::Result<()> {
+		if let Some(file) = staged.file.take() {
+			file.sync_all()?;
+		}
+		let path = self.path.clone();
+		drop(self);
+
+		let mut attempts = 1;
+		loop {
+			match fs::rename(&staged.path, &path) {
+				Ok(()) => return Ok(()),
+				Err(e) if attempts < REPLACE_ATTEMPTS && replace_blocked(&e) => {
+					attempts += 1;
+					thread::sleep(REPLACE_RETRY_DELAY);
+				}
+				Err(e) => return Err(e),
+			}
+		}
+	}
+
+	/// Leaves the save as it was after a failed restore, removing it again when it was only created
+	/// for the restore.
+	pub fn abandon(self) -> io::Result<()> {
+		if self.existed {
+			return Ok(());
+		}
+
+		let path = self.path.clone();
+		drop(self);
+		fs::remove_file(path)
+	}
+}
+
+/// A restored save being written next to the live save, removed unless it is moved over it.
+pub struct Staged {
+	path: PathBuf,
+	/// Closed before the move, as Windows may not move a file that is open.
+	file: Option<File>,
+}
+
+impl Staged {
+	pub const fn file(&mut self) -> &mut File {
+		self.file
+			.as_mut()
+			.expect("staged save is only closed when moved")
+	}
+}
+
+impl Drop for Staged {
+	fn drop(&mut self) {
+		// already gone once moved over the save
+		let _ = fs::remove_file(&self.path);
+	}
+}
+
+/// Whether opening a save failed because another program has it open, which Windows reports as a
+/// sharing or lock violation.
+pub(crate) fn opened_elsewhere(e: &io::Error) -> bool {
+	cfg!(windows) && matches!(e.raw_os_error(), Some(32 | 33))
+}
+
+/// Whether moving a file over a save failed because another program has the save open, which
+/// Windows also reports as access being denied.
+fn replace_blocked(e: &io::Error) -> bool {
+	opened_elsewhere(e) || cfg!(windows) && e.raw_os_error() == Some(5)
+}
+
+/// Hashes the contents of a file, or of the save inside it for compressed backups, so that the
+/// same save has the same hash however it is stored.
+pub fn hash_file(path: &Path) -> io::Result<String> {
+	let mut hasher = blake3::Hasher::new();
+	io::copy(&mut open_stored(path)?, &mut hasher)?;
+
+	Ok(hasher.finalize().to_hex().to_string())
+}
+
+/// Stores `source` at `destination`, hard linking it to an existing backup with identical contents
+/// anywhere in the backup folder rather than copying it again. Destinations ending in `.zst` are
+/// compressed. Returns the size of the save, the number of bytes actually written and the hash of
+/// what was stored.
+fn store_deduplicated(
+	source: &Path,
+	hash: &str,
+	destination: &Path,
+	store_root: &Path,
+) -> io::Result<(u64, u64, String)> {
+	let compress = is_compressed(destination);
+
+	// held until the copy is recorded, so that a backup of the same content taken meanwhile links
+	// to it rather than storing it again
+	let mut index = index::lock(store_root)?;
+	if let Some(existing) = index.lookup(hash) {
+		// only link to a copy stored the same way, or the new backup would be unreadable
+		if existing.is_file()
+			&& is_compressed(&existing) == compress
+			&& link_over(&existing, destination).is_ok()
+		{
+			return Ok((fs::metadata(source)?.len(), 0, hash.to_string()));
+		}
+	}
+
+	// hash what is actually copied, since the game may have rewritten the save in the meantime
+	let mut reader = File::open(source)?;
+	let total = reader.metadata()?.len();
+	let (size, hash) = if compress {
+		let mut progress = Progress::new("Compressing backup", total);
+		let mut encoder = zstd::Encoder::new(File::create(destination)?, 0)?;
+		let copied = copy_hashing(&mut reader, &mut encoder, &mut progress)?;
+		encoder.finish()?;
+		copied
+	} else {
+		let mut progress = Progress::new("Copying backup", total);
+		copy_hashing(&mut reader, &mut File::create(destination)?, &mut progress)?
+	};
+
+	index.record(&hash, destination)?;
+	drop(index);
+
+	Ok((size, fs::metadata(destination)?.len(), hash))
+}
+
+/// Hard links `existing` in place of the reserved file at `destination`. The link is made beside
+/// it and renamed over it, so the backup's number stays taken throughout.
+fn link_over(existing: &Path, destination: &Path) -> io::Result<()> {
+	let mut linked = destination.as_os_str().to_os_string();
+	linked.push(".link");
+	let linked = PathBuf::from(linked);
+
+	fs::hard_link(existing, &linked)?;
+	fs::rename(&linked, destination).inspect_err(|_| {
+		let _ = fs::remove_file(&linked);
+	})
+}
+
+/// Copies everything from `reader` to `writer`, returning the number of bytes and their hash.
+fn copy_hashing(
+	reader: &mut impl Read,
+	writer: &mut impl Write,
+	progress: &mut Progress,
+) -> io::Result<(u64, String)> {
+	let mut hasher = blake3::Hasher::new();
+	let size = copy_with_progress(
+		&mut TeeReader {
+			reader,
+			hasher: &mut hasher,
+		},
+		writer,
+		progress,
+	)?;
+
+	Ok((size, hasher.finalize().to_hex().to_string()))
+}
+
+/// Hashes everything read through it.
+struct TeeReader<'a, R> {
+	reader: &'a mut R,
+	hasher: &'a mut blake3::Hasher,
+}
+
+impl<R: Read> Read for TeeReader<'_, R> {
+	fn read(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+		// only backups are copied through here, and an unfinished one is thrown away
+		jobs::check_cancelled()?;
+		let read = self.reader.read(buffer)?;
+		self.hasher.update(&buffer[..read]);
+
+		Ok(read)
+	}
+}
+
+/// Copies everything from `reader` to `writer`, reporting progress along the way.
+fn copy_with_progress(
+	reader: &mut impl Read,
+	writer: &mut impl Write,
+	progress: &mut Progress,
+) -> io::Result<u64> {
+	let mut buffer = vec![0; 64 * 1024];
+	let mut size = 0;
+	loop {
+		progress.check_cancelled()?;
+		let read = reader.read(&mut buffer)?;
+		if read == 0 {
+			break;
+		}
+		writer.write_all(&buffer[..read])?;
+		progress.advance(read as u64);
+		size += read as u64;
+	}
+
+	Ok(size)
+}
+
+/// Reconstructs the content index from the backups on disk and drops manifest entries for backups
+/// that no longer exist, for when the metadata was deleted, corrupted or edited by hand.
+///
+/// Backups without an ID are given one by `ids`.
+pub fn rebuild_index(backup_path: &Path, ids: IdScheme) -> io::Result<Rebuilt> {
+	let _slot = jobs::acquire("Index rebuild");
+	let mut entries = HashMap::new();
+	let mut rebuilt = Rebuilt::default();
+
+	for backup_dir in save_dirs(backup_path)? {
+		let backups = list_backups(&backup_dir)?;
+		rebuilt.saves += 1;
+		rebuilt.backups += backups.len();
+
+		for backup in &backups {
+			entries
+				.entry(hash_file(&backup.path)?)
+				.or_insert_with(|| backup.path.clone());
+		}
+
+		let (stale, identified) = Manifest::update(&backup_dir, |manifest| {
+			let stale = manifest
+				.backups()
+				.into_iter()
+				.filter(|name| !backups.iter().any(|backup| &backup.file_name == name))
+				.collect::<Vec<_>>();
+			for name in &stale {
+				manifest.remove(name);
+			}
+
+			// backups from before IDs were given out get one now
+			let mut identified = 0;
+			for backup in &backups {
+				if manifest.id(&backup.file_name).is_none() {
+					if let Some(id) = ids.generate() {
+						manifest.set_id(&backup.file_name, &id);
+						identified += 1;
+					}
+				}
+			}
+
+			Ok((stale.len(), identified))
+		})?;
+		rebuilt.stale += stale;
+		rebuilt.identified += identified;
+	}
+
+	rebuilt.unique = entries.len();
+	index::replace(backup_path, entries)?;
+
+	info!(
+		"Rebuilt backup index: {} backups of {} saves, {} distinct",
+		rebuilt.backups, rebuilt.saves, rebuilt.unique
+	);
+
+	Ok(rebuilt)
+}
+
+/// Compares the backup files on disk with the content index and manifests describing them.
+pub fn check_consistency(backup_path: &Path) -> io::Result<Consistency> {
+	let started = Instant::now();
+	let mut hashed = 0;
+	let entries = index::entries(backup_path)?;
+	let mut consistency = Consistency::default();
+
+	for (hash, file) in &entries {
+		if !file.is_file() {
+			consistency.missing_objects.push(hash.clone());
+		}
+	}
+
+	let indexed = entries
+		.into_iter()
+		.map(|(hash, _)| hash)
+		.collect::<HashSet<_>>();
+
+	for backup_dir in save_dirs(backup_path)? {
+		let backups = list_backups(&backup_dir)?;
+
+		for backup in &backups {
+			if !indexed.contains(&hash_file(&backup.path)?) {
+				consistency.unindexed.push(backup.path.clone());
+			}
+			hashed += fs::metadata(&backup.path)?.len();
+		}
+
+		for entry in fs::read_dir(&backup_dir)?.filter_map(Result::ok) {
+			let path = entry.path();
+			let name = entry.file_name().to_string_lossy().to_string();
+			if path.is_file()
+				&& parse_number(&name).is_none()
+				&& !name.starts_with(MANIFEST_FILE)
+				&& name != NUMBER_LOCK_FILE
+			{
+				consistency.strays.push(path);
+			}
+		}
+
+		for name in Manifest::load(&backup_dir).backups() {
+			if !backups.iter().any(|backup| backup.file_name == name) {
+				consistency.missing_backups.push((backup_dir.clone(), name));
+			}
+		}
+	}
+	timing::record(backup_path, Operation::Verify, hashed, started.elapsed());
+
+	Ok(consistency)
+}
+
+/// Hashes every backup of a save again and compares it with the checksum and size recorded when it
+/// was taken, to find backups damaged on disk before they are needed.
+///
+/// Backups taken before checksums were recorded have theirs recorded now.
+pub fn verify_backups(backup_dir: &Path) -> io::Result<Verification> {
+	let started = Instant::now();
+	let _slot = jobs::acquire("Verify");
+	let manifest = Manifest::load(backup_dir);
+	let backups = list_backups(backup_dir)?;
+	let total = backups
+		.iter()
+		.filter_map(|backup| backup.size(&manifest))
+		.sum();
+	let mut progress = Progress::new("Verifying backups", total);
+
+	let mut verification = Verification::default();
+	let mut hashed = 0;
+	// the checksum and, when missing too, the size of each backup that had none recorded
+	let mut unrecorded = Vec::new();
+	for backup in backups {
+		verification.checked += 1;
+		let mut hasher = blake3::Hasher::new();
+		let size = match backup
+			.open()
+			.and_then(|mut reader| copy_with_progress(&mut reader, &mut hasher, &mut progress))
+		{
+			Ok(size) => size,
+			Err(e) => {
+				progress.check_cancelled()?;
+				verification
+					.damaged
+					.push((backup, format!("cannot be read: {}", e)));
+				continue;
+			}
+		};
+		let hash = hasher.finalize().to_hex().to_string();
+		hashed += size;
+
+		let problem = match (
+			manifest.hash(&backup.file_name),
+			manifest.size(&backup.file_name),
+		) {
+			(_, Some(recorded)) if size < recorded => Some(format!(
+				"truncated, {} of {}",
+				format_size(size),
+				format_size(recorded)
+			)),
+			(Some(recorded), _) if recorded != hash => {
+				Some("contents do not match the checksum".to_string())
+			}
+			(Some(_), _) => None,
+			(None, recorded) => {
+				unrecorded.push((
+					backup.file_name.clone(),
+					hash,
+					recorded.is_none().then_some(size),
+				));
+				None
+			}
+		};
+		if let Some(problem) = problem {
+			warn!(
+				event = "backup_damaged", save = save_name(backup_dir).as_str(), backup = backup.number;
+				"Backup number {} is damaged: {}",
+				backup.number,
+				problem
+			);
+			verification.damaged.push((backup, problem));
+		}
+	}
+	// the manifest is loaded again under the lock, as hashing everything can take minutes and
+	// backups are taken meanwhile
+	if !unrecorded.is_empty() {
+		Manifest::update(backup_dir, |manifest| {
+			for (file_name, hash, size) in &unrecorded {
+				manifest.set_hash(file_name, hash);
+				if let Some(size) = size {
+					manifest.set_size(file_name, *size);
+				}
+			}
+			Ok(())
+		})?;
+		verification.recorded = unrecorded.len();
+	}
+	if let Some(store_root) = index::store_root(backup_dir) {
+		timing::record(&store_root, Operation::Verify, hashed, started.elapsed());
+	}
+
+	Ok(verification)
+}
+
+/// Records how long an operation on a backup file took in the journal of the backup folder holding
+/// it.
+fn record_timing(backup_file: &Path, operation: Operation, bytes: u64, started: Instant) {
+	if let Some(store_root) = backup_file.parent().and_then(index::store_root) {
+		timing::record(&store_root, operation, bytes, started.elapsed());
+	}
+}
+
+/// Brings files the metadata does not know about under management: unindexed backups are added to
+/// the content index, and stray files are renamed into numbered backups keeping their old name as
+/// the note.
+pub fn adopt_orphans(backup_path: &Path, consistency: &Consistency) -> io::Result<()> {
+	let mut adopted = consistency.unindexed.clone();
+	for stray in &consistency.strays {
+		if let (Some(backup_dir), Some(name)) = (stray.parent(), stray.file_name()) {
+			let (_, destination) =
+				reserve(backup_dir, |_| name.to_string_lossy().into_owned(), false)?;
+			fs::rename(stray, &destination)?;
+			if let Some(file_name) = destination.file_name() {
+				Manifest::update(backup_dir, |manifest| {
+					manifest.set_origin(&file_name.to_string_lossy(), Origin::Adopted);
+					Ok(())
+				})?;
+			}
+			info!("Adopted {} as {}", stray.display(), destination.display());
+			adopted.push(destination);
+		}
+	}
+
+	for backup in adopted {
+		index::record(backup_path, &hash_file(&backup)?, &backup)?;
+	}
+
+	index::flush()
+}
+
+/// Drops content index and manifest entries that point at files which no longer exist.
+pub fn clean_orphans(backup_path: &Path, consistency: &Consistency) -> io::Result<()> {
+	index::remove(backup_path, &consistency.missing_objects)?;
+
+	for (backup_dir, name) in &consistency.missing_backups {
+		Manifest::update(backup_dir, |manifest| {
+			manifest.remove(name);
+			Ok(())
+		})?;
+	}
+
+	info!(
+		"Removed {} stale index entries and {} stale manifest entries",
+		consistency.missing_objects.len(),
+		consistency.missing_backups.len()
+	);
+
+	Ok(())
+}
+
+/// Lists the per-save folders inside the backup folder.
+///
+/// Saves found in folders of the save directory have their backups in the same folders here, so
+/// folders holding neither backups nor a manifest are looked into for the folders of such saves.
+pub fn save_dirs(backup_path: &Path) -> io::Result<Vec<PathBuf>> {
+	fn holds_backups(directory: &Path) -> io::Result<bool> {
+		Ok(fs::read_dir(directory)?
+			.filter_map(Result::ok)
+			.any(|entry| {
+				let name = entry.file_name().to_string_lossy().to_string();
+				name == MANIFEST_FILE
+					|| name == PRE_RESTORE_FOLDER
+					|| name == BAK_FOLDER
+					|| parse_number(&name).is_some()
+			}))
+	}
+
+	fn walk(directory: &Path, dirs: &mut Vec<PathBuf>) -> io::Result<()> {
+		for path in fs::read_dir(directory)?
+			.filter_map(Result::ok)
+			.map(|entry| entry.path())
+			.filter(|path| path.is_dir())
+		{
+			let found = dirs.len();
+			if !holds_backups(&path)? {
+				walk(&path, dirs)?;
+			}
+			// a folder without saves inside is the folder of a save without backups yet
+			if dirs.len() == found {
+				dirs.push(path);
+			}
+		}
+
+		Ok(())
+	}
+
+	let mut dirs = Vec::new();
+	walk(backup_path, &mut dirs)?;
+	dirs.sort();
+
+	Ok(dirs)
+}
+
+/// Copies the live save into the `pre-restore` folder next to its backups, so that a restore over
+/// it can be undone, and deletes all but the newest few copies.
+///
+/// When the backup folder cannot be written to, the copy is kept next to the save instead. Returns
+/// where the copy was kept, or `None` when there is no live save yet.
+pub fn keep_live_save(save: &mut SaveLock, backup_dir: &Path) -> io::Result<Option<PathBuf>> {
+	if !save.existed {
+		return Ok(None);
+	}
+
+	let save_file = save.path.clone();
+	let extension = save_file
+		.extension()
+		.map(|extension| format!(".{}", extension.to_string_lossy()))
+		.unwrap_or_default();
+	let snapshot_dir = backup_dir.join(PRE_RESTORE_FOLDER);
+	let stamp = clock::now().format("%Y-%m-%d_%H-%M-%S").to_string();
+
+	// restores within the same second each keep a copy of their own, numbered after the first so
+	// that they still sort in order
+	let kept = fs::create_dir_all(&snapshot_dir).and_then(|()| {
+		let mut count = 0;
+		loop {
+			let snapshot = if count == 0 {
+				snapshot_dir.join(format!("{}{}", stamp, extension))
+			} else {
+				snapshot_dir.join(format!("{}_{}{}", stamp, count, extension))
+			};
+			match save.copy_to_new(&snapshot) {
+				Err(e) if e.kind() == io::ErrorKind::AlreadyExists => count += 1,
+				copied => return copied.map(|_| snapshot),
+			}
+		}
+	});
+	let snapshot = match kept {
+		Ok(snapshot) => snapshot,
+		Err(e) => {
+			let beside = beside_save(&save_file);
+			save.copy_to(&beside).map_err(|_| e)?;
+			info!("The save was kept as {} before restoring", beside.display());
+			return Ok(Some(beside));
+		}
+	};
+
+	let mut snapshots = fs::read_dir(&snapshot_dir)?
+		.filter_map(Result::ok)
+		.map(|entry| entry.path())
+		.filter(|path| path.is_file())
+		.collect::<Vec<_>>();
+	snapshots.sort();
+	let excess = snapshots.len().saturating_sub(PRE_RESTORE_KEEP);
+	for old in &snapshots[..excess] {
+		fs::remove_file(old)?;
+	}
+
+	Ok(Some(snapshot))
+}
+
+/// Where the live save is kept before a restore when its backup folder cannot be written to.
+fn beside_save(save_file: &Path) -> PathBuf {
+	let mut name = save_file.file_name().unwrap_or_default().to_os_string();
+	name.push(".pre-restore");
+	save_file.with_file_name(name)
+}
+
+/// The newest copy of the live save taken before a restore, if any.
+pub fn latest_kept_save(save_file: &Path, backup_dir: &Path) -> Option<PathBuf> {
+	// without the folder, the save may still have been kept beside itself
+	let newest = fs::read_dir(backup_dir.join(PRE_RESTORE_FOLDER))
+		.into_iter()
+		.flatten()
+		.filter_map(Result::ok)
+		.map(|entry| entry.path())
+		.filter(|path| path.is_file())
+		.max();
+
+	newest.or_else(|| Some(beside_save(save_file)).filter(|beside| beside.is_file()))
+}
+
+/// Puts back the save that was kept before a restore, undoing the restore.
+pub fn undo_restore(kept: &Path, save_file: &Path) -> io::Result<()> {
+	let save = SaveLock::acquire(save_file)?;
+	let mut staged = save.stage()?;
+	io::copy(&mut File::open(kept)?, staged.file())?;
+	save.replace_with(staged)?;
+	info!(
+		event = "restore_undone", save = save_file.file_name().unwrap_or_default().to_string_lossy().as_ref();
+		"Restore undone, the save from before it is back"
+	);
+
+	Ok(())
+}
+
+/// How far back restoring a backup takes a live save that is further along than it.
+pub struct Rewind {
+	/// In-game dates of the live save and of the backup, when the live save is later in the game.
+	pub dates: Option<(GameDate, GameDate)>,
+	/// When the live save was written and when the backup was taken, when the save is newer.
+	pub written: Option<(DateTime<Local>, DateTime<Local>)>,
+}
+
+impl Rewind {
+	/// What would be lost, one line for the game and one for the time played, such as
+	/// "12 in-game years (1180.3.1 in the save, 1168.5.2 in the backup)".
+	pub fn describe(&self) -> String {
+		let mut lines = Vec::new();
+		if let Some((live, backup)) = self.dates {
+			let months = (i32::from(live.year) * 12 + i32::from(live.month))
+				- (i32::from(backup.year) * 12 + i32::from(backup.month));
+			let count = |count: i32, unit: &str| {
+				format!(
+					"{} in-game {}{}",
+					count,
+					unit,
+					if count == 1 { "" } else { "s" }
+				)
+			};
+			let span = match (months / 12, months % 12) {
+				(0, 0) => count(i32::from(live.day) - i32::from(backup.day), "day"),
+				(0, months) => count(months, "month"),
+				(years, _) => count(years, "year"),
+			};
+			lines.push(format!(
+				"{} ({} in the save, {} in the backup)",
+				span, live, backup
+			));
+		}
+		if let Some((live, backup)) = self.written {
+			let hours = (live - backup).num_minutes() as f64 / 60.0;
+			lines.push(format!(
+				"{:.1} hours of play (saved {}, backed up {})",
+				hours,
+				live.format("%Y-%m-%d %H:%M"),
+				backup.format("%Y-%m-%d %H:%M")
+			));
+		}
+
+		lines.join("\n")
+	}
+}
+
+/// How far back restoring `backup` over the live save would go, or `None` when the live save is
+/// neither later in the game nor written after the backup was taken.
+pub fn rewind(backup: &Backup, save_file: &Path) -> Option<Rewind> {
+	let modified = fs::metadata(save_file).and_then(|metadata| metadata.modified());
+	let live_written: DateTime<Local> = modified.ok()?.into();
+	let manifest = backup
+		.path
+		.parent()
+		.map_or_else(|| Manifest::parse(""), Manifest::load);
+
+	let dates = parser::read_header(save_file)
+		.date
+		.zip(backup.game_date(&manifest))
+		.filter(|(live, backup)| live > backup);
+	// a save written within a minute of its backup was not played on since
+	let written = backup
+		.created(&manifest)
+		.map(|created| (live_written, created))
+		.filter(|(live, backup)| *live - *backup > chrono::Duration::minutes(1));
+
+	(dates.is_some() || written.is_some()).then_some(Rewind { dates, written })
+}
+
+/// Copies a backup over the live save file, decompressing it if needed, with the save locked until
+/// it is replaced.
+///
+/// The backup is written next to the save and then moved over it, so that the game never loads a
+/// partly written save. The live save is kept first, and where it was kept is returned so the
+/// restore can be undone.
+pub fn restore_core(backup: &Backup, save_destination: &Path) -> error::Result<Option<PathBuf>> {
+	let started = Instant::now();
+	let _slot = jobs::acquire("Restore");
+	let mut save = SaveLock::acquire(save_destination)?;
+	let kept = keep_live_save(
+		&mut save,
+		backup.path.parent().unwrap_or_else(|| Path::new(".")),
+	)?;
+	// the size of a compressed save is only known once it has been decompressed
+	let total = if backup.compressed {
+		0
+	} else {
+		fs::metadata(&backup.path)?.len()
+	};
+	let mut progress = Progress::new("Restoring backup", total);
+	let restored = backup
+		.open()
+		.context("read", &backup.path)
+		.and_then(|mut reader| {
+			let mut staged = save.stage().context("write to", save_destination)?;
+			copy_with_progress(&mut reader, staged.file(), &mut progress)
+				.context("write to", save_destination)
+				.map(|size| (size, staged))
+		});
+	let size = match restored {
+		Ok((size, staged)) => {
+			save.replace_with(staged)
+				.context("write to", save_destination)?;
+			size
+		}
+		Err(e) => {
+			let _ = save.abandon();
+			return Err(e);
+		}
+	};
+	record_timing(&backup.path, Operation::Restore, size, started);
+
+	info!(
+		event = "backup_restored",
+		save = backup.path.parent().map(save_name).unwrap_or_default().as_str(),
+		backup = backup.number,
+		duration_ms = started.elapsed().as_millis() as u64;
+		"Backup number {} restored",
+		backup.number
+	);
+	if let Some(kept) = &kept {
+		undo::record(Step::Restore {
+			save_file: save_destination.to_path_buf(),
+			kept: kept.clone(),
+			backup: backup.number,
+		});
+	}
+
+	Ok(kept)
+}
+
+/// Writes a backup to a new save next to the live one, so that an old backup can be played as a
+/// separate campaign without touching the live save. Refuses to write over a save that exists.
+pub fn restore_copy(backup: &Backup, destination: &Path) -> io::Result<()> {
+	let started = Instant::now();
+	let _slot = jobs::acquire("Restore");
+	let mut file = OpenOptions::new()
+		.write(true)
+		.create_new(true)
+		.open(destination)
+		.map_err(|e| {
+			if e.kind() == io::ErrorKind::AlreadyExists {
+				io::Error::new(
+					e.kind(),
+					format!(
+						"{} already exists, pick another name",
+						destination
+							.file_name()
+							.unwrap_or_default()
+							.to_string_lossy()
+					),
+				)
+			} else {
+				e
+			}
+		})?;
+	let total = if backup.compressed {
+		0
+	} else {
+		fs::metadata(&backup.path)?.len()
+	};
+	let mut progress = Progress::new("Restoring backup as a copy", total);
+	let restored = backup
+		.open()
+		.and_then(|mut reader| copy_with_progress(&mut reader, &mut file, &mut progress));
+	let size = match restored {
+		Ok(size) => size,
+		Err(e) => {
+			drop(file);
+			let _ = fs::remove_file(destination);
+			return Err(e);
+		}
+	};
+	record_timing(&backup.path, Operation::Restore, size, started);
+
+	info!(
+		event = "backup_restored_as_copy",
+		save = backup.path.parent().map(save_name).unwrap_or_default().as_str(),
+		backup = backup.number;
+		"Backup number {} restored as {}",
+		backup.number,
+		destination.display()
+	);
+
+	Ok(())
+}
+
+/// Part of a staged copy's name between the save's name and the backup number.
+const STAGED_INFIX: &str = "_bk";
+
+/// Where a backup of the save at `save_file` is staged: next to it, named after the save and the
+/// backup number, such as `mysave_bk12.ck2`.
+pub fn staged_path(save_file: &Path, number: usize) -> PathBuf {
+	let mut name = save_file.file_stem().unwrap_or_default().to_os_string();
+	name.push(format!("{}{}", STAGED_INFIX, number));
+	if let Some(extension) = save_file.extension() {
+		name.push(".");
+		name.push(extension);
+	}
+
+	save_file.with_file_name(name)
+}
+
+/// Whether a save is a staged copy of a backup kept in `backup_path`, such as `mysave_bk12` while
+/// `mysave` has a backup number 12. A save merely named like that is a save of its own.
+pub fn is_staged_copy(backup_path: &Path, save: &str) -> bool {
+	let (original, number) = match save.rsplit_once(STAGED_INFIX) {
+		Some((original, number)) if !original.is_empty() => (original, number),
+		_ => return false,
+	};
+	// only names `staged_path` gives, so `mysave_bk012` is a save of its own
+	let number = match number.parse::<usize>() {
+		Ok(parsed) if parsed.to_string() == number => parsed,
+		_ => return false,
+	};
+
+	list_backups(&backup_path.join(original))
+		.is_ok_and(|backups| backups.iter().any(|backup| backup.number == number))
+}
+
+/// Copies backups next to the live save under names of their own, so that the game's load menu
+/// lists each of them to compare in-game.
+///
+/// Backups already staged are left as they are. Returns how many were staged.
+pub fn stage_backups(save_file: &Path, backups: &[Backup]) -> io::Result<usize> {
+	let mut staged = 0;
+	for backup in backups {
+		let path = staged_path(save_file, backup.number);
+		if path.exists() {
+			continue;
+		}
+		restore_copy(backup, &path)?;
+		staged += 1;
+	}
+
+	Ok(staged)
+}
+
+/// The copies of backups in `backup_dir` staged next to the save, with the backup each one is
+/// a copy of.
+pub fn staged_copies(save_file: &Path, backup_dir: &Path) -> io::Result<Vec<(PathBuf, Backup)>> {
+	Ok(list_backups(backup_dir)?
+		.into_iter()
+		.map(|backup| (staged_path(save_file, backup.number), backup))
+		.filter(|(path, _)| path.is_file())
+		.collect())
+}
+
+/// Removes the staged copies of backups that are still as they were staged.
+///
+/// Copies the game has written since, such as after playing on from one, are kept as saves of their
+/// own. Returns how many were removed and the file names of those kept.
+pub fn remove_staged(save_file: &Path, backup_dir: &Path) -> io::Result<(usize, Vec<String>)> {
+	let mut removed = 0;
+	let mut kept = Vec::new();
+	for (path, backup) in staged_copies(save_file, backup_dir)? {
+		if hash_file(&path)? == hash_file(&backup.path)? {
+			fs::remove_file(&path)?;
+			removed += 1;
+		} else {
+			kept.push(
+				path.file_name()
+					.unwrap_or_default()
+					.to_string_lossy()
+					.into_owned(),
+			);
+		}
+	}
+	info!(
+		"Removed {} staged copies of backups of {}",
+		removed,
+		save_name(backup_dir)
+	);
+
+	Ok((removed, kept))
+}
+
+/// Characters that cannot appear in a note, as it becomes part of the backup's file name.
+const NOTE_FORBIDDEN: &[char] = &['/', '\\', ':', '*', '?', '"', '<', '>', '|'];
+
+/// Refuses a note that cannot become part of a file name.
+pub fn check_note(note: &str) -> io::Result<()> {
+	if note.contains(NOTE_FORBIDDEN) {
+		return Err(io::Error::new(
+			io::ErrorKind::InvalidInput,
+			format!(
+				"A note cannot contain any of {}",
+				NOTE_FORBIDDEN.iter().collect::<String>()
+			),
+		));
+	}
+
+	Ok(())
+}
+
+/// Changes the note of a backup by renaming its file, keeping its number so that it stays in place
+/// among the other backups.
+///
+/// Backups named from a template keep the rest of their name, with the note in it replaced. The
+/// manifest and index follow the new name. An empty note removes the note. Returns the renamed
+/// backup.
+pub fn set_note(backup_dir: &Path, backup: &Backup, note: &str) -> io::Result<Backup> {
+	let note = note.trim();
+	check_note(note)?;
+
+	let (renamed, hash) = Manifest::update(backup_dir, |manifest| {
+		let recorded = manifest.note(&backup.file_name);
+		let name = recorded.as_ref().map_or_else(
+			|| note.to_string(),
+			|old| {
+				let rest = backup.name();
+				let rest = rest
+					.strip_suffix(old.as_str())
+					.filter(|_| !old.is_empty())
+					.unwrap_or(rest)
+					.trim_end_matches('_');
+				[rest, note]
+					.iter()
+					.filter(|part| !part.is_empty())
+					.copied()
+					.collect::<Vec<_>>()
+					.join("_")
+			},
+		);
+
+		let mut file_name = backup.number.to_string();
+		if !name.is_empty() {
+			file_name.push('_');
+			file_name.push_str(&name);
+		}
+		if backup.compressed {
+			file_name.push_str(COMPRESSED_EXTENSION);
+		}
+		let destination = backup_dir.join(&file_name);
+		if destination.exists() && file_name != backup.file_name {
+			return Err(io::Error::new(
+				io::ErrorKind::AlreadyExists,
+				format!("{} already exists", destination.display()),
+			));
+		}
+
+		if recorded.is_some() {
+			manifest.set_note(&backup.file_name, note);
+		}
+		if file_name == backup.file_name {
+			return Ok((backup.clone().with_recorded_note(manifest), None));
+		}
+
+		fs::rename(&backup.path, &destination)?;
+
+		let hash = manifest.hash(&backup.file_name);
+		manifest.rename(&backup.file_name, &file_name);
+		let renamed = Backup::from_path(destination).expect("file name starts with the number");
+		Ok((renamed.with_recorded_note(manifest), hash))
+	})?;
+	if renamed.file_name == backup.file_name {
+		return Ok(renamed);
+	}
+
+	// the index may point at this file as the copy other backups were linked from
+	if let Some(store_root) = index::store_root(backup_dir) {
+		let hash = hash.map_or_else(|| hash_file(&renamed.path), Ok)?;
+		let mut index = index::lock(&store_root)?;
+		if index.lookup(&hash).as_deref() == Some(backup.path.as_path()) {
+			index.record(&hash, &renamed.path)?;
+		}
+	}
+
+	info!(
+		event = "backup_renamed", save = save_name(backup_dir).as_str(), backup = backup.number;
+		"Backup number {} renamed to {}",
+		backup.number,
+		renamed.file_name
+	);
+
+	Ok(renamed)
+}
+
+/// Removes a backup file along with any metadata recorded for it.
+pub fn delete_backup(backup_dir: &Path, backup: &Backup) -> io::Result<()> {
+	fs::remove_file(&backup.path)?;
+
+	// automatic deletions run alongside backups taken elsewhere
+	Manifest::update(backup_dir, |manifest| {
+		manifest.remove(&backup.file_name);
+		Ok(())
+	})?;
+
+	info!(
+		event = "backup_deleted", save = save_name(backup_dir).as_str(), backup = backup.number;
+		"Backup number {} deleted",
+		backup.number
+	);
+
+	Ok(())
+}
+
+/// Moves backups into the trash folder next to them and removes them from the manifest, recording
+/// the deletion so that it can be undone until the program quits.
+///
+/// Backups moved before one fails are still recorded.
+pub fn trash_backups(backup_dir: &Path, backups: &[Backup]) -> io::Result<()> {
+	let trash = backup_dir.join(TRASH_FOLDER);
+	fs::create_dir_all(&trash)?;
+	// whatever a previous run left behind can no longer be undone
+	for entry in fs::read_dir(&trash)?.filter_map(Result::ok) {
+		if !undo::is_journaled(&entry.path()) {
+			let _ = fs::remove_file(entry.path());
+		}
+	}
+
+	let mut trashed = Vec::new();
+	let mut failed = Ok(());
+	for backup in backups {
+		let path = trash.join(format!("{}_{}", ulid::Ulid::new(), backup.file_name));
+		if let Err(e) = fs::rename(&backup.path, &path) {
+			failed = Err(e);
+			break;
+		}
+		trashed.push(Trashed {
+			number: backup.number,
+			file_name: backup.file_name.clone(),
+			path,
+		});
+	}
+	if trashed.is_empty() {
+		return failed;
+	}
+
+	Manifest::update(backup_dir, |manifest| {
+		let names = trashed
+			.iter()
+			.map(|trashed| trashed.file_name.as_str())
+			.collect::<Vec<_>>();
+		let recorded = manifest.subset(&names);
+		for name in &names {
+			manifest.remove(name);
+		}
+		for trashed in &trashed {
+			info!(
+				event = "backup_deleted", save = save_name(backup_dir).as_str(), backup = trashed.number;
+				"Backup number {} deleted",
+				trashed.number
+			);
+		}
+		undo::record(Step::Delete {
+			backup_dir: backup_dir.to_path_buf(),
+			backups: trashed,
+			manifest: recorded,
+		});
+		Ok(())
+	})?;
+
+	failed
+}
+
+/// Moves trashed backups back into their folder and records again what the manifest recorded
+/// about them.
+///
+/// Refuses when a backup of the same number has been taken since, as the number of the newest
+/// backup is given out again once it is trashed.
+pub fn untrash_backups(backup_dir: &Path, backups: &[Trashed], recorded: &str) -> io::Result<()> {
+	let taken = list_backups(backup_dir)?;
+	if let Some(trashed) = backups
+		.iter()
+		.find(|trashed| taken.iter().any(|backup| backup.number == trashed.number))
+	{
+		return Err(io::Error::new(
+			io::ErrorKind::AlreadyExists,
+			format!(
+				"backup number {} has been taken again since",
+				trashed.number
+			),
+		));
+	}
+
+	for trashed in backups {
+		fs::rename(&trashed.path, backup_dir.join(&trashed.file_name))?;
+	}
+
+	let recorded = Manifest::parse(recorded);
+	Manifest::update(backup_dir, |manifest| {
+		for trashed in backups {
+			manifest.copy_from(&trashed.file_name, &recorded, &trashed.file_name);
+			info!(
+				event = "backup_undeleted", save = save_name(backup_dir).as_str(), backup = trashed.number;
+				"Backup number {} put back",
+				trashed.number
+			);
+		}
+		Ok(())
+	})?;
+	let _ = fs::remove_dir(backup_dir.join(TRASH_FOLDER));
+
+	Ok(())
+}
@@ -0,0 +1,612 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt::Write as _;
+use std::fs::{self, Metadata, OpenOptions};
+use std::io::{self, Read as _, Write as _};
+use std::path::{Component, Path, PathBuf};
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Instant, UNIX_EPOCH};
+
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+
+use ini::Ini;
+
+use log::{info, warn};
+
+use serde_json::json;
+
+use crate::error::Context;
+use crate::index;
+use crate::jobs;
+use crate::manifest::{Manifest, MANIFEST_FILE};
+use crate::progress::Progress;
+use crate::store::Backup;
+use crate::timing::{self, Operation};
+use crate::undo::TRASH_FOLDER;
+use crate::JOURNAL_FILE;
+
+/// Section of conf.ini configuring the remote.
+const SECTION: &str = "sync";
+
+/// File in the backup folder listing what was pushed to the remote, so that only new and changed
+/// files are pushed again.
+const STATE_FILE: &str = "sync.txt";
+
+/// File on the remote listing every file pushed there from any machine with its size, so that the
+/// others know what they can pull.
+const LISTING_FILE: &str = "sync-list.txt";
+
+/// Dropbox folder pushed to when `folder` is not set.
+const DEFAULT_DROPBOX_FOLDER: &str = "/save-manager";
+
+const DROPBOX_UPLOAD: &str = "https://content.dropboxapi.com/2/files/upload";
+const DROPBOX_DOWNLOAD: &str = "https://content.dropboxapi.com/2/files/download";
+
+/// Largest file Dropbox takes in one upload request.
+const DROPBOX_LIMIT: usize = 150 * 1024 * 1024;
+
+/// Held while pushing or pulling, so that pushes started by backups in quick succession go one at
+/// a time and a pull never reads the state a push is writing.
+static SYNCING: Mutex<()> = Mutex::new(());
+
+/// How files are sent to the remote.
+#[derive(Clone)]
+enum Backend {
+	/// A WebDAV server, such as Nextcloud, or `rclone serve webdav` in front of an S3 bucket or
+	/// any other rclone remote.
+	WebDav {
+		url: String,
+		authorization: Option<String>,
+	},
+	/// A Dropbox folder, reached through the Dropbox API.
+	Dropbox { folder: String, token: String },
+	/// Any server storing the files sent to it with `PUT`.
+	Http {
+		url: String,
+		authorization: Option<String>,
+	},
+}
+
+/// A remote the backup folder is pushed to and pulled from, configured in the `[sync]` section of
+/// conf.ini:
+///
+/// * `backend`: `webdav`, `dropbox` or `http`
+/// * `url`: address of the remote folder for `webdav` and `http`
+/// * `user` and `password`: login for `webdav`
+/// * `token`: access token for `dropbox`, or a bearer token for `http`
+/// * `folder`: Dropbox folder to push to, `/save-manager` when left out
+/// * `after_backup`: `true` to push after every backup
+///
+/// Files are only ever added and replaced on the remote, never deleted. Pulling only adds the files
+/// missing from the backup folder, never replacing any.
+#[derive(Clone)]
+pub struct Remote {
+	backend: Backend,
+	pub after_backup: bool,
+}
+
+impl Remote {
+	/// Reads the remote settings, returning `None` when no remote is configured.
+	pub fn load(config: &Ini) -> Result<Option<Self>, String> {
+		let get = |key: &str| {
+			config
+				.get_from(Some(SECTION), key)
+				.map(str::trim)
+				.filter(|value| !value.is_empty())
+		};
+		let url = || {
+			get("url")
+				.map(|url| url.trim_end_matches('/').to_string())
+				.ok_or("url must be set to the address of the remote folder")
+		};
+		let bearer = || get("token").map(|token| format!("Bearer {}", token));
+
+		let backend = match get("backend") {
+			None => return Ok(None),
+			Some("webdav") => Backend::WebDav {
+				url: url()?,
+				authorization: get("user").map(|user| {
+					let login = format!("{}:{}", user, get("password").unwrap_or_default());
+					format!("Basic {}", BASE64.encode(login))
+				}),
+			},
+			Some("dropbox") => Backend::Dropbox {
+				folder: get("folder")
+					.unwrap_or(DEFAULT_DROPBOX_FOLDER)
+					.trim_end_matches('/')
+					.to_string(),
+				token: get("token")
+					.ok_or("token must be set to a Dropbox access token")?
+					.to_string(),
+			},
+			Some("http") => Backend::Http {
+				url: url()?,
+				authorization: bearer(),
+			},
+			Some(_) => return Err("backend must be webdav, dropbox or http".to_string()),
+		};
+
+		Ok(Some(Self {
+			backend,
+			after_backup: get("after_backup") == Some("true"),
+		}))
+	}
+
+	pub fn describe(&self) -> String {
+		match &self.backend {
+			Backend::WebDav { url, .. } => format!("WebDAV at {}", url),
+			Backend::Dropbox { folder, .. } => format!("Dropbox folder {}", folder),
+			Backend::Http { url, .. } => url.clone(),
+		}
+	}
+
+	/// Stores a file on the remote under its path in the backup folder, written with `/`.
+	/// `made` holds the remote folders known to exist, for backends that need them created.
+	fn put(
+		&self,
+		relative: &str,
+		body: &[u8],
+		made: &mut HashSet<String>,
+	) -> crate::error::Result<()> {
+		let request = match &self.backend {
+			Backend::WebDav { url, authorization } => {
+				let mut folder = String::new();
+				let folders = relative.rsplit_once('/').map_or("", |(folders, _)| folders);
+				for name in folders.split('/').filter(|name| !name.is_empty()) {
+					folder.push('/');
+					folder.push_str(&encode(name));
+					if made.insert(folder.clone()) {
+						let request = authorized(
+							ureq::request("MKCOL", &format!("{}{}/", url, folder)),
+							authorization.as_deref(),
+						);
+						match request.call() {
+							// already there
+							Ok(_) | Err(ureq::Error::Status(405, _)) => {}
+							Err(e) => return Err(refused(relative, e)),
+						}
+					}
+				}
+				authorized(
+					ureq::put(&format!("{}/{}", url, encode_path(relative))),
+					authorization.as_deref(),
+				)
+			}
+			Backend::Dropbox { folder, token } => {
+				if body.len() > DROPBOX_LIMIT {
+					return Err(format!("{} is too large to push to Dropbox.", relative).into());
+				}
+				let argument = json!({
+					"path": format!("{}/{}", folder, relative),
+					"mode": "overwrite",
+					"mute": true,
+				});
+				ureq::post(DROPBOX_UPLOAD)
+					.set("Authorization", &format!("Bearer {}", token))
+					.set("Dropbox-API-Arg", &ascii_json(&argument.to_string()))
+					.set("Content-Type", "application/octet-stream")
+			}
+			Backend::Http { url, authorization } => authorized(
+				ureq::put(&format!("{}/{}", url, encode_path(relative))),
+				authorization.as_deref(),
+			),
+		};
+
+		request
+			.send_bytes(body)
+			.map(drop)
+			.map_err(|e| refused(relative, e))
+	}
+
+	/// Fetches a file from the remote by its path in the backup folder, returning `None` when the
+	/// remote does not have it.
+	fn get(&self, relative: &str) -> crate::error::Result<Option<Vec<u8>>> {
+		let request = match &self.backend {
+			Backend::WebDav { url, authorization } | Backend::Http { url, authorization } => {
+				authorized(
+					ureq::get(&format!("{}/{}", url, encode_path(relative))),
+					authorization.as_deref(),
+				)
+			}
+			Backend::Dropbox { folder, token } => {
+				let argument = json!({ "path": format!("{}/{}", folder, relative) });
+				ureq::post(DROPBOX_DOWNLOAD)
+					.set("Authorization", &format!("Bearer {}", token))
+					.set("Dropbox-API-Arg", &ascii_json(&argument.to_string()))
+			}
+		};
+
+		let response = match request.call() {
+			Ok(response) => response,
+			// Dropbox answers a path that is not there with a conflict
+			Err(ureq::Error::Status(404 | 409, _)) => return Ok(None),
+			Err(e) => return Err(refused(relative, e)),
+		};
+		let mut body = Vec::new();
+		response
+			.into_reader()
+			.read_to_end(&mut body)
+			.map_err(|e| format!("Could not fetch {}: {}", relative, e))?;
+
+		Ok(Some(body))
+	}
+
+	/// Reads the listing of the files on the remote with their sizes, empty when nothing was
+	/// pushed there yet.
+	fn listing(&self) -> crate::error::Result<HashMap<String, u64>> {
+		let text = self.get(LISTING_FILE)?.unwrap_or_default();
+		Ok(String::from_utf8_lossy(&text)
+			.lines()
+			.filter_map(|line| {
+				let (size, relative) = line.split_once('\t')?;
+				Some((relative.to_string(), size.parse().ok()?))
+			})
+			.collect())
+	}
+}
+
+fn authorized(request: ureq::Request, authorization: Option<&str>) -> ureq::Request {
+	match authorization {
+		Some(authorization) => request.set("Authorization", authorization),
+		None => request,
+	}
+}
+
+fn refused(relative: &str, e: ureq::Error) -> crate::error::Error {
+	match e {
+		ureq::Error::Status(code, response) => format!(
+			"The remote refused {}: {} {}",
+			relative,
+			code,
+			response.status_text()
+		)
+		.into(),
+		e => format!("The remote could not be reached: {}", e).into(),
+	}
+}
+
+/// Percent-encodes a file or folder name for a URL.
+fn encode(name: &str) -> String {
+	let mut encoded = String::new();
+	for byte in name.bytes() {
+		if byte.is_ascii_alphanumeric() || b"-._~".contains(&byte) {
+			encoded.push(char::from(byte));
+		} else {
+			let _ = write!(encoded, "%{:02X}", byte);
+		}
+	}
+	encoded
+}
+
+fn encode_path(relative: &str) -> String {
+	relative
+		.split('/')
+		.map(encode)
+		.collect::<Vec<_>>()
+		.join("/")
+}
+
+/// JSON with every character outside ASCII escaped, as Dropbox requires of JSON in headers.
+fn ascii_json(json: &str) -> String {
+	let mut escaped = String::new();
+	for c in json.chars() {
+		if c.is_ascii() {
+			escaped.push(c);
+		} else {
+			for unit in c.encode_utf16(&mut [0; 2]) {
+				let _ = write!(escaped, "\\u{:04x}", unit);
+			}
+		}
+	}
+	escaped
+}
+
+/// Size and modification time of a file, which tell whether it changed since it was pushed.
+type Stamp = (u64, u64);
+
+/// What was pushed to the remote, by path in the backup folder.
+struct State {
+	remote: String,
+	pushed: HashMap<String, Stamp>,
+}
+
+impl State {
+	/// Reads what was pushed from the backup folder, forgetting it when it was pushed to another
+	/// remote.
+	fn load(backup_path: &Path, remote: &str) -> Self {
+		let mut pushed = HashMap::new();
+		let text = fs::read_to_string(backup_path.join(STATE_FILE)).unwrap_or_default();
+		let mut lines = text.lines();
+		if lines.next() == Some(remote) {
+			for line in lines {
+				let mut fields = line.splitn(3, '\t');
+				if let (Some(size), Some(modified), Some(relative)) =
+					(fields.next(), fields.next(), fields.next())
+				{
+					if let (Ok(size), Ok(modified)) = (size.parse(), modified.parse()) {
+						pushed.insert(relative.to_string(), (size, modified));
+					}
+				}
+			}
+		}
+
+		Self {
+			remote: remote.to_string(),
+			pushed,
+		}
+	}
+
+	fn save(&self, backup_path: &Path) -> io::Result<()> {
+		let mut text = format!("{}\n", self.remote);
+		for (relative, (size, modified)) in &self.pushed {
+			let _ = writeln!(text, "{}\t{}\t{}", size, modified, relative);
+		}
+		fs::write(backup_path.join(STATE_FILE), text)
+	}
+}
+
+fn stamp(metadata: &Metadata) -> io::Result<Stamp> {
+	let modified = metadata
+		.modified()?
+		.duration_since(UNIX_EPOCH)
+		.map_or(0, |modified| modified.as_secs());
+	Ok((metadata.len(), modified))
+}
+
+/// Whether a file of the backup folder, by its path there, is left out of syncing: hidden files
+/// such as restores in progress, what this machine knows of the remote, the journal, which every
+/// operation writes to, and the trash, which only this machine can put backups back from.
+fn stays_local(relative: &str) -> bool {
+	let components = relative.split('/').collect::<Vec<_>>();
+	let excluded = match components.as_slice() {
+		[name] => [STATE_FILE, LISTING_FILE, JOURNAL_FILE].contains(name),
+		// backups in the trash of a save
+		[_, folders @ .., _] => folders.contains(&TRASH_FOLDER),
+		[] => true,
+	};
+
+	excluded || components.iter().any(|name| name.starts_with('.'))
+}
+
+/// Where a file listed on the remote goes in the backup folder, or `None` when its path would
+/// lead out of it.
+fn local_path(backup_path: &Path, relative: &str) -> Option<PathBuf> {
+	if relative.contains('\\') {
+		return None;
+	}
+	let mut path = backup_path.to_path_buf();
+	for name in relative.split('/') {
+		let mut components = Path::new(name).components();
+		match (components.next(), components.next()) {
+			(Some(Component::Normal(_)), None) => path.push(name),
+			_ => return None,
+		}
+	}
+
+	Some(path)
+}
+
+/// Adds the files under `folder` to `files` with their path from the backup folder, leaving out
+/// those that stay local.
+fn walk(folder: &Path, prefix: &str, files: &mut Vec<(String, PathBuf, Stamp)>) -> io::Result<()> {
+	for entry in fs::read_dir(folder)? {
+		let entry = entry?;
+		let name = entry.file_name().to_string_lossy().to_string();
+		let relative = if prefix.is_empty() {
+			name
+		} else {
+			format!("{}/{}", prefix, name)
+		};
+		let metadata = entry.metadata()?;
+		if metadata.is_dir() {
+			walk(&entry.path(), &relative, files)?;
+		} else if !stays_local(&relative) {
+			files.push((relative, entry.path(), stamp(&metadata)?));
+		}
+	}
+
+	Ok(())
+}
+
+/// What a push sent to the remote.
+pub struct Pushed {
+	pub files: usize,
+	pub bytes: u64,
+}
+
+/// Sends the files of the backup folder `backup_path` that are new or changed since the last push
+/// to the remote.
+///
+/// What was sent is recorded as it goes, so that a push that fails or is cancelled carries on where
+/// it stopped the next time.
+pub fn push(remote: &Remote, backup_path: &Path) -> crate::error::Result<Pushed> {
+	let _syncing = SYNCING.lock().unwrap();
+	let started = Instant::now();
+	let mut state = State::load(backup_path, &remote.describe());
+
+	let mut files = Vec::new();
+	walk(backup_path, "", &mut files).context("read", backup_path)?;
+	files.retain(|(relative, _, stamp)| state.pushed.get(relative) != Some(stamp));
+	files.sort();
+
+	let total = files.iter().map(|(_, _, (size, _))| size).sum();
+	let mut progress = Progress::new("Pushing backups", total);
+	let mut made = HashSet::new();
+	let mut pushed = Pushed { files: 0, bytes: 0 };
+	for (relative, path, stamp) in files {
+		jobs::check_cancelled()?;
+		progress.check_cancelled()?;
+
+		let body = fs::read(&path).context("read", &path)?;
+		remote.put(&relative, &body, &mut made)?;
+		progress.advance(stamp.0);
+		pushed.files += 1;
+		pushed.bytes += stamp.0;
+
+		state.pushed.insert(relative, stamp);
+		state.save(backup_path).context("write to", backup_path)?;
+	}
+
+	// what other machines pushed stays listed next to what was pushed from here
+	let listed = remote.listing()?;
+	let mut listing = listed.clone();
+	for (relative, (size, _)) in &state.pushed {
+		listing.insert(relative.clone(), *size);
+	}
+	if listing != listed {
+		let mut text = String::new();
+		for (relative, size) in &listing {
+			let _ = writeln!(text, "{}\t{}", size, relative);
+		}
+		remote.put(LISTING_FILE, text.as_bytes(), &mut made)?;
+	}
+
+	if pushed.files > 0 {
+		timing::record(
+			backup_path,
+			Operation::Upload,
+			pushed.bytes,
+			started.elapsed(),
+		);
+		info!(
+			event = "sync_pushed", files = pushed.files;
+			"Pushed {} files to {}",
+			pushed.files,
+			remote.describe()
+		);
+	}
+
+	Ok(pushed)
+}
+
+/// What a pull fetched from the remote.
+pub struct Pulled {
+	pub files: usize,
+	pub bytes: u64,
+	/// Backups on the remote left alone because one of the same name but another size is in the
+	/// backup folder, such as when backups were taken on two machines since they last synced.
+	pub kept: usize,
+}
+
+/// Fetches the files other machines pushed to the remote that the backup folder `backup_path` is
+/// missing, so that a campaign can be carried on where it was left.
+///
+/// Pulled backups are recorded in the index and the manifest of their save, and as pushed, so that
+/// they are not sent back. Files pushed from here and deleted since are not pulled again, and
+/// nothing in the backup folder is ever replaced.
+pub fn pull(remote: &Remote, backup_path: &Path) -> crate::error::Result<Pulled> {
+	let _syncing = SYNCING.lock().unwrap();
+	let mut state = State::load(backup_path, &remote.describe());
+
+	let mut files = remote
+		.listing()?
+		.into_iter()
+		.filter(|(relative, _)| !stays_local(relative) && !state.pushed.contains_key(relative))
+		.filter_map(|(relative, size)| Some((local_path(backup_path, &relative)?, relative, size)))
+		.collect::<Vec<_>>();
+	files.sort();
+
+	let total = files.iter().map(|(_, _, size)| size).sum();
+	let mut progress = Progress::new("Pulling backups", total);
+	let mut pulled = Pulled {
+		files: 0,
+		bytes: 0,
+		kept: 0,
+	};
+	let mut backups: HashMap<PathBuf, Vec<String>> = HashMap::new();
+	for (path, relative, size) in files {
+		jobs::check_cancelled()?;
+		progress.check_cancelled()?;
+		progress.advance(size);
+
+		let backup = Backup::from_path(path.clone());
+		if let Ok(metadata) = fs::metadata(&path) {
+			if backup.is_some() && metadata.len() != size {
+				pulled.kept += 1;
+			}
+			continue;
+		}
+		let body = match remote.get(&relative)? {
+			Some(body) => body,
+			None => continue,
+		};
+
+		let folder = path.parent().unwrap_or(backup_path);
+		fs::create_dir_all(folder).context("create", folder)?;
+		let mut file = match OpenOptions::new().write(true).create_new(true).open(&path) {
+			// taken here in the meantime
+			Err(e) if e.kind() == io::ErrorKind::AlreadyExists => continue,
+			opened => opened.context("create", &path)?,
+		};
+		file.write_all(&body).context("write to", &path)?;
+		drop(file);
+		pulled.files += 1;
+		pulled.bytes += body.len() as u64;
+
+		let stamp = fs::metadata(&path).and_then(|metadata| stamp(&metadata));
+		state.pushed.insert(relative, stamp.context("read", &path)?);
+		state.save(backup_path).context("write to", backup_path)?;
+
+		if let Some(backup) = backup {
+			if let Some(store_root) = index::store_root(folder) {
+				let hash = crate::store::hash_file(&path).context("read", &path)?;
+				let mut index = index::lock(&store_root).context("write to", &store_root)?;
+				if index.lookup(&hash).is_none() {
+					index
+						.record(&hash, &path)
+						.context("write to", &store_root)?;
+				}
+			}
+			backups
+				.entry(folder.to_path_buf())
+				.or_default()
+				.push(backup.file_name);
+		}
+	}
+
+	// the manifests of the saves that got new backups tell their notes, tags and dates
+	for (backup_dir, names) in backups {
+		let save = backup_dir.strip_prefix(backup_path).unwrap_or(&backup_dir);
+		let relative = format!(
+			"{}/{}",
+			save.to_string_lossy().replace('\\', "/"),
+			MANIFEST_FILE
+		);
+		let pushed = match remote.get(&relative)? {
+			Some(text) => Manifest::parse(&String::from_utf8_lossy(&text)),
+			None => continue,
+		};
+		Manifest::update(&backup_dir, |manifest| {
+			let known = manifest.backups();
+			for name in names.iter().filter(|name| !known.contains(name)) {
+				manifest.copy_from(name, &pushed, name);
+			}
+			Ok(())
+		})
+		.context("write to", &backup_dir)?;
+	}
+
+	if pulled.files > 0 {
+		info!(
+			event = "sync_pulled", files = pulled.files;
+			"Pulled {} files from {}",
+			pulled.files,
+			remote.describe()
+		);
+	}
+
+	Ok(pulled)
+}
+
+/// Pushes the backup folder to the remote on another thread, taking a place in the job queue
+/// before returning so that quitting waits for the push.
+pub fn push_soon(remote: Remote, backup_path: PathBuf) {
+	let slot = jobs::acquire("Sync");
+	thread::spawn(move || {
+		let _slot = slot;
+		if let Err(e) = push(&remote, &backup_path) {
+			warn!("Could not push the backups to {}: {}", remote.describe(), e);
+		}
+	});
+}
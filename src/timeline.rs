@@ -0,0 +1,287 @@
+use std::io;
+use std::path::Path;
+
+use chrono::{DateTime, Duration, Local};
+
+use cursive::traits::*;
+use cursive::views::{Dialog, TextView};
+use cursive::Cursive;
+
+use ini::Ini;
+
+use crate::clock;
+use crate::error;
+use crate::format_duration;
+use crate::history;
+use crate::manifest::Manifest;
+use crate::parser::GameDate;
+use crate::profiles::Profile;
+use crate::settings::{current_save, display_name};
+use crate::store;
+
+/// Size of the chart in characters, leaving out its axes.
+const CHART_WIDTH: usize = 60;
+const CHART_HEIGHT: usize = 8;
+
+/// How many of the latest sessions are listed.
+const LISTED_SESSIONS: usize = 10;
+
+/// Days without a backup after which a campaign no longer counts as active.
+const ACTIVE_DAYS: i64 = 30;
+
+/// A stretch of play, made of backups taken no more than `SESSION_GAP` apart.
+struct Session {
+	start: DateTime<Local>,
+	end: DateTime<Local>,
+	backups: usize,
+	/// In-game dates of the first and last backup of the session that have one.
+	dates: Option<(GameDate, GameDate)>,
+}
+
+impl Session {
+	fn length(&self) -> std::time::Duration {
+		(self.end - self.start).to_std().unwrap_or_default()
+	}
+
+	fn label(&self) -> String {
+		let mut label = format!(
+			"{}  {:>11}  {:>4} backups",
+			self.start.format("%Y-%m-%d %H:%M"),
+			format_duration(self.length()),
+			self.backups
+		);
+		if let Some((first, last)) = self.dates {
+			label.push_str(&format!("  {} to {}", first, last));
+		}
+
+		label
+	}
+}
+
+/// A long stretch of time, in days once it is longer than two.
+fn describe_span(span: Duration) -> String {
+	if span.num_days() >= 2 {
+		format!("{} days", span.num_days())
+	} else {
+		format_duration(span.to_std().unwrap_or_default())
+	}
+}
+
+/// When each backup of a save was taken, oldest first, with its in-game date when `dates` is set
+/// and it is known.
+fn backups(backup_dir: &Path, dates: bool) -> io::Result<Vec<(DateTime<Local>, Option<GameDate>)>> {
+	let manifest = Manifest::load(backup_dir);
+	let mut backups = store::list_backups(backup_dir)?
+		.into_iter()
+		.filter_map(|backup| {
+			let date = if dates {
+				backup.game_date(&manifest)
+			} else {
+				None
+			};
+			Some((backup.created(&manifest)?, date))
+		})
+		.collect::<Vec<_>>();
+	backups.sort_by_key(|(created, _)| *created);
+
+	Ok(backups)
+}
+
+/// Groups backups taken in order into sessions of play.
+fn sessions(backups: &[(DateTime<Local>, Option<GameDate>)]) -> Vec<Session> {
+	history::sessions(backups, |(created, _)| Some(*created))
+		.into_iter()
+		.map(|session| {
+			let mut dates = session.iter().filter_map(|(_, date)| *date);
+			Session {
+				start: session[0].0,
+				end: session[session.len() - 1].0,
+				backups: session.len(),
+				dates: dates.clone().next().zip(dates.next_back()),
+			}
+		})
+		.collect()
+}
+
+/// Draws how many backups were taken over time, from the first backup until now, so that the
+/// stretches without any stand out.
+fn render(created: &[DateTime<Local>], now: DateTime<Local>) -> String {
+	let first = created[0];
+	let span = (now - first).num_seconds().max(1) as f64;
+	let mut counts = [0_usize; CHART_WIDTH];
+	for created in created {
+		let column = ((*created - first).num_seconds() as f64 / span * (CHART_WIDTH - 1) as f64)
+			.round()
+			.clamp(0.0, (CHART_WIDTH - 1) as f64) as usize;
+		counts[column] += 1;
+	}
+	let most = counts.iter().copied().max().unwrap_or(1).max(1);
+
+	let mut chart = String::new();
+	for row in (0..CHART_HEIGHT).rev() {
+		let label = match row {
+			_ if row == CHART_HEIGHT - 1 => format!("{:>5}", most),
+			0 => format!("{:>5}", 0),
+			_ => " ".repeat(5),
+		};
+		let bars = counts
+			.iter()
+			.map(|count| {
+				// any backup at all shows, however many the busiest stretch had
+				let height = (count * CHART_HEIGHT).div_ceil(most);
+				if height > row {
+					'#'
+				} else {
+					' '
+				}
+			})
+			.collect::<String>();
+		chart.push_str(&format!("{} |{}\n", label, bars));
+	}
+	chart.push_str(&format!("{} +{}\n", " ".repeat(5), "-".repeat(CHART_WIDTH)));
+	chart.push_str(&format!(
+		"{} {}{:>width$}\n",
+		" ".repeat(6),
+		first.format("%Y-%m-%d"),
+		"now",
+		width = CHART_WIDTH - 10
+	));
+	chart.push_str(&format!(
+		"{} each column is {}\n",
+		" ".repeat(6),
+		describe_span(Duration::seconds((span / CHART_WIDTH as f64) as i64))
+	));
+
+	chart
+}
+
+/// How active the campaign of every save of the game is, most recently played first.
+fn campaigns(config: &Ini, profile: &Profile, now: DateTime<Local>) -> io::Result<String> {
+	let mut campaigns = Vec::new();
+	for backup_dir in store::save_dirs(&profile.backup_path)? {
+		let backups = backups(&backup_dir, false)?;
+		let last = match backups.last() {
+			Some((last, _)) => *last,
+			None => continue,
+		};
+		let recent = sessions(&backups)
+			.iter()
+			.filter(|session| now - session.end <= Duration::days(ACTIVE_DAYS))
+			.count();
+		let save = profile.save_of(&backup_dir).unwrap_or_default();
+		campaigns.push((display_name(config, &save), last, recent));
+	}
+	campaigns.sort_by_key(|(_, last, _)| std::cmp::Reverse(*last));
+
+	let mut table = format!(
+		"{:<24} {:<16} Sessions in the last {} days\n",
+		"Save", "Last backup", ACTIVE_DAYS
+	);
+	for (save, last, recent) in campaigns {
+		let state = if recent == 0 {
+			format!("idle for {}", describe_span(now - last))
+		} else {
+			recent.to_string()
+		};
+		table.push_str(&format!(
+			"{:<24} {:<16} {}\n",
+			save,
+			last.format("%Y-%m-%d %H:%M"),
+			state
+		));
+	}
+
+	Ok(table)
+}
+
+/// Shows when the working save was backed up over time: a chart of the backups since the first,
+/// its latest sessions of play, how often backups are taken and the longest stretch without one,
+/// followed by how recently the campaigns of the other saves were played.
+pub fn show(s: &mut Cursive, profile: &Profile) -> error::Result<()> {
+	let config: &mut Ini = s
+		.user_data()
+		.expect("User data not set up correctly on program start");
+	let save = current_save(config)
+		.ok_or("No save file has been set.")?
+		.to_string();
+	let title = format!("Timeline of {}", display_name(config, &save));
+
+	let backups = backups(&profile.backup_path.join(&save), true)?;
+	if backups.is_empty() {
+		return Err("The save has no backups yet.".into());
+	}
+	let now = clock::now();
+	let campaigns = campaigns(config, profile, now)?;
+	let created = backups
+		.iter()
+		.map(|(created, _)| *created)
+		.collect::<Vec<_>>();
+	let sessions = sessions(&backups);
+
+	let played = sessions
+		.iter()
+		.map(Session::length)
+		.sum::<std::time::Duration>();
+	let mut summary = format!(
+		"{} backups in {} sessions since {}, {} played\n",
+		backups.len(),
+		sessions.len(),
+		created[0].format("%Y-%m-%d"),
+		format_duration(played)
+	);
+	let within_sessions = backups.len() - sessions.len();
+	if within_sessions > 0 {
+		summary.push_str(&format!(
+			"{:.1} backups per session, one every {} while playing\n",
+			backups.len() as f64 / sessions.len() as f64,
+			format_duration(played / within_sessions as u32)
+		));
+	}
+	if let Some((before, after)) = sessions
+		.windows(2)
+		.map(|pair| (pair[0].end, pair[1].start))
+		.max_by_key(|(before, after)| *after - *before)
+	{
+		summary.push_str(&format!(
+			"Longest gap: {} without a backup, from {} to {}\n",
+			describe_span(after - before),
+			before.format("%Y-%m-%d"),
+			after.format("%Y-%m-%d")
+		));
+	}
+	let dates = backups.iter().filter_map(|(_, date)| *date);
+	if let (Some(first), Some(last)) = (dates.clone().min(), dates.max()) {
+		summary.push_str(&format!("In-game from {} to {}\n", first, last));
+	}
+	summary.push_str(&format!(
+		"Last backup {} ago",
+		describe_span(now - created[created.len() - 1])
+	));
+
+	let latest = sessions
+		.iter()
+		.rev()
+		.take(LISTED_SESSIONS)
+		.map(Session::label)
+		.collect::<Vec<_>>()
+		.join("\n");
+
+	s.add_layer(
+		Dialog::around(
+			TextView::new(format!(
+				"{}\n{}\n\nLatest sessions\n{}\n\nCampaigns\n{}",
+				render(&created, now),
+				summary,
+				latest,
+				campaigns
+			))
+			.scrollable(),
+		)
+		.title(title)
+		.button("Ok", |s| {
+			s.pop_layer();
+		}),
+	);
+
+	Ok(())
+}
@@ -0,0 +1,152 @@
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::time::Duration;
+
+use log::warn;
+
+use crate::{format_size, write_journal, JOURNAL_FILE};
+
+/// First word of the journal entries recording how long an operation took.
+const ENTRY_PREFIX: &str = "timing";
+
+/// Operations whose duration and throughput are recorded in the journal.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Operation {
+	Backup,
+	Restore,
+	/// Hashing backups to check them.
+	Verify,
+	/// Sending backups to a chat channel or a remote.
+	Upload,
+}
+
+impl Operation {
+	const ALL: [Self; 4] = [Self::Backup, Self::Restore, Self::Verify, Self::Upload];
+
+	const fn name(self) -> &'static str {
+		match self {
+			Self::Backup => "backup",
+			Self::Restore => "restore",
+			Self::Verify => "verify",
+			Self::Upload => "upload",
+		}
+	}
+
+	fn from_name(name: &str) -> Option<Self> {
+		Self::ALL
+			.iter()
+			.copied()
+			.find(|operation| operation.name() == name)
+	}
+}
+
+impl fmt::Display for Operation {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		f.write_str(self.name())
+	}
+}
+
+/// Bytes handled per second, or zero for operations too quick to measure.
+fn throughput(bytes: u64, elapsed: Duration) -> u64 {
+	let seconds = elapsed.as_secs_f64();
+	if seconds > 0.0 {
+		(bytes as f64 / seconds) as u64
+	} else {
+		0
+	}
+}
+
+/// Adds how long an operation on `bytes` bytes took to the journal in the backup folder
+/// `backup_path`. Failing to is only worth a warning, as the operation itself went through.
+pub fn record(backup_path: &Path, operation: Operation, bytes: u64, elapsed: Duration) {
+	let entry = format!(
+		"{} {} {} ms {} bytes ({}/s)",
+		ENTRY_PREFIX,
+		operation,
+		elapsed.as_millis(),
+		bytes,
+		format_size(throughput(bytes, elapsed))
+	);
+	if let Err(e) = write_journal(backup_path, &entry) {
+		warn!("Could not write to journal: {}", e);
+	}
+}
+
+/// How long one kind of operation has taken, over every time it was recorded.
+pub struct Timings {
+	operation: Operation,
+	count: u32,
+	total: Duration,
+	bytes: u64,
+	last: (Duration, u64),
+}
+
+impl Timings {
+	/// A line comparing the latest run with the average, to make slowdowns stand out.
+	pub fn describe(&self) -> String {
+		let average = self.total / self.count;
+		format!(
+			"{:<8} {:>5} {}, average {:.2} s at {}/s, last {:.2} s at {}/s",
+			self.operation.name(),
+			self.count,
+			if self.count == 1 { "run" } else { "runs" },
+			average.as_secs_f64(),
+			format_size(throughput(self.bytes, self.total)),
+			self.last.0.as_secs_f64(),
+			format_size(throughput(self.last.1, self.last.0))
+		)
+	}
+}
+
+/// Reads back the timings recorded in the journal of the backup folder `backup_path`, for the
+/// operations that have any.
+pub fn read(backup_path: &Path) -> io::Result<Vec<Timings>> {
+	let journal = match fs::read_to_string(backup_path.join(JOURNAL_FILE)) {
+		Ok(journal) => journal,
+		Err(e) if e.kind() == io::ErrorKind::NotFound => String::new(),
+		Err(e) => return Err(e),
+	};
+
+	let mut timings: Vec<Timings> = Vec::new();
+	for line in journal.lines() {
+		let entry = line.split_once('\t').map_or(line, |(_, entry)| entry);
+		let fields = entry.split_whitespace().collect::<Vec<_>>();
+		let (operation, millis, bytes) = match fields.as_slice() {
+			[ENTRY_PREFIX, operation, millis, "ms", bytes, "bytes", ..] => {
+				match (
+					Operation::from_name(operation),
+					millis.parse::<u64>(),
+					bytes.parse::<u64>(),
+				) {
+					(Some(operation), Ok(millis), Ok(bytes)) => (operation, millis, bytes),
+					_ => continue,
+				}
+			}
+			_ => continue,
+		};
+		let elapsed = Duration::from_millis(millis);
+
+		match timings
+			.iter_mut()
+			.find(|timings| timings.operation == operation)
+		{
+			Some(timings) => {
+				timings.count += 1;
+				timings.total += elapsed;
+				timings.bytes += bytes;
+				timings.last = (elapsed, bytes);
+			}
+			None => timings.push(Timings {
+				operation,
+				count: 1,
+				total: elapsed,
+				bytes,
+				last: (elapsed, bytes),
+			}),
+		}
+	}
+
+	Ok(timings)
+}
@@ -0,0 +1,213 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+use crate::parser::ZIP_MAGIC;
+
+/// How a game writes its saves, which decides what can be read back from them.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum SaveFormat {
+	/// Plain text, or a zip archive holding text when the game compresses saves.
+	Text,
+	/// Always a zip archive holding text.
+	Zip,
+	/// Text, except in ironman mode, where the game writes a binary format.
+	BinaryIronman,
+	/// A short text header followed by a binary archive, so only what the header holds can be read.
+	Binary,
+}
+
+impl SaveFormat {
+	pub const fn describe(self) -> &'static str {
+		match self {
+			Self::Text => "text, zipped when compressed",
+			Self::Zip => "zip archive",
+			Self::BinaryIronman => "text, binary in ironman",
+			Self::Binary => "binary",
+		}
+	}
+}
+
+/// A Paradox game the save manager knows how to set up, so that a profile for it can be picked
+/// from a list instead of written by hand.
+pub struct Title {
+	pub name: &'static str,
+	/// Extension of its saves including the dot, which is the same whatever the game's language.
+	pub extension: &'static str,
+	/// Folder of the game inside `Paradox Interactive` in the documents folder.
+	pub folder: &'static str,
+	/// Steam app id, which names its Proton prefix and Steam Cloud folder.
+	pub steam_app_id: &'static str,
+	/// Executable name on Windows and on Linux and macOS.
+	pub process: (&'static str, &'static str),
+	pub format: SaveFormat,
+	/// What saves start with, apart from the zip archives of compressed saves.
+	pub signatures: &'static [&'static str],
+	/// Whether saves are kept in folders inside the save directory, such as one per empire.
+	pub nested: bool,
+	/// Whether the game keeps a `_Backup` copy next to ironman saves.
+	pub ironman_copies: bool,
+}
+
+impl Title {
+	/// Executable name of the game on this system, for `process`.
+	pub const fn process(&self) -> &'static str {
+		if cfg!(windows) {
+			self.process.0
+		} else {
+			self.process.1
+		}
+	}
+
+	/// Whether a file starting with `start` looks like a save of the game, whatever its name.
+	pub fn has_signature(&self, start: &[u8]) -> bool {
+		self.signatures
+			.iter()
+			.any(|signature| start.starts_with(signature.as_bytes()))
+			|| (self.format != SaveFormat::Binary && start.starts_with(ZIP_MAGIC))
+	}
+}
+
+/// Folder inside the game's folder holding its saves.
+pub const SAVE_GAMES: &str = "save games";
+
+/// Every mainline Paradox game whose saves live in the documents folder.
+pub const TITLES: [Title; 8] = [
+	Title {
+		name: "Crusader Kings II",
+		extension: ".ck2",
+		folder: "Crusader Kings II",
+		steam_app_id: "203770",
+		process: ("CK2game.exe", "ck2"),
+		format: SaveFormat::Text,
+		signatures: &["CK2txt"],
+		nested: false,
+		ironman_copies: false,
+	},
+	Title {
+		name: "Crusader Kings III",
+		extension: ".ck3",
+		folder: "Crusader Kings III",
+		steam_app_id: "1158310",
+		process: ("ck3.exe", "ck3"),
+		format: SaveFormat::Binary,
+		signatures: &["SAV0"],
+		nested: false,
+		ironman_copies: false,
+	},
+	Title {
+		name: "Europa Universalis IV",
+		extension: ".eu4",
+		folder: "Europa Universalis IV",
+		steam_app_id: "236850",
+		process: ("eu4.exe", "eu4"),
+		format: SaveFormat::BinaryIronman,
+		signatures: &["EU4txt", "EU4bin"],
+		nested: false,
+		ironman_copies: true,
+	},
+	Title {
+		name: "Europa Universalis V",
+		extension: ".eu5",
+		folder: "Europa Universalis V",
+		steam_app_id: "3450310",
+		process: ("eu5.exe", "eu5"),
+		format: SaveFormat::Binary,
+		signatures: &["SAV"],
+		nested: false,
+		ironman_copies: false,
+	},
+	Title {
+		name: "Hearts of Iron IV",
+		extension: ".hoi4",
+		folder: "Hearts of Iron IV",
+		steam_app_id: "394360",
+		process: ("hoi4.exe", "hoi4"),
+		format: SaveFormat::BinaryIronman,
+		signatures: &["HOI4txt", "HOI4bin"],
+		nested: false,
+		ironman_copies: false,
+	},
+	Title {
+		name: "Imperator: Rome",
+		extension: ".rome",
+		folder: "Imperator",
+		steam_app_id: "859580",
+		process: ("imperator.exe", "imperator"),
+		format: SaveFormat::Binary,
+		signatures: &["SAV0"],
+		nested: false,
+		ironman_copies: false,
+	},
+	Title {
+		name: "Stellaris",
+		extension: ".sav",
+		folder: "Stellaris",
+		steam_app_id: "281990",
+		process: ("stellaris.exe", "stellaris"),
+		format: SaveFormat::Zip,
+		signatures: &[],
+		nested: true,
+		ironman_copies: false,
+	},
+	Title {
+		name: "Victoria 3",
+		extension: ".v3",
+		folder: "Victoria 3",
+		steam_app_id: "529340",
+		process: ("victoria3.exe", "victoria3"),
+		format: SaveFormat::Binary,
+		signatures: &["SAV0"],
+		nested: false,
+		ironman_copies: false,
+	},
+];
+
+/// The known game with the given name, compared without regard to case.
+pub fn find(name: &str) -> Option<&'static Title> {
+	TITLES
+		.iter()
+		.find(|title| title.name.eq_ignore_ascii_case(name.trim()))
+}
+
+/// The known game whose saves have the given extension, with or without the dot.
+pub fn by_extension(extension: &str) -> Option<&'static Title> {
+	let extension = extension.trim_start_matches('.');
+	TITLES
+		.iter()
+		.find(|title| title.extension[1..].eq_ignore_ascii_case(extension))
+}
+
+/// The known game most of the files in a save directory are saves of, for save directories set up
+/// without saying which game they belong to.
+///
+/// Folder names are not relied on, as they can be translated or moved.
+pub fn guess(save_path: &Path) -> Option<&'static Title> {
+	let mut counts: HashMap<&str, usize> = HashMap::new();
+	for entry in fs::read_dir(save_path).ok()?.filter_map(Result::ok) {
+		let path = entry.path();
+		let title = if path.is_dir() {
+			// games keeping saves in folders of their own have them one level down
+			fs::read_dir(&path)
+				.ok()
+				.and_then(|mut entries| {
+					entries.find_map(|entry| {
+						let path = entry.ok()?.path();
+						by_extension(&path.extension()?.to_string_lossy())
+					})
+				})
+				.filter(|title| title.nested)
+		} else {
+			path.extension()
+				.and_then(|extension| by_extension(&extension.to_string_lossy()))
+		};
+		if let Some(title) = title {
+			*counts.entry(title.name).or_default() += 1;
+		}
+	}
+
+	counts
+		.into_iter()
+		.max_by_key(|(name, count)| (*count, *name))
+		.and_then(|(name, _)| find(name))
+}
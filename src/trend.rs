@@ -0,0 +1,78 @@
+use std::io;
+use std::path::Path;
+
+use crate::format_size;
+use crate::manifest::Manifest;
+use crate::store::{self, Backup};
+
+/// How many of the newest backups the usual size of a save is taken from.
+const WINDOW: usize = 10;
+
+/// Fewest earlier backups needed before a size can stand out from them.
+const MIN_HISTORY: usize = 3;
+
+/// A save whose size is far off the usual size of its recent backups, which hints at a corrupted
+/// save or a changed mod list.
+pub struct Anomaly {
+	pub size: u64,
+	/// Median size of the recent backups.
+	pub usual: u64,
+	/// Newest backup from before the anomaly, pinned as a restore point.
+	pub restore_point: Backup,
+}
+
+impl Anomaly {
+	pub fn describe(&self) -> String {
+		let direction = if self.size > self.usual {
+			"larger"
+		} else {
+			"smaller"
+		};
+
+		format!(
+			"{} is much {} than the usual {}, backup number {} was pinned as a restore point",
+			format_size(self.size),
+			direction,
+			format_size(self.usual),
+			self.restore_point.number
+		)
+	}
+}
+
+/// Compares the size of a save about to become backup number `number` with the backups before it,
+/// and reports it when it is more than `ratio` times larger or smaller than their median.
+pub fn check(
+	backup_dir: &Path,
+	number: usize,
+	size: u64,
+	ratio: f64,
+) -> io::Result<Option<Anomaly>> {
+	let manifest = Manifest::load(backup_dir);
+	let recent = store::list_backups(backup_dir)?
+		.into_iter()
+		.filter(|backup| backup.number < number)
+		.rev()
+		.take(WINDOW)
+		.filter_map(|backup| {
+			let size = backup.size(&manifest)?;
+			Some((backup, size))
+		})
+		.collect::<Vec<_>>();
+	if recent.len() < MIN_HISTORY {
+		return Ok(None);
+	}
+
+	let mut sizes = recent.iter().map(|(_, size)| *size).collect::<Vec<_>>();
+	sizes.sort_unstable();
+	let usual = sizes[sizes.len() / 2];
+
+	if size as f64 <= usual as f64 * ratio && size as f64 * ratio >= usual as f64 {
+		return Ok(None);
+	}
+
+	Ok(recent.into_iter().next().map(|(restore_point, _)| Anomaly {
+		size,
+		usual,
+		restore_point,
+	}))
+}
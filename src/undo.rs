@@ -0,0 +1,148 @@
+//! Journal of the restores and deletions made while the program runs, so that the latest can be
+//! undone.
+//!
+//! Deleted backups are moved into a trash folder next to the other backups of their save until the
+//! program quits, rather than removed straight away.
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use log::warn;
+
+use crate::error::{self, Context};
+use crate::store;
+
+/// Folder next to the backups of a save where deleted backups wait until the program quits.
+pub const TRASH_FOLDER: &str = "trash";
+
+/// A backup moved into the trash.
+pub struct Trashed {
+	pub number: usize,
+	pub file_name: String,
+	/// Where the backup is in the trash.
+	pub path: PathBuf,
+}
+
+/// Something done to the backups or the save that can be undone.
+pub enum Step {
+	/// A backup was restored over the save, which was kept at `kept` first.
+	Restore {
+		save_file: PathBuf,
+		kept: PathBuf,
+		backup: usize,
+	},
+	/// Backups were moved into the trash, with the manifest entries they had.
+	Delete {
+		backup_dir: PathBuf,
+		backups: Vec<Trashed>,
+		manifest: String,
+	},
+}
+
+impl Step {
+	fn describe(&self) -> String {
+		match self {
+			Self::Restore {
+				save_file, backup, ..
+			} => format!(
+				"Restoring backup {} over {}",
+				backup,
+				save_file.file_name().unwrap_or_default().to_string_lossy()
+			),
+			Self::Delete { backups, .. } => match backups.as_slice() {
+				[trashed] => format!("Deleting backup {}", trashed.number),
+				_ => format!("Deleting {} backups", backups.len()),
+			},
+		}
+	}
+}
+
+/// Steps taken this session, oldest first.
+static STEPS: Mutex<Vec<Step>> = Mutex::new(Vec::new());
+
+/// Records a step so that it can be undone later in the session.
+pub fn record(step: Step) {
+	STEPS.lock().unwrap().push(step);
+}
+
+/// What undoing would reverse, or `None` when nothing was done this session.
+pub fn describe_last() -> Option<String> {
+	STEPS.lock().unwrap().last().map(Step::describe)
+}
+
+/// Reverses the latest step of the session, returning what was undone. A step that could not be
+/// undone stays in the journal to be tried again.
+pub fn undo_last() -> error::Result<String> {
+	let step = STEPS
+		.lock()
+		.unwrap()
+		.pop()
+		.ok_or("Nothing to undo in this session.")?;
+
+	let undone = match &step {
+		Step::Restore {
+			save_file, kept, ..
+		} => {
+			if kept.is_file() {
+				store::undo_restore(kept, save_file).context("write to", save_file)
+			} else {
+				Err(format!(
+					"The save kept before the restore is gone from {}.",
+					kept.display()
+				)
+				.into())
+			}
+		}
+		Step::Delete {
+			backup_dir,
+			backups,
+			manifest,
+		} => store::untrash_backups(backup_dir, backups, manifest)
+			.context("put back backups in", backup_dir),
+	};
+
+	match undone {
+		Ok(()) => Ok(step.describe()),
+		Err(e) => {
+			STEPS.lock().unwrap().push(step);
+			Err(e)
+		}
+	}
+}
+
+/// Whether a file in a trash folder belongs to a deletion that can still be undone.
+pub fn is_journaled(path: &Path) -> bool {
+	STEPS.lock().unwrap().iter().any(|step| match step {
+		Step::Delete { backups, .. } => backups.iter().any(|trashed| trashed.path == path),
+		Step::Restore { .. } => false,
+	})
+}
+
+/// Removes the backups deleted this session for good, once they can no longer be undone.
+pub fn empty_trash() -> io::Result<()> {
+	let mut result = Ok(());
+	for step in STEPS.lock().unwrap().drain(..) {
+		if let Step::Delete {
+			backup_dir,
+			backups,
+			..
+		} = step
+		{
+			for trashed in backups {
+				match fs::remove_file(&trashed.path) {
+					Err(e) if e.kind() != io::ErrorKind::NotFound => {
+						warn!("Could not empty {}: {}", trashed.path.display(), e);
+						result = Err(e);
+					}
+					_ => {}
+				}
+			}
+			// left in place while something else is still inside
+			let _ = fs::remove_dir(backup_dir.join(TRASH_FOLDER));
+		}
+	}
+
+	result
+}
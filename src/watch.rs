@@ -0,0 +1,1083 @@
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+
+use chrono::{DateTime, Local};
+
+use ini::Ini;
+
+use log::{error, info, warn};
+
+use notify::{DebouncedEvent, RecommendedWatcher, RecursiveMode, Watcher as _};
+
+use crate::alerts::Preferences;
+use crate::clock;
+use crate::game;
+use crate::index;
+use crate::jobs::Moment;
+use crate::manifest::Origin;
+use crate::profiles::Profile;
+use crate::retention::{self, Policy, Trigger};
+use crate::schedule;
+use crate::settings::{
+	auto_mode, auto_scope, autosave_generations, backup_options, current_save, retention_policy,
+	retention_trigger,
+};
+use crate::store::{self, backup_retrying, BackupOptions, Created, LockRetry};
+use crate::{format_duration, format_size, write_journal};
+
+/// How long the save directory has to be left alone before a new save in it is reported, so that
+/// the game has finished writing it.
+const NEW_SAVE_DELAY: Duration = Duration::from_secs(2);
+
+/// How often an automatic session checks that the save directory can still be reached.
+const WATCHDOG_INTERVAL: Duration = Duration::from_secs(5);
+
+/// How often the game process is looked for, when backing up at the end of each play session.
+const GAME_CHECK_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Note of the backups taken when the game exits.
+const SESSION_END_NOTE: &str = "end of session";
+
+/// How often the cloud copies of the saves are checked for being written by the cloud sync.
+const CLOUD_CHECK_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Note of the backups holding a cloud copy that disagrees with the local save.
+const CLOUD_NOTE: &str = "cloud copy";
+
+/// Name of the save the game writes its autosaves to.
+pub const AUTOSAVE: &str = "autosave";
+
+/// How often the game's autosave is checked for being written, in case it cannot be watched.
+const AUTOSAVE_CHECK_INTERVAL: Duration = Duration::from_secs(60);
+
+/// What makes an automatic session take a backup, set with the `auto_mode` key.
+#[derive(Clone, Copy)]
+pub enum Mode {
+	/// Whenever the game writes the save.
+	Watch,
+	/// At a fixed interval, if the save changed since the last backup.
+	Interval(Duration),
+	/// Both, without backing up the same write twice.
+	Both(Duration),
+}
+
+impl Mode {
+	const fn watches(self) -> bool {
+		matches!(self, Self::Watch | Self::Both(_))
+	}
+
+	const fn interval(self) -> Option<Duration> {
+		match self {
+			Self::Watch => None,
+			Self::Interval(interval) | Self::Both(interval) => Some(interval),
+		}
+	}
+}
+
+/// Running totals for a single automatic backup session.
+#[derive(Default)]
+pub struct SessionStats {
+	backups: usize,
+	/// Total size of the backups taken.
+	bytes: u64,
+	/// Bytes that had to be written, lower than `bytes` when identical backups were stored once.
+	written: u64,
+	failures: Vec<String>,
+	/// When the newest backup of the session was taken.
+	last_backup: Option<DateTime<Local>>,
+}
+
+impl SessionStats {
+	pub fn record_backup(&mut self, created: &Created) {
+		self.backups += 1;
+		self.bytes += created.size;
+		self.written += created.written;
+		self.last_backup = Some(clock::now());
+	}
+
+	pub fn summary(&self, elapsed: Duration) -> String {
+		let mut summary = format!(
+			"Session length: {}\nBackups taken: {}\nData written: {}",
+			format_duration(elapsed),
+			self.backups,
+			format_size(self.written)
+		);
+
+		if self.written != self.bytes {
+			summary.push_str(&format!(
+				" ({} before deduplication)",
+				format_size(self.bytes)
+			));
+		}
+
+		if self.failures.is_empty() {
+			summary.push_str("\nFailures: none");
+		} else {
+			summary.push_str(&format!("\nFailures: {}", self.failures.len()));
+			for failure in &self.failures {
+				summary.push_str(&format!("\n  {}", failure));
+			}
+		}
+
+		summary
+	}
+}
+
+/// Which saves an automatic session backs up, set with the `auto_saves` key.
+pub enum Scope {
+	/// These saves, by name.
+	Saves(Vec<String>),
+	/// Every save in the save directory, including ones started while the session runs.
+	All,
+}
+
+/// Looks up how a newly seen save file should be backed up, for sessions covering every save.
+type Discover = Box<dyn Fn(&Path) -> Option<Target> + Send>;
+
+/// An automatic backup session, backing up saves every time the game writes them until stopped.
+pub struct Session {
+	saves: Vec<String>,
+	backup_path: PathBuf,
+	stats: Arc<Mutex<SessionStats>>,
+	started: Instant,
+	/// Set to stop the interval timers and scheduled tasks running alongside the session.
+	stop: Arc<AtomicBool>,
+	/// Set while backups are paused from the menu.
+	held: Arc<AtomicBool>,
+	/// Set while the save directory cannot be reached.
+	paused: Arc<AtomicBool>,
+	/// Kept alive for as long as the session runs, since dropping it stops the events. Shared with
+	/// the watchdog, which watches the saves again after their directory comes back.
+	_watcher: Arc<Mutex<RecommendedWatcher>>,
+}
+
+impl Session {
+	/// Starts a session for the working save, first taking a backup of the current state of its
+	/// saves when `baseline` is set or `auto_baseline` is `true`.
+	pub fn start_working(
+		config: &Ini,
+		profile: &Profile,
+		baseline: bool,
+	) -> crate::error::Result<Self> {
+		let save = current_save(config).ok_or("No save file has been set.")?;
+		let baseline = baseline || config.general_section().get("auto_baseline") == Some("true");
+		Self::start(config, profile, save, baseline)
+	}
+
+	/// Starts backing up the saves of the given game picked by `auto_saves`, each as configured by
+	/// its `auto_mode`, first taking a backup of their current state when `baseline` is set. The
+	/// game's scheduled tasks run for as long as the session does, for the working save `save`.
+	pub fn start(
+		config: &Ini,
+		profile: &Profile,
+		save: &str,
+		baseline: bool,
+	) -> crate::error::Result<Self> {
+		let scope = auto_scope(config, save);
+		let saves = match &scope {
+			Scope::Saves(saves) => saves.clone(),
+			Scope::All => save_names(profile)?,
+		}
+		.into_iter()
+		.filter(|save| {
+			let found = profile.save_file(save).is_file();
+			if !found {
+				warn!("{}: save file not found, not backed up", save);
+			}
+			found
+		})
+		.collect::<Vec<_>>();
+		if saves.is_empty() {
+			return Err("Save file not found.".into());
+		}
+
+		let (tx, rx) = mpsc::channel();
+		let mut watcher = notify::watcher(tx.clone(), Duration::from_secs(10))?;
+		let stats = Arc::new(Mutex::new(SessionStats::default()));
+		let stop = Arc::new(AtomicBool::new(false));
+		let paused = Arc::new(AtomicBool::new(false));
+		let held = Arc::new(AtomicBool::new(false));
+
+		let mut targets = HashMap::new();
+		let mut watched = Vec::new();
+		for save in &saves {
+			let file_path = profile.save_file(save);
+			let mut target = Target::new(config, profile, save, &paused)?;
+			let mode = auto_mode(config, save);
+
+			// capture the state the session starts from, as the game may not write again for a while.
+			// It is tried once, as waiting for the game to let go of the save would hold up whoever
+			// started the session, and the next write is backed up anyway
+			if baseline {
+				target.backed_up = modified(&file_path);
+				match store::backup_core(
+					&file_path,
+					&target.backup_dir,
+					"",
+					Origin::Auto,
+					&target.options,
+				) {
+					Ok(created) => {
+						if !created.skipped {
+							stats.lock().unwrap().record_backup(&created);
+						}
+					}
+					Err(e) if e.in_use() => {
+						warn!("{}: in use by the game, starting without a backup", save);
+						target.backed_up = None;
+					}
+					Err(e) => return Err(e),
+				}
+			}
+
+			// the whole directory is watched instead when covering every save
+			if mode.watches() && matches!(scope, Scope::Saves(_)) {
+				watcher.watch(&file_path, RecursiveMode::NonRecursive)?;
+				watched.push((file_path.clone(), RecursiveMode::NonRecursive));
+			}
+			if let Some(interval) = mode.interval() {
+				spawn_timer(file_path.clone(), interval, tx.clone(), Arc::clone(&stop));
+			}
+
+			targets.insert(file_path, target);
+		}
+
+		// the game's autosave is kept in generations of its own, whichever saves are backed up, so
+		// that a campaign never set up still has its recent states
+		if let Some(generations) = autosave_generations(config) {
+			let file_path = profile.save_file(AUTOSAVE);
+			let mut target = match targets.remove(&file_path) {
+				Some(target) => target,
+				None => {
+					if file_path.is_file() && matches!(scope, Scope::Saves(_)) {
+						watcher.watch(&file_path, RecursiveMode::NonRecursive)?;
+						watched.push((file_path.clone(), RecursiveMode::NonRecursive));
+					}
+					spawn_timer(
+						file_path.clone(),
+						AUTOSAVE_CHECK_INTERVAL,
+						tx,
+						Arc::clone(&stop),
+					);
+					Target::new(config, profile, AUTOSAVE, &paused)?
+				}
+			};
+			target.retention = Policy::Last(generations);
+			target.retention_trigger = Trigger::AfterBackup;
+			targets.insert(file_path, target);
+		}
+
+		let discover = match scope {
+			Scope::Saves(_) => None,
+			Scope::All => {
+				let mode = if profile.recursive {
+					RecursiveMode::Recursive
+				} else {
+					RecursiveMode::NonRecursive
+				};
+				watcher.watch(&profile.save_path, mode)?;
+				watched.push((profile.save_path.clone(), mode));
+				Some(discover(
+					config.clone(),
+					profile.clone(),
+					Arc::clone(&paused),
+				))
+			}
+		};
+
+		if let Some(process) = &profile.process {
+			spawn_exit_watch(
+				process.clone(),
+				targets.clone(),
+				Arc::clone(&held),
+				Arc::clone(&stats),
+				Arc::clone(&stop),
+			);
+		}
+
+		if profile.cloud_path.is_some() {
+			let clouds = targets
+				.iter()
+				.filter_map(|(file_path, target)| {
+					let cloud_file = profile.cloud_file(&target.name)?;
+					Some((file_path.clone(), cloud_file, target.clone()))
+				})
+				.collect();
+			spawn_cloud_watch(
+				clouds,
+				Arc::clone(&held),
+				Arc::clone(&stats),
+				Arc::clone(&stop),
+			);
+		}
+
+		let watcher = Arc::new(Mutex::new(watcher));
+		spawn_watchdog(
+			Watchdog {
+				save_path: profile.save_path.clone(),
+				watched,
+				watcher: Arc::clone(&watcher),
+				alerts: Preferences::load(config, save),
+				paused: Arc::clone(&paused),
+			},
+			Arc::clone(&stop),
+		);
+		schedule::spawn(
+			schedule::load(config, &profile.name),
+			profile.clone(),
+			save.to_string(),
+			backup_options(config, save),
+			(
+				retention_policy(config, save),
+				retention_trigger(config, save),
+			),
+			Arc::clone(&stop),
+		);
+		spawn_dispatcher(rx, targets, discover, Arc::clone(&held), Arc::clone(&stats));
+
+		Ok(Self {
+			saves,
+			backup_path: profile.backup_path.clone(),
+			stats,
+			started: Instant::now(),
+			stop,
+			held,
+			paused,
+			_watcher: watcher,
+		})
+	}
+
+	/// Holds off backups until resumed. Saves written in the meantime are backed up the next time
+	/// the game writes them.
+	pub fn pause(&self) {
+		self.held.store(true, Ordering::Relaxed);
+		info!(
+			event = "session_paused", save = self.saves.join(", ").as_str();
+			"Paused automatic backups"
+		);
+	}
+
+	pub fn resume(&self) {
+		self.held.store(false, Ordering::Relaxed);
+		info!(
+			event = "session_resumed", save = self.saves.join(", ").as_str();
+			"Resumed automatic backups"
+		);
+	}
+
+	pub fn is_paused(&self) -> bool {
+		self.held.load(Ordering::Relaxed)
+	}
+
+	/// One line describing the session for the status line of the main screen, such as
+	/// "Auto-backup: ON (last backup 12:04)".
+	pub fn status(&self) -> String {
+		if self.is_paused() {
+			"Auto-backup: PAUSED".to_string()
+		} else if self.paused.load(Ordering::Relaxed) {
+			"Auto-backup: WAITING (save folder unreachable)".to_string()
+		} else {
+			let last_backup = self.stats.lock().unwrap().last_backup;
+			last_backup.map_or_else(
+				|| "Auto-backup: ON (no backup yet)".to_string(),
+				|last| format!("Auto-backup: ON (last backup {})", last.format("%H:%M")),
+			)
+		}
+	}
+
+	/// Names of the saves backed up since the session started.
+	pub fn saves(&self) -> &[String] {
+		&self.saves
+	}
+
+	/// Stops watching, writes out the backup index and records the session in the journal.
+	/// Returns a summary of the session.
+	pub fn stop(self) -> String {
+		let saves = self.saves.join(", ");
+		info!(
+			event = "session_stopped",
+			save = saves.as_str(),
+			duration_ms = self.started.elapsed().as_millis() as u64;
+			"Stopped automatic backups"
+		);
+		self.stop.store(true, Ordering::Relaxed);
+		if let Err(e) = index::flush() {
+			error!("Could not save the backup index: {}", e);
+		}
+
+		let summary = self.stats.lock().unwrap().summary(self.started.elapsed());
+		if let Err(e) = write_journal(
+			&self.backup_path,
+			&format!(
+				"auto session for {}: {}",
+				saves,
+				summary.replace('\n', "; ")
+			),
+		) {
+			error!("Could not write to journal: {}", e);
+		}
+
+		summary
+	}
+}
+
+/// Backs up one save every time the game writes it, as configured for the save in `config`, for
+/// programs using the library. Other saves picked by `auto_saves` are left to the menu's sessions.
+pub struct Watcher(Session);
+
+impl Watcher {
+	pub fn start(config: &Ini, profile: &Profile, save: &str) -> crate::error::Result<Self> {
+		let mut config = config.clone();
+		config.general_section_mut().remove("auto_saves");
+		config.general_section_mut().remove("autosave_generations");
+		Session::start(&config, profile, save, false).map(Self)
+	}
+
+	pub fn pause(&self) {
+		self.0.pause();
+	}
+
+	pub fn resume(&self) {
+		self.0.resume();
+	}
+
+	pub fn is_paused(&self) -> bool {
+		self.0.is_paused()
+	}
+
+	pub fn status(&self) -> String {
+		self.0.status()
+	}
+
+	/// Stops watching the save, returning a summary of the backups taken.
+	pub fn stop(self) -> String {
+		self.0.stop()
+	}
+}
+
+/// Watches the save directory for saves that were not there when watching started, such as the
+/// first save of a new campaign, for as long as it is kept.
+pub struct NewSaves {
+	/// Dropping it ends the thread reporting new saves.
+	_watcher: RecommendedWatcher,
+}
+
+impl NewSaves {
+	/// Starts watching, calling `found` from a thread of its own with the name of each new save, once
+	/// per save. The game's autosave is left out, as it is not a campaign of its own.
+	pub fn watch(
+		profile: &Profile,
+		found: impl Fn(String) + Send + 'static,
+	) -> crate::error::Result<Self> {
+		let mut known = save_names(profile)?.into_iter().collect::<HashSet<_>>();
+		known.insert(AUTOSAVE.to_string());
+
+		let (tx, rx) = mpsc::channel();
+		let mut watcher = notify::watcher(tx, NEW_SAVE_DELAY)?;
+		let mode = if profile.recursive {
+			RecursiveMode::Recursive
+		} else {
+			RecursiveMode::NonRecursive
+		};
+		watcher.watch(&profile.save_path, mode)?;
+
+		let profile = profile.clone();
+		thread::spawn(move || {
+			// ends once the watcher is dropped, along with the sending end
+			for event in rx {
+				let path = match event {
+					DebouncedEvent::Create(path) | DebouncedEvent::Rename(_, path) => path,
+					_ => continue,
+				};
+				if let Some(save) = save_name(&profile, &path) {
+					if known.insert(save.clone()) {
+						info!("{}: new save found", save);
+						found(save);
+					}
+				}
+			}
+		});
+
+		Ok(Self { _watcher: watcher })
+	}
+}
+
+/// Names of the saves in the save directory, without the game's own `.bak` copies.
+fn save_names(profile: &Profile) -> io::Result<Vec<String>> {
+	Ok(profile
+		.files()?
+		.iter()
+		.filter_map(|path| save_name(profile, path))
+		.collect())
+}
+
+/// Name of the save a file in the save directory holds, if it is a save of the game. Backups
+/// staged for comparing in-game are copies rather than saves of their own.
+fn save_name(profile: &Profile, path: &Path) -> Option<String> {
+	if !path.is_file() {
+		return None;
+	}
+
+	profile.save_name(path).filter(|save| {
+		!profile.is_ironman_copy(save) && !store::is_staged_copy(&profile.backup_path, save)
+	})
+}
+
+/// Sets up backing up saves first written while a session covering every save runs.
+fn discover(config: Ini, profile: Profile, paused: Arc<AtomicBool>) -> Discover {
+	Box::new(move |path| {
+		let save = save_name(&profile, path)?;
+		match Target::new(&config, &profile, &save, &paused) {
+			Ok(target) => {
+				info!("{}: new save found, backing it up automatically", save);
+				Some(target)
+			}
+			Err(e) => {
+				error!("{}: could not set up backups: {}", save, e);
+				None
+			}
+		}
+	})
+}
+
+/// A watched save file and the folder its backups go to.
+#[derive(Clone)]
+pub struct Target {
+	pub name: String,
+	pub backup_dir: PathBuf,
+	pub alerts: Preferences,
+	pub options: BackupOptions,
+	/// Which of the automatic backups to keep.
+	pub retention: Policy,
+	/// Whether the backups the policy does not keep are deleted after each new one.
+	pub retention_trigger: Trigger,
+	/// When the save was last written as of its latest backup, if one was taken before watching.
+	pub backed_up: Option<SystemTime>,
+	/// Set while the save directory cannot be reached, holding off backups until it returns.
+	pub paused: Arc<AtomicBool>,
+	/// Whether the save is an ironman save, which the game holds locked while writing it.
+	pub ironman: bool,
+}
+
+impl Target {
+	/// Sets up backing up a save as configured for it, creating its backup folder if needed.
+	pub fn new(
+		config: &Ini,
+		profile: &Profile,
+		save: &str,
+		paused: &Arc<AtomicBool>,
+	) -> io::Result<Self> {
+		let backup_dir = profile.backup_path.join(save);
+		if !backup_dir.is_dir() {
+			fs::create_dir_all(&backup_dir)?;
+		}
+
+		Ok(Self {
+			name: save.to_string(),
+			backup_dir,
+			alerts: Preferences::load(config, save),
+			options: backup_options(config, save),
+			retention: retention_policy(config, save),
+			retention_trigger: retention_trigger(config, save),
+			backed_up: None,
+			paused: Arc::clone(paused),
+			ironman: profile.ironman,
+		})
+	}
+}
+
+/// What the watchdog of a session needs to pause and resume it.
+struct Watchdog {
+	save_path: PathBuf,
+	/// Saves or directories the watcher is pointed at again once they return, with how each is
+	/// watched, empty when the session only uses timers.
+	watched: Vec<(PathBuf, RecursiveMode)>,
+	watcher: Arc<Mutex<RecommendedWatcher>>,
+	alerts: Preferences,
+	paused: Arc<AtomicBool>,
+}
+
+/// Keeps checking that the save directory can be reached, which network drives do not always
+/// guarantee. Backups are paused with an alert while it is gone and resume once it is back,
+/// rather than failing and stopping the session.
+fn spawn_watchdog(watchdog: Watchdog, stop: Arc<AtomicBool>) {
+	thread::spawn(move || {
+		let directory = watchdog.save_path.display().to_string();
+
+		while !stop.load(Ordering::Relaxed) {
+			thread::sleep(WATCHDOG_INTERVAL);
+			let reachable = watchdog.save_path.is_dir();
+			let paused = watchdog.paused.load(Ordering::Relaxed);
+
+			if !reachable && !paused {
+				watchdog.paused.store(true, Ordering::Relaxed);
+				error!(
+					event = "save_dir_lost";
+					"{} can no longer be reached, automatic backups are paused until it returns",
+					directory
+				);
+				watchdog.alerts.notify(
+					"Save folder unreachable",
+					&format!("{} is gone, automatic backups are paused", directory),
+				);
+			} else if reachable && paused {
+				// the old watches died with the directory, and the saves may not be back yet
+				let mut watcher = watchdog.watcher.lock().unwrap();
+				let rewatched = watchdog.watched.iter().all(|(path, mode)| {
+					let _ = watcher.unwatch(path);
+					watcher.watch(path, *mode).is_ok()
+				});
+				drop(watcher);
+				if !rewatched {
+					continue;
+				}
+
+				watchdog.paused.store(false, Ordering::Relaxed);
+				info!(
+					event = "save_dir_restored";
+					"{} can be reached again, automatic backups resumed",
+					directory
+				);
+				watchdog.alerts.notify(
+					"Save folder back",
+					&format!("{} is back, automatic backups resumed", directory),
+				);
+			}
+		}
+	});
+}
+
+/// Backs up the saves written while the game ran each time its process exits, so that every play
+/// session ends on a labelled backup. Saves first written after a session covering every save
+/// started are left out.
+fn spawn_exit_watch(
+	process: String,
+	targets: HashMap<PathBuf, Target>,
+	held: Arc<AtomicBool>,
+	stats: Arc<Mutex<SessionStats>>,
+	stop: Arc<AtomicBool>,
+) {
+	thread::spawn(move || {
+		// when the game was first seen running, as saves written before do not belong to the session
+		let mut playing_since = None;
+
+		while !stop.load(Ordering::Relaxed) {
+			thread::sleep(GAME_CHECK_INTERVAL);
+			let running = game::is_running(&process);
+
+			let since = match (running, playing_since) {
+				(true, None) => {
+					info!(
+						"{} is running, its saves are backed up when it exits",
+						process
+					);
+					playing_since = Some(SystemTime::now());
+					continue;
+				}
+				(false, Some(since)) => since,
+				_ => continue,
+			};
+			playing_since = None;
+
+			info!(event = "game_exited"; "{} exited", process);
+			if held.load(Ordering::Relaxed) {
+				info!("Automatic backups paused, end of session backup skipped");
+				continue;
+			}
+
+			for (file_path, target) in &targets {
+				if modified(file_path).is_none_or(|modified| modified < since) {
+					continue;
+				}
+
+				match backup_retrying(
+					file_path,
+					&target.backup_dir,
+					SESSION_END_NOTE,
+					Origin::SessionEnd,
+					&target.options,
+				) {
+					Ok(created) => {
+						info!(
+							"{}: backup number {} taken at the end of the session",
+							target.name, created.number
+						);
+						stats.lock().unwrap().record_backup(&created);
+						target.alerts.notify(
+							"Session backed up",
+							&format!(
+								"{}: backup number {} taken at the end of the session",
+								target.name, created.number
+							),
+						);
+					}
+					Err(e) => {
+						error!(
+							event = "backup_failed", save = target.name.as_str(), error_kind = e.kind().as_str();
+							"{}: end of session backup failed: {}",
+							target.name,
+							e
+						);
+						target
+							.alerts
+							.notify("Backup failed", &format!("{}: {}", target.name, e));
+					}
+				}
+			}
+		}
+	});
+}
+
+/// Watches the cloud copies of saves, given with their local save, and backs up both copies when
+/// the cloud sync writes a cloud copy that differs from the local save. A conflict the sync then
+/// settles the wrong way can be undone by restoring the copy that was lost.
+fn spawn_cloud_watch(
+	clouds: Vec<(PathBuf, PathBuf, Target)>,
+	held: Arc<AtomicBool>,
+	stats: Arc<Mutex<SessionStats>>,
+	stop: Arc<AtomicBool>,
+) {
+	thread::spawn(move || {
+		// copies already there when the session started are taken as settled
+		let mut seen = clouds
+			.iter()
+			.map(|(_, cloud_file, _)| modified(cloud_file))
+			.collect::<Vec<_>>();
+
+		while !stop.load(Ordering::Relaxed) {
+			thread::sleep(CLOUD_CHECK_INTERVAL);
+
+			for ((file_path, cloud_file, target), seen) in clouds.iter().zip(seen.iter_mut()) {
+				let written = modified(cloud_file);
+				if written.is_none() || written == *seen || held.load(Ordering::Relaxed) {
+					continue;
+				}
+				*seen = written;
+
+				let differs = store::hash_file(cloud_file)
+					.and_then(|cloud| Ok(cloud != store::hash_file(file_path)?));
+				match differs {
+					Ok(true) => {}
+					Ok(false) => continue,
+					Err(e) => {
+						warn!(
+							"{}: could not compare with its cloud copy: {}",
+							target.name, e
+						);
+						continue;
+					}
+				}
+
+				warn!(
+					event = "cloud_conflict", save = target.name.as_str();
+					"{}: the cloud copy differs from the local save, backing up both",
+					target.name
+				);
+				let backups = [
+					(file_path, "", Origin::Auto),
+					(cloud_file, CLOUD_NOTE, Origin::Cloud),
+				];
+				for (path, note, origin) in backups {
+					match backup_retrying(path, &target.backup_dir, note, origin, &target.options) {
+						Ok(created) => {
+							if !created.skipped {
+								stats.lock().unwrap().record_backup(&created);
+							}
+						}
+						Err(e) => {
+							error!(
+								event = "backup_failed", save = target.name.as_str(), error_kind = e.kind().as_str();
+								"{}: could not back up {}: {}",
+								target.name,
+								path.display(),
+								e
+							);
+						}
+					}
+				}
+				target.alerts.notify(
+					"Cloud copy differs",
+					&format!(
+						"{}: the cloud copy differs from the local save, both were backed up",
+						target.name
+					),
+				);
+			}
+		}
+	});
+}
+
+/// When a file was last written, if that can be found out.
+fn modified(path: &Path) -> Option<SystemTime> {
+	fs::metadata(path)
+		.and_then(|metadata| metadata.modified())
+		.ok()
+}
+
+/// Waits for the game to let go of an ironman save it holds locked, checking as many times as
+/// `retry` allows. Returns what is left of `retry` for the backup itself, or nothing when the save
+/// is still locked.
+fn wait_unlocked(file_path: &Path, name: &str, mut retry: LockRetry) -> Option<LockRetry> {
+	let unlocked = retry.run(
+		name,
+		|_: &()| true,
+		|| {
+			if store::is_locked(file_path) {
+				Err(())
+			} else {
+				Ok(())
+			}
+		},
+	);
+
+	unlocked.ok().map(|()| retry)
+}
+
+/// Asks for a backup of a save at a fixed interval, the same way a write seen by the watcher
+/// does. The worker skips saves unchanged since their last backup, so a write caught by both
+/// the watcher and the timer is only backed up once.
+fn spawn_timer(
+	file_path: PathBuf,
+	interval: Duration,
+	events: Sender<DebouncedEvent>,
+	stop: Arc<AtomicBool>,
+) {
+	thread::spawn(move || {
+		let mut next = Instant::now() + interval;
+
+		while !stop.load(Ordering::Relaxed) {
+			// wake up often enough to notice the session stopping
+			thread::sleep(Duration::from_secs(1));
+			if Instant::now() < next {
+				continue;
+			}
+
+			next += interval;
+			// a save not written yet, such as the game's autosave early in a campaign, is not
+			// asked for until it is
+			if !file_path.is_file() {
+				continue;
+			}
+			if events
+				.send(DebouncedEvent::Write(file_path.clone()))
+				.is_err()
+			{
+				break;
+			}
+		}
+	});
+}
+
+/// Routes watcher events to one worker thread per save file, so that saves written at nearly the
+/// same time are backed up in parallel instead of queueing behind each other. Files without a
+/// target are given one by `discover`, when set. Events are dropped while `held` is set.
+fn spawn_dispatcher(
+	events: Receiver<DebouncedEvent>,
+	mut targets: HashMap<PathBuf, Target>,
+	discover: Option<Discover>,
+	held: Arc<AtomicBool>,
+	stats: Arc<Mutex<SessionStats>>,
+) {
+	thread::spawn(move || {
+		let mut workers: HashMap<PathBuf, Sender<()>> = HashMap::new();
+
+		loop {
+			let path = match events.recv() {
+				// a save written for the first time shows up as created rather than written
+				Ok(DebouncedEvent::Write(path))
+				| Ok(DebouncedEvent::Create(path))
+				| Ok(DebouncedEvent::Rename(_, path)) => path,
+				Ok(_) => continue,
+				Err(e) => {
+					warn!("{}", e);
+					if let Some(target) = targets.values().next() {
+						target.alerts.notify(
+							"Automatic backups stopped",
+							&format!("Saves are no longer watched: {}", e),
+						);
+					}
+					break;
+				}
+			};
+
+			if !targets.contains_key(&path) {
+				match discover.as_ref().and_then(|discover| discover(&path)) {
+					Some(target) => {
+						targets.insert(path.clone(), target);
+					}
+					None => continue,
+				}
+			}
+			let target = &targets[&path];
+			if held.load(Ordering::Relaxed) {
+				info!("{}: automatic backups paused, backup skipped", target.name);
+				continue;
+			}
+
+			// a worker runs for as long as the session, whatever became of earlier backups
+			let _ = workers
+				.entry(path.clone())
+				.or_insert_with(|| spawn_worker(path.clone(), target, Arc::clone(&stats)))
+				.send(());
+		}
+	});
+}
+
+/// Backs up one save each time it is written, applying the retention policy after each backup when
+/// its trigger says so.
+pub struct Worker {
+	file_path: PathBuf,
+	name: String,
+	backup_dir: PathBuf,
+	alerts: Preferences,
+	options: BackupOptions,
+	retention: Policy,
+	retention_trigger: Trigger,
+	backed_up: Option<SystemTime>,
+	paused: Arc<AtomicBool>,
+	ironman: bool,
+	stats: Arc<Mutex<SessionStats>>,
+}
+
+impl Worker {
+	pub fn new(file_path: PathBuf, target: &Target, stats: Arc<Mutex<SessionStats>>) -> Self {
+		Self {
+			file_path,
+			name: target.name.clone(),
+			backup_dir: target.backup_dir.clone(),
+			alerts: target.alerts.clone(),
+			options: target.options.clone(),
+			retention: target.retention,
+			retention_trigger: target.retention_trigger,
+			backed_up: target.backed_up,
+			paused: Arc::clone(&target.paused),
+			ironman: target.ironman,
+			stats,
+		}
+	}
+
+	/// Handles the game writing the save. Returns whether the save was backed up or needed no
+	/// backup, rather than the backup failing.
+	///
+	/// A failed backup is logged and reported, and the next write is handled as usual: automatic
+	/// sessions go on regardless, while the simulation stops at the first failure.
+	pub fn on_write(&mut self) -> bool {
+		let name = &self.name;
+		if self.paused.load(Ordering::Relaxed) {
+			info!("{}: save folder unreachable, backup skipped", name);
+			return true;
+		}
+
+		let modified = modified(&self.file_path);
+		if modified.is_some() && modified == self.backed_up {
+			info!("{}: unchanged since the last backup, skipped", name);
+			return true;
+		}
+		// waiting for the lock and retrying the backup share a single budget of tries
+		let lock_retry = if self.ironman {
+			match wait_unlocked(&self.file_path, name, self.options.lock_retry) {
+				Some(left) => left,
+				None => {
+					warn!("{}: still locked by the game, backup skipped", name);
+					return true;
+				}
+			}
+		} else {
+			self.options.lock_retry
+		};
+		info!("{}: backing up", name);
+
+		let options = BackupOptions {
+			lock_retry,
+			..self.options.clone()
+		};
+		match backup_retrying(
+			&self.file_path,
+			&self.backup_dir,
+			"",
+			Origin::Auto,
+			&options,
+		) {
+			Ok(created) if created.skipped => self.backed_up = modified,
+			Ok(created) => {
+				self.backed_up = modified;
+				info!(
+					"{}: backup number {} finished ({})",
+					name,
+					created.number,
+					format_size(created.size)
+				);
+				self.stats.lock().unwrap().record_backup(&created);
+				self.alerts.notify(
+					"Backup created",
+					&format!("{}: backup number {} created", name, created.number),
+				);
+				if let Some(anomaly) = &created.anomaly {
+					self.alerts.notify(
+						"Unusual save size",
+						&format!("{}: {}", name, anomaly.describe()),
+					);
+				}
+
+				// a failure to clean up leaves extra backups behind, so keep backing up
+				if let Some(Err(e)) = retention::run(
+					&self.backup_dir,
+					self.retention,
+					self.retention_trigger,
+					&self.options.keep_tags,
+					Moment::AfterBackup,
+				) {
+					warn!("{}: could not apply the retention policy: {}", name, e);
+					self.alerts.notify(
+						"Old backups not deleted",
+						&format!("{}: could not apply the retention policy: {}", name, e),
+					);
+				}
+			}
+			Err(e) => {
+				error!(
+					event = "backup_failed", save = name.as_str(), error_kind = e.kind().as_str();
+					"{}: {}",
+					name,
+					e
+				);
+				self.alerts
+					.notify("Backup failed", &format!("{}: {}", name, e));
+				self.stats
+					.lock()
+					.unwrap()
+					.failures
+					.push(format!("{}: {}", name, e));
+				return false;
+			}
+		}
+
+		true
+	}
+}
+
+fn spawn_worker(
+	file_path: PathBuf,
+	target: &Target,
+	stats: Arc<Mutex<SessionStats>>,
+) -> Sender<()> {
+	let (tx, rx) = mpsc::channel();
+	let mut worker = Worker::new(file_path, target, stats);
+
+	thread::spawn(move || {
+		// a failed backup, such as of a save the game kept open too long, is logged and reported
+		// by the worker, and the next write is backed up as usual
+		for () in rx {
+			worker.on_write();
+		}
+	});
+
+	tx
+}
@@ -0,0 +1,864 @@
+//! Takes, finds and restores backups through the library, without the menu.
+
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::io::{self, BufRead as _, BufReader, Read as _, Write as _};
+use std::net::{TcpListener, TcpStream};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+
+use save_manager::abandoned;
+use save_manager::archive::Archive;
+use save_manager::audit::{Report, Write};
+use save_manager::clock;
+use save_manager::lost;
+use save_manager::manifest::{Manifest, Origin};
+use save_manager::profiles::Profile;
+use save_manager::retention::Policy;
+use save_manager::schedule::{Schedule, Task};
+use save_manager::settings;
+use save_manager::share;
+use save_manager::snapshot::Snapshot;
+use save_manager::store::{self, BackupOptions, IdScheme, LockRetry};
+use save_manager::sync::{self, Remote};
+use save_manager::watch::{self, NewSaves};
+use save_manager::{format_duration, format_size, BackupStore, Watcher};
+
+use chrono::{NaiveDate, NaiveTime};
+
+use ini::Ini;
+
+use zip::write::SimpleFileOptions;
+use zip::ZipWriter;
+
+/// A folder of its own for each test, as the tests run in parallel.
+fn scratch(name: &str) -> PathBuf {
+	static COUNT: AtomicUsize = AtomicUsize::new(0);
+
+	let dir = env::temp_dir().join(format!(
+		"save-manager-library-{}-{}-{}",
+		name,
+		std::process::id(),
+		COUNT.fetch_add(1, Ordering::Relaxed)
+	));
+	let _ = fs::remove_dir_all(&dir);
+	fs::create_dir_all(&dir).unwrap();
+	dir
+}
+
+fn save_text(day: u32) -> String {
+	format!("CK2txt\nversion=\"2.8.3.4\"\ndate=\"1066.9.{}\"\n}}\n", day)
+}
+
+#[test]
+fn backs_up_and_lists_in_order() {
+	let dir = scratch("list");
+	let save = dir.join("Ironman.ck2");
+	let store = BackupStore::open(dir.join("backups").join("Ironman")).unwrap();
+
+	fs::write(&save, save_text(1)).unwrap();
+	let first = store.back_up(&save, "start", Origin::Cli).unwrap();
+	fs::write(&save, save_text(2)).unwrap();
+	let second = store.back_up(&save, "", Origin::Cli).unwrap();
+
+	let backups = store.backups().unwrap();
+	assert_eq!(
+		backups
+			.iter()
+			.map(|backup| backup.number)
+			.collect::<Vec<_>>(),
+		vec![first.number, second.number]
+	);
+	assert_eq!(backups[0].note, "start");
+	assert_eq!(fs::read_to_string(&backups[1].path).unwrap(), save_text(2));
+
+	fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn skips_an_unchanged_save() {
+	let dir = scratch("skip");
+	let save = dir.join("Ironman.ck2");
+	let store = BackupStore::open(dir.join("backups").join("Ironman")).unwrap();
+
+	fs::write(&save, save_text(1)).unwrap();
+	let first = store.back_up(&save, "", Origin::Cli).unwrap();
+	let again = store.back_up(&save, "", Origin::Cli).unwrap();
+
+	assert!(!first.skipped);
+	assert!(again.skipped);
+	assert_eq!(again.number, first.number);
+	assert_eq!(store.backups().unwrap().len(), 1);
+
+	fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn refuses_a_note_that_cannot_be_a_file_name() {
+	let dir = scratch("note");
+	let save = dir.join("Ironman.ck2");
+	let store = BackupStore::open(dir.join("backups").join("Ironman")).unwrap();
+
+	fs::write(&save, save_text(1)).unwrap();
+	assert!(store.back_up(&save, "a/b", Origin::Cli).is_err());
+	// the menu and the command line back up without going through the store
+	assert!(store::backup_core(
+		&save,
+		store.path(),
+		"before: the war",
+		Origin::Cli,
+		&BackupOptions::default()
+	)
+	.is_err());
+	assert!(store.backups().unwrap().is_empty());
+
+	fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn names_backups_with_their_number_anywhere_in_the_template() {
+	let dir = scratch("naming");
+	let save = dir.join("Ironman.ck2");
+	let store = BackupStore::open(dir.join("backups").join("Ironman"))
+		.unwrap()
+		.with_options(BackupOptions {
+			naming: Some("{num}_{save}_v{num}_{note}".to_string()),
+			..BackupOptions::default()
+		});
+
+	fs::write(&save, save_text(1)).unwrap();
+	store.back_up(&save, "start", Origin::Cli).unwrap();
+	fs::write(&save, save_text(2)).unwrap();
+	store.back_up(&save, "", Origin::Cli).unwrap();
+
+	let names = store
+		.backups()
+		.unwrap()
+		.into_iter()
+		.map(|backup| backup.file_name)
+		.collect::<Vec<_>>();
+	assert_eq!(names, vec!["1_Ironman_v1_start", "2_Ironman_v2"]);
+
+	fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn finds_backups_by_number_and_id() {
+	let dir = scratch("find");
+	let save = dir.join("Ironman.ck2");
+	let store = BackupStore::open(dir.join("backups").join("Ironman")).unwrap();
+
+	fs::write(&save, save_text(1)).unwrap();
+	let created = store.back_up(&save, "", Origin::Cli).unwrap();
+
+	let by_number = store.find(&created.number.to_string()).unwrap();
+	let id = by_number.id(&store.manifest()).unwrap();
+	let by_id = store.find(&id).unwrap();
+	assert_eq!(by_id.number, created.number);
+	assert!(store.find("999").is_err());
+
+	fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn restores_and_undoes_the_restore() {
+	let dir = scratch("restore");
+	let save = dir.join("Ironman.ck2");
+	let store = BackupStore::open(dir.join("backups").join("Ironman")).unwrap();
+
+	fs::write(&save, save_text(1)).unwrap();
+	let created = store.back_up(&save, "", Origin::Cli).unwrap();
+	fs::write(&save, save_text(2)).unwrap();
+
+	let backup = store.find(&created.number.to_string()).unwrap();
+	let kept = store.restore(&backup, &save).unwrap();
+	assert!(kept.is_some());
+	assert_eq!(fs::read_to_string(&save).unwrap(), save_text(1));
+
+	// a second restore right after keeps the save it replaced as well, next to the first
+	fs::write(&save, save_text(3)).unwrap();
+	let kept_again = store.restore(&backup, &save).unwrap();
+	assert_ne!(kept_again, kept);
+	assert_eq!(fs::read_to_string(kept.unwrap()).unwrap(), save_text(2));
+	assert_eq!(
+		fs::read_to_string(kept_again.unwrap()).unwrap(),
+		save_text(3)
+	);
+
+	store.undo_restore(&save).unwrap();
+	assert_eq!(fs::read_to_string(&save).unwrap(), save_text(3));
+
+	// nothing is left over next to the save from replacing it
+	let beside = fs::read_dir(&dir)
+		.unwrap()
+		.filter_map(Result::ok)
+		.filter(|entry| entry.path().is_file())
+		.count();
+	assert_eq!(beside, 1);
+
+	fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn undoes_a_restore_kept_beside_the_save() {
+	let dir = scratch("kept-beside");
+	let save = dir.join("Ironman.ck2");
+	let store = BackupStore::open(dir.join("backups").join("Ironman")).unwrap();
+
+	fs::write(&save, save_text(1)).unwrap();
+	store.back_up(&save, "", Origin::Cli).unwrap();
+	// as kept when the backup folder could not be written to
+	fs::write(dir.join("Ironman.ck2.pre-restore"), save_text(2)).unwrap();
+
+	store.undo_restore(&save).unwrap();
+	assert_eq!(fs::read_to_string(&save).unwrap(), save_text(2));
+
+	fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn imports_a_lost_save_among_the_backups_of_its_name() {
+	let dir = scratch("lost");
+	let profile = Profile::builtin(dir.join("save games"));
+	let store = BackupStore::open(profile.backup_path.join("Ironman")).unwrap();
+	let save = profile.save_file("Ironman");
+	fs::create_dir_all(&profile.save_path).unwrap();
+	fs::write(&save, save_text(1)).unwrap();
+	store.back_up(&save, "", Origin::Cli).unwrap();
+
+	let downloads = dir.join("Downloads");
+	fs::create_dir_all(&downloads).unwrap();
+	fs::write(downloads.join("Ironman.ck2"), save_text(2)).unwrap();
+	let found = lost::scan(std::slice::from_ref(&dir), &profile).unwrap();
+	assert_eq!(found.len(), 1);
+
+	let mut config = Ini::new();
+	settings::set_current_save(&mut config, "Ironman");
+	let (imported, created) = lost::import(&config, &profile, &found[0].path).unwrap();
+	assert_eq!(imported, "Ironman");
+	let backups = store.backups().unwrap();
+	assert_eq!(backups.len(), 2);
+	assert_eq!(backups[1].number, created.number);
+	assert_eq!(fs::read_to_string(&backups[1].path).unwrap(), save_text(2));
+
+	fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn retention_only_deletes_automatic_backups() {
+	let dir = scratch("retention");
+	let save = dir.join("Ironman.ck2");
+	let store = BackupStore::open(dir.join("backups").join("Ironman")).unwrap();
+
+	fs::write(&save, save_text(1)).unwrap();
+	let manual = store.back_up(&save, "", Origin::Cli).unwrap();
+	for day in 2..6 {
+		fs::write(&save, save_text(day)).unwrap();
+		store.back_up(&save, "", Origin::Auto).unwrap();
+	}
+	let newest = store.backups().unwrap().last().unwrap().number;
+
+	let deleted = store.apply_retention(Policy::Last(1), &[]).unwrap();
+	assert_eq!(deleted, 3);
+	assert_eq!(
+		store
+			.backups()
+			.unwrap()
+			.iter()
+			.map(|backup| backup.number)
+			.collect::<Vec<_>>(),
+		vec![manual.number, newest]
+	);
+
+	fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn writers_sharing_a_folder_get_their_own_numbers() {
+	let dir = scratch("shared");
+	let backup_dir = dir.join("backups").join("Ironman");
+
+	let writers = ["anna@living-room", "ben@laptop"]
+		.iter()
+		.map(|author| {
+			let save = dir.join(format!("{}.ck2", author));
+			let store = BackupStore::open(&backup_dir)
+				.unwrap()
+				.with_options(BackupOptions {
+					author: author.to_string(),
+					..BackupOptions::default()
+				});
+			thread::spawn(move || {
+				for day in 1..6 {
+					fs::write(&save, format!("{}{}", save_text(day), author)).unwrap();
+					store.back_up(&save, "", Origin::Auto).unwrap();
+				}
+			})
+		})
+		.collect::<Vec<_>>();
+	for writer in writers {
+		writer.join().unwrap();
+	}
+
+	let store = BackupStore::open(&backup_dir).unwrap();
+	let manifest = store.manifest();
+	let backups = store.backups().unwrap();
+	assert_eq!(
+		backups
+			.iter()
+			.map(|backup| backup.number)
+			.collect::<Vec<_>>(),
+		(1..=10).collect::<Vec<_>>()
+	);
+	for author in &["anna@living-room", "ben@laptop"] {
+		let taken = backups
+			.iter()
+			.filter(|backup| backup.author(&manifest).as_deref() == Some(author))
+			.count();
+		assert_eq!(taken, 5);
+	}
+
+	fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn tells_how_far_back_a_restore_goes() {
+	let dir = scratch("rewind");
+	let save = dir.join("Ironman.ck2");
+	let store = BackupStore::open(dir.join("backups").join("Ironman")).unwrap();
+
+	fs::write(&save, save_text(1)).unwrap();
+	let created = store.back_up(&save, "", Origin::Cli).unwrap();
+	let backup = store.find(&created.number.to_string()).unwrap();
+	assert!(store::rewind(&backup, &save).is_none());
+
+	fs::write(&save, save_text(5)).unwrap();
+	let rewind = store::rewind(&backup, &save).unwrap();
+	assert!(rewind.written.is_none());
+	assert_eq!(
+		rewind.describe(),
+		"4 in-game days (1066.9.5 in the save, 1066.9.1 in the backup)"
+	);
+
+	fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn rolls_back_metadata_from_a_snapshot() {
+	let dir = scratch("snapshot");
+	let save = dir.join("Ironman.ck2");
+	let backup_path = dir.join("backups");
+	let store = BackupStore::open(backup_path.join("Ironman")).unwrap();
+
+	fs::write(&save, save_text(1)).unwrap();
+	let created = store.back_up(&save, "start", Origin::Cli).unwrap();
+	let backup = store.find(&created.number.to_string()).unwrap();
+	let mut manifest = store.manifest();
+	manifest.set_tags(&backup.file_name, &["crusade".to_string()]);
+	manifest.save().unwrap();
+
+	let snapshot = Snapshot::take(&backup_path, "testing").unwrap();
+	let mut manifest = store.manifest();
+	manifest.remove(&backup.file_name);
+	manifest.save().unwrap();
+
+	// an operation that never finished leaves its snapshot behind
+	drop(snapshot);
+	let leftover = Snapshot::leftover(&backup_path).unwrap();
+	assert_eq!(leftover.operation().unwrap(), "testing");
+	assert!(leftover.roll_back().unwrap() >= 1);
+	assert_eq!(store.manifest().tags(&backup.file_name), vec!["crusade"]);
+	assert!(Snapshot::leftover(&backup_path).is_none());
+
+	fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn refuses_archive_entries_outside_the_backup_folder() {
+	let dir = scratch("hostile-archive");
+	let backup_path = dir.join("backups");
+	fs::create_dir_all(&backup_path).unwrap();
+
+	let write_zip = |path: &PathBuf, entries: &[&str]| {
+		let mut zip = ZipWriter::new(fs::File::create(path).unwrap());
+		for entry in entries {
+			zip.start_file(*entry, SimpleFileOptions::default())
+				.unwrap();
+			zip.write_all(save_text(1).as_bytes()).unwrap();
+		}
+		zip.finish().unwrap();
+	};
+
+	for (i, entry) in [
+		"../escaped/1",
+		"Ironman/../../escaped/2",
+		"/tmp/escaped/3",
+		"..\\escaped\\4",
+		"Ironman/./5",
+	]
+	.iter()
+	.enumerate()
+	{
+		let path = dir.join(format!("hostile-{}.zip", i));
+		write_zip(&path, &[entry]);
+		let archive = Archive::open(&path).unwrap();
+
+		assert!(archive.backups().unwrap().is_empty(), "{}", entry);
+		assert!(
+			archive
+				.import(&backup_path, "Ironman", IdScheme::None)
+				.is_err(),
+			"{}",
+			entry
+		);
+	}
+	assert!(!dir.join("escaped").exists());
+	assert!(store::save_dirs(&backup_path).unwrap().is_empty());
+
+	// saves in folders of the archive still import into a folder of their own
+	let path = dir.join("friendly.zip");
+	write_zip(&path, &["exported/Ironman/1"]);
+	let archive = Archive::open(&path).unwrap();
+	assert_eq!(archive.import(&backup_path, "", IdScheme::None).unwrap(), 1);
+	assert_eq!(
+		store::list_backups(&backup_path.join("Ironman"))
+			.unwrap()
+			.len(),
+		1
+	);
+
+	fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn shares_settings_without_what_is_local_to_the_machine() {
+	let config = Ini::load_from_str(
+		"current=Ironman\nauthor=anna@living-room\ncompress=true\ncold_storage_folder=/mnt/usb\n\n[game.ck2]\nsave_dir=/home/anna/saves\nextension=.ck2\ncloud_dir=/home/anna/cloud\n",
+	)
+	.unwrap();
+
+	let snippet = share::export(&config, "ck2");
+	for local in &[
+		"current",
+		"author",
+		"cold_storage_folder",
+		"save_dir",
+		"cloud_dir",
+	] {
+		assert!(!snippet.contains(local), "{} in {}", local, snippet);
+	}
+
+	let mut imported = Ini::new();
+	assert_eq!(share::import(&mut imported, &snippet).unwrap(), 2);
+	assert_eq!(imported.general_section().get("compress"), Some("true"));
+	assert_eq!(
+		imported.get_from(Some("game.ck2"), "extension"),
+		Some(".ck2")
+	);
+}
+
+/// A remote keeping what is put to it in memory and answering `GET` with it, at the address
+/// returned.
+fn serve_remote() -> String {
+	let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+	let url = format!("http://{}", listener.local_addr().unwrap());
+	let files = Arc::new(Mutex::new(HashMap::new()));
+	thread::spawn(move || {
+		for stream in listener.incoming().filter_map(Result::ok) {
+			let files = Arc::clone(&files);
+			thread::spawn(move || answer(stream, &files));
+		}
+	});
+	url
+}
+
+fn answer(mut stream: TcpStream, files: &Mutex<HashMap<String, Vec<u8>>>) {
+	let mut reader = BufReader::new(stream.try_clone().unwrap());
+	loop {
+		let mut request = String::new();
+		if reader.read_line(&mut request).unwrap_or(0) == 0 {
+			return;
+		}
+		let mut length = 0;
+		loop {
+			let mut header = String::new();
+			reader.read_line(&mut header).unwrap();
+			match header.trim_end().split_once(':') {
+				Some((name, value)) if name.eq_ignore_ascii_case("content-length") => {
+					length = value.trim().parse().unwrap();
+				}
+				Some(_) => {}
+				None => break,
+			}
+		}
+		let mut body = vec![0; length];
+		reader.read_exact(&mut body).unwrap();
+
+		let mut words = request.split_whitespace();
+		let (method, path) = (words.next().unwrap(), words.next().unwrap().to_string());
+		let found = if method == "PUT" {
+			files.lock().unwrap().insert(path, body);
+			Some(Vec::new())
+		} else {
+			files.lock().unwrap().get(&path).cloned()
+		};
+		let (status, body) = found.map_or(("404 Not Found", Vec::new()), |body| ("200 OK", body));
+		write!(
+			stream,
+			"HTTP/1.1 {}\r\nContent-Length: {}\r\n\r\n",
+			status,
+			body.len()
+		)
+		.unwrap();
+		stream.write_all(&body).unwrap();
+	}
+}
+
+#[test]
+fn pulls_what_another_machine_pushed() {
+	let dir = scratch("sync");
+	let config =
+		Ini::load_from_str(&format!("[sync]\nbackend=http\nurl={}\n", serve_remote())).unwrap();
+	let remote = Remote::load(&config).unwrap().unwrap();
+
+	let save = dir.join("Ironman.ck2");
+	let (here, there) = (dir.join("here"), dir.join("there"));
+	let store = BackupStore::open(here.join("Ironman")).unwrap();
+	fs::write(&save, save_text(1)).unwrap();
+	store.back_up(&save, "start", Origin::Cli).unwrap();
+	fs::write(&save, save_text(2)).unwrap();
+	store.back_up(&save, "", Origin::Cli).unwrap();
+	fs::write(here.join("journal.txt"), "backup 1\n").unwrap();
+	fs::create_dir_all(here.join("Ironman").join("trash")).unwrap();
+	fs::write(here.join("Ironman").join("trash").join("9.ck2"), "").unwrap();
+	sync::push(&remote, &here).unwrap();
+
+	let pulled = sync::pull(&remote, &there).unwrap();
+	assert_eq!(pulled.files, 3);
+	let names = |folder: &PathBuf| {
+		BackupStore::open(folder.join("Ironman"))
+			.unwrap()
+			.backups()
+			.unwrap()
+			.into_iter()
+			.map(|backup| backup.file_name)
+			.collect::<Vec<_>>()
+	};
+	assert_eq!(names(&there), names(&here));
+	assert!(!there.join("journal.txt").exists());
+	assert!(!there.join("Ironman").join("trash").exists());
+	// what was pulled is not pushed back
+	assert_eq!(sync::push(&remote, &there).unwrap().files, 0);
+
+	// a backup taken later is recorded in the manifest pulled the first time
+	fs::write(&save, save_text(3)).unwrap();
+	let third = store.back_up(&save, "", Origin::Cli).unwrap();
+	sync::push(&remote, &here).unwrap();
+	assert_eq!(sync::pull(&remote, &there).unwrap().files, 1);
+	let name = names(&there).pop().unwrap();
+	assert!(name.starts_with(&third.number.to_string()));
+	assert!(Manifest::load(&there.join("Ironman"))
+		.backups()
+		.contains(&name));
+
+	fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn suggests_a_debounce_from_audited_writes() {
+	let start = chrono::Local::now();
+	let write = |seconds: i64, took: u64, hash: &str| Write {
+		at: start + chrono::Duration::seconds(seconds),
+		took: Duration::from_millis(took),
+		events: 3,
+		size: 1000,
+		hash: hash.to_string(),
+	};
+	let report = Report {
+		save: "Ironman.ck2".to_string(),
+		writes: vec![
+			write(0, 300, "a"),
+			write(60, 1800, "b"),
+			write(120, 200, "b"),
+		],
+	};
+
+	// the longest write with time to settle, rounded up to whole seconds
+	assert_eq!(report.suggested_debounce(), Some(Duration::from_secs(3)));
+	let described = report.describe();
+	assert!(described.contains("3 writes of Ironman.ck2"));
+	assert!(described.contains("1 writes left the save as it was"));
+
+	let idle = Report {
+		save: "Ironman.ck2".to_string(),
+		writes: Vec::new(),
+	};
+	assert_eq!(idle.suggested_debounce(), None);
+}
+
+#[test]
+fn shows_saves_by_their_label() {
+	let mut config = Ini::new();
+	settings::set_current_save(&mut config, "ironman_autosave_2");
+	assert_eq!(
+		settings::display_name(&config, "ironman_autosave_2"),
+		"ironman_autosave_2"
+	);
+
+	settings::set_save_label(
+		&mut config,
+		"ironman_autosave_2",
+		" Ireland 867 - Attempt 2 ",
+	);
+	assert_eq!(
+		settings::display_name(&config, "ironman_autosave_2"),
+		"Ireland 867 - Attempt 2"
+	);
+	// a label is never taken from the general section for every save
+	assert_eq!(settings::save_label(&config, "other"), None);
+
+	settings::set_save_label(&mut config, "ironman_autosave_2", "");
+	assert_eq!(settings::save_label(&config, "ironman_autosave_2"), None);
+}
+
+#[test]
+fn stages_backups_next_to_the_save_and_removes_them_again() {
+	let dir = scratch("stage");
+	let save = dir.join("Ironman.ck2");
+	let backup_dir = dir.join("backups").join("Ironman");
+	let store = BackupStore::open(&backup_dir).unwrap();
+
+	fs::write(&save, save_text(1)).unwrap();
+	let first = store.back_up(&save, "", Origin::Cli).unwrap();
+	fs::write(&save, save_text(2)).unwrap();
+	let second = store.back_up(&save, "", Origin::Cli).unwrap();
+
+	let backups = store.backups().unwrap();
+	assert_eq!(store::stage_backups(&save, &backups).unwrap(), 2);
+	// staging again leaves the copies already there alone
+	assert_eq!(store::stage_backups(&save, &backups).unwrap(), 0);
+	let first_copy = dir.join(format!("Ironman_bk{}.ck2", first.number));
+	let second_copy = dir.join(format!("Ironman_bk{}.ck2", second.number));
+	assert_eq!(fs::read_to_string(&first_copy).unwrap(), save_text(1));
+	assert_eq!(fs::read_to_string(&second_copy).unwrap(), save_text(2));
+	let backup_path = dir.join("backups");
+	assert!(store::is_staged_copy(
+		&backup_path,
+		&format!("Ironman_bk{}", first.number)
+	));
+	// named like a staged copy, but of a backup that does not exist
+	assert!(!store::is_staged_copy(
+		&backup_path,
+		&format!("Ironman_bk{}", second.number + 1)
+	));
+	assert!(!store::is_staged_copy(&backup_path, "Journey_bk1"));
+	assert!(!store::is_staged_copy(&backup_path, "Ironman_bk"));
+	assert!(!store::is_staged_copy(&backup_path, "Ironman"));
+
+	// played on in-game, so it is a save of its own now
+	fs::write(&second_copy, save_text(3)).unwrap();
+	let (removed, kept) = store::remove_staged(&save, &backup_dir).unwrap();
+	assert_eq!(removed, 1);
+	assert_eq!(kept, vec![format!("Ironman_bk{}.ck2", second.number)]);
+	assert!(!first_copy.exists());
+	assert_eq!(fs::read_to_string(&save).unwrap(), save_text(2));
+
+	fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn reports_each_new_save_once() {
+	let dir = scratch("new-saves");
+	let mut profile = Profile::builtin(dir.join("save games"));
+	profile.ironman = true;
+	fs::create_dir_all(&profile.save_path).unwrap();
+	fs::write(profile.save_file("Known"), save_text(1)).unwrap();
+
+	let (tx, rx) = mpsc::channel();
+	let watch = NewSaves::watch(&profile, move |save| tx.send(save).unwrap()).unwrap();
+	fs::write(profile.save_file("Known"), save_text(2)).unwrap();
+	fs::write(profile.save_file(watch::AUTOSAVE), save_text(1)).unwrap();
+	fs::write(profile.save_file("Iron_Backup"), save_text(1)).unwrap();
+	fs::write(profile.save_file("Fresh"), save_text(1)).unwrap();
+	assert_eq!(rx.recv_timeout(Duration::from_secs(10)).unwrap(), "Fresh");
+
+	// saved over from scratch, it is still the same campaign
+	fs::remove_file(profile.save_file("Fresh")).unwrap();
+	fs::write(profile.save_file("Fresh"), save_text(2)).unwrap();
+	assert!(rx.recv_timeout(Duration::from_secs(5)).is_err());
+
+	drop(watch);
+	fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn retries_a_save_in_use_only_as_often_as_allowed() {
+	let mut retry = LockRetry {
+		attempts: 3,
+		delay: Duration::from_millis(1),
+	};
+	let mut tries = 0;
+	let result = retry.run(
+		"Ironman",
+		|e: &io::Error| e.kind() == io::ErrorKind::WouldBlock,
+		|| -> io::Result<()> {
+			tries += 1;
+			Err(io::ErrorKind::WouldBlock.into())
+		},
+	);
+	assert!(result.is_err());
+	assert_eq!(tries, 3);
+	assert_eq!(retry.attempts, 1);
+
+	// a missing save is no sharing violation, so it fails at once however long retries would wait
+	let dir = scratch("retry");
+	let options = BackupOptions {
+		lock_retry: LockRetry {
+			attempts: 5,
+			delay: Duration::from_secs(30),
+		},
+		..BackupOptions::default()
+	};
+	let started = Instant::now();
+	let missing = store::backup_retrying(
+		&dir.join("Ironman.ck2"),
+		&dir.join("backups").join("Ironman"),
+		"",
+		Origin::Cli,
+		&options,
+	);
+	assert!(missing.is_err());
+	assert!(started.elapsed() < Duration::from_secs(30));
+
+	fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn archives_campaigns_left_alone_since_the_cutoff() {
+	let dir = scratch("abandoned");
+	let profile = Profile::builtin(dir.join("save games"));
+	fs::create_dir_all(&profile.save_path).unwrap();
+	let weeks_ago = |weeks: u64| SystemTime::now() - Duration::from_secs(weeks * 7 * 24 * 60 * 60);
+	for (save, last_played) in &[
+		("Old", weeks_ago(10)),
+		("Recent", weeks_ago(1)),
+		("Working", weeks_ago(20)),
+	] {
+		let path = profile.save_file(save);
+		fs::write(&path, save_text(1)).unwrap();
+		BackupStore::open(profile.backup_path.join(save))
+			.unwrap()
+			.back_up(&path, "", Origin::Cli)
+			.unwrap();
+		fs::File::options()
+			.write(true)
+			.open(&path)
+			.unwrap()
+			.set_modified(*last_played)
+			.unwrap();
+	}
+
+	let mut config = Ini::load_from_str("abandoned_weeks=8\n").unwrap();
+	settings::set_current_save(&mut config, "Working");
+	let campaigns = abandoned::find(&config, &profile).unwrap();
+	let saves = campaigns
+		.iter()
+		.map(|campaign| campaign.save.as_str())
+		.collect::<Vec<_>>();
+	assert_eq!(saves, ["Old"]);
+
+	let cold = dir.join("cold");
+	let (archived, path) = abandoned::archive(&campaigns[0].backup_dir, "Old", &cold).unwrap();
+	assert_eq!(archived, 1);
+	assert_eq!(Archive::open(&path).unwrap().backups().unwrap().len(), 1);
+	assert!(store::list_backups(&campaigns[0].backup_dir)
+		.unwrap()
+		.is_empty());
+
+	// an archive that cannot be written leaves the backups where they are
+	let recent = profile.backup_path.join("Recent");
+	fs::write(
+		cold.join(format!(
+			"Recent backups {}.tar.zst",
+			clock::now().format("%Y-%m-%d")
+		)),
+		"",
+	)
+	.unwrap();
+	assert!(abandoned::archive(&recent, "Recent", &cold).is_err());
+	assert_eq!(store::list_backups(&recent).unwrap().len(), 1);
+
+	fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn formats_durations_and_sizes() {
+	assert_eq!(format_duration(Duration::from_millis(999)), "0h 00m 00s");
+	assert_eq!(format_duration(Duration::from_secs(3723)), "1h 02m 03s");
+	assert_eq!(
+		format_duration(Duration::from_secs(100 * 3600)),
+		"100h 00m 00s"
+	);
+
+	assert_eq!(format_size(0), "0 B");
+	assert_eq!(format_size(1023), "1023 B");
+	assert_eq!(format_size(1024), "1.0 KB");
+	assert_eq!(format_size(1536 * 1024), "1.5 MB");
+	// nothing goes past gigabytes
+	assert_eq!(format_size(2048 * 1024 * 1024 * 1024), "2048.0 GB");
+}
+
+#[test]
+fn backs_up_a_save_each_time_it_is_written() {
+	let dir = scratch("watcher");
+	let profile = Profile::builtin(dir.join("save games"));
+	fs::create_dir_all(&profile.save_path).unwrap();
+	let save = profile.save_file("Ironman");
+	fs::write(&save, save_text(1)).unwrap();
+	let store = BackupStore::open(profile.backup_path.join("Ironman")).unwrap();
+
+	let mut config = Ini::new();
+	settings::set_current_save(&mut config, "Ironman");
+	let watcher = Watcher::start(&config, &profile, "Ironman").unwrap();
+	assert!(!watcher.is_paused());
+	fs::write(&save, save_text(2)).unwrap();
+
+	// writes are gathered for a while before the backup is taken
+	let started = Instant::now();
+	while store.backups().unwrap().is_empty() && started.elapsed() < Duration::from_secs(30) {
+		thread::sleep(Duration::from_millis(100));
+	}
+	watcher.stop();
+	let backups = store.backups().unwrap();
+	assert_eq!(backups.len(), 1);
+	assert_eq!(fs::read_to_string(&backups[0].path).unwrap(), save_text(2));
+
+	fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn runs_a_scheduled_task_missed_while_asleep_once() {
+	let at = |day, hour, minute| {
+		NaiveDate::from_ymd_opt(2024, 3, day)
+			.unwrap()
+			.and_hms_opt(hour, minute, 0)
+			.unwrap()
+	};
+	let schedule = Schedule {
+		name: "nightly".to_string(),
+		task: Task::Verify,
+		at: NaiveTime::from_hms_opt(3, 0, 0).unwrap(),
+		days: Vec::new(),
+		day_of_month: None,
+	};
+
+	assert!(!schedule.is_due(at(1, 2, 0), at(1, 2, 59)));
+	assert!(schedule.is_due(at(1, 2, 59), at(1, 3, 0)));
+	assert!(!schedule.is_due(at(1, 3, 0), at(1, 3, 1)));
+	// asleep from before the run until two days later
+	assert!(schedule.is_due(at(1, 1, 0), at(3, 9, 0)));
+}
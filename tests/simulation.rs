@@ -0,0 +1,207 @@
+//! Plays synthetic histories through automatic backups and the retention policies with the
+//! `simulate` command, which runs on a simulated clock in a folder of its own.
+
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::process::{Command, Output};
+use std::slice;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use save_manager::manifest::Origin;
+use save_manager::profiles::Profile;
+use save_manager::store;
+use save_manager::BackupStore;
+
+/// A backup left after a simulation: its number, when it was taken and its origin.
+struct Kept {
+	number: usize,
+	created: String,
+	origin: String,
+}
+
+/// A folder of its own for each test, as the tests run in parallel.
+fn scratch(name: &str) -> PathBuf {
+	static COUNT: AtomicUsize = AtomicUsize::new(0);
+
+	let dir = env::temp_dir().join(format!(
+		"save-manager-test-{}-{}-{}",
+		name,
+		std::process::id(),
+		COUNT.fetch_add(1, Ordering::Relaxed)
+	));
+	let _ = fs::remove_dir_all(&dir);
+	fs::create_dir_all(&dir).unwrap();
+	dir
+}
+
+fn run(config: &str, arguments: &[&str], root: Option<&PathBuf>) -> Output {
+	let dir = scratch("config");
+	let config_path = dir.join("conf.ini");
+	fs::write(&config_path, config).unwrap();
+
+	let mut command = Command::new(env!("CARGO_BIN_EXE_save-manager"));
+	command
+		.arg("--config")
+		.arg(&config_path)
+		.arg("simulate")
+		.args(arguments);
+	if let Some(root) = root {
+		command.arg("--root").arg(root);
+	}
+	let output = command.output().unwrap();
+
+	fs::remove_dir_all(&dir).unwrap();
+	output
+}
+
+fn simulate(config: &str, arguments: &[&str]) -> Vec<Kept> {
+	let output = run(config, arguments, None);
+	assert!(
+		output.status.success(),
+		"simulation failed: {}",
+		String::from_utf8_lossy(&output.stderr)
+	);
+
+	String::from_utf8(output.stdout)
+		.unwrap()
+		.lines()
+		.map(|line| {
+			let fields = line.split('\t').collect::<Vec<_>>();
+			Kept {
+				number: fields[0].parse().unwrap(),
+				created: fields[1].to_string(),
+				origin: fields[2].to_string(),
+			}
+		})
+		.collect()
+}
+
+fn numbers(kept: &[Kept]) -> Vec<usize> {
+	kept.iter().map(|backup| backup.number).collect()
+}
+
+#[test]
+fn keeping_all_numbers_every_save_in_order() {
+	let kept = simulate("", &["--every", "60", "--days", "1"]);
+
+	assert_eq!(numbers(&kept), (1..=24).collect::<Vec<_>>());
+	assert!(kept.iter().all(|backup| backup.origin == "auto"));
+}
+
+#[test]
+fn backups_are_dated_by_the_simulated_clock() {
+	let kept = simulate(
+		"",
+		&[
+			"--every",
+			"720",
+			"--days",
+			"2",
+			"--start",
+			"2024-02-28 09:00",
+		],
+	);
+
+	let created = kept
+		.iter()
+		.map(|backup| backup.created.as_str())
+		.collect::<Vec<_>>();
+	assert_eq!(
+		created,
+		vec![
+			"2024-02-28 21:00",
+			"2024-02-29 09:00",
+			"2024-02-29 21:00",
+			"2024-03-01 09:00",
+		]
+	);
+}
+
+#[test]
+fn last_keeps_the_newest() {
+	let kept = simulate(
+		"retention=last\nretention_count=5\n",
+		&["--every", "30", "--days", "1"],
+	);
+
+	assert_eq!(numbers(&kept), vec![44, 45, 46, 47, 48]);
+}
+
+#[test]
+fn days_keeps_backups_up_to_an_age() {
+	let kept = simulate(
+		"retention=days\nretention_days=1\n",
+		&["--every", "60", "--days", "3"],
+	);
+
+	// the backup exactly a day old is the last one kept
+	assert_eq!(numbers(&kept), (48..=72).collect::<Vec<_>>());
+}
+
+#[test]
+fn tiered_keeps_one_per_hour_then_one_per_day() {
+	let kept = simulate(
+		"retention=tiered\nretention_hourly=3\nretention_daily=3\n",
+		&[
+			"--every",
+			"30",
+			"--days",
+			"4",
+			"--start",
+			"2024-01-01 08:15",
+		],
+	);
+
+	// the newest of the two days before today, then of each of the last three hours
+	let created = kept
+		.iter()
+		.map(|backup| backup.created.as_str())
+		.collect::<Vec<_>>();
+	assert_eq!(
+		created,
+		vec![
+			"2024-01-03 23:45",
+			"2024-01-04 23:45",
+			"2024-01-05 06:45",
+			"2024-01-05 07:45",
+			"2024-01-05 08:15",
+		]
+	);
+	assert_eq!(numbers(&kept), vec![127, 175, 189, 191, 192]);
+}
+
+#[test]
+fn numbering_continues_past_deleted_backups() {
+	let root = scratch("root");
+	let config = "retention=last\nretention_count=2\n";
+
+	let output = run(config, &["--every", "60", "--days", "1"], Some(&root));
+	assert!(output.status.success());
+	let first = String::from_utf8(output.stdout).unwrap();
+	assert_eq!(first.lines().count(), 2);
+	assert!(first.starts_with("23\t"));
+
+	// a folder that already holds backups is refused, so histories never mix
+	let output = run(config, &["--every", "60", "--days", "1"], Some(&root));
+	assert!(!output.status.success());
+
+	// deleting a backup by hand does not give its number out again while a newer one is kept
+	let profile = Profile::builtin(root.join("saves"));
+	let save = profile.save_file("simulation");
+	let store = BackupStore::open(profile.backup_path.join("simulation")).unwrap();
+	let oldest = store.find("23").unwrap();
+	store::trash_backups(store.path(), slice::from_ref(&oldest)).unwrap();
+	fs::write(&save, "CK2txt\ndate=\"1090.1.1\"\n").unwrap();
+	let created = store.back_up(&save, "", Origin::Cli).unwrap();
+	assert_eq!(created.number, 25);
+	let numbers = store
+		.backups()
+		.unwrap()
+		.iter()
+		.map(|backup| backup.number)
+		.collect::<Vec<_>>();
+	assert_eq!(numbers, [24, 25]);
+
+	fs::remove_dir_all(&root).unwrap();
+}
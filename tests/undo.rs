@@ -0,0 +1,68 @@
+//! Undoes restores and deletions through the journal. The journal is shared by the whole process,
+//! so everything is done in one test.
+
+use std::env;
+use std::fs;
+use std::slice;
+
+use save_manager::manifest::Origin;
+use save_manager::store;
+use save_manager::undo;
+use save_manager::BackupStore;
+
+fn save_text(day: u32) -> String {
+	format!("CK2txt\nversion=\"2.8.3.4\"\ndate=\"1066.9.{}\"\n}}\n", day)
+}
+
+#[test]
+fn undoes_the_latest_operation_first() {
+	let dir = env::temp_dir().join(format!("save-manager-undo-{}", std::process::id()));
+	let _ = fs::remove_dir_all(&dir);
+	fs::create_dir_all(&dir).unwrap();
+	let save = dir.join("Ironman.ck2");
+	let store = BackupStore::open(dir.join("backups").join("Ironman")).unwrap();
+
+	fs::write(&save, save_text(1)).unwrap();
+	store.back_up(&save, "start", Origin::Cli).unwrap();
+	fs::write(&save, save_text(2)).unwrap();
+	store.back_up(&save, "", Origin::Cli).unwrap();
+	let mut manifest = store.manifest();
+	let first = store.find("1").unwrap();
+	manifest.set_tags(&first.file_name, &["crusade".to_string()]);
+	manifest.save().unwrap();
+
+	store.restore(&first, &save).unwrap();
+	store::trash_backups(store.path(), slice::from_ref(&first)).unwrap();
+	assert!(store.find("1").is_err());
+	assert_eq!(undo::describe_last().unwrap(), "Deleting backup 1");
+
+	// the deletion is undone first, with what the manifest recorded about the backup
+	undo::undo_last().unwrap();
+	let first = store.find("1").unwrap();
+	assert_eq!(first.note, "start");
+	assert_eq!(store.manifest().tags(&first.file_name), vec!["crusade"]);
+	assert_eq!(fs::read_to_string(&save).unwrap(), save_text(1));
+
+	undo::undo_last().unwrap();
+	assert_eq!(fs::read_to_string(&save).unwrap(), save_text(2));
+	assert!(undo::undo_last().is_err());
+
+	// the number of the newest backup is given out again once it is trashed, so the trashed
+	// backup is not put back next to the one taken since
+	let second = store.find("2").unwrap();
+	store::trash_backups(store.path(), slice::from_ref(&second)).unwrap();
+	fs::write(&save, save_text(3)).unwrap();
+	let retaken = store.back_up(&save, "retaken", Origin::Cli).unwrap();
+	assert_eq!(retaken.number, second.number);
+	assert!(undo::undo_last().is_err());
+	let backups = store.backups().unwrap();
+	assert_eq!(backups.len(), 2);
+	assert_eq!(backups[1].note, "retaken");
+
+	// what is still in the trash at the end of the session is removed for good
+	undo::empty_trash().unwrap();
+	assert!(undo::describe_last().is_none());
+	assert!(!store.path().join(undo::TRASH_FOLDER).exists());
+
+	fs::remove_dir_all(&dir).unwrap();
+}